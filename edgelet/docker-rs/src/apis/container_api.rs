@@ -115,6 +115,7 @@ pub trait ContainerApi: Send + Sync {
         stdout: bool,
         stderr: bool,
         since: i32,
+        until: Option<i32>,
         timestamps: bool,
         tail: &str,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
@@ -817,6 +818,7 @@ where
         stdout: bool,
         stderr: bool,
         since: i32,
+        until: Option<i32>,
         timestamps: bool,
         tail: &str,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
@@ -824,14 +826,18 @@ where
 
         let method = hyper::Method::GET;
 
-        let query = ::url::form_urlencoded::Serializer::new(String::new())
+        let mut query = ::url::form_urlencoded::Serializer::new(String::new());
+        query
             .append_pair("follow", &follow.to_string())
             .append_pair("stdout", &stdout.to_string())
             .append_pair("stderr", &stderr.to_string())
             .append_pair("since", &since.to_string())
             .append_pair("timestamps", &timestamps.to_string())
-            .append_pair("tail", &tail.to_string())
-            .finish();
+            .append_pair("tail", &tail.to_string());
+        if let Some(until) = until {
+            query.append_pair("until", &until.to_string());
+        }
+        let query = query.finish();
         let uri_str = format!("/containers/{id}/logs?{}", query, id = id);
 
         let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
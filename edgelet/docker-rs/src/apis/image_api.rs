@@ -89,7 +89,7 @@ pub trait ImageApi: Send + Sync {
         name: &str,
         force: bool,
         noprune: bool,
-    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>> + Send>;
     fn image_get(
         &self,
         name: &str,
@@ -113,7 +113,10 @@ pub trait ImageApi: Send + Sync {
         all: bool,
         filters: &str,
         digests: bool,
-    ) -> Box<dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>>;
+    ) -> Box<
+        dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>
+            + Send,
+    >;
     fn image_load(
         &self,
         images_tarball: Vec<u8>,
@@ -469,7 +472,7 @@ where
         name: &str,
         force: bool,
         noprune: bool,
-    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>>>
+    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>> + Send>
     {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
@@ -733,8 +736,10 @@ where
         all: bool,
         filters: &str,
         digests: bool,
-    ) -> Box<dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>>
-    {
+    ) -> Box<
+        dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>
+            + Send,
+    > {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
@@ -86,6 +86,9 @@ pub enum ErrorKind {
     #[fail(display = "Invalid log tail {:?}", _0)]
     InvalidLogTail(String),
 
+    #[fail(display = "Invalid log stream {:?}", _0)]
+    InvalidLogStream(String),
+
     #[fail(display = "Invalid module name {:?}", _0)]
     InvalidModuleName(String),
 
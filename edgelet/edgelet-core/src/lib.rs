@@ -40,12 +40,12 @@ pub use crypto::{
 };
 pub use error::{Error, ErrorKind};
 pub use identity::{AuthType, Identity, IdentityManager, IdentityOperation, IdentitySpec};
-pub use logs::{Chunked, LogChunk, LogDecode};
+pub use logs::{Chunked, LogChunk, LogDecode, LogLine};
 pub use module::{
-    DiskInfo, ImagePullPolicy, LogOptions, LogTail, MakeModuleRuntime, Module, ModuleOperation,
-    ModuleRegistry, ModuleRuntime, ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleSpec,
-    ModuleStatus, ModuleTop, ProvisioningResult, RegistryOperation, RuntimeOperation, SystemInfo,
-    SystemResources,
+    DiskInfo, ImagePullPolicy, LogOptions, LogStream, LogTail, MakeModuleRuntime, Module,
+    ModuleOperation, ModuleRegistry, ModuleRuntime, ModuleRuntimeErrorReason, ModuleRuntimeState,
+    ModuleSpec, ModuleStats, ModuleStatus, ModuleTop, ProvisioningResult, RegistryOperation,
+    RuntimeOperation, SystemInfo, SystemResources,
 };
 pub use network::{Ipam, IpamConfig, MobyNetwork, Network};
 pub use parse_since::parse_since;
@@ -4,6 +4,7 @@ use std::cmp;
 use std::io;
 
 use bytes::{Buf, BufMut, Bytes, BytesMut, IntoBuf};
+use chrono::{DateTime, Utc};
 use futures::prelude::*;
 use futures::try_ready;
 use tokio::codec::length_delimited;
@@ -34,6 +35,73 @@ pub enum LogChunk {
     Unknown(Bytes),
 }
 
+impl LogChunk {
+    fn split_timestamp(self) -> (Option<DateTime<Utc>>, Self) {
+        match self {
+            LogChunk::Stdin(payload) => {
+                let (timestamp, payload) = split_timestamp(payload);
+                (timestamp, LogChunk::Stdin(payload))
+            }
+            LogChunk::Stdout(payload) => {
+                let (timestamp, payload) = split_timestamp(payload);
+                (timestamp, LogChunk::Stdout(payload))
+            }
+            LogChunk::Stderr(payload) => {
+                let (timestamp, payload) = split_timestamp(payload);
+                (timestamp, LogChunk::Stderr(payload))
+            }
+            LogChunk::Unknown(payload) => {
+                let (timestamp, payload) = split_timestamp(payload);
+                (timestamp, LogChunk::Unknown(payload))
+            }
+        }
+    }
+}
+
+/// Docker prefixes every line with an RFC 3339 timestamp followed by a space
+/// when logs are requested with timestamps:
+///
+/// 2019-11-05T18:22:43.123456789Z Roses are red
+fn split_timestamp(payload: Bytes) -> (Option<DateTime<Utc>>, Bytes) {
+    let timestamp = payload.iter().position(|&b| b == b' ').and_then(|pos| {
+        let timestamp = std::str::from_utf8(&payload[..pos]).ok()?;
+        let timestamp = DateTime::parse_from_rfc3339(timestamp).ok()?;
+        Some((timestamp.with_timezone(&Utc), pos))
+    });
+
+    match timestamp {
+        Some((timestamp, pos)) => (Some(timestamp), payload.slice_from(pos + 1)),
+        None => (None, payload),
+    }
+}
+
+/// A log chunk with the timestamp it was prefixed with, if any.
+#[derive(Debug, PartialEq)]
+pub struct LogLine {
+    timestamp: Option<DateTime<Utc>>,
+    chunk: LogChunk,
+}
+
+impl LogLine {
+    /// Splits the timestamp prefix off a chunk. A chunk without a valid timestamp prefix is kept unchanged.
+    pub fn from_chunk(chunk: LogChunk) -> Self {
+        let (timestamp, chunk) = chunk.split_timestamp();
+        LogLine { timestamp, chunk }
+    }
+
+    pub fn timestamp(&self) -> Option<&DateTime<Utc>> {
+        self.timestamp.as_ref()
+    }
+
+    pub fn chunk(&self) -> &LogChunk {
+        &self.chunk
+    }
+
+    pub fn into_chunk(self) -> LogChunk {
+        self.chunk
+    }
+}
+
 pub struct LogDecode<T: AsyncRead> {
     inner: FramedRead<T, length_delimited::LengthDelimitedCodec>,
 }
@@ -48,6 +116,11 @@ impl<T: AsyncRead> LogDecode<T> {
             .new_read(inner);
         LogDecode { inner: delimited }
     }
+
+    /// Splits the timestamp prefix off every chunk of logs requested with timestamps.
+    pub fn with_timestamps(self) -> impl Stream<Item = LogLine, Error = io::Error> {
+        self.map(LogLine::from_chunk)
+    }
 }
 
 impl<T: AsyncRead> Stream for LogDecode<T> {
@@ -171,10 +244,11 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{io, Bytes, Chunked, Future, LogChunk, LogDecode, Stream};
+    use super::{io, Bytes, Chunked, Future, LogChunk, LogDecode, LogLine, Stream};
 
     use std::io::Read;
 
+    use chrono::{TimeZone, Utc};
     use futures::stream::iter_ok;
 
     #[test]
@@ -201,6 +275,36 @@ mod tests {
         assert_eq!(expected, decoded);
     }
 
+    #[test]
+    fn split_timestamps() {
+        let mut chunks = vec![0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2c];
+        chunks.extend_from_slice(b"2019-11-05T18:22:43.123456789Z Roses are red");
+        chunks.extend_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10]);
+        chunks.extend_from_slice(b"violets are blue");
+
+        let stream = iter_ok::<Vec<Vec<u8>>, io::Error>(vec![chunks]);
+        let decoded = LogDecode::new(Chunked::new(stream))
+            .with_timestamps()
+            .collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(2, decoded.len());
+        assert_eq!(
+            Some(&Utc.ymd(2019, 11, 5).and_hms_nano(18, 22, 43, 123_456_789)),
+            decoded[0].timestamp()
+        );
+        assert_eq!(
+            &LogChunk::Stdout(Bytes::from("Roses are red")),
+            decoded[0].chunk()
+        );
+        assert_eq!(
+            LogLine::from_chunk(LogChunk::Stderr(Bytes::from("violets are blue"))),
+            decoded[1]
+        );
+        assert_eq!(None, decoded[1].timestamp());
+    }
+
     #[test]
     fn test_read() {
         let chunks = vec![
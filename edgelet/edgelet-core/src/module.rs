@@ -56,6 +56,8 @@ pub struct ModuleRuntimeState {
     finished_at: Option<DateTime<Utc>>,
     image_id: Option<String>,
     pid: Option<i32>,
+    #[serde(default)]
+    restart_count: Option<u32>,
 }
 
 impl Default for ModuleRuntimeState {
@@ -68,6 +70,7 @@ impl Default for ModuleRuntimeState {
             finished_at: None,
             image_id: None,
             pid: None,
+            restart_count: None,
         }
     }
 }
@@ -135,6 +138,16 @@ impl ModuleRuntimeState {
         self.pid = pid;
         self
     }
+
+    /// The number of times the module runtime restarted the module.
+    pub fn restart_count(&self) -> Option<u32> {
+        self.restart_count
+    }
+
+    pub fn with_restart_count(mut self, restart_count: Option<u32>) -> Self {
+        self.restart_count = restart_count;
+        self
+    }
 }
 
 #[derive(serde_derive::Deserialize, Debug, serde_derive::Serialize)]
@@ -280,11 +293,61 @@ impl ToString for LogTail {
     }
 }
 
+/// The output streams of a module to return logs from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogStream {
+    All,
+    Stdout,
+    Stderr,
+}
+
+impl LogStream {
+    pub fn stdout(self) -> bool {
+        self != LogStream::Stderr
+    }
+
+    pub fn stderr(self) -> bool {
+        self != LogStream::Stdout
+    }
+}
+
+impl Default for LogStream {
+    fn default() -> Self {
+        LogStream::All
+    }
+}
+
+impl FromStr for LogStream {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "all" => Ok(LogStream::All),
+            "stdout" => Ok(LogStream::Stdout),
+            "stderr" => Ok(LogStream::Stderr),
+            _ => Err(Error::from(ErrorKind::InvalidLogStream(s.to_string()))),
+        }
+    }
+}
+
+impl ToString for LogStream {
+    fn to_string(&self) -> String {
+        match self {
+            LogStream::All => "all".to_string(),
+            LogStream::Stdout => "stdout".to_string(),
+            LogStream::Stderr => "stderr".to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct LogOptions {
     follow: bool,
     tail: LogTail,
     since: i32,
+    until: Option<i32>,
+    timestamps: bool,
+    stream: LogStream,
 }
 
 impl LogOptions {
@@ -293,6 +356,9 @@ impl LogOptions {
             follow: false,
             tail: LogTail::All,
             since: 0,
+            until: None,
+            timestamps: false,
+            stream: LogStream::All,
         }
     }
 
@@ -311,6 +377,21 @@ impl LogOptions {
         self
     }
 
+    pub fn with_until(mut self, until: i32) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    pub fn with_timestamps(mut self, timestamps: bool) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    pub fn with_stream(mut self, stream: LogStream) -> Self {
+        self.stream = stream;
+        self
+    }
+
     pub fn follow(&self) -> bool {
         self.follow
     }
@@ -322,6 +403,18 @@ impl LogOptions {
     pub fn since(&self) -> i32 {
         self.since
     }
+
+    pub fn until(&self) -> Option<i32> {
+        self.until
+    }
+
+    pub fn timestamps(&self) -> bool {
+        self.timestamps
+    }
+
+    pub fn stream(&self) -> LogStream {
+        self.stream
+    }
 }
 
 pub trait Module {
@@ -385,10 +478,12 @@ pub struct SystemResources {
     used_ram: u64,
     total_ram: u64,
     disks: Vec<DiskInfo>,
+    modules: Vec<ModuleStats>,
     docker_stats: String,
 }
 
 impl SystemResources {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         host_uptime: u64,
         process_uptime: u64,
@@ -396,6 +491,7 @@ impl SystemResources {
         used_ram: u64,
         total_ram: u64,
         disks: Vec<DiskInfo>,
+        modules: Vec<ModuleStats>,
         docker_stats: String,
     ) -> Self {
         SystemResources {
@@ -405,9 +501,14 @@ impl SystemResources {
             used_ram,
             total_ram,
             disks,
+            modules,
             docker_stats,
         }
     }
+
+    pub fn modules(&self) -> &[ModuleStats] {
+        &self.modules
+    }
 }
 
 #[derive(Debug, serde_derive::Serialize)]
@@ -437,6 +538,97 @@ impl DiskInfo {
     }
 }
 
+/// Resource usage of a single module, as sampled by the module runtime.
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Serialize)]
+pub struct ModuleStats {
+    /// Name of the module. Example: tempSensor
+    name: String,
+    /// CPU usage as a percentage of a single CPU, so it can exceed 100 on multi-core hosts.
+    cpu_percent: f64,
+    memory_usage: u64,
+    memory_limit: u64,
+    network_rx_bytes: u64,
+    network_tx_bytes: u64,
+    block_read_bytes: u64,
+    block_write_bytes: u64,
+}
+
+impl ModuleStats {
+    pub fn new(name: String) -> Self {
+        ModuleStats {
+            name,
+            ..ModuleStats::default()
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cpu_percent(&self) -> f64 {
+        self.cpu_percent
+    }
+
+    pub fn with_cpu_percent(mut self, cpu_percent: f64) -> Self {
+        self.cpu_percent = cpu_percent;
+        self
+    }
+
+    pub fn memory_usage(&self) -> u64 {
+        self.memory_usage
+    }
+
+    pub fn with_memory_usage(mut self, memory_usage: u64) -> Self {
+        self.memory_usage = memory_usage;
+        self
+    }
+
+    pub fn memory_limit(&self) -> u64 {
+        self.memory_limit
+    }
+
+    pub fn with_memory_limit(mut self, memory_limit: u64) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    pub fn network_rx_bytes(&self) -> u64 {
+        self.network_rx_bytes
+    }
+
+    pub fn with_network_rx_bytes(mut self, network_rx_bytes: u64) -> Self {
+        self.network_rx_bytes = network_rx_bytes;
+        self
+    }
+
+    pub fn network_tx_bytes(&self) -> u64 {
+        self.network_tx_bytes
+    }
+
+    pub fn with_network_tx_bytes(mut self, network_tx_bytes: u64) -> Self {
+        self.network_tx_bytes = network_tx_bytes;
+        self
+    }
+
+    pub fn block_read_bytes(&self) -> u64 {
+        self.block_read_bytes
+    }
+
+    pub fn with_block_read_bytes(mut self, block_read_bytes: u64) -> Self {
+        self.block_read_bytes = block_read_bytes;
+        self
+    }
+
+    pub fn block_write_bytes(&self) -> u64 {
+        self.block_write_bytes
+    }
+
+    pub fn with_block_write_bytes(mut self, block_write_bytes: u64) -> Self {
+        self.block_write_bytes = block_write_bytes;
+        self
+    }
+}
+
 #[derive(Debug)]
 pub struct ModuleTop {
     /// Name of the module. Example: tempSensor
@@ -494,6 +686,7 @@ pub trait ModuleRuntime: Sized {
     type ListWithDetailsStream: Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error>
         + Send;
     type LogsFuture: Future<Item = Self::Logs, Error = Self::Error> + Send;
+    type ModuleStatsFuture: Future<Item = ModuleStats, Error = Self::Error> + Send;
     type RemoveFuture: Future<Item = (), Error = Self::Error> + Send;
     type RestartFuture: Future<Item = (), Error = Self::Error> + Send;
     type StartFuture: Future<Item = (), Error = Self::Error> + Send;
@@ -513,6 +706,7 @@ pub trait ModuleRuntime: Sized {
     fn list(&self) -> Self::ListFuture;
     fn list_with_details(&self) -> Self::ListWithDetailsStream;
     fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture;
+    fn module_stats(&self, id: &str) -> Self::ModuleStatsFuture;
     fn registry(&self) -> &Self::ModuleRegistry;
     fn remove_all(&self) -> Self::RemoveAllFuture;
 }
@@ -540,6 +734,7 @@ impl fmt::Display for ModuleOperation {
 // Useful for error contexts
 #[derive(Clone, Debug)]
 pub enum RegistryOperation {
+    ListImages,
     PullImage(String),
     RemoveImage(String),
 }
@@ -547,6 +742,7 @@ pub enum RegistryOperation {
 impl fmt::Display for RegistryOperation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            RegistryOperation::ListImages => write!(f, "Could not list images"),
             RegistryOperation::PullImage(name) => write!(f, "Could not pull image {}", name),
             RegistryOperation::RemoveImage(name) => write!(f, "Could not remove image {}", name),
         }
@@ -559,6 +755,7 @@ pub enum RuntimeOperation {
     CreateModule(String),
     GetModule(String),
     GetModuleLogs(String),
+    GetModuleStats(String),
     Init,
     ListModules,
     RemoveModule(String),
@@ -578,6 +775,9 @@ impl fmt::Display for RuntimeOperation {
             RuntimeOperation::GetModuleLogs(name) => {
                 write!(f, "Could not get logs for module {}", name)
             }
+            RuntimeOperation::GetModuleStats(name) => {
+                write!(f, "Could not get stats for module {}", name)
+            }
             RuntimeOperation::Init => write!(f, "Could not initialize module runtime"),
             RuntimeOperation::ListModules => write!(f, "Could not list modules"),
             RuntimeOperation::RemoveModule(name) => write!(f, "Could not remove module {}", name),
@@ -621,7 +821,7 @@ impl FromStr for ImagePullPolicy {
 
 #[cfg(test)]
 mod tests {
-    use super::{BTreeMap, Default, ImagePullPolicy, ModuleSpec, SystemInfo};
+    use super::{BTreeMap, Default, ImagePullPolicy, LogStream, ModuleSpec, SystemInfo};
 
     use std::str::FromStr;
     use std::string::ToString;
@@ -654,6 +854,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn log_stream_roundtrip() {
+        for &(s, stream, stdout, stderr) in &[
+            ("all", LogStream::All, true, true),
+            ("stdout", LogStream::Stdout, true, false),
+            ("stderr", LogStream::Stderr, false, true),
+        ] {
+            assert_eq!(stream, LogStream::from_str(s).unwrap());
+            assert_eq!(s, &stream.to_string());
+            assert_eq!(stdout, stream.stdout());
+            assert_eq!(stderr, stream.stderr());
+        }
+    }
+
+    #[test]
+    fn log_stream_invalid_fails() {
+        let err = LogStream::from_str("stdin").unwrap_err();
+        if let ErrorKind::InvalidLogStream(s) = err.kind() {
+            assert_eq!(s, "stdin");
+        } else {
+            panic!("Expected `InvalidLogStream` but got {:?}", err);
+        }
+    }
+
     #[test]
     fn module_config_empty_name_fails() {
         let name = "".to_string();
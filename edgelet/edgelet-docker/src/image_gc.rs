@@ -0,0 +1,599 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::Either;
+use futures::prelude::*;
+use futures::{future, stream};
+use log::{debug, info, warn, Level};
+use sysinfo::{DiskExt, System, SystemExt};
+use tokio::timer::Interval;
+
+use docker::models::ImageSummary;
+use edgelet_core::{RegistryOperation, RuntimeOperation};
+use edgelet_http::UrlConnector;
+use edgelet_utils::log_failure;
+
+use crate::client::DockerClient;
+use crate::error::{Error, ErrorKind};
+use crate::runtime::{LABEL_KEY, LABEL_VALUE};
+use crate::settings::ImageGarbageCollection;
+
+const PULLED_REPOSITORIES_FILE: &str = "pulled_image_repositories.json";
+
+/// The images pulled for modules.
+///
+/// The repositories of the pulled images are saved in the home directory, so that the
+/// images of modules removed from the deployment are still collected after a restart.
+/// When every image was last pulled is only kept in memory.
+#[derive(Clone)]
+pub(crate) struct PulledImages {
+    path: PathBuf,
+    inner: Arc<Mutex<PulledImagesInner>>,
+}
+
+struct PulledImagesInner {
+    repositories: BTreeSet<String>,
+    pulled_at: HashMap<String, Instant>,
+}
+
+impl PulledImages {
+    pub(crate) fn load(homedir: &Path) -> Self {
+        let path = homedir.join(PULLED_REPOSITORIES_FILE);
+        let repositories = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|err| {
+                warn!(
+                    "Could not parse the pulled image repositories in {}: {}",
+                    path.display(),
+                    err
+                );
+                BTreeSet::new()
+            }),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(err) => {
+                warn!(
+                    "Could not read the pulled image repositories from {}: {}",
+                    path.display(),
+                    err
+                );
+                BTreeSet::new()
+            }
+        };
+
+        PulledImages {
+            path,
+            inner: Arc::new(Mutex::new(PulledImagesInner {
+                repositories,
+                pulled_at: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Records that `image` was just pulled.
+    pub(crate) fn record(&self, image: &str) {
+        let image = normalize_image_name(image);
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Could not acquire pulled images lock");
+
+        let added = inner
+            .repositories
+            .insert(name_repository(&image).to_string());
+        inner.pulled_at.insert(image, Instant::now());
+
+        if added {
+            let saved = serde_json::to_vec(&inner.repositories)
+                .map_err(io::Error::from)
+                .and_then(|contents| fs::write(&self.path, contents));
+            if let Err(err) = saved {
+                warn!(
+                    "Could not save the pulled image repositories to {}: {}",
+                    self.path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    fn repositories(&self) -> HashSet<String> {
+        let inner = self
+            .inner
+            .lock()
+            .expect("Could not acquire pulled images lock");
+        inner.repositories.iter().cloned().collect()
+    }
+
+    /// The names of the images pulled less than `grace_period` ago.
+    fn pulled_within(&self, grace_period: Duration) -> Vec<String> {
+        let inner = self
+            .inner
+            .lock()
+            .expect("Could not acquire pulled images lock");
+        inner
+            .pulled_at
+            .iter()
+            .filter(|(_, pulled_at)| pulled_at.elapsed() < grace_period)
+            .map(|(image, _)| image.clone())
+            .collect()
+    }
+}
+
+/// Periodically removes the module images that aren't used by any container.
+///
+/// Only images of the repositories pulled for modules, used by the containers created
+/// for module specs, or used by the edge agent spec are removed. Images pulled for
+/// anything else on the device are left alone. The images used by any container, and
+/// the images pulled within the grace period for a deployment still being applied,
+/// are always kept.
+pub(crate) struct ImageGarbageCollector {
+    client: DockerClient<UrlConnector>,
+    system_resources: Arc<Mutex<System>>,
+    settings: ImageGarbageCollection,
+    agent_image: String,
+    pulled_images: PulledImages,
+}
+
+impl ImageGarbageCollector {
+    pub(crate) fn new(
+        client: DockerClient<UrlConnector>,
+        system_resources: Arc<Mutex<System>>,
+        settings: ImageGarbageCollection,
+        agent_image: String,
+        pulled_images: PulledImages,
+    ) -> Self {
+        ImageGarbageCollector {
+            client,
+            system_resources,
+            settings,
+            agent_image,
+            pulled_images,
+        }
+    }
+
+    /// Runs a collection every configured interval. The returned future never completes.
+    pub(crate) fn run(self) -> impl Future<Item = (), Error = ()> + Send {
+        let interval = cmp::max(self.settings.interval(), Duration::from_secs(1));
+        info!(
+            "Removing unused images every {} seconds",
+            interval.as_secs()
+        );
+
+        let collector = Arc::new(self);
+        Interval::new(Instant::now() + interval, interval)
+            .map_err(|err| warn!("Image garbage collection timer failed: {}", err))
+            .for_each(move |_| {
+                collector.collect().then(|result| {
+                    if let Err(err) = result {
+                        log_failure(Level::Warn, &err);
+                    }
+                    Ok(())
+                })
+            })
+    }
+
+    fn collect(&self) -> impl Future<Item = (), Error = Error> + Send {
+        let client = self.client.clone();
+        let system_resources = self.system_resources.clone();
+        let threshold = self.settings.disk_usage_threshold_percent();
+        let keep_previous_versions = self.settings.keep_previous_versions();
+        let agent_image = normalize_image_name(&self.agent_image);
+        let pulled_images = self.pulled_images.clone();
+        let pull_grace_period = self.settings.pull_grace_period();
+
+        self.client
+            .system_api()
+            .system_info()
+            .map_err(|err| {
+                Error::from_docker_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo),
+                )
+            })
+            .and_then(move |system_info| {
+                let disk_usage = system_info.docker_root_dir().and_then(|root_dir| {
+                    let mut system_resources = system_resources
+                        .lock()
+                        .expect("Could not acquire system resources lock");
+                    disk_usage_percent(&mut system_resources, Path::new(root_dir))
+                });
+
+                match disk_usage {
+                    Some(disk_usage) if disk_usage < threshold => {
+                        debug!(
+                            "Disk usage {}% is below {}%, not removing unused images",
+                            disk_usage, threshold
+                        );
+                        Either::A(future::ok(()))
+                    }
+                    Some(_) => Either::B(remove_unused_images(
+                        client,
+                        agent_image,
+                        pulled_images.repositories(),
+                        pulled_images.pulled_within(pull_grace_period),
+                        keep_previous_versions,
+                    )),
+                    None => {
+                        warn!("Could not determine the disk usage of the container runtime, not removing unused images");
+                        Either::A(future::ok(()))
+                    }
+                }
+            })
+    }
+}
+
+fn remove_unused_images(
+    client: DockerClient<UrlConnector>,
+    agent_image: String,
+    mut module_repositories: HashSet<String>,
+    recently_pulled: Vec<String>,
+    keep_previous_versions: usize,
+) -> impl Future<Item = (), Error = Error> + Send {
+    let list_client = client.clone();
+
+    client
+        .container_api()
+        .container_list(true, 0, false, "")
+        .map_err(|err| {
+            Error::from_docker_error(
+                err,
+                ErrorKind::RuntimeOperation(RuntimeOperation::ListModules),
+            )
+        })
+        .and_then(move |containers| {
+            let mut in_use: HashSet<String> = containers
+                .iter()
+                .flat_map(|container| {
+                    vec![
+                        container.image_id().to_string(),
+                        normalize_image_name(container.image()),
+                    ]
+                })
+                .collect();
+
+            // Images pulled for a deployment that is still being applied may not have a container yet.
+            in_use.extend(recently_pulled);
+
+            // The containers created for the module specs are the ones owned by the edge agent.
+            module_repositories.extend(
+                containers
+                    .iter()
+                    .filter(|container| {
+                        container
+                            .labels()
+                            .get(LABEL_KEY)
+                            .map_or(false, |owner| owner == LABEL_VALUE)
+                    })
+                    .map(|container| {
+                        name_repository(&normalize_image_name(container.image())).to_string()
+                    }),
+            );
+            module_repositories.insert(name_repository(&agent_image).to_string());
+            in_use.insert(agent_image);
+
+            list_client
+                .image_api()
+                .image_list(false, "", false)
+                .map_err(|err| {
+                    Error::from_docker_error(
+                        err,
+                        ErrorKind::RegistryOperation(RegistryOperation::ListImages),
+                    )
+                })
+                .map(move |images| {
+                    images_to_remove(
+                        &images,
+                        &in_use,
+                        &module_repositories,
+                        keep_previous_versions,
+                    )
+                })
+        })
+        .and_then(move |unused| {
+            stream::iter_ok(unused).for_each(move |id| {
+                info!("Removing unused image {}...", id);
+                // Images used by containers that this daemon doesn't manage can't be removed,
+                // so failures are logged without stopping the collection.
+                client
+                    .image_api()
+                    .image_delete(&id, false, false)
+                    .then(|result| {
+                        match result {
+                            Ok(_) => info!("Successfully removed image {}", id),
+                            Err(err) => log_failure(
+                                Level::Warn,
+                                &Error::from_docker_error(
+                                    err,
+                                    ErrorKind::RegistryOperation(RegistryOperation::RemoveImage(
+                                        id,
+                                    )),
+                                ),
+                            ),
+                        }
+                        Ok(())
+                    })
+            })
+        })
+}
+
+/// Returns the percentage of the disk holding `path` that is in use, if that disk can be found.
+fn disk_usage_percent(system_resources: &mut System, path: &Path) -> Option<u8> {
+    system_resources.refresh_disks();
+
+    system_resources
+        .get_disks()
+        .iter()
+        .filter(|disk| path.starts_with(disk.get_mount_point()))
+        .max_by_key(|disk| disk.get_mount_point().as_os_str().len())
+        .filter(|disk| disk.get_total_space() > 0)
+        .map(|disk| {
+            let used = disk.get_total_space() - disk.get_available_space();
+            (used * 100 / disk.get_total_space())
+                .try_into()
+                .unwrap_or(100)
+        })
+}
+
+/// Picks the unused images of the module repositories to remove, keeping the
+/// `keep_previous_versions` newest unused images of every repository.
+///
+/// Images without a repository, such as intermediate build images, are never removed.
+fn images_to_remove(
+    images: &[ImageSummary],
+    in_use: &HashSet<String>,
+    module_repositories: &HashSet<String>,
+    keep_previous_versions: usize,
+) -> Vec<String> {
+    let mut unused: BTreeMap<&str, Vec<&ImageSummary>> = BTreeMap::new();
+    for image in images {
+        let used = in_use.contains(image.id())
+            || image
+                .repo_tags()
+                .unwrap_or_default()
+                .iter()
+                .chain(image.repo_digests().unwrap_or_default())
+                .any(|name| in_use.contains(name));
+        if used {
+            continue;
+        }
+
+        if let Some(repository) = repository(image) {
+            if module_repositories.contains(repository) {
+                unused.entry(repository).or_default().push(image);
+            }
+        }
+    }
+
+    unused
+        .into_iter()
+        .flat_map(|(_, mut images)| {
+            images.sort_by(|a, b| b.created().cmp(a.created()));
+            images
+                .into_iter()
+                .skip(keep_previous_versions)
+                .map(|image| image.id().clone())
+        })
+        .collect()
+}
+
+/// The repository of an image, taken from its tags or, for untagged images, from its digests.
+fn repository(image: &ImageSummary) -> Option<&str> {
+    let tagged = image
+        .repo_tags()
+        .unwrap_or_default()
+        .iter()
+        .find(|tag| tag.as_str() != "<none>:<none>")
+        .map(|tag| name_repository(tag));
+
+    tagged.or_else(|| {
+        image
+            .repo_digests()
+            .unwrap_or_default()
+            .iter()
+            .find(|digest| digest.as_str() != "<none>@<none>")
+            .and_then(|digest| digest.split('@').next())
+    })
+}
+
+/// The repository of an image name, that is the name without its tag or digest.
+fn name_repository(name: &str) -> &str {
+    if let Some(pos) = name.find('@') {
+        return &name[..pos];
+    }
+
+    match name.rfind(':') {
+        Some(pos) if !name[pos..].contains('/') => &name[..pos],
+        _ => name,
+    }
+}
+
+/// Adds the implicit `latest` tag to image names without a tag or digest,
+/// so that they can be compared with the tags of an image.
+fn normalize_image_name(name: &str) -> String {
+    let has_tag = name
+        .rsplit('/')
+        .next()
+        .map_or(false, |last| last.contains(':'));
+    if has_tag || name.contains('@') {
+        name.to_string()
+    } else {
+        format!("{}:latest", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tempdir::TempDir;
+
+    use super::{
+        images_to_remove, name_repository, normalize_image_name, repository, HashSet, ImageSummary,
+        PulledImages,
+    };
+
+    fn image(id: &str, created: i32, repo_tags: &[&str]) -> ImageSummary {
+        ImageSummary::new(id.to_string(), String::new(), created, 0, 0, 0, -1)
+            .with_repo_tags(repo_tags.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn normalize_adds_latest_tag() {
+        assert_eq!("ubuntu:latest", normalize_image_name("ubuntu"));
+        assert_eq!("ubuntu:18.04", normalize_image_name("ubuntu:18.04"));
+        assert_eq!(
+            "localhost:5000/edge/module:latest",
+            normalize_image_name("localhost:5000/edge/module")
+        );
+        assert_eq!(
+            "localhost:5000/edge/module:1.0",
+            normalize_image_name("localhost:5000/edge/module:1.0")
+        );
+        assert_eq!(
+            "ubuntu@sha256:abcd",
+            normalize_image_name("ubuntu@sha256:abcd")
+        );
+    }
+
+    #[test]
+    fn name_repository_strips_tag_or_digest() {
+        assert_eq!("ubuntu", name_repository("ubuntu:18.04"));
+        assert_eq!("ubuntu", name_repository("ubuntu@sha256:abcd"));
+        assert_eq!(
+            "localhost:5000/edge/module",
+            name_repository("localhost:5000/edge/module:1.0")
+        );
+        assert_eq!(
+            "localhost:5000/edge/module",
+            name_repository("localhost:5000/edge/module")
+        );
+    }
+
+    #[test]
+    fn repository_from_tags_or_digests() {
+        assert_eq!(
+            Some("localhost:5000/edge/module"),
+            repository(&image("1", 0, &["localhost:5000/edge/module:1.0"]))
+        );
+        assert_eq!(
+            Some("edge/module"),
+            repository(
+                &image("2", 0, &["<none>:<none>"])
+                    .with_repo_digests(vec!["edge/module@sha256:abcd".to_string()])
+            )
+        );
+        assert_eq!(None, repository(&image("3", 0, &["<none>:<none>"])));
+        assert_eq!(
+            None,
+            repository(&ImageSummary::new(
+                "4".to_string(),
+                String::new(),
+                0,
+                0,
+                0,
+                0,
+                -1
+            ))
+        );
+    }
+
+    #[test]
+    fn unused_images_are_removed_except_previous_versions() {
+        let images = vec![
+            image("agent-1.0", 1, &["edge/agent:1.0"]),
+            image("agent-1.1", 2, &["edge/agent:1.1"]),
+            image("agent-1.2", 3, &["edge/agent:1.2"]),
+            image("module-1", 1, &["edge/module:1"]),
+            image("module-2", 2, &["edge/module:2"]),
+            image("module-3", 3, &["edge/module:3"]),
+            image("removed-1", 1, &["edge/removed:1"]),
+            image("untagged", 1, &[]),
+        ];
+        let in_use: HashSet<String> = vec!["edge/agent:1.0".to_string(), "module-3".to_string()]
+            .into_iter()
+            .collect();
+        let module_repositories: HashSet<String> = vec![
+            "edge/agent".to_string(),
+            "edge/module".to_string(),
+            "edge/removed".to_string(),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            vec!["agent-1.1", "module-1"],
+            images_to_remove(&images, &in_use, &module_repositories, 1)
+        );
+        assert_eq!(
+            vec![
+                "agent-1.2",
+                "agent-1.1",
+                "module-2",
+                "module-1",
+                "removed-1"
+            ],
+            images_to_remove(&images, &in_use, &module_repositories, 0)
+        );
+        assert!(images_to_remove(&images, &in_use, &module_repositories, 2).is_empty());
+    }
+
+    #[test]
+    fn images_of_other_repositories_are_kept() {
+        let images = vec![
+            image("module-1", 1, &["edge/module:1"]),
+            image("other-1", 1, &["other/image:1"]),
+        ];
+        let module_repositories: HashSet<String> =
+            vec!["edge/module".to_string()].into_iter().collect();
+
+        assert_eq!(
+            vec!["module-1"],
+            images_to_remove(&images, &HashSet::new(), &module_repositories, 0)
+        );
+    }
+
+    #[test]
+    fn pulled_repositories_are_saved() {
+        let tmp_dir = TempDir::new("image_gc").unwrap();
+
+        let pulled_images = PulledImages::load(tmp_dir.path());
+        assert!(pulled_images.repositories().is_empty());
+        pulled_images.record("edge/module:1");
+        pulled_images.record("edge/module:2");
+        pulled_images.record("localhost:5000/edge/removed");
+
+        let expected: HashSet<String> = vec![
+            "edge/module".to_string(),
+            "localhost:5000/edge/removed".to_string(),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(expected, pulled_images.repositories());
+
+        let reloaded = PulledImages::load(tmp_dir.path());
+        assert_eq!(expected, reloaded.repositories());
+        assert!(reloaded.pulled_within(Duration::from_secs(3600)).is_empty());
+    }
+
+    #[test]
+    fn recently_pulled_images_are_in_grace_period() {
+        let tmp_dir = TempDir::new("image_gc").unwrap();
+        let pulled_images = PulledImages::load(tmp_dir.path());
+        pulled_images.record("edge/module");
+
+        assert_eq!(
+            vec!["edge/module:latest"],
+            pulled_images.pulled_within(Duration::from_secs(3600))
+        );
+        assert!(pulled_images
+            .pulled_within(Duration::from_secs(0))
+            .is_empty());
+    }
+}
@@ -13,12 +13,14 @@
 mod client;
 mod config;
 mod error;
+mod image_gc;
 mod module;
 mod runtime;
 mod settings;
+mod stats;
 
 pub use crate::config::DockerConfig;
 pub use error::{Error, ErrorKind};
 pub use module::{DockerModule, MODULE_TYPE};
 pub use runtime::DockerModuleRuntime;
-pub use settings::{LoadSettingsError, Settings, DEFAULTS};
+pub use settings::{ImageGarbageCollection, LoadSettingsError, Settings, DEFAULTS};
@@ -20,7 +20,8 @@ use docker::models::{ContainerCreateBody, InlineResponse200, Ipam, NetworkConfig
 use edgelet_core::{
     AuthId, Authenticator, GetTrustBundle, Ipam as CoreIpam, LogOptions, MakeModuleRuntime,
     MobyNetwork, Module, ModuleId, ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec,
-    RegistryOperation, RuntimeOperation, SystemInfo as CoreSystemInfo, SystemResources, UrlExt,
+    ModuleStats, RegistryOperation, RuntimeOperation, RuntimeSettings,
+    SystemInfo as CoreSystemInfo, SystemResources, UrlExt,
 };
 use edgelet_http::{Pid, UrlConnector};
 use edgelet_utils::{ensure_not_empty_with_context, log_failure};
@@ -29,10 +30,12 @@ use provisioning::ProvisioningResult;
 use crate::client::DockerClient;
 use crate::config::DockerConfig;
 use crate::error::{Error, ErrorKind, Result};
+use crate::image_gc::{ImageGarbageCollector, PulledImages};
 use crate::module::{
     runtime_state, DockerModule, DockerModuleTop, MODULE_TYPE as DOCKER_MODULE_TYPE,
 };
 use crate::settings::Settings;
+use crate::stats::module_stats;
 
 use edgelet_core::DiskInfo;
 use std::convert::TryInto;
@@ -44,8 +47,8 @@ use sysinfo::{DiskExt, ProcessExt, ProcessorExt, System, SystemExt};
 
 type Deserializer = &'static mut serde_json::Deserializer<serde_json::de::IoRead<std::io::Empty>>;
 
-static LABEL_KEY: &str = "net.azure-devices.edge.owner";
-static LABEL_VALUE: &str = "Microsoft.Azure.Devices.Edge.Agent";
+pub(crate) static LABEL_KEY: &str = "net.azure-devices.edge.owner";
+pub(crate) static LABEL_VALUE: &str = "Microsoft.Azure.Devices.Edge.Agent";
 
 lazy_static! {
     static ref LABELS: Vec<&'static str> = {
@@ -59,6 +62,7 @@ lazy_static! {
 pub struct DockerModuleRuntime {
     client: DockerClient<UrlConnector>,
     system_resources: Arc<Mutex<System>>,
+    pulled_images: Option<PulledImages>,
 }
 
 impl DockerModuleRuntime {
@@ -102,6 +106,8 @@ impl ModuleRegistry for DockerModuleRuntime {
 
         info!("Pulling image {}...", image);
 
+        let pulled_images = self.pulled_images.clone();
+
         let creds: Result<String> = config.auth().map_or_else(
             || Ok("".to_string()),
             |a| {
@@ -130,6 +136,9 @@ impl ModuleRegistry for DockerModuleRuntime {
             .then(move |result| match result {
                 Ok(image) => {
                     info!("Successfully pulled image {}", image);
+                    if let Some(pulled_images) = pulled_images {
+                        pulled_images.record(&image);
+                    }
                     Ok(())
                 }
                 Err(err) => {
@@ -211,6 +220,10 @@ impl MakeModuleRuntime for DockerModuleRuntime {
                 let (enable_i_pv6, ipam) = get_ipv6_settings(settings.moby_runtime().network());
                 info!("Using runtime network id {}", network_id);
 
+                let image_gc_settings = settings.moby_runtime().image_garbage_collection().clone();
+                let agent_image = settings.agent().config().image().to_string();
+                let homedir = settings.homedir().to_path_buf();
+
                 let filter = format!(r#"{{"name":{{"{}":true}}}}"#, network_id);
                 let client_copy = client.clone();
                 let fut = client
@@ -245,10 +258,28 @@ impl MakeModuleRuntime for DockerModuleRuntime {
                     .map(|client| {
                         let mut system_resources = System::new_all();
                         system_resources.refresh_all();
+                        let system_resources = Arc::new(Mutex::new(system_resources));
+
+                        let pulled_images = if image_gc_settings.enabled() {
+                            let pulled_images = PulledImages::load(&homedir);
+                            let image_gc = ImageGarbageCollector::new(
+                                client.clone(),
+                                system_resources.clone(),
+                                image_gc_settings,
+                                agent_image,
+                                pulled_images.clone(),
+                            );
+                            tokio::spawn(image_gc.run());
+                            Some(pulled_images)
+                        } else {
+                            None
+                        };
+
                         info!("Successfully initialized module runtime");
                         DockerModuleRuntime {
                             client,
-                            system_resources: Arc::new(Mutex::new(system_resources)),
+                            system_resources,
+                            pulled_images,
                         }
                     });
 
@@ -312,6 +343,7 @@ impl ModuleRuntime for DockerModuleRuntime {
     type ListWithDetailsStream =
         Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
     type LogsFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
+    type ModuleStatsFuture = Box<dyn Future<Item = ModuleStats, Error = Self::Error> + Send>;
     type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type RestartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
@@ -421,7 +453,11 @@ impl ModuleRuntime for DockerModuleRuntime {
                             DockerModule::new(client_copy, name, config).with_context(|_| {
                                 ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id.clone()))
                             })?;
-                        let state = runtime_state(container.id(), container.state());
+                        let state = runtime_state(
+                            container.id(),
+                            container.state(),
+                            container.restart_count(),
+                        );
                         Ok((module, state))
                     }
                     Err(err) => {
@@ -615,7 +651,11 @@ impl ModuleRuntime for DockerModuleRuntime {
                 remove_not_found(
                     stream::iter_ok(modules)
                         .and_then(move |module| {
-                            client.container_api().container_stats(module.name(), false)
+                            let name = module.name().to_string();
+                            client
+                                .container_api()
+                                .container_stats(&name, false)
+                                .map(|stats| (name, stats))
                         })
                         .map_err(|err| {
                             Error::from_docker_error(
@@ -626,14 +666,21 @@ impl ModuleRuntime for DockerModuleRuntime {
                 )
                 .collect()
             })
-            .map(serde_json::Value::Array) // Condense into single json value
-            .and_then(|stats| {
-                // convert to string
-                serde_json::to_string(&stats).map_err(|_| {
-                    Error::from(ErrorKind::RuntimeOperation(
-                        RuntimeOperation::SystemResources,
-                    ))
-                })
+            .and_then(|stats: Vec<(String, serde_json::Value)>| -> Result<_> {
+                let modules: Vec<ModuleStats> = stats
+                    .iter()
+                    .map(|(name, stats)| module_stats(name.clone(), stats))
+                    .collect();
+
+                // The raw stats are kept for the clients that still parse them
+                let stats = stats.into_iter().map(|(_, stats)| stats).collect();
+                let stats =
+                    serde_json::to_string(&serde_json::Value::Array(stats)).map_err(|_| {
+                        Error::from(ErrorKind::RuntimeOperation(
+                            RuntimeOperation::SystemResources,
+                        ))
+                    })?;
+                Ok((modules, stats))
             });
 
         #[cfg(not(any(windows, target_os = "linux")))]
@@ -693,7 +740,7 @@ impl ModuleRuntime for DockerModuleRuntime {
             })
             .collect();
 
-        let result = docker_stats.map(move |stats: String| {
+        let result = docker_stats.map(move |(modules, stats)| {
             SystemResources::new(
                 uptime,
                 current_time - start_time,
@@ -701,6 +748,7 @@ impl ModuleRuntime for DockerModuleRuntime {
                 used_memory,
                 total_memory,
                 disks,
+                modules,
                 stats,
             )
         });
@@ -794,10 +842,11 @@ impl ModuleRuntime for DockerModuleRuntime {
             .container_logs(
                 &id,
                 options.follow(),
-                true,
-                true,
+                options.stream().stdout(),
+                options.stream().stderr(),
                 options.since(),
-                false,
+                options.until(),
+                options.timestamps(),
                 tail,
             )
             .then(|result| match result {
@@ -817,6 +866,31 @@ impl ModuleRuntime for DockerModuleRuntime {
         Box::new(result)
     }
 
+    fn module_stats(&self, id: &str) -> Self::ModuleStatsFuture {
+        info!("Getting stats for module {}...", id);
+        let id = id.to_string();
+
+        let result = self
+            .client
+            .container_api()
+            .container_stats(&id, false)
+            .then(|result| match result {
+                Ok(stats) => {
+                    info!("Successfully got stats for module {}", id);
+                    Ok(module_stats(id, &stats))
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+        Box::new(result)
+    }
+
     fn registry(&self) -> &Self::ModuleRegistry {
         self
     }
@@ -1035,8 +1109,8 @@ mod tests {
         authenticate, future, list_with_details, parse_get_response, AuthId, Authenticator,
         BTreeMap, Body, CoreSystemInfo, Deserializer, DockerModuleRuntime, DockerModuleTop,
         Duration, Error, ErrorKind, Future, GetTrustBundle, InlineResponse200, LogOptions,
-        MakeModuleRuntime, Module, ModuleId, ModuleRuntime, ModuleRuntimeState, ModuleSpec, Pid,
-        ProvisioningResult, Request, Settings, Stream, SystemResources,
+        MakeModuleRuntime, Module, ModuleId, ModuleRuntime, ModuleRuntimeState, ModuleSpec,
+        ModuleStats, Pid, ProvisioningResult, Request, Settings, Stream, SystemResources,
     };
 
     use std::path::Path;
@@ -1478,6 +1552,7 @@ mod tests {
         type ListWithDetailsStream =
             Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
         type LogsFuture = FutureResult<Self::Logs, Self::Error>;
+        type ModuleStatsFuture = FutureResult<ModuleStats, Self::Error>;
         type RemoveFuture = FutureResult<(), Self::Error>;
         type RestartFuture = FutureResult<(), Self::Error>;
         type StartFuture = FutureResult<(), Self::Error>;
@@ -1531,6 +1606,10 @@ mod tests {
             unimplemented!()
         }
 
+        fn module_stats(&self, _id: &str) -> Self::ModuleStatsFuture {
+            unimplemented!()
+        }
+
         fn registry(&self) -> &Self::ModuleRegistry {
             self
         }
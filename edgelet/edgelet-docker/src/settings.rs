@@ -2,6 +2,7 @@
 
 use std::collections::BTreeMap;
 use std::path::Path;
+use std::time::Duration;
 
 use config::{Config, Environment};
 use docker::models::{ContainerCreateBodyNetworkingConfig, EndpointSettings, HostConfig};
@@ -33,6 +34,8 @@ pub struct MobyRuntime {
     #[serde(with = "url_serde")]
     uri: Url,
     network: MobyNetwork,
+    #[serde(default)]
+    image_garbage_collection: ImageGarbageCollection,
 }
 
 impl MobyRuntime {
@@ -43,6 +46,61 @@ impl MobyRuntime {
     pub fn network(&self) -> &MobyNetwork {
         &self.network
     }
+
+    pub fn image_garbage_collection(&self) -> &ImageGarbageCollection {
+        &self.image_garbage_collection
+    }
+}
+
+/// Settings for periodically removing container images that no module uses any more.
+///
+/// Collection is disabled unless `enabled` is set. Only images of the repositories
+/// used by the modules are removed, and only while the disk holding the container
+/// runtime's data is at least `disk_usage_threshold_percent` full. The newest `keep_previous_versions`
+/// unused images of every repository are kept so that a deployment can be rolled back, and images
+/// pulled less than `pull_grace_period_secs` ago are kept so that a deployment can finish.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(default)]
+pub struct ImageGarbageCollection {
+    enabled: bool,
+    interval_secs: u64,
+    keep_previous_versions: usize,
+    disk_usage_threshold_percent: u8,
+    pull_grace_period_secs: u64,
+}
+
+impl ImageGarbageCollection {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+
+    pub fn keep_previous_versions(&self) -> usize {
+        self.keep_previous_versions
+    }
+
+    pub fn disk_usage_threshold_percent(&self) -> u8 {
+        self.disk_usage_threshold_percent
+    }
+
+    pub fn pull_grace_period(&self) -> Duration {
+        Duration::from_secs(self.pull_grace_period_secs)
+    }
+}
+
+impl Default for ImageGarbageCollection {
+    fn default() -> Self {
+        ImageGarbageCollection {
+            enabled: false,
+            interval_secs: 60 * 60,
+            keep_previous_versions: 1,
+            disk_usage_threshold_percent: 80,
+            pull_grace_period_secs: 60 * 60,
+        }
+    }
 }
 
 /// This struct is the same as the Settings type from the `edgelet_core` crate
@@ -283,7 +341,10 @@ impl From<ErrorKind> for LoadSettingsError {
 
 #[cfg(test)]
 mod tests {
-    use super::{MobyNetwork, MobyRuntime, Path, RuntimeSettings, Settings, Url};
+    use super::{
+        Duration, ImageGarbageCollection, MobyNetwork, MobyRuntime, Path, RuntimeSettings,
+        Settings, Url,
+    };
 
     use std::cmp::Ordering;
     use std::fs::File;
@@ -353,6 +414,8 @@ mod tests {
     #[cfg(unix)]
     static GOOD_SETTINGS_NETWORK: &str = "test/linux/sample_settings.network.yaml";
     #[cfg(unix)]
+    static GOOD_SETTINGS_IMAGE_GC: &str = "test/linux/sample_settings.image_gc.yaml";
+    #[cfg(unix)]
     static GOOD_SETTINGS_DYNAMIC_REPROVISIONING: &str = "test/linux/sample_settings.dyn.repro.yaml";
     #[cfg(unix)]
     static BAD_SETTINGS_DYNAMIC_REPROVISIONING: &str =
@@ -417,6 +480,8 @@ mod tests {
     #[cfg(windows)]
     static GOOD_SETTINGS_NETWORK: &str = "test/windows/sample_settings.network.yaml";
     #[cfg(windows)]
+    static GOOD_SETTINGS_IMAGE_GC: &str = "test/windows/sample_settings.image_gc.yaml";
+    #[cfg(windows)]
     static GOOD_SETTINGS_DYNAMIC_REPROVISIONING: &str =
         "test/windows/sample_settings.dyn.repro.yaml";
     #[cfg(windows)]
@@ -444,12 +509,14 @@ mod tests {
         let moby1 = MobyRuntime {
             uri: Url::parse("http://test").unwrap(),
             network: MobyNetwork::Name("".to_string()),
+            image_garbage_collection: ImageGarbageCollection::default(),
         };
         assert_eq!(DEFAULT_NETWORKID, moby1.network().name());
 
         let moby2 = MobyRuntime {
             uri: Url::parse("http://test").unwrap(),
             network: MobyNetwork::Name("some-network".to_string()),
+            image_garbage_collection: ImageGarbageCollection::default(),
         };
         assert_eq!("some-network", moby2.network().name());
     }
@@ -490,6 +557,28 @@ mod tests {
         };
     }
 
+    #[test]
+    fn image_garbage_collection_default() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        let image_gc = settings.moby_runtime().image_garbage_collection();
+        assert!(!image_gc.enabled());
+        assert_eq!(Duration::from_secs(3600), image_gc.interval());
+        assert_eq!(1, image_gc.keep_previous_versions());
+        assert_eq!(80, image_gc.disk_usage_threshold_percent());
+        assert_eq!(Duration::from_secs(3600), image_gc.pull_grace_period());
+    }
+
+    #[test]
+    fn image_garbage_collection_get_settings() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_IMAGE_GC)).unwrap();
+        let image_gc = settings.moby_runtime().image_garbage_collection();
+        assert!(image_gc.enabled());
+        assert_eq!(Duration::from_secs(600), image_gc.interval());
+        assert_eq!(3, image_gc.keep_previous_versions());
+        assert_eq!(90, image_gc.disk_usage_threshold_percent());
+        assert_eq!(Duration::from_secs(1800), image_gc.pull_grace_period());
+    }
+
     #[test]
     fn no_file_gets_error() {
         let settings = Settings::new(Path::new("garbage"));
@@ -0,0 +1,274 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use edgelet_core::ModuleStats;
+
+/// Converts a one-shot response of the Docker container stats API into the stats of a module.
+///
+/// Values that are missing from the response, which depends on the platform and on the state
+/// of the container, are reported as zero.
+pub(crate) fn module_stats(name: String, stats: &Value) -> ModuleStats {
+    let (read, written) = block_io(stats);
+    let (received, transmitted) = network_io(stats);
+
+    ModuleStats::new(name)
+        .with_cpu_percent(cpu_percent(stats))
+        .with_memory_usage(memory_usage(stats))
+        .with_memory_limit(u64_at(stats, "/memory_stats/limit"))
+        .with_network_rx_bytes(received)
+        .with_network_tx_bytes(transmitted)
+        .with_block_read_bytes(read)
+        .with_block_write_bytes(written)
+}
+
+fn u64_at(value: &Value, pointer: &str) -> u64 {
+    value
+        .pointer(pointer)
+        .and_then(Value::as_u64)
+        .unwrap_or_default()
+}
+
+/// Computes the CPU usage as a percentage of a single CPU, the same way as `docker stats` on Linux.
+///
+/// Linux containers report the host CPU time next to their own, while Windows containers
+/// only report their own CPU time in 100ns intervals, to be compared with the elapsed time.
+/// Unlike `docker stats`, the Windows usage isn't divided by the number of processors,
+/// so that it has the same scale as the Linux usage.
+#[allow(clippy::cast_precision_loss)]
+fn cpu_percent(stats: &Value) -> f64 {
+    let cpu_delta = u64_at(stats, "/cpu_stats/cpu_usage/total_usage")
+        .saturating_sub(u64_at(stats, "/precpu_stats/cpu_usage/total_usage"));
+
+    let system_delta = if stats.pointer("/cpu_stats/system_cpu_usage").is_some() {
+        let system_delta = u64_at(stats, "/cpu_stats/system_cpu_usage")
+            .saturating_sub(u64_at(stats, "/precpu_stats/system_cpu_usage"));
+        let online_cpus = match u64_at(stats, "/cpu_stats/online_cpus") {
+            0 => stats
+                .pointer("/cpu_stats/cpu_usage/percpu_usage")
+                .and_then(Value::as_array)
+                .map_or(0, Vec::len) as u64,
+            online_cpus => online_cpus,
+        };
+        (system_delta as f64) / (online_cpus.max(1) as f64)
+    } else {
+        let elapsed = timestamp(stats, "read")
+            .and_then(|read| timestamp(stats, "preread").map(|preread| read - preread))
+            .and_then(|elapsed| elapsed.num_nanoseconds())
+            .unwrap_or_default();
+        (elapsed.max(0) as f64) / 100.0
+    };
+
+    if cpu_delta > 0 && system_delta > 0.0 {
+        (cpu_delta as f64) / system_delta * 100.0
+    } else {
+        0.0
+    }
+}
+
+fn timestamp(stats: &Value, field: &str) -> Option<DateTime<Utc>> {
+    stats
+        .get(field)
+        .and_then(Value::as_str)
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
+
+/// The memory used by the container, excluding the inactive page cache like `docker stats`.
+fn memory_usage(stats: &Value) -> u64 {
+    if stats.pointer("/memory_stats/usage").is_none() {
+        return u64_at(stats, "/memory_stats/privateworkingset");
+    }
+
+    let inactive_file = match u64_at(stats, "/memory_stats/stats/total_inactive_file") {
+        0 => u64_at(stats, "/memory_stats/stats/inactive_file"),
+        inactive_file => inactive_file,
+    };
+    u64_at(stats, "/memory_stats/usage").saturating_sub(inactive_file)
+}
+
+/// The bytes received and transmitted, summed over all the networks of the container.
+fn network_io(stats: &Value) -> (u64, u64) {
+    stats
+        .get("networks")
+        .and_then(Value::as_object)
+        .map_or((0, 0), |networks| {
+            networks.values().fold((0, 0), |(rx, tx), network| {
+                (
+                    rx + u64_at(network, "/rx_bytes"),
+                    tx + u64_at(network, "/tx_bytes"),
+                )
+            })
+        })
+}
+
+/// The bytes read from and written to block devices, summed over all devices.
+fn block_io(stats: &Value) -> (u64, u64) {
+    if let Some(entries) = stats
+        .pointer("/blkio_stats/io_service_bytes_recursive")
+        .and_then(Value::as_array)
+    {
+        entries.iter().fold((0, 0), |(read, write), entry| {
+            let value = u64_at(entry, "/value");
+            match entry.get("op").and_then(Value::as_str) {
+                Some(op) if op.eq_ignore_ascii_case("read") => (read + value, write),
+                Some(op) if op.eq_ignore_ascii_case("write") => (read, write + value),
+                _ => (read, write),
+            }
+        })
+    } else {
+        (
+            u64_at(stats, "/storage_stats/read_size_bytes"),
+            u64_at(stats, "/storage_stats/write_size_bytes"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{module_stats, ModuleStats};
+
+    #[test]
+    fn linux_stats() {
+        let stats = json!({
+            "read": "2019-12-10T10:00:01.000000000Z",
+            "preread": "2019-12-10T10:00:00.000000000Z",
+            "cpu_stats": {
+                "cpu_usage": {
+                    "total_usage": 300_000_000,
+                    "percpu_usage": [150_000_000, 150_000_000]
+                },
+                "system_cpu_usage": 2_000_000_000,
+                "online_cpus": 2
+            },
+            "precpu_stats": {
+                "cpu_usage": {
+                    "total_usage": 100_000_000
+                },
+                "system_cpu_usage": 1_000_000_000
+            },
+            "memory_stats": {
+                "usage": 50_000,
+                "limit": 1_000_000,
+                "stats": {
+                    "total_inactive_file": 10_000
+                }
+            },
+            "networks": {
+                "eth0": { "rx_bytes": 100, "tx_bytes": 200 },
+                "eth1": { "rx_bytes": 1, "tx_bytes": 2 }
+            },
+            "blkio_stats": {
+                "io_service_bytes_recursive": [
+                    { "major": 8, "minor": 0, "op": "Read", "value": 1024 },
+                    { "major": 8, "minor": 0, "op": "Write", "value": 2048 },
+                    { "major": 8, "minor": 0, "op": "Total", "value": 3072 },
+                    { "major": 8, "minor": 16, "op": "read", "value": 1 }
+                ]
+            }
+        });
+
+        let stats = module_stats("tempSensor".to_string(), &stats);
+
+        assert_eq!("tempSensor", stats.name());
+        assert!((stats.cpu_percent() - 40.0).abs() < f64::EPSILON);
+        assert_eq!(40_000, stats.memory_usage());
+        assert_eq!(1_000_000, stats.memory_limit());
+        assert_eq!(101, stats.network_rx_bytes());
+        assert_eq!(202, stats.network_tx_bytes());
+        assert_eq!(1025, stats.block_read_bytes());
+        assert_eq!(2048, stats.block_write_bytes());
+    }
+
+    #[test]
+    fn windows_stats() {
+        // As returned by a Windows daemon, which omits the host CPU usage
+        let stats = json!({
+            "read": "2019-12-10T10:00:01.000000000Z",
+            "preread": "2019-12-10T10:00:00.000000000Z",
+            "pids_stats": {},
+            "blkio_stats": {
+                "io_service_bytes_recursive": null,
+                "io_serviced_recursive": null,
+                "io_queue_recursive": null,
+                "io_service_time_recursive": null,
+                "io_wait_time_recursive": null,
+                "io_merged_recursive": null,
+                "io_time_recursive": null,
+                "sectors_recursive": null
+            },
+            "num_procs": 4,
+            "storage_stats": {
+                "read_count_normalized": 12,
+                "read_size_bytes": 4096,
+                "write_count_normalized": 34,
+                "write_size_bytes": 8192
+            },
+            "cpu_stats": {
+                "cpu_usage": {
+                    "total_usage": 25_000_000,
+                    "usage_in_kernelmode": 5_000_000,
+                    "usage_in_usermode": 20_000_000
+                },
+                "throttling_data": {
+                    "periods": 0,
+                    "throttled_periods": 0,
+                    "throttled_time": 0
+                }
+            },
+            "precpu_stats": {
+                "cpu_usage": {
+                    "total_usage": 10_000_000,
+                    "usage_in_kernelmode": 2_000_000,
+                    "usage_in_usermode": 8_000_000
+                },
+                "throttling_data": {
+                    "periods": 0,
+                    "throttled_periods": 0,
+                    "throttled_time": 0
+                }
+            },
+            "memory_stats": {
+                "commitbytes": 40_000,
+                "commitpeakbytes": 50_000,
+                "privateworkingset": 30_000
+            },
+            "name": "/tempSensor",
+            "id": "6a4b5b2c5d9e",
+            "networks": {
+                "ethernet": {
+                    "rx_bytes": 10,
+                    "rx_packets": 1,
+                    "rx_errors": 0,
+                    "rx_dropped": 0,
+                    "tx_bytes": 20,
+                    "tx_packets": 2,
+                    "tx_errors": 0,
+                    "tx_dropped": 0
+                }
+            }
+        });
+
+        let stats = module_stats("tempSensor".to_string(), &stats);
+
+        // 1.5 seconds of CPU time in 1 second is 150% of a single CPU, whatever the number of processors
+        assert!((stats.cpu_percent() - 150.0).abs() < f64::EPSILON);
+        assert_eq!(30_000, stats.memory_usage());
+        assert_eq!(0, stats.memory_limit());
+        assert_eq!(10, stats.network_rx_bytes());
+        assert_eq!(20, stats.network_tx_bytes());
+        assert_eq!(4096, stats.block_read_bytes());
+        assert_eq!(8192, stats.block_write_bytes());
+    }
+
+    #[test]
+    fn missing_stats_are_zero() {
+        assert_eq!(
+            ModuleStats::new("tempSensor".to_string()),
+            module_stats("tempSensor".to_string(), &json!({}))
+        );
+    }
+}
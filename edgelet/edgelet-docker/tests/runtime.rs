@@ -26,7 +26,7 @@ use docker::models::{
 };
 
 use edgelet_core::{
-    GetTrustBundle, ImagePullPolicy, LogOptions, LogTail, MakeModuleRuntime, Module,
+    GetTrustBundle, ImagePullPolicy, LogOptions, LogStream, LogTail, MakeModuleRuntime, Module,
     ModuleRegistry, ModuleRuntime, ModuleSpec, RegistryOperation, RuntimeOperation,
 };
 use edgelet_docker::{DockerConfig, DockerModuleRuntime, Settings};
@@ -1176,6 +1176,10 @@ fn container_logs_handler(req: Request<Body>) -> ResponseFuture {
     assert_eq!("true", query_map["follow"]);
     assert_eq!("all", query_map["tail"]);
     assert_eq!("100000", query_map["since"]);
+    assert!(!query_map.contains_key("until"));
+    assert_eq!("true", query_map["stdout"]);
+    assert_eq!("true", query_map["stderr"]);
+    assert_eq!("false", query_map["timestamps"]);
 
     let body = vec![
         0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x52, 0x6f, 0x73, 0x65, 0x73, 0x20, 0x61,
@@ -1233,6 +1237,64 @@ fn container_logs_succeeds() {
     runtime.block_on(assert).unwrap();
 }
 
+#[allow(clippy::needless_pass_by_value)]
+fn container_logs_with_options_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/containers/mod1/logs");
+
+    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
+        .into_owned()
+        .collect();
+    assert_eq!("false", query_map["follow"]);
+    assert_eq!("10", query_map["tail"]);
+    assert_eq!("100000", query_map["since"]);
+    assert_eq!("200000", query_map["until"]);
+    assert_eq!("true", query_map["timestamps"]);
+    assert_eq!("false", query_map["stdout"]);
+    assert_eq!("true", query_map["stderr"]);
+
+    Box::new(future::ok(Response::new(Body::empty())))
+}
+
+#[test]
+fn container_logs_with_options_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/mod1/logs" => container_logs_with_options_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            let options = LogOptions::new()
+                .with_tail(LogTail::Num(10))
+                .with_since(100_000)
+                .with_until(200_000)
+                .with_timestamps(true)
+                .with_stream(LogStream::Stderr);
+
+            runtime.logs("mod1", &options)
+        })
+        .and_then(Stream::concat2)
+        .map(|b| assert!(b.is_empty()));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
 #[test]
 fn image_remove_with_white_space_name_fails() {
     let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
@@ -1,5 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::convert::TryInto;
 use std::fmt;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -12,11 +13,14 @@ use futures::stream;
 use hyper::{Body, Chunk as HyperChunk, Client};
 use management::apis::client::APIClient;
 use management::apis::configuration::Configuration;
-use management::models::{Config, ModuleDetails as HttpModuleDetails};
+use management::models::{
+    Config, ModuleDetails as HttpModuleDetails, ModuleStats as HttpModuleStats,
+};
 use url::Url;
 
 use edgelet_core::{
-    LogOptions, Module, ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec, ModuleStatus,
+    DiskInfo, LogOptions, Module, ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec,
+    ModuleStats, ModuleStatus,
 };
 use edgelet_core::{
     ModuleOperation, RuntimeOperation, SystemInfo as CoreSystemInfo, SystemResources, UrlExt,
@@ -120,13 +124,18 @@ fn runtime_status(details: &HttpModuleDetails) -> Result<ModuleRuntimeState, Err
         .exit_status()
         .and_then(|e| e.exit_time().parse().ok());
     let start_time = details.status().start_time().and_then(|s| s.parse().ok());
+    let restart_count = details
+        .status()
+        .restart_count()
+        .and_then(|count| count.try_into().ok());
 
     let state = ModuleRuntimeState::default()
         .with_status(status)
         .with_status_description(description)
         .with_exit_code(exit_code)
         .with_started_at(start_time)
-        .with_finished_at(exit_time);
+        .with_finished_at(exit_time)
+        .with_restart_count(restart_count);
     Ok(state)
 }
 
@@ -160,6 +169,7 @@ impl ModuleRuntime for ModuleClient {
     type ListWithDetailsStream =
         Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
     type LogsFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
+    type ModuleStatsFuture = Box<dyn Future<Item = ModuleStats, Error = Self::Error> + Send>;
     type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type RestartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
@@ -255,7 +265,50 @@ impl ModuleRuntime for ModuleClient {
     }
 
     fn system_resources(&self) -> Self::SystemResourcesFuture {
-        unimplemented!()
+        let resources = self
+            .client
+            .system_information_api()
+            .get_system_resources(&API_VERSION.to_string())
+            .map(|resources| {
+                let bytes = |value: i64| -> u64 { value.try_into().unwrap_or_default() };
+
+                let disks = resources
+                    .disks()
+                    .iter()
+                    .map(|disk| {
+                        DiskInfo::new(
+                            disk.name().clone(),
+                            bytes(disk.available_space()),
+                            bytes(disk.total_space()),
+                            disk.file_system().clone(),
+                            disk.file_type().clone(),
+                        )
+                    })
+                    .collect();
+                let modules = resources
+                    .modules()
+                    .iter()
+                    .map(convert_module_stats)
+                    .collect();
+
+                SystemResources::new(
+                    bytes(resources.host_uptime()),
+                    bytes(resources.process_uptime()),
+                    resources.used_cpu(),
+                    bytes(resources.used_ram()),
+                    bytes(resources.total_ram()),
+                    disks,
+                    modules,
+                    resources.docker_stats().clone(),
+                )
+            })
+            .map_err(|err| {
+                Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::SystemResources),
+                )
+            });
+        Box::new(resources)
     }
 
     fn list(&self) -> Self::ListFuture {
@@ -313,6 +366,7 @@ impl ModuleRuntime for ModuleClient {
         let id = id.to_string();
 
         let tail = &options.tail().to_string();
+        let stream = &options.stream().to_string();
         let result = self
             .client
             .module_api()
@@ -322,6 +376,9 @@ impl ModuleRuntime for ModuleClient {
                 options.follow(),
                 tail,
                 options.since(),
+                options.until(),
+                options.timestamps(),
+                stream,
             )
             .then(|logs| match logs {
                 Ok(logs) => Ok(Logs(id, logs)),
@@ -333,6 +390,23 @@ impl ModuleRuntime for ModuleClient {
         Box::new(result)
     }
 
+    fn module_stats(&self, id: &str) -> Self::ModuleStatsFuture {
+        let id = id.to_string();
+
+        let result = self
+            .client
+            .module_api()
+            .module_stats(&API_VERSION.to_string(), &id)
+            .then(|stats| match stats {
+                Ok(stats) => Ok(convert_module_stats(&stats)),
+                Err(err) => Err(Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(id)),
+                )),
+            });
+        Box::new(result)
+    }
+
     fn registry(&self) -> &Self::ModuleRegistry {
         self
     }
@@ -348,6 +422,19 @@ impl ModuleRuntime for ModuleClient {
     }
 }
 
+fn convert_module_stats(stats: &HttpModuleStats) -> ModuleStats {
+    let bytes = |value: i64| -> u64 { value.try_into().unwrap_or_default() };
+
+    ModuleStats::new(stats.name().clone())
+        .with_cpu_percent(stats.cpu_percent())
+        .with_memory_usage(bytes(stats.memory_usage()))
+        .with_memory_limit(bytes(stats.memory_limit()))
+        .with_network_rx_bytes(bytes(stats.network_rx_bytes()))
+        .with_network_tx_bytes(bytes(stats.network_tx_bytes()))
+        .with_block_read_bytes(bytes(stats.block_read_bytes()))
+        .with_block_write_bytes(bytes(stats.block_write_bytes()))
+}
+
 pub struct Logs(String, Body);
 
 impl Stream for Logs {
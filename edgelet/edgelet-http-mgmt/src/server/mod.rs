@@ -55,7 +55,8 @@ impl ManagementService {
         <M::AuthenticateFuture as Future>::Error: Fail,
     {
         let router = router!(
-            get     Version2018_06_28 runtime Policy::Anonymous             => "/modules"                           => ListModules::new(runtime.clone()),
+            get     Version2020_07_07 runtime Policy::Anonymous             => "/modules"                           => ListModules::new(runtime.clone()),
+            get     Version2018_06_28 runtime Policy::Anonymous             => "/modules"                           => ListModules::new(runtime.clone()).without_restart_count(),
             post    Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/modules"                           => CreateModule::new(runtime.clone()),
             get     Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)"           => GetModule,
             put     Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)"           => UpdateModule::new(runtime.clone()),
@@ -64,7 +65,9 @@ impl ManagementService {
             post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/start"     => StartModule::new(runtime.clone()),
             post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/stop"      => StopModule::new(runtime.clone()),
             post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/restart"   => RestartModule::new(runtime.clone()),
-            get     Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/logs"      => ModuleLogs::new(runtime.clone()),
+            get     Version2020_07_07 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/logs"      => ModuleLogs::new(runtime.clone()),
+            get     Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/logs"      => ModuleLogs::new(runtime.clone()).without_extended_options(),
+            get     Version2020_07_07 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/stats"     => GetModuleStats::new(runtime.clone()),
 
             get     Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities"                        => ListIdentities::new(identity.clone()),
             post    Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities"                        => CreateIdentity::new(identity.clone()),
@@ -72,7 +75,8 @@ impl ManagementService {
             delete  Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities/(?P<name>[^/]+)"        => DeleteIdentity::new(identity.clone()),
 
             get     Version2018_06_28 runtime Policy::Anonymous             => "/systeminfo"                        => GetSystemInfo::new(runtime.clone()),
-            get     Version2019_11_05 runtime Policy::Anonymous             => "/systeminfo/resources"              => GetSystemResources::new(runtime.clone()),
+            get     Version2020_07_07 runtime Policy::Anonymous             => "/systeminfo/resources"              => GetSystemResources::new(runtime.clone()),
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/systeminfo/resources"              => GetSystemResources::new(runtime.clone()).without_module_stats(),
 
             post    Version2019_10_22 runtime Policy::Module(&*AGENT_NAME)  => "/device/reprovision"                => ReprovisionDevice::new(initiate_shutdown_and_reprovision),
         );
@@ -1,5 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::convert::TryInto;
+
 use failure::ResultExt;
 use futures::{Future, Stream};
 use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
@@ -17,11 +19,23 @@ use crate::IntoResponse;
 
 pub struct ListModules<M> {
     runtime: M,
+    restart_count: bool,
 }
 
 impl<M> ListModules<M> {
     pub fn new(runtime: M) -> Self {
-        ListModules { runtime }
+        ListModules {
+            runtime,
+            restart_count: true,
+        }
+    }
+
+    /// Leaves out the restart count of the modules, which API versions before 2020-07-07 don't have.
+    pub fn without_restart_count(self) -> Self {
+        ListModules {
+            restart_count: false,
+            ..self
+        }
     }
 }
 
@@ -37,6 +51,7 @@ where
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         debug!("List modules");
 
+        let restart_count = self.restart_count;
         let response = self
             .runtime
             .list_with_details()
@@ -45,7 +60,7 @@ where
                 let details: Result<_, Error> = result
                     .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?
                     .into_iter()
-                    .map(|(module, state)| core_to_details(&module, &state))
+                    .map(|(module, state)| core_to_details(&module, &state, restart_count))
                     .collect();
                 let body = ModuleList::new(details?);
                 let b = serde_json::to_string(&body)
@@ -64,7 +79,11 @@ where
     }
 }
 
-fn core_to_details<M>(module: &M, state: &ModuleRuntimeState) -> Result<ModuleDetails, Error>
+fn core_to_details<M>(
+    module: &M,
+    state: &ModuleRuntimeState,
+    restart_count: bool,
+) -> Result<ModuleDetails, Error>
 where
     M: 'static + Module + Send,
     M::Config: Serialize,
@@ -85,6 +104,14 @@ where
             status.set_exit_status(ExitStatus::new(finished_at.to_rfc3339(), code.to_string()));
         }
     }
+    if restart_count {
+        if let Some(restart_count) = state
+            .restart_count()
+            .and_then(|count| count.try_into().ok())
+        {
+            status.set_restart_count(restart_count);
+        }
+    }
 
     Ok(ModuleDetails::new(
         "id".to_string(),
@@ -119,7 +146,8 @@ mod tests {
             .with_status_description(Some("description".to_string()))
             .with_started_at(Some(Utc.ymd(2018, 4, 13).and_hms_milli(14, 20, 0, 1)))
             .with_finished_at(Some(Utc.ymd(2018, 4, 13).and_hms_milli(15, 20, 0, 1)))
-            .with_image_id(Some("image-id".to_string()));
+            .with_image_id(Some("image-id".to_string()))
+            .with_restart_count(Some(2));
         let config = TestConfig::new("microsoft/test-image".to_string());
         let module: TestModule<Error, _> =
             TestModule::new("test-module".to_string(), config, Ok(state));
@@ -170,6 +198,46 @@ mod tests {
                     "description",
                     module.status().runtime_status().description().unwrap()
                 );
+                assert_eq!(Some(2), module.status().restart_count());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn restart_count_left_out_before_2020_07_07() {
+        // arrange
+        let state = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Running)
+            .with_restart_count(Some(2));
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = ListModules::new(runtime).without_restart_count();
+        let request = Request::get("http://localhost/modules")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let list: ModuleList = serde_json::from_slice(&b).unwrap();
+                let module = list.modules().iter().next().unwrap();
+                assert_eq!(None, module.status().restart_count());
                 Ok(())
             })
             .wait()
@@ -1,24 +1,51 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::io;
+
+use chrono::{DateTime, Utc};
 use failure::ResultExt;
-use futures::{future, Future, IntoFuture};
+use futures::{future, Future, IntoFuture, Stream};
+use hyper::header::{ACCEPT, CONTENT_TYPE};
 use hyper::{Body, Request, Response, StatusCode};
+use regex::Regex;
+use serde_derive::Serialize;
 use url::form_urlencoded;
 
-use edgelet_core::{parse_since, LogOptions, LogTail, ModuleRuntime, RuntimeOperation};
+use edgelet_core::{
+    parse_since, Chunked, LogChunk, LogDecode, LogLine, LogOptions, LogStream, LogTail,
+    ModuleRuntime, RuntimeOperation,
+};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Severity of lines without a syslog-style `<N>` prefix (informational).
+const DEFAULT_SEVERITY: u8 = 6;
+
 pub struct ModuleLogs<M> {
     runtime: M,
+    extended_options: bool,
 }
 
 impl<M> ModuleLogs<M> {
     pub fn new(runtime: M) -> Self {
-        ModuleLogs { runtime }
+        ModuleLogs {
+            runtime,
+            extended_options: true,
+        }
+    }
+
+    /// Ignores the `until`, `timestamps`, `stream` and `regex` options and always
+    /// returns the raw logs, as API versions before 2020-07-07 don't have them.
+    pub fn without_extended_options(self) -> Self {
+        ModuleLogs {
+            extended_options: false,
+            ..self
+        }
     }
 }
 
@@ -33,29 +60,46 @@ where
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let runtime = self.runtime.clone();
+        let extended_options = self.extended_options;
 
         let response = params
             .name("name")
             .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
             .and_then(|name| {
                 let name = name.to_string();
-                let options = req
-                    .uri()
-                    .query()
-                    .map_or_else(|| Ok(LogOptions::default()), parse_options)?;
-                Ok((name, options))
+                let query = req.uri().query().unwrap_or_default();
+                let options = parse_options(query, extended_options)?;
+                let format = if extended_options && accepts_ndjson(&req) {
+                    LogFormat::Ndjson(parse_filter(query)?)
+                } else {
+                    LogFormat::Raw
+                };
+                Ok((name, options, format))
             })
-            .map(move |(name, options)| {
+            .map(move |(name, options, format)| {
+                // Structured records always carry the timestamp, so ask the runtime for it
+                let options = match format {
+                    LogFormat::Raw => options,
+                    LogFormat::Ndjson(_) => options.with_timestamps(true),
+                };
+
                 runtime.logs(&name, &options).then(|s| -> Result<_, Error> {
                     let s = s.with_context(|_| {
                         ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(name.clone()))
                     })?;
-                    let response = Response::builder()
-                        .status(StatusCode::OK)
-                        .body(s.into())
-                        .context(ErrorKind::RuntimeOperation(
-                            RuntimeOperation::GetModuleLogs(name),
-                        ))?;
+
+                    let mut response = Response::builder();
+                    response.status(StatusCode::OK);
+                    let body = match format {
+                        LogFormat::Raw => s.into(),
+                        LogFormat::Ndjson(filter) => {
+                            response.header(CONTENT_TYPE, NDJSON_CONTENT_TYPE);
+                            Body::wrap_stream(structured_logs(s, filter))
+                        }
+                    };
+                    let response = response.body(body).context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::GetModuleLogs(name),
+                    ))?;
                     Ok(response)
                 })
             })
@@ -67,7 +111,118 @@ where
     }
 }
 
-fn parse_options(query: &str) -> Result<LogOptions, Error> {
+enum LogFormat {
+    /// The multiplexed frames as returned by the runtime
+    Raw,
+
+    /// Newline-delimited JSON records, optionally filtered by a regex on the message
+    Ndjson(Option<Regex>),
+}
+
+fn accepts_ndjson(req: &Request<Body>) -> bool {
+    req.headers()
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_range| {
+            let mut parts = media_range.split(';');
+            let media_type = parts.next().unwrap_or_default().trim();
+
+            // A quality of 0 means the client refuses the media type
+            let quality = parts
+                .filter_map(|param| {
+                    let mut param = param.splitn(2, '=');
+                    match (param.next(), param.next()) {
+                        (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("q") => {
+                            value.trim().parse::<f32>().ok()
+                        }
+                        _ => None,
+                    }
+                })
+                .next()
+                .unwrap_or(1.0);
+
+            media_type.eq_ignore_ascii_case(NDJSON_CONTENT_TYPE) && quality > 0.0
+        })
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct LogRecord {
+    timestamp: Option<DateTime<Utc>>,
+    stream: &'static str,
+    severity: u8,
+    message: String,
+}
+
+impl LogRecord {
+    fn new(line: &LogLine) -> Self {
+        let (stream, payload) = match line.chunk() {
+            LogChunk::Stdin(payload) => ("stdin", payload),
+            LogChunk::Stdout(payload) => ("stdout", payload),
+            LogChunk::Stderr(payload) => ("stderr", payload),
+            LogChunk::Unknown(payload) => ("unknown", payload),
+        };
+        let message = String::from_utf8_lossy(payload);
+        let (severity, message) = parse_severity(message.trim_end_matches(&['\r', '\n'][..]));
+
+        LogRecord {
+            timestamp: line.timestamp().copied(),
+            stream,
+            severity,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Splits a syslog-style severity prefix such as `<6>` off a log message.
+fn parse_severity(message: &str) -> (u8, &str) {
+    let bytes = message.as_bytes();
+    if bytes.len() >= 3 && bytes[0] == b'<' && bytes[2] == b'>' && (b'0'..=b'7').contains(&bytes[1])
+    {
+        (bytes[1] - b'0', message[3..].trim_start())
+    } else {
+        (DEFAULT_SEVERITY, message)
+    }
+}
+
+fn structured_logs<S>(
+    logs: S,
+    filter: Option<Regex>,
+) -> impl Stream<Item = Vec<u8>, Error = io::Error> + Send
+where
+    S: Stream + Send,
+    S::Item: AsRef<[u8]>,
+{
+    let chunked = Chunked::new(
+        logs.map_err(|_| io::Error::new(io::ErrorKind::Other, "could not read module logs")),
+    );
+    LogDecode::new(chunked)
+        .with_timestamps()
+        .map(|line| LogRecord::new(&line))
+        .filter(move |record| {
+            filter
+                .as_ref()
+                .map_or(true, |filter| filter.is_match(&record.message))
+        })
+        .and_then(|record| {
+            let mut line = serde_json::to_vec(&record)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            line.push(b'\n');
+            Ok(line)
+        })
+}
+
+fn parse_filter(query: &str) -> Result<Option<Regex>, Error> {
+    let filter = form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "regex")
+        .map(|(_, val)| Regex::new(&val))
+        .transpose()
+        .context(ErrorKind::MalformedRequestParameter("regex"))?;
+    Ok(filter)
+}
+
+fn parse_options(query: &str, extended_options: bool) -> Result<LogOptions, Error> {
     let parse: Vec<_> = form_urlencoded::parse(query.as_bytes()).collect();
     let tail = parse
         .iter()
@@ -88,6 +243,33 @@ fn parse_options(query: &str) -> Result<LogOptions, Error> {
         .with_follow(follow)
         .with_tail(tail)
         .with_since(since);
+    if !extended_options {
+        return Ok(options);
+    }
+
+    let until = parse
+        .iter()
+        .find(|&(ref key, _)| key == "until")
+        .map(|(_, val)| parse_since(val))
+        .transpose()
+        .context(ErrorKind::MalformedRequestParameter("until"))?;
+    let timestamps = parse
+        .iter()
+        .find(|&(ref key, _)| key == "timestamps")
+        .map_or_else(|| Ok(false), |(_, val)| val.parse::<bool>())
+        .context(ErrorKind::MalformedRequestParameter("timestamps"))?;
+    let stream = parse
+        .iter()
+        .find(|&(ref key, _)| key == "stream")
+        .map_or_else(
+            || Ok(LogStream::default()),
+            |(_, val)| val.parse::<LogStream>(),
+        )
+        .context(ErrorKind::MalformedRequestParameter("stream"))?;
+    let mut options = options.with_timestamps(timestamps).with_stream(stream);
+    if let Some(until) = until {
+        options = options.with_until(until);
+    }
     Ok(options)
 }
 
@@ -103,32 +285,58 @@ mod tests {
     use management::models::ErrorResponse;
 
     use super::{
-        parse_options, Body, Future, Handler, LogTail, ModuleLogs, Parameters, Request, StatusCode,
+        parse_filter, parse_options, parse_severity, structured_logs, Body, Future, Handler,
+        LogStream, LogTail, ModuleLogs, Parameters, Request, StatusCode, ACCEPT, CONTENT_TYPE,
+        NDJSON_CONTENT_TYPE,
     };
     use crate::server::module::tests::Error;
 
     #[test]
     fn correct_logoptions() {
         let query = "follow=true&tail=6&since=1551885923";
-        let options = parse_options(&query).unwrap();
+        let options = parse_options(&query, true).unwrap();
         assert_eq!(LogTail::Num(6), *options.tail());
         assert_eq!(true, options.follow());
         assert_eq!(1_551_885_923, options.since());
+        assert_eq!(None, options.until());
+    }
+
+    #[test]
+    fn correct_logoptions_with_until_timestamps_and_stream() {
+        let query = "since=1551885923&until=1551885999&timestamps=true&stream=stderr";
+        let options = parse_options(&query, true).unwrap();
+        assert_eq!(1_551_885_923, options.since());
+        assert_eq!(Some(1_551_885_999), options.until());
+        assert_eq!(true, options.timestamps());
+        assert_eq!(LogStream::Stderr, options.stream());
+    }
+
+    #[test]
+    fn logoptions_ignores_extended_options_before_2020_07_07() {
+        let query = "since=1551885923&until=15abc&timestamps=true&stream=stderr";
+        let options = parse_options(&query, false).unwrap();
+        assert_eq!(1_551_885_923, options.since());
+        assert_eq!(None, options.until());
+        assert_eq!(false, options.timestamps());
+        assert_eq!(LogStream::All, options.stream());
     }
 
     #[test]
     fn logoption_defaults() {
         let query = "";
-        let options = parse_options(&query).unwrap();
+        let options = parse_options(&query, true).unwrap();
         assert_eq!(LogTail::default(), *options.tail());
         assert_eq!(false, options.follow());
         assert_eq!(0, options.since());
+        assert_eq!(None, options.until());
+        assert_eq!(false, options.timestamps());
+        assert_eq!(LogStream::All, options.stream());
     }
 
     #[test]
     fn logoption_follow_error() {
         let query = "follow=34&tail=6";
-        let options = parse_options(&query);
+        let options = parse_options(&query, true);
         assert!(options.is_err());
         assert_eq!(
             "The request parameter `follow` is malformed",
@@ -139,7 +347,7 @@ mod tests {
     #[test]
     fn logoption_tail_error() {
         let query = "follow=false&tail=adsaf";
-        let options = parse_options(&query);
+        let options = parse_options(&query, true);
         assert!(options.is_err());
         assert_eq!(
             "The request parameter `tail` is malformed",
@@ -150,7 +358,7 @@ mod tests {
     #[test]
     fn logoption_since_error() {
         let query = "follow=true&tail=6&since=15abc";
-        let options = parse_options(&query);
+        let options = parse_options(&query, true);
         assert!(options.is_err());
         assert_eq!(
             "The request parameter `since` is malformed",
@@ -158,6 +366,97 @@ mod tests {
         );
     }
 
+    #[test]
+    fn logoption_until_error() {
+        let query = "follow=true&tail=6&until=15abc";
+        let options = parse_options(&query, true);
+        assert!(options.is_err());
+        assert_eq!(
+            "The request parameter `until` is malformed",
+            options.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn logoption_stream_error() {
+        let query = "follow=true&tail=6&stream=stdin";
+        let options = parse_options(&query, true);
+        assert!(options.is_err());
+        assert_eq!(
+            "The request parameter `stream` is malformed",
+            options.err().unwrap().to_string()
+        );
+    }
+
+    const STRUCTURED_LOGS: &[u8] =
+        b"\x01\x00\x00\x00\x00\x00\x00\x312019-11-05T18:22:43.123456789Z <6> Roses are red\n\
+        \x02\x00\x00\x00\x00\x00\x00\x2a2019-11-05T18:22:44Z <3> violets are blue\n";
+
+    #[test]
+    fn logoption_regex_error() {
+        let query = "regex=(roses";
+        let filter = parse_filter(&query);
+        assert!(filter.is_err());
+        assert_eq!(
+            "The request parameter `regex` is malformed",
+            filter.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn severity_prefix() {
+        assert_eq!((3, "error"), parse_severity("<3> error"));
+        assert_eq!((7, "debug"), parse_severity("<7>debug"));
+        assert_eq!((6, "no prefix"), parse_severity("no prefix"));
+        assert_eq!((6, "<8> unknown"), parse_severity("<8> unknown"));
+        assert_eq!((6, "<6"), parse_severity("<6"));
+    }
+
+    #[test]
+    fn accept_ndjson() {
+        let accepts = |accept: &str| {
+            let request = Request::get("http://localhost/modules/mod1/logs")
+                .header(ACCEPT, accept)
+                .body(Body::default())
+                .unwrap();
+            accepts_ndjson(&request)
+        };
+
+        assert!(accepts("application/x-ndjson"));
+        assert!(accepts("application/json, Application/X-NDJSON ; q=0.5"));
+        assert!(!accepts("application/octet-stream"));
+        assert!(!accepts("application/x-ndjson;q=0"));
+        assert!(!accepts("application/x-ndjson; q=0.000, application/json"));
+    }
+
+    #[test]
+    fn structured_logs_records() {
+        let logs = futures::stream::iter_ok::<_, ()>(vec![STRUCTURED_LOGS]);
+        let records = structured_logs(logs, None).collect().wait().unwrap();
+        assert_eq!(
+            vec![
+                &br#"{"timestamp":"2019-11-05T18:22:43.123456789Z","stream":"stdout","severity":6,"message":"Roses are red"}"#[..],
+                &br#"{"timestamp":"2019-11-05T18:22:44Z","stream":"stderr","severity":3,"message":"violets are blue"}"#[..],
+            ],
+            records.iter().map(|r| &r[..r.len() - 1]).collect::<Vec<_>>()
+        );
+        assert!(records.iter().all(|r| r.ends_with(b"\n")));
+    }
+
+    #[test]
+    fn structured_logs_filter() {
+        let logs = futures::stream::iter_ok::<_, ()>(vec![STRUCTURED_LOGS]);
+        let filter = parse_filter("regex=%5Eviolets").unwrap();
+        let records = structured_logs(logs, filter).collect().wait().unwrap();
+        assert_eq!(
+            vec![
+                br#"{"timestamp":"2019-11-05T18:22:44Z","stream":"stderr","severity":3,"message":"violets are blue"}
+"#.to_vec(),
+            ],
+            records
+        );
+    }
+
     #[test]
     fn test_success() {
         let state = ModuleRuntimeState::default()
@@ -205,6 +504,101 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn ndjson_success() {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new_with_logs(
+            "test-module".to_string(),
+            config,
+            Ok(state),
+            vec![STRUCTURED_LOGS],
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = ModuleLogs::new(runtime);
+        let request =
+            Request::get("http://localhost/modules/mod1/logs?api-version=2020-07-07&regex=red")
+                .header(ACCEPT, "application/json, application/x-ndjson;q=0.9")
+                .body(Body::default())
+                .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            NDJSON_CONTENT_TYPE,
+            response.headers().get(CONTENT_TYPE).unwrap()
+        );
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                assert_eq!(
+                    &br#"{"timestamp":"2019-11-05T18:22:43.123456789Z","stream":"stdout","severity":6,"message":"Roses are red"}
+"#[..],
+                    b.as_ref()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn raw_logs_before_2020_07_07() {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new_with_logs(
+            "test-module".to_string(),
+            config,
+            Ok(state),
+            vec![STRUCTURED_LOGS],
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = ModuleLogs::new(runtime).without_extended_options();
+        let request =
+            Request::get("http://localhost/modules/mod1/logs?api-version=2019-11-05&regex=red")
+                .header(ACCEPT, "application/x-ndjson")
+                .body(Body::default())
+                .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert!(response.headers().get(CONTENT_TYPE).is_none());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                assert_eq!(STRUCTURED_LOGS, b.as_ref());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
     #[test]
     fn runtime_error() {
         let runtime = TestRuntime::make_runtime(
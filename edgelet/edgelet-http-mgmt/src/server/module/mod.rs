@@ -21,6 +21,7 @@ mod logs;
 mod prepare_update;
 mod restart;
 mod start;
+mod stats;
 mod stop;
 mod update;
 
@@ -32,6 +33,7 @@ pub use self::logs::ModuleLogs;
 pub use self::prepare_update::PrepareUpdateModule;
 pub use self::restart::RestartModule;
 pub use self::start::StartModule;
+pub use self::stats::GetModuleStats;
 pub use self::stop::StopModule;
 pub use self::update::UpdateModule;
 
@@ -0,0 +1,174 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct GetModuleStats<M> {
+    runtime: M,
+}
+
+impl<M> GetModuleStats<M> {
+    pub fn new(runtime: M) -> Self {
+        GetModuleStats { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetModuleStats<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                self.runtime
+                    .module_stats(&name)
+                    .then(|result| match result {
+                        Ok(stats) => Ok((name, stats)),
+                        Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                            RuntimeOperation::GetModuleStats(name),
+                        )))),
+                    })
+            })
+            .into_future()
+            .flatten()
+            .and_then(|(name, stats)| -> Result<_, Error> {
+                let body = serde_json::to_string(&stats).with_context(|_| {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(name.clone()))
+                })?;
+
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::GetModuleStats(name),
+                    ))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_http::route::Parameters;
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::{
+        TestConfig, TestModule, TestProvisioningResult, TestRuntime, TestSettings,
+    };
+    use futures::Stream;
+    use serde_json::{json, Value};
+
+    use super::{Body, Future, GetModuleStats, Handler, Request, StatusCode};
+    use crate::server::module::tests::Error;
+
+    fn runtime(
+        module: Result<TestModule<Error, TestConfig>, Error>,
+    ) -> TestRuntime<Error, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(module)
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let handler = GetModuleStats::new(runtime(Ok(module)));
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "test".to_string())]);
+        let request = Request::get("http://localhost/modules/test/stats")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let stats: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json!({
+                "name": "test",
+                "cpu_percent": 12.5,
+                "memory_usage": 1024,
+                "memory_limit": 4096,
+                "network_rx_bytes": 100,
+                "network_tx_bytes": 200,
+                "block_read_bytes": 300,
+                "block_write_bytes": 400,
+            }),
+            stats
+        );
+    }
+
+    #[test]
+    fn stats_bad_params() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let handler = GetModuleStats::new(runtime(Ok(module)));
+        let request = Request::get("http://localhost/modules/test/stats")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = GetModuleStats::new(runtime(Err(Error::General)));
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "test".to_string())]);
+        let request = Request::get("http://localhost/modules/test/stats")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+    }
+}
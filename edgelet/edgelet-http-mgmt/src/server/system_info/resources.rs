@@ -16,11 +16,23 @@ use crate::IntoResponse;
 
 pub struct GetSystemResources<M> {
     runtime: M,
+    module_stats: bool,
 }
 
 impl<M> GetSystemResources<M> {
     pub fn new(runtime: M) -> Self {
-        GetSystemResources { runtime }
+        GetSystemResources {
+            runtime,
+            module_stats: true,
+        }
+    }
+
+    /// Leaves out the per-module stats, which API versions before 2020-07-07 don't have.
+    pub fn without_module_stats(self) -> Self {
+        GetSystemResources {
+            module_stats: false,
+            ..self
+        }
     }
 }
 
@@ -36,14 +48,24 @@ where
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         debug!("Get System Resources");
 
+        let module_stats = self.module_stats;
         let response = self
             .runtime
             .system_resources()
-            .then(|system_resources| -> Result<_, Error> {
+            .then(move |system_resources| -> Result<_, Error> {
                 let system_resources = system_resources.context(ErrorKind::RuntimeOperation(
                     RuntimeOperation::SystemResources,
                 ))?;
 
+                let mut system_resources = serde_json::to_value(&system_resources).context(
+                    ErrorKind::RuntimeOperation(RuntimeOperation::SystemResources),
+                )?;
+                if !module_stats {
+                    if let Some(system_resources) = system_resources.as_object_mut() {
+                        system_resources.remove("modules");
+                    }
+                }
+
                 let body = serde_json::to_string(&system_resources).context(
                     ErrorKind::RuntimeOperation(RuntimeOperation::SystemResources),
                 )?;
@@ -63,3 +85,63 @@ where
         Box::new(response)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_http::route::Parameters;
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::{
+        TestConfig, TestModule, TestProvisioningResult, TestRuntime, TestSettings,
+    };
+    use futures::Stream;
+    use management::models::SystemResources;
+
+    use super::{Body, Future, GetSystemResources, Handler, Request};
+    use crate::server::module::tests::Error;
+
+    fn runtime() -> TestRuntime<Error, TestSettings> {
+        let state = ModuleRuntimeState::default();
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module))
+    }
+
+    fn body<H: Handler<Parameters>>(handler: &H) -> serde_json::Value {
+        let request = Request::get("http://localhost/systeminfo/resources")
+            .body(Body::default())
+            .unwrap();
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+        let body = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test]
+    fn system_resources_success() {
+        let handler = GetSystemResources::new(runtime());
+
+        let resources: SystemResources = serde_json::from_value(body(&handler)).unwrap();
+
+        assert_eq!(8000, resources.total_ram());
+        assert_eq!(1, resources.modules().len());
+        assert_eq!("test-module", resources.modules()[0].name());
+    }
+
+    #[test]
+    fn system_resources_without_module_stats() {
+        let handler = GetSystemResources::new(runtime()).without_module_stats();
+
+        let resources = body(&handler);
+
+        assert_eq!(8000, resources["total_ram"]);
+        assert!(resources.get("modules").is_none());
+    }
+}
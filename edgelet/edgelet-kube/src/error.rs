@@ -66,6 +66,9 @@ pub enum ErrorKind {
 
     #[fail(display = "Kubernetes object metadata is missing")]
     MissingMetadata(MissingMetadataReason),
+
+    #[fail(display = "Module stats are not supported by the kubernetes runtime")]
+    ModuleStatsNotSupported,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -15,8 +15,8 @@ use hyper_tls::HttpsConnector;
 
 use edgelet_core::{
     AuthId, Authenticator, GetTrustBundle, LogOptions, MakeModuleRuntime, ModuleRegistry,
-    ModuleRuntime, ModuleRuntimeState, ModuleSpec, ProvisioningResult as CoreProvisioningResult,
-    RuntimeOperation, SystemInfo, SystemResources,
+    ModuleRuntime, ModuleRuntimeState, ModuleSpec, ModuleStats,
+    ProvisioningResult as CoreProvisioningResult, RuntimeOperation, SystemInfo, SystemResources,
 };
 use edgelet_docker::DockerConfig;
 use kube_client::{get_config, Client as KubeClient, HttpClient, TokenSource, ValueToken};
@@ -155,6 +155,7 @@ where
     type ListWithDetailsStream =
         Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
     type LogsFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
+    type ModuleStatsFuture = Box<dyn Future<Item = ModuleStats, Error = Self::Error> + Send>;
     type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type RestartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
@@ -255,6 +256,7 @@ where
             0,
             0,
             vec![],
+            vec![],
             "".to_owned(),
         )))
     }
@@ -296,6 +298,11 @@ where
         Box::new(future::ok(Logs("".to_string(), Body::empty())))
     }
 
+    fn module_stats(&self, _id: &str) -> Self::ModuleStatsFuture {
+        // TODO: add support for module stats on k8s
+        Box::new(future::err(Error::from(ErrorKind::ModuleStatsNotSupported)))
+    }
+
     fn registry(&self) -> &Self::ModuleRegistry {
         self
     }
@@ -389,6 +396,7 @@ mod tests {
         make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
     };
 
+    use crate::error::ErrorKind;
     use crate::tests::{create_runtime, make_settings, not_found_handler, response};
 
     #[test]
@@ -459,6 +467,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn runtime_module_stats_not_supported() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET "/api/v1/nodes" => list_node_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = runtime.module_stats("edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        let err = runtime.block_on(task).unwrap_err();
+
+        assert_eq!(err.kind(), &ErrorKind::ModuleStatsNotSupported);
+    }
+
     fn list_node_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
         move |_| {
             response(StatusCode::OK, || {
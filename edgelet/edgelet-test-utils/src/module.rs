@@ -7,7 +7,7 @@ use std::time::Duration;
 use edgelet_core::{
     AuthId, Authenticator, Certificates, Connect, DiskInfo, GetTrustBundle, Listen, LogOptions,
     MakeModuleRuntime, Module, ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec,
-    Provisioning, ProvisioningResult, RuntimeSettings, SystemInfo, SystemResources,
+    ModuleStats, Provisioning, ProvisioningResult, RuntimeSettings, SystemInfo, SystemResources,
     WatchdogSettings,
 };
 use failure::Fail;
@@ -337,6 +337,7 @@ where
     type ListWithDetailsStream =
         Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
     type LogsFuture = FutureResult<Self::Logs, Self::Error>;
+    type ModuleStatsFuture = FutureResult<ModuleStats, Self::Error>;
     type RemoveFuture = FutureResult<(), Self::Error>;
     type RestartFuture = FutureResult<(), Self::Error>;
     type StartFuture = FutureResult<(), Self::Error>;
@@ -412,6 +413,7 @@ where
                     "test system".to_owned(),
                     "test type".to_owned(),
                 )],
+                vec![test_module_stats("test-module")],
                 "fake docker stats".to_owned(),
             )),
             Err(ref e) => future::err(e.clone()),
@@ -442,6 +444,13 @@ where
         }
     }
 
+    fn module_stats(&self, id: &str) -> Self::ModuleStatsFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(test_module_stats(id)),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
     fn registry(&self) -> &Self::ModuleRegistry {
         &self.registry
     }
@@ -450,3 +459,14 @@ where
         future::ok(())
     }
 }
+
+fn test_module_stats(name: &str) -> ModuleStats {
+    ModuleStats::new(name.to_string())
+        .with_cpu_percent(12.5)
+        .with_memory_usage(1024)
+        .with_memory_limit(4096)
+        .with_network_rx_bytes(100)
+        .with_network_tx_bytes(200)
+        .with_block_read_bytes(300)
+        .with_block_write_bytes(400)
+}
@@ -18,9 +18,15 @@ pub enum ErrorKind {
     #[fail(display = "Invalid value for --since parameter")]
     BadSinceParameter,
 
+    #[fail(display = "Invalid value for --stream parameter")]
+    BadStreamParameter,
+
     #[fail(display = "Invalid value for --tail parameter")]
     BadTailParameter,
 
+    #[fail(display = "Invalid value for --until parameter")]
+    BadUntilParameter,
+
     #[fail(display = "")]
     Diagnostics,
 
@@ -39,6 +45,9 @@ pub enum ErrorKind {
     #[fail(display = "A module runtime error occurred")]
     ModuleRuntime,
 
+    #[fail(display = "Could not refresh module stats")]
+    RefreshTimer,
+
     #[fail(display = "Could not generate support bundle")]
     SupportBundle,
 
@@ -22,6 +22,7 @@ mod error;
 mod list;
 mod logs;
 mod restart;
+mod stats;
 mod support_bundle;
 mod unknown;
 mod version;
@@ -31,6 +32,7 @@ pub use crate::error::{Error, ErrorKind, FetchLatestVersionsReason};
 pub use crate::list::List;
 pub use crate::logs::Logs;
 pub use crate::restart::Restart;
+pub use crate::stats::Stats;
 pub use crate::support_bundle::{OutputLocation, SupportBundle};
 pub use crate::unknown::Unknown;
 pub use crate::version::Version;
@@ -109,7 +109,7 @@ fn humanize_state(state: &ModuleRuntimeState) -> String {
     }
 }
 
-fn time_string(ht: &HumanTime, tense: Tense) -> String {
+pub(crate) fn time_string(ht: &HumanTime, tense: Tense) -> String {
     if *ht <= HumanTime::from(Duration::seconds(20)) {
         ht.to_text_en(Accuracy::Precise, tense)
     } else {
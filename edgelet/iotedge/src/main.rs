@@ -18,7 +18,7 @@ use edgelet_core::{parse_since, LogOptions, LogTail};
 use edgelet_http_mgmt::ModuleClient;
 
 use iotedge::{
-    Check, Command, Error, ErrorKind, List, Logs, OutputFormat, OutputLocation, Restart,
+    Check, Command, Error, ErrorKind, List, Logs, OutputFormat, OutputLocation, Restart, Stats,
     SupportBundle, Unknown, Version,
 };
 
@@ -233,6 +233,27 @@ fn run() -> Result<(), Error> {
                         .value_name("DURATION or TIMESTAMP")
                         .default_value("1 day"),
                 )
+                .arg(
+                    Arg::with_name("until")
+                        .help("Only return logs before this time, as a duration (1 day, 90 minutes, 2 days 3 hours 2 minutes), rfc3339 timestamp, or UNIX timestamp")
+                        .long("until")
+                        .takes_value(true)
+                        .value_name("DURATION or TIMESTAMP"),
+                )
+                .arg(
+                    Arg::with_name("stream")
+                        .help("Only return logs from this output stream")
+                        .long("stream")
+                        .takes_value(true)
+                        .possible_values(&["all", "stdout", "stderr"])
+                        .default_value("all"),
+                )
+                .arg(
+                    Arg::with_name("timestamps")
+                        .help("Show timestamps")
+                        .short("t")
+                        .long("timestamps"),
+                )
                 .arg(
                     Arg::with_name("follow")
                         .help("Follow output log")
@@ -240,6 +261,15 @@ fn run() -> Result<(), Error> {
                         .long("follow"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Show the resource usage of modules")
+                .arg(
+                    Arg::with_name("no-stream")
+                        .help("Print the resource usage once as JSON instead of a table that is refreshed 2 seconds after the previous refresh completes")
+                        .long("no-stream"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("support-bundle")
                 .about("Bundles troubleshooting information")
@@ -366,12 +396,34 @@ fn run() -> Result<(), Error> {
                 .transpose()
                 .context(ErrorKind::BadSinceParameter)?
                 .expect("arg has a default value");
-            let options = LogOptions::new()
+            let until = args
+                .value_of("until")
+                .map(|s| parse_since(s))
+                .transpose()
+                .context(ErrorKind::BadUntilParameter)?;
+            let stream = args
+                .value_of("stream")
+                .map(str::parse)
+                .transpose()
+                .map_err(|err: edgelet_core::Error| {
+                    Error::from(err.context(ErrorKind::BadStreamParameter))
+                })?
+                .expect("arg has a default value");
+            let timestamps = args.is_present("timestamps");
+            let mut options = LogOptions::new()
                 .with_follow(follow)
                 .with_tail(tail)
-                .with_since(since);
+                .with_since(since)
+                .with_timestamps(timestamps)
+                .with_stream(stream);
+            if let Some(until) = until {
+                options = options.with_until(until);
+            }
             tokio_runtime.block_on(Logs::new(id, options, runtime()?).execute())
         }
+        ("stats", Some(args)) => tokio_runtime.block_on(
+            Stats::new(runtime()?, !args.is_present("no-stream"), io::stdout()).execute(),
+        ),
         ("support-bundle", Some(args)) => {
             let location = args.value_of_os("output").expect("arg has a default value");
             let since = args
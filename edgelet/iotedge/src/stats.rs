@@ -0,0 +1,361 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use chrono_humanize::{HumanTime, Tense};
+use failure::{Fail, ResultExt};
+use futures::{stream, Future, Stream};
+use serde_derive::Serialize;
+use tabwriter::TabWriter;
+use tokio::timer::Delay;
+
+use edgelet_core::{Module, ModuleRuntime, ModuleRuntimeState, ModuleStats, ModuleStatus};
+
+use crate::error::{Error, ErrorKind};
+use crate::list::time_string;
+use crate::Command;
+
+/// How long to wait after a refresh has been printed before fetching the usage again.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Moves the cursor to the top left corner of the cleared terminal.
+const CLEAR_SCREEN: &str = "\x1B[2J\x1B[H";
+
+pub struct Stats<M, W> {
+    runtime: M,
+    stream: bool,
+    output: Arc<Mutex<W>>,
+}
+
+impl<M, W> Stats<M, W>
+where
+    W: Write,
+{
+    pub fn new(runtime: M, stream: bool, output: W) -> Self {
+        Stats {
+            runtime,
+            stream,
+            output: Arc::new(Mutex::new(output)),
+        }
+    }
+}
+
+impl<M, W> Command for Stats<M, W>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    W: 'static + Write + Send,
+{
+    type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    fn execute(self) -> Self::Future {
+        let runtime = self.runtime;
+        let output = self.output;
+
+        if !self.stream {
+            let result = module_usage(&runtime).and_then(move |usage| {
+                let mut w = output.lock().unwrap();
+                serde_json::to_writer(&mut *w, &usage).context(ErrorKind::WriteToStdout)?;
+                writeln!(w).context(ErrorKind::WriteToStdout)?;
+                w.flush().context(ErrorKind::WriteToStdout)?;
+                Ok(())
+            });
+            return Box::new(result);
+        }
+
+        // Fetching the stats can take longer than the interval, so the next refresh is
+        // scheduled once the previous one has been printed rather than at fixed times.
+        let result = stream::repeat::<_, Error>(()).for_each(move |()| {
+            let output = output.clone();
+            module_usage(&runtime)
+                .and_then(move |usage| {
+                    let mut w = output.lock().unwrap();
+                    w.write_all(CLEAR_SCREEN.as_bytes())
+                        .context(ErrorKind::WriteToStdout)?;
+                    write_table(&mut *w, &usage, Utc::now())
+                })
+                .and_then(|()| {
+                    Delay::new(Instant::now() + REFRESH_INTERVAL)
+                        .map_err(|err| Error::from(err.context(ErrorKind::RefreshTimer)))
+                })
+        });
+        Box::new(result)
+    }
+}
+
+/// The resource usage and runtime state of a module, as printed by `iotedge stats`.
+#[derive(Debug, Serialize)]
+struct ModuleUsage {
+    #[serde(flatten)]
+    stats: ModuleStats,
+    status: ModuleStatus,
+    restart_count: Option<u32>,
+    started_at: Option<DateTime<Utc>>,
+}
+
+fn module_usage<M>(runtime: &M) -> impl Future<Item = Vec<ModuleUsage>, Error = Error> + Send
+where
+    M: 'static + ModuleRuntime,
+{
+    let modules = runtime
+        .list_with_details()
+        .map(|(module, state)| (module.name().to_string(), state))
+        .collect();
+
+    runtime
+        .system_resources()
+        .join(modules)
+        .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
+        .map(|(resources, modules)| merge_usage(resources.modules(), modules))
+}
+
+/// Pairs the state of every module with its stats, sorted by module name.
+///
+/// Modules that the runtime has no stats for, such as modules that aren't running, are
+/// reported with zero usage.
+fn merge_usage(
+    stats: &[ModuleStats],
+    modules: Vec<(String, ModuleRuntimeState)>,
+) -> Vec<ModuleUsage> {
+    let mut usage: Vec<ModuleUsage> = modules
+        .into_iter()
+        .map(|(name, state)| {
+            let stats = stats
+                .iter()
+                .find(|stats| stats.name() == name)
+                .cloned()
+                .unwrap_or_else(|| ModuleStats::new(name));
+            ModuleUsage {
+                stats,
+                status: *state.status(),
+                restart_count: state.restart_count(),
+                started_at: state.started_at().copied(),
+            }
+        })
+        .collect();
+    usage.sort_by(|a, b| a.stats.name().cmp(b.stats.name()));
+    usage
+}
+
+fn write_table<W>(output: W, usage: &[ModuleUsage], now: DateTime<Utc>) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut w = TabWriter::new(output).minwidth(10);
+    writeln!(
+        w,
+        "NAME\tSTATUS\tCPU %\tMEM USAGE / LIMIT\tMEM %\tNET I/O\tBLOCK I/O\tRESTARTS\tUPTIME"
+    )
+    .context(ErrorKind::WriteToStdout)?;
+    for module in usage {
+        let stats = &module.stats;
+        writeln!(
+            w,
+            "{}\t{}\t{:.2}%\t{} / {}\t{}\t{} / {}\t{} / {}\t{}\t{}",
+            stats.name(),
+            module.status,
+            stats.cpu_percent(),
+            format_bytes(stats.memory_usage()),
+            format_bytes(stats.memory_limit()),
+            memory_percent(stats),
+            format_bytes(stats.network_rx_bytes()),
+            format_bytes(stats.network_tx_bytes()),
+            format_bytes(stats.block_read_bytes()),
+            format_bytes(stats.block_write_bytes()),
+            module
+                .restart_count
+                .map_or_else(|| "-".to_string(), |count| count.to_string()),
+            uptime(module, now),
+        )
+        .context(ErrorKind::WriteToStdout)?;
+    }
+    w.flush().context(ErrorKind::WriteToStdout)?;
+    Ok(())
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn memory_percent(stats: &ModuleStats) -> String {
+    if stats.memory_limit() == 0 {
+        "-".to_string()
+    } else {
+        format!(
+            "{:.2}%",
+            stats.memory_usage() as f64 / stats.memory_limit() as f64 * 100.0
+        )
+    }
+}
+
+fn uptime(module: &ModuleUsage, now: DateTime<Utc>) -> String {
+    match (module.status, module.started_at) {
+        (ModuleStatus::Running, Some(started_at)) => {
+            time_string(&HumanTime::from(now - started_at), Tense::Present)
+        }
+        _ => "-".to_string(),
+    }
+}
+
+/// Formats a number of bytes with binary units, like `docker stats`.
+#[allow(clippy::cast_precision_loss)]
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}{}", bytes, UNITS[0])
+    } else {
+        format!("{:.2}{}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use edgelet_core::{ModuleRuntimeState, ModuleStats, ModuleStatus};
+
+    use super::{format_bytes, merge_usage, write_table};
+
+    #[test]
+    fn bytes_are_formatted_with_binary_units() {
+        assert_eq!("0B", format_bytes(0));
+        assert_eq!("1023B", format_bytes(1023));
+        assert_eq!("1.00KiB", format_bytes(1024));
+        assert_eq!("1.50MiB", format_bytes(1024 * 1024 * 3 / 2));
+        assert_eq!("2.00GiB", format_bytes(2 * 1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn usage_is_merged_and_sorted_by_name() {
+        let started_at = Utc.ymd(2019, 12, 10).and_hms(10, 0, 0);
+        let stats = vec![
+            ModuleStats::new("edgeHub".to_string()).with_cpu_percent(1.5),
+            ModuleStats::new("edgeAgent".to_string()).with_memory_usage(1024),
+        ];
+        let modules = vec![
+            (
+                "tempSensor".to_string(),
+                ModuleRuntimeState::default().with_status(ModuleStatus::Stopped),
+            ),
+            (
+                "edgeHub".to_string(),
+                ModuleRuntimeState::default()
+                    .with_status(ModuleStatus::Running)
+                    .with_started_at(Some(started_at))
+                    .with_restart_count(Some(2)),
+            ),
+            (
+                "edgeAgent".to_string(),
+                ModuleRuntimeState::default().with_status(ModuleStatus::Running),
+            ),
+        ];
+
+        let usage = merge_usage(&stats, modules);
+
+        let names: Vec<&str> = usage.iter().map(|module| module.stats.name()).collect();
+        assert_eq!(vec!["edgeAgent", "edgeHub", "tempSensor"], names);
+        assert_eq!(1024, usage[0].stats.memory_usage());
+        assert_eq!(None, usage[0].restart_count);
+        assert!((usage[1].stats.cpu_percent() - 1.5).abs() < f64::EPSILON);
+        assert_eq!(Some(2), usage[1].restart_count);
+        assert_eq!(Some(started_at), usage[1].started_at);
+        assert_eq!(ModuleStats::new("tempSensor".to_string()), usage[2].stats);
+        assert_eq!(ModuleStatus::Stopped, usage[2].status);
+    }
+
+    #[test]
+    fn usage_serializes_flat() {
+        let usage = merge_usage(
+            &[ModuleStats::new("edgeHub".to_string()).with_memory_limit(4096)],
+            vec![(
+                "edgeHub".to_string(),
+                ModuleRuntimeState::default()
+                    .with_status(ModuleStatus::Running)
+                    .with_restart_count(Some(1)),
+            )],
+        );
+
+        let json = serde_json::to_value(&usage).unwrap();
+
+        assert_eq!("edgeHub", json[0]["name"]);
+        assert_eq!(4096, json[0]["memory_limit"]);
+        assert_eq!("running", json[0]["status"]);
+        assert_eq!(1, json[0]["restart_count"]);
+    }
+
+    #[test]
+    fn table_shows_usage() {
+        let now = Utc.ymd(2019, 12, 10).and_hms(12, 0, 0);
+        let usage = merge_usage(
+            &[ModuleStats::new("edgeHub".to_string())
+                .with_cpu_percent(12.345)
+                .with_memory_usage(512)
+                .with_memory_limit(2048)
+                .with_network_rx_bytes(2048)
+                .with_network_tx_bytes(1)],
+            vec![
+                (
+                    "edgeHub".to_string(),
+                    ModuleRuntimeState::default()
+                        .with_status(ModuleStatus::Running)
+                        .with_started_at(Some(now - Duration::hours(2)))
+                        .with_restart_count(Some(3)),
+                ),
+                (
+                    "tempSensor".to_string(),
+                    ModuleRuntimeState::default().with_status(ModuleStatus::Stopped),
+                ),
+            ],
+        );
+        let mut output = Vec::new();
+
+        write_table(&mut output, &usage, now).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<Vec<&str>> = output
+            .lines()
+            .map(|line| {
+                line.split("  ")
+                    .map(str::trim)
+                    .filter(|cell| !cell.is_empty())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(3, lines.len());
+        assert_eq!(
+            vec![
+                "edgeHub",
+                "running",
+                "12.35%",
+                "512B / 2.00KiB",
+                "25.00%",
+                "2.00KiB / 1B",
+                "0B / 0B",
+                "3",
+                "2 hours"
+            ],
+            lines[1]
+        );
+        assert_eq!(
+            vec![
+                "tempSensor",
+                "stopped",
+                "0.00%",
+                "0B / 0B",
+                "-",
+                "0B / 0B",
+                "0B / 0B",
+                "-",
+                "-"
+            ],
+            lines[2]
+        );
+    }
+}
@@ -54,7 +54,15 @@ pub trait ModuleApi: Send + Sync {
         follow: bool,
         tail: &str,
         since: i32,
+        until: Option<i32>,
+        timestamps: bool,
+        stream: &str,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
+    fn module_stats(
+        &self,
+        api_version: &str,
+        name: &str,
+    ) -> Box<dyn Future<Item = crate::models::ModuleStats, Error = Error<serde_json::Value>> + Send>;
     fn restart_module(
         &self,
         api_version: &str,
@@ -324,17 +332,26 @@ where
         follow: bool,
         tail: &str,
         since: i32,
+        until: Option<i32>,
+        timestamps: bool,
+        stream: &str,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
 
-        let query = ::url::form_urlencoded::Serializer::new(String::new())
+        let mut query = ::url::form_urlencoded::Serializer::new(String::new());
+        query
             .append_pair("api-version", &api_version.to_string())
             .append_pair("follow", &follow.to_string())
             .append_pair("tail", &tail.to_string())
             .append_pair("since", &since.to_string())
-            .finish();
+            .append_pair("timestamps", &timestamps.to_string())
+            .append_pair("stream", &stream.to_string());
+        if let Some(until) = until {
+            query.append_pair("until", &until.to_string());
+        }
+        let query = query.finish();
         let uri_str = format!(
             "/modules/{name}/logs?{}",
             query,
@@ -373,6 +390,66 @@ where
         )
     }
 
+    fn module_stats(
+        &self,
+        api_version: &str,
+        name: &str,
+    ) -> Box<dyn Future<Item = crate::models::ModuleStats, Error = Error<serde_json::Value>> + Send>
+    {
+        let configuration: &configuration::Configuration<C> = self.configuration.borrow();
+
+        let method = hyper::Method::GET;
+
+        let query = ::url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("api-version", &api_version.to_string())
+            .finish();
+        let uri_str = format!(
+            "/modules/{name}/stats?{}",
+            query,
+            name = percent_encode(name.as_bytes(), PATH_SEGMENT_ENCODE_SET)
+        );
+
+        let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
+        // TODO(farcaller): handle error
+        // if let Err(e) = uri {
+        //     return Box::new(futures::future::err(e));
+        // }
+        let mut req = hyper::Request::builder();
+        req.method(method).uri(uri.unwrap());
+        if let Some(ref user_agent) = configuration.user_agent {
+            req.header(http::header::USER_AGENT, &**user_agent);
+        }
+        let req = req
+            .body(hyper::Body::empty())
+            .expect("could not build hyper::Request");
+
+        // send request
+        Box::new(
+            configuration
+                .client
+                .request(req)
+                .map_err(Error::from)
+                .and_then(|resp| {
+                    let (http::response::Parts { status, .. }, body) = resp.into_parts();
+                    body.concat2()
+                        .and_then(move |body| Ok((status, body)))
+                        .map_err(Error::from)
+                })
+                .and_then(|(status, body)| {
+                    if status.is_success() {
+                        Ok(body)
+                    } else {
+                        Err(Error::from((status, &*body)))
+                    }
+                })
+                .and_then(|body| {
+                    let parsed: Result<crate::models::ModuleStats, _> =
+                        serde_json::from_slice(&body);
+                    parsed.map_err(Error::from)
+                }),
+        )
+    }
+
     fn restart_module(
         &self,
         api_version: &str,
@@ -1,5 +1,7 @@
 mod config;
 pub use self::config::Config;
+mod disk;
+pub use self::disk::Disk;
 mod env_var;
 pub use self::env_var::EnvVar;
 mod error_response;
@@ -20,12 +22,16 @@ mod module_list;
 pub use self::module_list::ModuleList;
 mod module_spec;
 pub use self::module_spec::ModuleSpec;
+mod module_stats;
+pub use self::module_stats::ModuleStats;
 mod runtime_status;
 pub use self::runtime_status::RuntimeStatus;
 mod status;
 pub use self::status::Status;
 mod system_info;
 pub use self::system_info::SystemInfo;
+mod system_resources;
+pub use self::system_resources::SystemResources;
 
 // TODO(farcaller): sort out files
 pub struct File;
@@ -0,0 +1,161 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2018-06-28
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModuleStats {
+    #[serde(rename = "name")]
+    name: String,
+    #[serde(rename = "cpu_percent")]
+    cpu_percent: f64,
+    #[serde(rename = "memory_usage")]
+    memory_usage: i64,
+    #[serde(rename = "memory_limit")]
+    memory_limit: i64,
+    #[serde(rename = "network_rx_bytes")]
+    network_rx_bytes: i64,
+    #[serde(rename = "network_tx_bytes")]
+    network_tx_bytes: i64,
+    #[serde(rename = "block_read_bytes")]
+    block_read_bytes: i64,
+    #[serde(rename = "block_write_bytes")]
+    block_write_bytes: i64,
+}
+
+impl ModuleStats {
+    pub fn new(
+        name: String,
+        cpu_percent: f64,
+        memory_usage: i64,
+        memory_limit: i64,
+        network_rx_bytes: i64,
+        network_tx_bytes: i64,
+        block_read_bytes: i64,
+        block_write_bytes: i64,
+    ) -> Self {
+        ModuleStats {
+            name,
+            cpu_percent,
+            memory_usage,
+            memory_limit,
+            network_rx_bytes,
+            network_tx_bytes,
+            block_read_bytes,
+            block_write_bytes,
+        }
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn set_cpu_percent(&mut self, cpu_percent: f64) {
+        self.cpu_percent = cpu_percent;
+    }
+
+    pub fn with_cpu_percent(mut self, cpu_percent: f64) -> Self {
+        self.cpu_percent = cpu_percent;
+        self
+    }
+
+    pub fn cpu_percent(&self) -> f64 {
+        self.cpu_percent
+    }
+
+    pub fn set_memory_usage(&mut self, memory_usage: i64) {
+        self.memory_usage = memory_usage;
+    }
+
+    pub fn with_memory_usage(mut self, memory_usage: i64) -> Self {
+        self.memory_usage = memory_usage;
+        self
+    }
+
+    pub fn memory_usage(&self) -> i64 {
+        self.memory_usage
+    }
+
+    pub fn set_memory_limit(&mut self, memory_limit: i64) {
+        self.memory_limit = memory_limit;
+    }
+
+    pub fn with_memory_limit(mut self, memory_limit: i64) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    pub fn memory_limit(&self) -> i64 {
+        self.memory_limit
+    }
+
+    pub fn set_network_rx_bytes(&mut self, network_rx_bytes: i64) {
+        self.network_rx_bytes = network_rx_bytes;
+    }
+
+    pub fn with_network_rx_bytes(mut self, network_rx_bytes: i64) -> Self {
+        self.network_rx_bytes = network_rx_bytes;
+        self
+    }
+
+    pub fn network_rx_bytes(&self) -> i64 {
+        self.network_rx_bytes
+    }
+
+    pub fn set_network_tx_bytes(&mut self, network_tx_bytes: i64) {
+        self.network_tx_bytes = network_tx_bytes;
+    }
+
+    pub fn with_network_tx_bytes(mut self, network_tx_bytes: i64) -> Self {
+        self.network_tx_bytes = network_tx_bytes;
+        self
+    }
+
+    pub fn network_tx_bytes(&self) -> i64 {
+        self.network_tx_bytes
+    }
+
+    pub fn set_block_read_bytes(&mut self, block_read_bytes: i64) {
+        self.block_read_bytes = block_read_bytes;
+    }
+
+    pub fn with_block_read_bytes(mut self, block_read_bytes: i64) -> Self {
+        self.block_read_bytes = block_read_bytes;
+        self
+    }
+
+    pub fn block_read_bytes(&self) -> i64 {
+        self.block_read_bytes
+    }
+
+    pub fn set_block_write_bytes(&mut self, block_write_bytes: i64) {
+        self.block_write_bytes = block_write_bytes;
+    }
+
+    pub fn with_block_write_bytes(mut self, block_write_bytes: i64) -> Self {
+        self.block_write_bytes = block_write_bytes;
+        self
+    }
+
+    pub fn block_write_bytes(&self) -> i64 {
+        self.block_write_bytes
+    }
+}
@@ -20,6 +20,8 @@ pub struct Status {
     exit_status: Option<crate::models::ExitStatus>,
     #[serde(rename = "runtimeStatus")]
     runtime_status: crate::models::RuntimeStatus,
+    #[serde(rename = "restartCount", skip_serializing_if = "Option::is_none")]
+    restart_count: Option<i32>,
 }
 
 impl Status {
@@ -28,6 +30,7 @@ impl Status {
             start_time: None,
             exit_status: None,
             runtime_status,
+            restart_count: None,
         }
     }
 
@@ -77,4 +80,21 @@ impl Status {
     pub fn runtime_status(&self) -> &crate::models::RuntimeStatus {
         &self.runtime_status
     }
+
+    pub fn set_restart_count(&mut self, restart_count: i32) {
+        self.restart_count = Some(restart_count);
+    }
+
+    pub fn with_restart_count(mut self, restart_count: i32) -> Self {
+        self.restart_count = Some(restart_count);
+        self
+    }
+
+    pub fn restart_count(&self) -> Option<i32> {
+        self.restart_count
+    }
+
+    pub fn reset_restart_count(&mut self) {
+        self.restart_count = None;
+    }
 }
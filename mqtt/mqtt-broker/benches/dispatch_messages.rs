@@ -8,6 +8,7 @@
 //! Scenarios supported:
 //! * all subscribers receive messages on their own topic
 //! * all subscribers receive messages on shared topic
+//! * many subscribers with wildcard subscriptions, one of them receives each message
 //!
 //! How to run benches
 //! ```bash
@@ -55,7 +56,8 @@ use mqtt_broker::{
 criterion_group!(
     basic,
     subscribe_to_separate_topic,
-    subscribe_to_common_topic
+    subscribe_to_common_topic,
+    subscribe_to_device_topics
 );
 criterion_main!(basic);
 
@@ -113,6 +115,37 @@ fn subscribe_to_common_topic(c: &mut Criterion) {
     }
 }
 
+/// Each subscriber subscribes to several wildcard filters under its own prefix,
+/// the way a gateway serves its leaf devices.
+/// Publisher randomly selects one of the devices and publishes a message to it.
+/// Fan-out cost is driven by the number of subscriptions in the broker rather
+/// than by the number of receivers.
+/// ```
+/// publisher  pub topic/{random: 1..N}/inputs/input1
+///
+/// subscriber1 sub topic/1/inputs/#, topic/1/+/twin, topic/1/methods/+
+/// subscriber2 sub topic/2/inputs/#, topic/2/+/twin, topic/2/methods/+
+/// subscriberN sub topic/N/inputs/#, topic/N/+/twin, topic/N/methods/+
+/// ```
+fn subscribe_to_device_topics(c: &mut Criterion) {
+    init_logging();
+
+    let clients = vec![10, 100, 500, 1000];
+
+    for qos in vec![proto::QoS::AtMostOnce, proto::QoS::AtLeastOnce] {
+        let payload_size = Size::B(32);
+        let name = format!("sub_devices_{}_q{}", payload_size, u8::from(qos));
+        let mut group = c.benchmark_group(&name);
+
+        for count in &clients {
+            let strategy = Strategy::DeviceTopics(*count);
+            dispatch_messages(&mut group, strategy, *count, payload_size, qos);
+        }
+
+        group.finish();
+    }
+}
+
 fn scenarios() -> (Vec<(proto::QoS, Size)>, Vec<usize>) {
     let sizes = vec![Size::B(32), Size::Kb(1), Size::Kb(128), Size::Mb(1)];
     let qoses = vec![proto::QoS::AtMostOnce, proto::QoS::AtLeastOnce];
@@ -147,11 +180,11 @@ fn dispatch_messages(
             let broker_handle = broker_handle.clone();
 
             let id = Id::new_subscriber(sub_id);
-            let topic = strategy.sub_topic(&id);
+            let topics = strategy.sub_topics(&id);
 
             let client = runtime.block_on(async move {
                 let mut client = Client::connect(id, broker_handle.clone()).await;
-                client.subscribe(topic, qos).await;
+                client.subscribe(topics, qos).await;
 
                 client
             });
@@ -244,6 +277,7 @@ impl Client {
             keep_alive: Duration::from_secs(10),
             protocol_name: PROTOCOL_NAME.into(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
         let connreq = ConnReq::new(client.id.as_client_id(), connect, None, connection_handle);
         let message = Message::Client(client.id.as_client_id(), ClientEvent::ConnReq(connreq));
@@ -252,13 +286,16 @@ impl Client {
         client
     }
 
-    async fn subscribe(&mut self, topic_filter: String, max_qos: proto::QoS) {
+    async fn subscribe(&mut self, topic_filters: Vec<String>, max_qos: proto::QoS) {
         let subscribe = proto::Subscribe {
             packet_identifier: proto::PacketIdentifier::new(1).unwrap(),
-            subscribe_to: vec![proto::SubscribeTo {
-                topic_filter,
-                qos: max_qos,
-            }],
+            subscribe_to: topic_filters
+                .into_iter()
+                .map(|topic_filter| proto::SubscribeTo {
+                    topic_filter,
+                    qos: max_qos,
+                })
+                .collect(),
         };
         let message = Message::Client(self.id.as_client_id(), ClientEvent::Subscribe(subscribe));
         self.broker_handle.send(message).await.expect("subscribe");
@@ -368,6 +405,7 @@ impl PublishHandle {
             retain: false,
             topic_name,
             payload: Bytes::from_iter((0..payload_size.into()).map(|_| rand::random::<u8>())),
+            properties: proto::Properties::default(),
         }
     }
 }
@@ -437,13 +475,22 @@ const PREFIX: &str = "topic";
 enum Strategy {
     SeparateTopic(usize),
     SharedTopic,
+    DeviceTopics(usize),
 }
 
 impl Strategy {
-    fn sub_topic(&self, client_id: &Id) -> String {
+    fn sub_topics(&self, client_id: &Id) -> Vec<String> {
         match self {
-            Self::SeparateTopic(_) => format!("{}/{}", PREFIX, client_id.as_number()),
-            Self::SharedTopic => PREFIX.into(),
+            Self::SeparateTopic(_) => vec![format!("{}/{}", PREFIX, client_id.as_number())],
+            Self::SharedTopic => vec![PREFIX.into()],
+            Self::DeviceTopics(_) => {
+                let id = client_id.as_number();
+                vec![
+                    format!("{}/{}/inputs/#", PREFIX, id),
+                    format!("{}/{}/+/twin", PREFIX, id),
+                    format!("{}/{}/methods/+", PREFIX, id),
+                ]
+            }
         }
     }
 
@@ -454,6 +501,10 @@ impl Strategy {
                 format!("{}/{}", PREFIX, sub_id)
             }
             Self::SharedTopic => PREFIX.into(),
+            Self::DeviceTopics(subscribers) => {
+                let sub_id = rand::thread_rng().gen_range(0, subscribers);
+                format!("{}/{}/inputs/input1", PREFIX, sub_id)
+            }
         }
     }
 }
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::iter::FromIterator;
+use std::time::SystemTime;
 
 use bytes::Bytes;
 use criterion::{
@@ -10,7 +11,7 @@ use tempfile::TempDir;
 use mqtt3::proto::{Publication, QoS};
 use mqtt_broker::{
     BrokerState, ClientId, ConsolidatedStateFormat, FileFormat, FilePersistor, Persist,
-    PersistError, SessionState,
+    PersistError, RetainedPublication, SessionState,
 };
 
 fn test_write<F>(
@@ -97,7 +98,10 @@ fn make_fake_state(
     let retained = HashMap::from_iter((0..num_retained).map(|i| {
         (
             format!("Retained {}", i),
-            make_fake_publish(format!("Retained {}", i)),
+            RetainedPublication::new(
+                make_fake_publish(format!("Retained {}", i)),
+                SystemTime::now(),
+            ),
         )
     }));
 
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::broker::BrokerHandle;
+use crate::snapshot::StateSnapshotHandle;
+use crate::{ClientEvent, ClientId, Error, Message, SystemEvent};
+
+/// A request from the admin interface, answered by the broker on the enclosed channel.
+#[derive(Debug)]
+pub enum AdminRequest {
+    Sessions(oneshot::Sender<Vec<SessionInfo>>),
+    DeleteSession(ClientId, oneshot::Sender<Result<(), AdminError>>),
+    ClearRetained(String, oneshot::Sender<Result<usize, AdminError>>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AdminError {
+    #[error("No session for {0}.")]
+    NoSession(ClientId),
+
+    #[error("Session for {0} is connected.")]
+    SessionConnected(ClientId),
+
+    #[error(transparent)]
+    Broker(#[from] Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    Connected,
+    Offline,
+}
+
+/// A session as seen by the admin interface.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SessionInfo {
+    client_id: ClientId,
+    status: SessionStatus,
+    subscriptions: BTreeMap<String, u8>,
+    queued_messages: usize,
+    inflight_messages: usize,
+}
+
+impl SessionInfo {
+    pub(crate) fn new(
+        client_id: ClientId,
+        status: SessionStatus,
+        subscriptions: BTreeMap<String, u8>,
+        queued_messages: usize,
+        inflight_messages: usize,
+    ) -> Self {
+        Self {
+            client_id,
+            status,
+            subscriptions,
+            queued_messages,
+            inflight_messages,
+        }
+    }
+
+    pub fn client_id(&self) -> &ClientId {
+        &self.client_id
+    }
+
+    pub fn status(&self) -> SessionStatus {
+        self.status
+    }
+
+    /// Topic filters of the session along with their maximum QoS.
+    pub fn subscriptions(&self) -> &BTreeMap<String, u8> {
+        &self.subscriptions
+    }
+
+    pub fn queued_messages(&self) -> usize {
+        self.queued_messages
+    }
+
+    pub fn inflight_messages(&self) -> usize {
+        self.inflight_messages
+    }
+}
+
+/// Inspects and manages the sessions of a running broker.
+///
+/// All operations go through the broker event loop, so they are serialized
+/// with the traffic of the clients.
+#[derive(Clone, Debug)]
+pub struct AdminHandle(BrokerHandle);
+
+impl AdminHandle {
+    pub fn new(broker_handle: BrokerHandle) -> Self {
+        Self(broker_handle)
+    }
+
+    /// Lists the connected and offline sessions, ordered by client id.
+    pub async fn sessions(&mut self) -> Result<Vec<SessionInfo>, AdminError> {
+        let (reply, response) = oneshot::channel();
+        self.send(AdminRequest::Sessions(reply)).await?;
+        Ok(response.await.map_err(Error::from)?)
+    }
+
+    /// Drops the connection of a client, as if the network connection was lost.
+    pub async fn disconnect(&mut self, client_id: ClientId) -> Result<(), AdminError> {
+        let message = Message::Client(client_id, ClientEvent::DropConnection);
+        self.0.send(message).await?;
+        Ok(())
+    }
+
+    /// Removes the session of a client which is not connected.
+    pub async fn delete_session(&mut self, client_id: ClientId) -> Result<(), AdminError> {
+        let (reply, response) = oneshot::channel();
+        self.send(AdminRequest::DeleteSession(client_id, reply))
+            .await?;
+        response.await.map_err(Error::from)?
+    }
+
+    /// Removes the retained messages with a topic matching the topic filter,
+    /// returning how many were removed.
+    pub async fn clear_retained(&mut self, topic_filter: String) -> Result<usize, AdminError> {
+        let (reply, response) = oneshot::channel();
+        self.send(AdminRequest::ClearRetained(topic_filter, reply))
+            .await?;
+        response.await.map_err(Error::from)?
+    }
+
+    /// Asks the broker to send its state to the snapshotter.
+    pub async fn snapshot(&mut self, handle: StateSnapshotHandle) -> Result<(), AdminError> {
+        let message = Message::System(SystemEvent::StateSnapshot(handle));
+        self.0.send(message).await?;
+        Ok(())
+    }
+
+    /// Asks the broker to compact its write-ahead log into a checkpoint.
+    pub async fn checkpoint(&mut self) -> Result<(), AdminError> {
+        let message = Message::System(SystemEvent::JournalCheckpoint);
+        self.0.send(message).await?;
+        Ok(())
+    }
+
+    async fn send(&mut self, request: AdminRequest) -> Result<(), Error> {
+        self.0
+            .send(Message::System(SystemEvent::Admin(request)))
+            .await
+    }
+}
@@ -1,3 +1,5 @@
+use openssl::{nid::Nid, sha::sha256, x509::X509};
+
 use crate::auth::AuthId;
 
 /// Describes a MQTT client credentials.
@@ -7,15 +9,96 @@ pub enum Credentials {
 
     /// Client certificate credentials.
     ClientCertificate(Certificate),
+
+    /// Username along with credentials of a peer process connected over unix domain socket.
+    PeerCredentials(Option<String>, PeerCredentials),
 }
 
-/// Represents a client certificate.
+/// Represents a client certificate along with the chain of intermediate
+/// certificates presented by a client. All certificates are DER encoded.
 #[derive(Clone, Debug)]
-pub struct Certificate(Vec<u8>);
+pub struct Certificate {
+    der: Vec<u8>,
+    chain: Vec<Vec<u8>>,
+}
+
+impl Certificate {
+    #[must_use]
+    pub fn with_chain(mut self, chain: Vec<Vec<u8>>) -> Self {
+        self.chain = chain;
+        self
+    }
+
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// Intermediate certificates presented by a client, not including the client certificate.
+    pub fn chain(&self) -> &[Vec<u8>] {
+        &self.chain
+    }
+
+    /// Returns upper case hex encoded SHA-256 thumbprint of the client certificate.
+    pub fn thumbprint(&self) -> String {
+        sha256(&self.der)
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect()
+    }
+
+    /// Returns subject common name of the client certificate if any.
+    pub fn common_name(&self) -> Result<Option<String>, CertificateError> {
+        let cert = X509::from_der(&self.der).map_err(CertificateError)?;
+        let common_name = cert
+            .subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .map(|entry| entry.data().as_utf8().map(|name| name.to_string()))
+            .transpose()
+            .map_err(CertificateError)?;
+
+        Ok(common_name)
+    }
+}
 
 impl From<Vec<u8>> for Certificate {
-    fn from(certificate: Vec<u8>) -> Self {
-        Self(certificate)
+    fn from(der: Vec<u8>) -> Self {
+        Self {
+            der,
+            chain: Vec::new(),
+        }
+    }
+}
+
+/// Client certificate parsing error.
+#[derive(Debug, thiserror::Error)]
+#[error("An error occurred parsing client certificate.")]
+pub struct CertificateError(#[source] openssl::error::ErrorStack);
+
+/// Represents credentials of a process on the other end of unix domain socket.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeerCredentials {
+    pid: Option<i32>,
+    uid: u32,
+    gid: u32,
+}
+
+impl PeerCredentials {
+    pub fn new(pid: Option<i32>, uid: u32, gid: u32) -> Self {
+        Self { pid, uid, gid }
+    }
+
+    /// Process id of a peer. Not every platform reports it.
+    pub fn pid(&self) -> Option<i32> {
+        self.pid
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.gid
     }
 }
 
@@ -61,6 +61,14 @@ impl Activity {
         }
     }
 
+    pub fn auth_id(&self) -> &AuthId {
+        &self.auth_id
+    }
+
+    pub fn client_id(&self) -> &ClientId {
+        &self.client_id
+    }
+
     pub fn operation(&self) -> &Operation {
         &self.operation
     }
@@ -119,6 +127,12 @@ pub struct Publication {
     retain: bool,
 }
 
+impl Publication {
+    pub fn topic_name(&self) -> &str {
+        &self.topic_name
+    }
+}
+
 impl From<proto::Publication> for Publication {
     fn from(publication: proto::Publication) -> Self {
         Self {
@@ -134,6 +148,12 @@ pub struct Publish {
     publication: Publication,
 }
 
+impl Publish {
+    pub fn publication(&self) -> &Publication {
+        &self.publication
+    }
+}
+
 impl From<proto::Publish> for Publish {
     fn from(publish: proto::Publish) -> Self {
         Self {
@@ -176,6 +196,12 @@ pub struct Receive {
     publication: Publication,
 }
 
+impl Receive {
+    pub fn publication(&self) -> &Publication {
+        &self.publication
+    }
+}
+
 impl From<proto::Publication> for Receive {
     fn from(publication: proto::Publication) -> Self {
         Self {
@@ -203,6 +229,7 @@ mod tests {
             keep_alive: Duration::from_secs(1),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         }
     }
 
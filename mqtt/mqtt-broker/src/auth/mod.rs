@@ -1,10 +1,15 @@
 mod authentication;
 mod authorization;
+mod password;
+mod policy;
 
 pub use authentication::{
-    AuthenticateError, Authenticator, Certificate, Credentials, DefaultAuthenticator,
+    AuthenticateError, Authenticator, Certificate, CertificateError, Credentials,
+    DefaultAuthenticator, PeerCredentials,
 };
 pub use authorization::{Activity, AuthorizeError, Authorizer, DefaultAuthorizer, Operation};
+pub use password::{PasswordAuthenticator, PasswordFile, PasswordFileError};
+pub use policy::{Policy, PolicyAuthorizer, PolicyError};
 
 /// Authenticated MQTT client identity.
 #[derive(Clone, Debug, PartialEq)]
@@ -0,0 +1,703 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use openssl::{
+    base64, hash::MessageDigest, memcmp, pkcs5::pbkdf2_hmac, pkey::PKey, rand::rand_bytes,
+    sha::sha256, sign::Signer,
+};
+use percent_encoding::percent_decode_str;
+
+use crate::auth::{AuthId, AuthenticateError, Authenticator, Credentials};
+
+const PBKDF2_SHA256: &str = "pbkdf2-sha256";
+const SAS: &str = "sas";
+const ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+const SAS_PREFIX: &str = "SharedAccessSignature ";
+const DEVICES_SEGMENT: &str = "/devices/";
+const FAILED_VERIFY_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_FAILED_VERIFY_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Authenticator which validates username and password against a password file.
+///
+/// Password can be either a plain password which is checked against a salted
+/// hash stored in the file, or a shared access signature (SAS) token signed
+/// with a key stored in the file for a user.
+///
+/// Only username and password credentials are checked against the file,
+/// any other credentials are not identified by this authenticator.
+pub struct PasswordAuthenticator {
+    file: PasswordFile,
+}
+
+impl PasswordAuthenticator {
+    pub fn new(file: PasswordFile) -> Self {
+        Self { file }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PasswordFileError> {
+        PasswordFile::load(path).map(Self::new)
+    }
+}
+
+impl Authenticator for PasswordAuthenticator {
+    type Error = AuthenticateError;
+
+    fn authenticate(&self, credentials: Credentials) -> Result<Option<AuthId>, Self::Error> {
+        match credentials {
+            Credentials::Basic(Some(username), Some(password)) => {
+                if self
+                    .file
+                    .verify(&username, &password)
+                    .map_err(|_| AuthenticateError)?
+                {
+                    Ok(Some(AuthId::from_identity(username)))
+                } else {
+                    Ok(None)
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A set of users along with their secrets.
+///
+/// Every non-empty line of the file which doesn't start with `#`
+/// describes a single user in one of the following formats:
+/// * `<username>:pbkdf2-sha256$<iterations>$<base64 salt>$<base64 hash>`
+/// * `<username>:sas$<base64 key>`
+#[derive(Debug, Default)]
+pub struct PasswordFile {
+    users: BTreeMap<String, Secret>,
+    verified: Mutex<HashMap<String, [u8; 32]>>,
+    failed: Mutex<HashMap<String, FailedVerify>>,
+}
+
+impl PasswordFile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PasswordFileError> {
+        let contents = std::fs::read_to_string(path).map_err(PasswordFileError::Read)?;
+        contents.parse()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PasswordFileError> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        // The mode only applies to a new file, one left over from an interrupted save is removed.
+        match std::fs::remove_file(&tmp) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(PasswordFileError::Write(e))
+            }
+            _ => (),
+        }
+        let mut file = options.open(&tmp).map_err(PasswordFileError::Write)?;
+        file.write_all(self.to_string().as_bytes())
+            .map_err(PasswordFileError::Write)?;
+        drop(file);
+
+        std::fs::rename(&tmp, path).map_err(PasswordFileError::Write)
+    }
+
+    /// Adds a new user or replaces secret of an existing one with a salted password hash.
+    pub fn add_password(
+        &mut self,
+        username: &str,
+        password: &str,
+    ) -> Result<(), PasswordFileError> {
+        validate_username(username)?;
+
+        let mut salt = vec![0; SALT_LEN];
+        rand_bytes(&mut salt).map_err(PasswordFileError::Crypto)?;
+        let hash = pbkdf2(password, &salt, ITERATIONS)?;
+
+        let secret = Secret::Hash {
+            iterations: ITERATIONS,
+            salt,
+            hash,
+        };
+        self.users.insert(username.to_string(), secret);
+        self.forget(username);
+        Ok(())
+    }
+
+    /// Adds a new user or replaces secret of an existing one with a base64 encoded
+    /// key used to validate SAS token signatures.
+    pub fn add_sas_key(&mut self, username: &str, key: &str) -> Result<(), PasswordFileError> {
+        validate_username(username)?;
+
+        let key = base64::decode_block(key).map_err(|_| PasswordFileError::InvalidKey)?;
+        self.users.insert(username.to_string(), Secret::SasKey(key));
+        self.forget(username);
+        Ok(())
+    }
+
+    /// Removes a user. Returns `false` if there was no such user.
+    pub fn remove(&mut self, username: &str) -> bool {
+        self.forget(username);
+        self.users.remove(username).is_some()
+    }
+
+    /// Checks whether a given password is valid for a user.
+    ///
+    /// PBKDF2 is slow by design and clients are authenticated on the broker loop,
+    /// so a password which has been verified once is remembered as a SHA-256 digest
+    /// and later checks of the same password don't hash it with PBKDF2 again.
+    ///
+    /// After a wrong password, passwords of the user which aren't remembered are
+    /// refused without hashing them for a backoff that doubles with every failure,
+    /// so that clients guessing passwords can't keep the broker loop busy.
+    pub fn verify(&self, username: &str, password: &str) -> Result<bool, PasswordFileError> {
+        self.verify_at(username, password, Instant::now())
+    }
+
+    fn verify_at(
+        &self,
+        username: &str,
+        password: &str,
+        now: Instant,
+    ) -> Result<bool, PasswordFileError> {
+        match self.users.get(username) {
+            Some(Secret::Hash {
+                iterations,
+                salt,
+                hash,
+            }) => {
+                let digest = sha256(password.as_bytes());
+                if self.is_verified(username, &digest) {
+                    return Ok(true);
+                }
+                if self.is_backing_off(username, now) {
+                    return Ok(false);
+                }
+
+                let actual = pbkdf2(password, salt, *iterations)?;
+                let verified = memcmp::eq(&actual, hash);
+                if verified {
+                    if let Ok(mut cache) = self.verified.lock() {
+                        cache.insert(username.to_string(), digest);
+                    }
+                    if let Ok(mut failed) = self.failed.lock() {
+                        failed.remove(username);
+                    }
+                } else if let Ok(mut failed) = self.failed.lock() {
+                    failed
+                        .entry(username.to_string())
+                        .and_modify(|failed| failed.again(now))
+                        .or_insert_with(|| FailedVerify::new(now));
+                }
+                Ok(verified)
+            }
+            Some(Secret::SasKey(key)) => {
+                verify_sas_token(key, username, password, SystemTime::now())
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn is_verified(&self, username: &str, digest: &[u8; 32]) -> bool {
+        self.verified.lock().map_or(false, |cache| {
+            cache
+                .get(username)
+                .map_or(false, |verified| memcmp::eq(verified, digest))
+        })
+    }
+
+    fn is_backing_off(&self, username: &str, now: Instant) -> bool {
+        self.failed.lock().map_or(true, |failed| {
+            failed
+                .get(username)
+                .map_or(false, |failed| now < failed.retry_at)
+        })
+    }
+
+    fn forget(&mut self, username: &str) {
+        if let Ok(cache) = self.verified.get_mut() {
+            cache.remove(username);
+        }
+        if let Ok(failed) = self.failed.get_mut() {
+            failed.remove(username);
+        }
+    }
+}
+
+/// Time before which passwords of a user are refused after a wrong one.
+#[derive(Debug)]
+struct FailedVerify {
+    retry_at: Instant,
+    backoff: Duration,
+}
+
+impl FailedVerify {
+    fn new(now: Instant) -> Self {
+        Self {
+            retry_at: now + FAILED_VERIFY_BACKOFF,
+            backoff: FAILED_VERIFY_BACKOFF,
+        }
+    }
+
+    fn again(&mut self, now: Instant) {
+        self.backoff = (self.backoff * 2).min(MAX_FAILED_VERIFY_BACKOFF);
+        self.retry_at = now + self.backoff;
+    }
+}
+
+impl FromStr for PasswordFile {
+    type Err = PasswordFileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut users = BTreeMap::new();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || PasswordFileError::InvalidLine(number + 1);
+            let mut parts = line.splitn(2, ':');
+            let username = parts.next().filter(|u| !u.is_empty()).ok_or_else(invalid)?;
+            let secret = parts.next().ok_or_else(invalid)?;
+            let secret = Secret::from_str(secret).map_err(|()| invalid())?;
+
+            users.insert(username.to_string(), secret);
+        }
+
+        Ok(Self {
+            users,
+            verified: Mutex::default(),
+            failed: Mutex::default(),
+        })
+    }
+}
+
+impl fmt::Display for PasswordFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (username, secret) in &self.users {
+            writeln!(f, "{}:{}", username, secret)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Secret {
+    Hash {
+        iterations: u32,
+        salt: Vec<u8>,
+        hash: Vec<u8>,
+    },
+    SasKey(Vec<u8>),
+}
+
+impl FromStr for Secret {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<_> = s.split('$').collect();
+        match parts.as_slice() {
+            [PBKDF2_SHA256, iterations, salt, hash] => Ok(Self::Hash {
+                iterations: iterations.parse().map_err(drop)?,
+                salt: base64::decode_block(salt).map_err(drop)?,
+                hash: base64::decode_block(hash).map_err(drop)?,
+            }),
+            [SAS, key] => Ok(Self::SasKey(base64::decode_block(key).map_err(drop)?)),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hash {
+                iterations,
+                salt,
+                hash,
+            } => write!(
+                f,
+                "{}${}${}${}",
+                PBKDF2_SHA256,
+                iterations,
+                base64::encode_block(salt),
+                base64::encode_block(hash)
+            ),
+            Self::SasKey(key) => write!(f, "{}${}", SAS, base64::encode_block(key)),
+        }
+    }
+}
+
+fn validate_username(username: &str) -> Result<(), PasswordFileError> {
+    if username.is_empty()
+        || username.starts_with('#')
+        || username.contains(|c: char| c == ':' || c.is_whitespace())
+    {
+        return Err(PasswordFileError::InvalidUsername(username.to_string()));
+    }
+
+    Ok(())
+}
+
+fn pbkdf2(password: &str, salt: &[u8], iterations: u32) -> Result<Vec<u8>, PasswordFileError> {
+    let mut hash = vec![0; HASH_LEN];
+    pbkdf2_hmac(
+        password.as_bytes(),
+        salt,
+        iterations as usize,
+        MessageDigest::sha256(),
+        &mut hash,
+    )
+    .map_err(PasswordFileError::Crypto)?;
+
+    Ok(hash)
+}
+
+/// Validates a SAS token of the following format
+/// `SharedAccessSignature sr={URL-encoded-resourceURI}&sig={signature}&se={expiry}[&skn={policyName}]`
+/// where signature is HMAC-SHA256 of `{URL-encoded-resourceURI}\n{expiry}`.
+///
+/// The token is only valid for a user it has been issued for,
+/// so the resource URI must be `{host}/devices/{username}`.
+fn verify_sas_token(
+    key: &[u8],
+    username: &str,
+    token: &str,
+    now: SystemTime,
+) -> Result<bool, PasswordFileError> {
+    if !token.starts_with(SAS_PREFIX) {
+        return Ok(false);
+    }
+
+    let mut resource = None;
+    let mut signature = None;
+    let mut expiry = None;
+    for field in token[SAS_PREFIX.len()..].split('&') {
+        let mut parts = field.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some("sr"), Some(value)) => resource = Some(value),
+            (Some("sig"), Some(value)) => signature = Some(value),
+            (Some("se"), Some(value)) => expiry = Some(value),
+            _ => (),
+        }
+    }
+
+    let (resource, signature, expiry) = match (resource, signature, expiry) {
+        (Some(resource), Some(signature), Some(expiry)) => (resource, signature, expiry),
+        _ => return Ok(false),
+    };
+
+    let issued_for = percent_decode_str(resource)
+        .decode_utf8()
+        .ok()
+        .and_then(|resource| {
+            resource
+                .find(DEVICES_SEGMENT)
+                .map(|index| resource[index + DEVICES_SEGMENT.len()..] == *username)
+        });
+    if issued_for != Some(true) {
+        return Ok(false);
+    }
+
+    let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    match expiry.parse::<u64>() {
+        Ok(expiry) if expiry > now => (),
+        _ => return Ok(false),
+    }
+
+    let signature = match percent_decode_str(signature)
+        .decode_utf8()
+        .ok()
+        .and_then(|signature| base64::decode_block(&signature).ok())
+    {
+        Some(signature) => signature,
+        None => return Ok(false),
+    };
+
+    let expected = sign(key, &format!("{}\n{}", resource, expiry))?;
+    Ok(expected.len() == signature.len() && memcmp::eq(&expected, &signature))
+}
+
+fn sign(key: &[u8], data: &str) -> Result<Vec<u8>, PasswordFileError> {
+    let key = PKey::hmac(key).map_err(PasswordFileError::Crypto)?;
+    let mut signer =
+        Signer::new(MessageDigest::sha256(), &key).map_err(PasswordFileError::Crypto)?;
+    signer
+        .update(data.as_bytes())
+        .map_err(PasswordFileError::Crypto)?;
+    signer.sign_to_vec().map_err(PasswordFileError::Crypto)
+}
+
+/// Represents errors occurred while working with a password file.
+#[derive(Debug, thiserror::Error)]
+pub enum PasswordFileError {
+    #[error("An error occurred reading password file.")]
+    Read(#[source] std::io::Error),
+
+    #[error("An error occurred writing password file.")]
+    Write(#[source] std::io::Error),
+
+    #[error("Password file contains invalid entry at line {0}.")]
+    InvalidLine(usize),
+
+    #[error("Invalid username {0:?}.")]
+    InvalidUsername(String),
+
+    #[error("SAS key must be base64 encoded.")]
+    InvalidKey,
+
+    #[error("An error occurred hashing password.")]
+    Crypto(#[source] openssl::error::ErrorStack),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    use matches::assert_matches;
+    use openssl::base64;
+
+    use super::{
+        sign, verify_sas_token, PasswordAuthenticator, PasswordFile, PasswordFileError,
+        FAILED_VERIFY_BACKOFF,
+    };
+    use crate::auth::{AuthId, Authenticator, Credentials, PeerCredentials};
+
+    const KEY: &[u8] = b"01234567890123456789012345678901";
+
+    fn sas_token(resource: &str, expiry: u64) -> String {
+        let signature = sign(KEY, &format!("{}\n{}", resource, expiry)).unwrap();
+        let signature = base64::encode_block(&signature)
+            .replace('+', "%2B")
+            .replace('/', "%2F")
+            .replace('=', "%3D");
+        format!(
+            "SharedAccessSignature sr={}&sig={}&se={}",
+            resource, signature, expiry
+        )
+    }
+
+    fn basic(username: &str, password: &str) -> Credentials {
+        Credentials::Basic(Some(username.into()), Some(password.into()))
+    }
+
+    #[test]
+    fn it_authenticates_user_with_valid_password() {
+        let mut file = PasswordFile::default();
+        file.add_password("device-1", "secret").unwrap();
+        let authenticator = PasswordAuthenticator::new(file);
+
+        assert_matches!(
+            authenticator.authenticate(basic("device-1", "secret")),
+            Ok(Some(AuthId::Identity(id))) if id == "device-1"
+        );
+        assert_matches!(
+            authenticator.authenticate(basic("device-1", "wrong")),
+            Ok(None)
+        );
+        assert_matches!(
+            authenticator.authenticate(basic("device-2", "secret")),
+            Ok(None)
+        );
+        assert_matches!(
+            authenticator.authenticate(Credentials::Basic(Some("device-1".into()), None)),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn it_roundtrips_password_file() {
+        let mut file = PasswordFile::default();
+        file.add_password("device-1", "secret").unwrap();
+        file.add_sas_key("device-2", &base64::encode_block(KEY))
+            .unwrap();
+
+        let text = file.to_string();
+        let parsed: PasswordFile = format!("# users\n\n{}", text).parse().unwrap();
+
+        assert_eq!(parsed.users, file.users);
+        assert_matches!(parsed.verify("device-1", "secret"), Ok(true));
+    }
+
+    #[test]
+    fn it_removes_user() {
+        let mut file = PasswordFile::default();
+        file.add_password("device-1", "secret").unwrap();
+
+        assert!(file.remove("device-1"));
+        assert!(!file.remove("device-1"));
+        assert_matches!(file.verify("device-1", "secret"), Ok(false));
+    }
+
+    #[test]
+    fn it_refuses_invalid_entries() {
+        assert_matches!(
+            "device-1".parse::<PasswordFile>(),
+            Err(PasswordFileError::InvalidLine(1))
+        );
+        assert_matches!(
+            "device-1:md5$abc".parse::<PasswordFile>(),
+            Err(PasswordFileError::InvalidLine(1))
+        );
+        assert_matches!(
+            PasswordFile::default().add_password("a:b", "secret"),
+            Err(PasswordFileError::InvalidUsername(_))
+        );
+    }
+
+    #[test]
+    fn it_validates_sas_token() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+        let resource = "myhub.azure-devices.net%2Fdevices%2Fdevice-2";
+
+        let token = sas_token(resource, 2_000);
+        assert_matches!(verify_sas_token(KEY, "device-2", &token, now), Ok(true));
+
+        let expired = sas_token(resource, 500);
+        assert_matches!(verify_sas_token(KEY, "device-2", &expired, now), Ok(false));
+
+        let tampered = token.replace("device-2", "device-3");
+        assert_matches!(verify_sas_token(KEY, "device-2", &tampered, now), Ok(false));
+
+        assert_matches!(verify_sas_token(KEY, "device-2", "secret", now), Ok(false));
+    }
+
+    #[test]
+    fn it_refuses_sas_token_issued_for_another_user() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+
+        let token = sas_token("myhub.azure-devices.net%2Fdevices%2Fdevice-3", 2_000);
+        assert_matches!(verify_sas_token(KEY, "device-2", &token, now), Ok(false));
+        assert_matches!(verify_sas_token(KEY, "device-3", &token, now), Ok(true));
+
+        let token = sas_token(
+            "myhub.azure-devices.net%2Fdevices%2Fdevice-2%2Fmodules%2Fm",
+            2_000,
+        );
+        assert_matches!(verify_sas_token(KEY, "device-2", &token, now), Ok(false));
+        assert_matches!(
+            verify_sas_token(KEY, "device-2/modules/m", &token, now),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn it_remembers_verified_password() {
+        let mut file = PasswordFile::default();
+        file.add_password("device-1", "secret").unwrap();
+
+        let now = Instant::now();
+        assert_matches!(file.verify_at("device-1", "wrong", now), Ok(false));
+        assert!(file.verified.lock().unwrap().is_empty());
+
+        let now = now + FAILED_VERIFY_BACKOFF;
+        assert_matches!(file.verify_at("device-1", "secret", now), Ok(true));
+        assert!(file.verified.lock().unwrap().contains_key("device-1"));
+        assert_matches!(file.verify("device-1", "secret"), Ok(true));
+        assert_matches!(file.verify("device-1", "wrong"), Ok(false));
+
+        file.add_password("device-1", "changed").unwrap();
+        assert!(file.verified.lock().unwrap().is_empty());
+        assert_matches!(file.verify("device-1", "secret"), Ok(false));
+    }
+
+    #[test]
+    fn it_backs_off_after_wrong_password() {
+        let mut file = PasswordFile::default();
+        file.add_password("device-1", "secret").unwrap();
+        file.add_password("device-2", "secret").unwrap();
+
+        let now = Instant::now();
+        assert_matches!(file.verify_at("device-1", "wrong", now), Ok(false));
+        assert_matches!(file.verify_at("device-1", "secret", now), Ok(false));
+        assert_matches!(file.verify_at("device-2", "secret", now), Ok(true));
+
+        let now = now + FAILED_VERIFY_BACKOFF;
+        assert_matches!(file.verify_at("device-1", "wrong", now), Ok(false));
+        let retry_at = now + FAILED_VERIFY_BACKOFF * 2;
+        assert_matches!(
+            file.verify_at("device-1", "secret", retry_at - Duration::from_millis(1)),
+            Ok(false)
+        );
+        assert_matches!(file.verify_at("device-1", "secret", retry_at), Ok(true));
+        assert!(file.failed.lock().unwrap().is_empty());
+
+        // a remembered password isn't refused during a backoff
+        assert_matches!(file.verify_at("device-1", "wrong", retry_at), Ok(false));
+        assert_matches!(file.verify_at("device-1", "secret", retry_at), Ok(true));
+    }
+
+    #[test]
+    fn it_refuses_credentials_other_than_username_and_password() {
+        let mut file = PasswordFile::default();
+        file.add_password("device-1", "secret").unwrap();
+        let authenticator = PasswordAuthenticator::new(file);
+
+        assert_matches!(
+            authenticator.authenticate(Credentials::ClientCertificate(b"cert".to_vec().into())),
+            Ok(None)
+        );
+        assert_matches!(
+            authenticator.authenticate(Credentials::PeerCredentials(
+                Some("device-1".into()),
+                PeerCredentials::new(Some(1), 0, 0)
+            )),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn it_authenticates_user_with_sas_token() {
+        let mut file = PasswordFile::default();
+        file.add_sas_key("device-2", &base64::encode_block(KEY))
+            .unwrap();
+        let authenticator = PasswordAuthenticator::new(file);
+
+        let expiry = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600;
+        let token = sas_token("myhub%2Fdevices%2Fdevice-2", expiry);
+
+        assert_matches!(
+            authenticator.authenticate(basic("device-2", &token)),
+            Ok(Some(AuthId::Identity(id))) if id == "device-2"
+        );
+    }
+
+    #[test]
+    fn it_saves_and_loads_password_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users");
+
+        let mut file = PasswordFile::default();
+        file.add_password("device-1", "secret").unwrap();
+        file.save(&path).unwrap();
+
+        let loaded = PasswordFile::load(&path).unwrap();
+        assert_matches!(loaded.verify("device-1", "secret"), Ok(true));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+    }
+}
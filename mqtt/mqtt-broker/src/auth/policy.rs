@@ -0,0 +1,583 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use config::{Config, ConfigError, File};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::auth::{Activity, AuthId, AuthorizeError, Authorizer, Operation};
+use crate::{Segment, TopicFilter};
+
+const CLIENT_ID_VARIABLE: &str = "{{clientId}}";
+const IDENTITY_VARIABLE: &str = "{{identity}}";
+const ANY_IDENTITY: &str = "*";
+
+/// Authorizer which evaluates client activities against a set of
+/// allow and deny statements loaded from a policy file.
+///
+/// An activity is allowed when at least one `allow` statement and
+/// no `deny` statements match it. Activities no statement matches are denied.
+///
+/// The policy file can be changed while the broker is running, see [`reload`].
+///
+/// [`reload`]: #method.reload
+#[derive(Clone)]
+pub struct PolicyAuthorizer {
+    path: PathBuf,
+    state: Arc<RwLock<PolicyState>>,
+}
+
+struct PolicyState {
+    policy: Policy,
+    contents: Vec<u8>,
+}
+
+impl PolicyAuthorizer {
+    /// Loads a policy from JSON or YAML file. The format is determined by file extension.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PolicyError> {
+        let path = path.as_ref().to_path_buf();
+        let contents = std::fs::read(&path).map_err(PolicyError::Read)?;
+        let policy = Policy::from_file(&path)?;
+        info!("loaded authorization policy from {}", path.display());
+
+        Ok(Self {
+            path,
+            state: Arc::new(RwLock::new(PolicyState { policy, contents })),
+        })
+    }
+
+    /// Reloads the policy if the file content has changed since it was loaded last time.
+    ///
+    /// A policy which fails to load doesn't replace the current one.
+    ///
+    /// ## Returns
+    /// * `Ok(true)` - the policy has been reloaded.
+    /// * `Ok(false)` - the policy file hasn't changed.
+    /// * `Err(e)` - an error occurred loading the policy file.
+    pub fn reload(&self) -> Result<bool, PolicyError> {
+        let contents = std::fs::read(&self.path).map_err(PolicyError::Read)?;
+        if self
+            .state
+            .read()
+            .map_err(|_| PolicyError::Poisoned)?
+            .contents
+            == contents
+        {
+            return Ok(false);
+        }
+
+        let policy = Policy::from_file(&self.path)?;
+
+        let mut state = self.state.write().map_err(|_| PolicyError::Poisoned)?;
+        state.policy = policy;
+        state.contents = contents;
+        info!("reloaded authorization policy from {}", self.path.display());
+
+        Ok(true)
+    }
+}
+
+impl Authorizer for PolicyAuthorizer {
+    type Error = AuthorizeError;
+
+    fn authorize(&self, activity: Activity) -> Result<bool, Self::Error> {
+        let state = self.state.read().map_err(|_| AuthorizeError)?;
+        Ok(state.policy.evaluate(&activity))
+    }
+}
+
+/// A set of statements to authorize client activities.
+#[derive(Debug, Deserialize)]
+pub struct Policy {
+    statements: Vec<Statement>,
+}
+
+impl Policy {
+    fn from_file(path: &Path) -> Result<Self, PolicyError> {
+        let mut config = Config::new();
+        config.merge(File::from(path)).map_err(PolicyError::Load)?;
+
+        let policy: Self = config.try_into().map_err(PolicyError::Load)?;
+        policy.compile()
+    }
+
+    /// Parses statement resources into topic filters, so that they
+    /// aren't parsed again each time an activity is evaluated.
+    fn compile(mut self) -> Result<Self, PolicyError> {
+        for statement in &mut self.statements {
+            statement.filters = statement
+                .resources
+                .iter()
+                .map(|resource| Resource::from_str(resource))
+                .collect::<Result<_, _>>()?;
+        }
+
+        Ok(self)
+    }
+
+    /// Checks whether an activity is allowed by the policy.
+    pub fn evaluate(&self, activity: &Activity) -> bool {
+        let mut allowed = false;
+        for statement in self.statements.iter().filter(|s| s.matches(activity)) {
+            match statement.effect {
+                Effect::Deny => return false,
+                Effect::Allow => allowed = true,
+            }
+        }
+
+        allowed
+    }
+}
+
+impl FromStr for Policy {
+    type Err = PolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let policy: Self = serde_json::from_str(s).map_err(PolicyError::Parse)?;
+        policy.compile()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Statement {
+    effect: Effect,
+    identities: Vec<String>,
+    operations: Vec<OperationKind>,
+    #[serde(default)]
+    resources: Vec<String>,
+    #[serde(skip)]
+    filters: Vec<Resource>,
+}
+
+impl Statement {
+    fn matches(&self, activity: &Activity) -> bool {
+        self.matches_identity(activity.auth_id())
+            && self
+                .operations
+                .contains(&OperationKind::from(activity.operation()))
+            && self.matches_resource(activity)
+    }
+
+    fn matches_identity(&self, auth_id: &AuthId) -> bool {
+        self.identities.iter().any(|identity| match auth_id {
+            _ if identity == ANY_IDENTITY => true,
+            AuthId::Identity(id) => id == identity,
+            AuthId::Anonymous => false,
+        })
+    }
+
+    fn matches_resource(&self, activity: &Activity) -> bool {
+        let identity = match activity.auth_id() {
+            AuthId::Identity(identity) => Some(identity.as_str()),
+            AuthId::Anonymous => None,
+        };
+        let client_id = activity.client_id().as_str();
+        let mut filters = self
+            .filters
+            .iter()
+            .filter_map(|resource| resource.filter(client_id, identity));
+
+        match activity.operation() {
+            Operation::Connect(_) => true,
+            Operation::Publish(publish) => {
+                let topic = publish.publication().topic_name();
+                filters.any(|filter| filter.matches(topic))
+            }
+            // A subscription is allowed when a resource covers every topic it
+            // can receive and denied when a resource matches any of them.
+            Operation::Subscribe(subscribe) => {
+                match TopicFilter::from_str(subscribe.topic_filter()) {
+                    Ok(requested) => match self.effect {
+                        Effect::Allow => filters.any(|filter| filter.covers(&requested)),
+                        Effect::Deny => filters.any(|filter| filter.intersects(&requested)),
+                    },
+                    Err(_) => false,
+                }
+            }
+            Operation::Receive(receive) => {
+                let topic = receive.publication().topic_name();
+                filters.any(|filter| filter.matches(topic))
+            }
+        }
+    }
+}
+
+/// A statement resource parsed into a topic filter.
+///
+/// Variables are kept as is within the levels of the filter
+/// and substituted when the resource is matched against an activity.
+#[derive(Debug)]
+struct Resource {
+    filter: TopicFilter,
+    has_client_id: bool,
+    has_identity: bool,
+}
+
+impl Resource {
+    /// Returns the filter with variables substituted, or `None` when
+    /// the values can't be substituted into it.
+    fn filter(&self, client_id: &str, identity: Option<&str>) -> Option<Cow<'_, TopicFilter>> {
+        if !self.has_client_id && !self.has_identity {
+            return Some(Cow::Borrowed(&self.filter));
+        }
+
+        // A value which contains wildcards or separators would change
+        // the structure of the filter, e.g. client id `#` substituted into
+        // `devices/{{clientId}}/#` grants access to all devices.
+        if self.has_client_id && !is_level(client_id) {
+            return None;
+        }
+        let identity = match identity {
+            Some(identity) if is_level(identity) => identity,
+            _ if self.has_identity => return None,
+            _ => "",
+        };
+
+        let segments = self
+            .filter
+            .segments()
+            .iter()
+            .map(|segment| match segment {
+                Segment::Level(level) => Segment::Level(
+                    level
+                        .replace(CLIENT_ID_VARIABLE, client_id)
+                        .replace(IDENTITY_VARIABLE, identity),
+                ),
+                wildcard => wildcard.clone(),
+            })
+            .collect();
+
+        Some(Cow::Owned(TopicFilter::new(segments)))
+    }
+}
+
+impl FromStr for Resource {
+    type Err = PolicyError;
+
+    fn from_str(resource: &str) -> Result<Self, Self::Err> {
+        let filter = TopicFilter::from_str(resource)
+            .map_err(|_| PolicyError::InvalidResource(resource.to_string()))?;
+
+        Ok(Self {
+            filter,
+            has_client_id: resource.contains(CLIENT_ID_VARIABLE),
+            has_identity: resource.contains(IDENTITY_VARIABLE),
+        })
+    }
+}
+
+/// Checks whether a value can be substituted as a single topic level.
+fn is_level(value: &str) -> bool {
+    !value.contains(&['/', '+', '#'][..])
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Effect {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum OperationKind {
+    Connect,
+    Publish,
+    Subscribe,
+    Receive,
+}
+
+impl From<&Operation> for OperationKind {
+    fn from(operation: &Operation) -> Self {
+        match operation {
+            Operation::Connect(_) => Self::Connect,
+            Operation::Publish(_) => Self::Publish,
+            Operation::Subscribe(_) => Self::Subscribe,
+            Operation::Receive(_) => Self::Receive,
+        }
+    }
+}
+
+/// Represents errors occurred while loading authorization policy.
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyError {
+    #[error("An error occurred reading policy file.")]
+    Read(#[source] std::io::Error),
+
+    #[error("An error occurred loading policy file.")]
+    Load(#[source] ConfigError),
+
+    #[error("An error occurred parsing policy.")]
+    Parse(#[source] serde_json::Error),
+
+    #[error("Policy resource is not a valid topic filter: {0}")]
+    InvalidResource(String),
+
+    #[error("Policy lock is poisoned.")]
+    Poisoned,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, time::Duration};
+
+    use bytes::Bytes;
+    use matches::assert_matches;
+
+    use mqtt3::{proto, PROTOCOL_LEVEL, PROTOCOL_NAME};
+
+    use super::{Policy, PolicyAuthorizer, PolicyError};
+    use crate::auth::{Activity, AuthId, Authorizer, Operation};
+
+    const POLICY: &str = r#"{
+        "statements": [
+            {
+                "effect": "allow",
+                "identities": ["*"],
+                "operations": ["connect"]
+            },
+            {
+                "effect": "allow",
+                "identities": ["device-1", "device-2"],
+                "operations": ["publish", "subscribe", "receive"],
+                "resources": ["devices/{{clientId}}/#", "identities/{{identity}}/+"]
+            },
+            {
+                "effect": "deny",
+                "identities": ["device-2"],
+                "operations": ["publish"],
+                "resources": ["devices/+/readonly"]
+            }
+        ]
+    }"#;
+
+    fn connect() -> proto::Connect {
+        proto::Connect {
+            username: None,
+            password: None,
+            will: None,
+            client_id: proto::ClientId::ServerGenerated,
+            keep_alive: Duration::from_secs(1),
+            protocol_name: PROTOCOL_NAME.to_string(),
+            protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
+        }
+    }
+
+    fn publish(topic_name: &str) -> proto::Publish {
+        proto::Publish {
+            packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtMostOnce,
+            retain: false,
+            topic_name: topic_name.to_string(),
+            payload: Bytes::new(),
+            properties: proto::Properties::default(),
+        }
+    }
+
+    fn subscribe(topic_filter: &str) -> proto::SubscribeTo {
+        proto::SubscribeTo {
+            topic_filter: topic_filter.to_string(),
+            qos: proto::QoS::AtLeastOnce,
+        }
+    }
+
+    fn evaluate(auth_id: impl Into<AuthId>, client_id: &str, operation: Operation) -> bool {
+        let policy = Policy::from_str(POLICY).unwrap();
+        policy.evaluate(&Activity::new(auth_id, client_id, operation))
+    }
+
+    #[test]
+    fn it_allows_any_identity_to_connect() {
+        assert!(evaluate(
+            AuthId::Anonymous,
+            "c",
+            Operation::new_connect(connect())
+        ));
+        assert!(evaluate("device-3", "c", Operation::new_connect(connect())));
+    }
+
+    #[test]
+    fn it_substitutes_variables_in_resources() {
+        let operation = Operation::new_publish(publish("devices/device-1/telemetry"));
+        assert!(evaluate("device-1", "device-1", operation));
+
+        let operation = Operation::new_publish(publish("devices/device-2/telemetry"));
+        assert!(!evaluate("device-1", "device-1", operation));
+
+        let operation = Operation::new_subscribe(subscribe("identities/device-1/inbox"));
+        assert!(evaluate("device-1", "client", operation));
+    }
+
+    #[test]
+    fn it_denies_anonymous_client_resources_with_identity_variable() {
+        let operation = Operation::new_subscribe(subscribe("identities/*/inbox"));
+        assert!(!evaluate(AuthId::Anonymous, "client", operation));
+    }
+
+    #[test]
+    fn it_denies_variables_with_wildcards_or_separators() {
+        let operation = Operation::new_subscribe(subscribe("devices/device-2/telemetry"));
+        assert!(!evaluate("device-1", "+", operation));
+
+        let operation = Operation::new_publish(publish("devices/a/b/telemetry"));
+        assert!(!evaluate("device-1", "a/b", operation));
+
+        let policy = Policy::from_str(
+            r#"{
+                "statements": [
+                    {
+                        "effect": "allow",
+                        "identities": ["*"],
+                        "operations": ["publish"],
+                        "resources": ["identities/{{identity}}/#"]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let operation = Operation::new_publish(publish("identities/device-1/inbox"));
+        assert!(!policy.evaluate(&Activity::new("+", "client", operation)));
+
+        let operation = Operation::new_publish(publish("identities/device-1/inbox"));
+        assert!(policy.evaluate(&Activity::new("device-1", "client", operation)));
+    }
+
+    #[test]
+    fn it_denies_when_deny_statement_matches() {
+        let operation = Operation::new_publish(publish("devices/device-2/readonly"));
+        assert!(!evaluate("device-2", "device-2", operation));
+
+        let operation = Operation::new_publish(publish("devices/device-1/readonly"));
+        assert!(evaluate("device-1", "device-1", operation));
+    }
+
+    #[test]
+    fn it_allows_subscriptions_covered_by_resources() {
+        let policy = Policy::from_str(
+            r#"{
+                "statements": [
+                    {
+                        "effect": "allow",
+                        "identities": ["*"],
+                        "operations": ["subscribe"],
+                        "resources": ["devices/+"]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let allowed = |topic_filter| {
+            let operation = Operation::new_subscribe(subscribe(topic_filter));
+            policy.evaluate(&Activity::new("device-1", "device-1", operation))
+        };
+
+        assert!(allowed("devices/device-1"));
+        assert!(allowed("devices/+"));
+        assert!(!allowed("devices/#"));
+        assert!(!allowed("devices/+/x"));
+        assert!(!allowed("devices"));
+    }
+
+    #[test]
+    fn it_denies_subscriptions_matching_deny_resources() {
+        let policy = Policy::from_str(
+            r#"{
+                "statements": [
+                    {
+                        "effect": "allow",
+                        "identities": ["*"],
+                        "operations": ["subscribe"],
+                        "resources": ["devices/#"]
+                    },
+                    {
+                        "effect": "deny",
+                        "identities": ["*"],
+                        "operations": ["subscribe"],
+                        "resources": ["devices/secret"]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let allowed = |topic_filter| {
+            let operation = Operation::new_subscribe(subscribe(topic_filter));
+            policy.evaluate(&Activity::new("device-1", "device-1", operation))
+        };
+
+        assert!(allowed("devices/public"));
+        assert!(!allowed("devices/secret"));
+        assert!(!allowed("devices/+"));
+        assert!(!allowed("devices/#"));
+    }
+
+    #[test]
+    fn it_denies_when_no_statement_matches() {
+        let operation = Operation::new_publish(publish("devices/device-3/telemetry"));
+        assert!(!evaluate("device-3", "device-3", operation));
+    }
+
+    #[test]
+    fn it_refuses_invalid_resource() {
+        let policy = r#"{
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identities": ["*"],
+                    "operations": ["publish"],
+                    "resources": ["devices/#/telemetry"]
+                }
+            ]
+        }"#;
+
+        assert_matches!(
+            Policy::from_str(policy),
+            Err(PolicyError::InvalidResource(_))
+        );
+    }
+
+    #[test]
+    fn it_reloads_modified_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.json");
+        std::fs::write(&path, POLICY).unwrap();
+
+        let authorizer = PolicyAuthorizer::from_file(&path).unwrap();
+        assert_matches!(authorizer.reload(), Ok(false));
+
+        let activity = || {
+            Activity::new(
+                "device-3",
+                "device-3",
+                Operation::new_publish(publish("devices/device-3/telemetry")),
+            )
+        };
+        assert_matches!(authorizer.authorize(activity()), Ok(false));
+
+        let policy = POLICY.replace(r#"["device-1", "device-2"]"#, r#"["device-3"]"#);
+        std::fs::write(&path, policy).unwrap();
+
+        assert_matches!(authorizer.reload(), Ok(true));
+        assert_matches!(authorizer.authorize(activity()), Ok(true));
+    }
+
+    #[test]
+    fn it_keeps_current_policy_when_reload_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.json");
+        std::fs::write(&path, POLICY).unwrap();
+
+        let authorizer = PolicyAuthorizer::from_file(&path).unwrap();
+
+        std::fs::write(&path, "{").unwrap();
+
+        assert_matches!(authorizer.reload(), Err(PolicyError::Load(_)));
+
+        let activity = Activity::new("device-3", "c", Operation::new_connect(connect()));
+        assert_matches!(authorizer.authorize(activity), Ok(true));
+    }
+}
@@ -0,0 +1,660 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures_util::StreamExt;
+use openssl::ssl::{SslConnector, SslMethod};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    sync::mpsc::{self, Receiver, Sender, UnboundedReceiver, UnboundedSender},
+};
+use tracing::{debug, info, warn};
+
+use mqtt3::{proto, Client, Event, PublishHandle, ReceivedPublication};
+
+use crate::configuration::{BridgeSettings, TopicRule};
+use crate::subscription::TopicFilter;
+use crate::{
+    AuthId, BrokerHandle, ClientEvent, ClientId, ConnReq, ConnectionHandle, Error, Message, Publish,
+};
+
+/// Client ids starting with this prefix are reserved for bridges.
+pub const BRIDGE_CLIENT_ID_PREFIX: &str = "$bridge/";
+
+/// The number of publications forwarded from the upstream broker which are
+/// remembered in order not to send them back when a topic is bridged both ways.
+const MAX_ECHOES: usize = 1024;
+
+/// The number of QoS 0 publications waiting to be sent upstream.
+/// Publications which don't fit are dropped.
+const MAX_PENDING_QOS0: usize = 1024;
+
+/// A connection to the upstream broker, either plain TCP or TLS.
+trait UpstreamIo: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T> UpstreamIo for T where T: AsyncRead + AsyncWrite + Send + Unpin {}
+
+type IoFuture =
+    Pin<Box<dyn Future<Output = io::Result<(Box<dyn UpstreamIo>, Option<String>)>> + Send>>;
+type UpstreamClient = Client<Box<dyn FnMut() -> IoFuture + Send>>;
+
+/// Forwards publications between the local broker and an upstream broker.
+///
+/// The bridge connects to the local broker as an in-process client with
+/// a persistent session, so outbound publications are queued by the broker
+/// and only acknowledged once the upstream broker has acknowledged them.
+pub struct Bridge {
+    settings: BridgeSettings,
+    client_id: ClientId,
+    rules: Arc<Vec<Rule>>,
+    connector: Option<SslConnector>,
+    broker_handle: BrokerHandle,
+}
+
+impl Bridge {
+    pub fn new(settings: BridgeSettings, broker_handle: BrokerHandle) -> Result<Self, Error> {
+        let rules = settings
+            .topics()
+            .iter()
+            .cloned()
+            .map(Rule::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        let client_id = ClientId::from(format!("{}{}", BRIDGE_CLIENT_ID_PREFIX, settings.name()));
+        let connector = if settings.tls() {
+            Some(
+                tls_connector(&settings)
+                    .map_err(|e| Error::BridgeClient(settings.name().to_string(), Box::new(e)))?,
+            )
+        } else {
+            None
+        };
+
+        Ok(Self {
+            settings,
+            client_id,
+            rules: Arc::new(rules),
+            connector,
+            broker_handle,
+        })
+    }
+
+    pub fn client_id(&self) -> &ClientId {
+        &self.client_id
+    }
+
+    /// Runs the bridge until the local broker drops its connection.
+    pub async fn run(self) -> Result<(), Error> {
+        let Bridge {
+            settings,
+            client_id,
+            rules,
+            connector,
+            mut broker_handle,
+        } = self;
+
+        info!(
+            "starting bridge {} to {}",
+            settings.name(),
+            settings.address()
+        );
+
+        let (sender, events) = mpsc::unbounded_channel();
+        let connect = proto::Connect {
+            username: None,
+            password: None,
+            will: None,
+            client_id: proto::ClientId::IdWithExistingSession(client_id.to_string()),
+            keep_alive: Duration::default(),
+            protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
+            protocol_level: mqtt3::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
+        };
+        let req = ConnReq::new(
+            client_id.clone(),
+            connect,
+            None,
+            ConnectionHandle::from_sender(sender),
+        )
+        .with_auth_id(Some(AuthId::from_identity(client_id.as_str())));
+        broker_handle
+            .send(Message::Client(
+                client_id.clone(),
+                ClientEvent::ConnReq(req),
+            ))
+            .await?;
+
+        let mut client = upstream_client(&settings, connector);
+        for rule in rules
+            .iter()
+            .filter(|rule| rule.settings.direction().is_in())
+        {
+            let subscribe_to = proto::SubscribeTo {
+                topic_filter: rule.settings.remote_filter(),
+                qos: rule.settings.qos(),
+            };
+            client
+                .subscribe(subscribe_to)
+                .map_err(|e| Error::BridgeClient(settings.name().to_string(), Box::new(e)))?;
+        }
+
+        let publish_handle = client
+            .publish_handle()
+            .map_err(|e| Error::BridgeClient(settings.name().to_string(), Box::new(e)))?;
+        let mut shutdown_handle = client
+            .shutdown_handle()
+            .map_err(|e| Error::BridgeClient(settings.name().to_string(), Box::new(e)))?;
+
+        let echoes = Echoes::default();
+        let upstream = tokio::spawn(upstream(
+            client,
+            client_id.clone(),
+            rules.clone(),
+            broker_handle.clone(),
+            echoes.clone(),
+        ));
+
+        let (qos0, pending) = mpsc::channel(MAX_PENDING_QOS0);
+        let forward_qos0 = tokio::spawn(forward_qos0(pending, publish_handle.clone()));
+
+        let (qos12, pending) = mpsc::unbounded_channel();
+        let forward = tokio::spawn(forward(
+            pending,
+            publish_handle,
+            client_id.clone(),
+            broker_handle.clone(),
+        ));
+
+        let result = local(events, client_id, rules, broker_handle, qos0, qos12, echoes).await;
+
+        debug!("shutting down bridge {}...", settings.name());
+        if let Err(e) = shutdown_handle.shutdown().await {
+            warn!(message = "failed to shutdown upstream client", error = %e);
+        }
+        upstream.await?;
+        forward_qos0.await?;
+        forward.await?;
+        info!("bridge {} stopped", settings.name());
+
+        result
+    }
+}
+
+fn tls_connector(settings: &BridgeSettings) -> Result<SslConnector, openssl::error::ErrorStack> {
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    if let Some(ca_bundle) = settings.ca_bundle() {
+        builder.set_ca_file(ca_bundle)?;
+    }
+
+    Ok(builder.build())
+}
+
+fn upstream_client(settings: &BridgeSettings, connector: Option<SslConnector>) -> UpstreamClient {
+    let address = settings.address().to_string();
+    let password = settings.password().map(str::to_string);
+    let io_source = move || -> IoFuture {
+        let address = address.clone();
+        let password = password.clone();
+        let connector = connector.clone();
+        Box::pin(async move {
+            let tcp = TcpStream::connect(&address).await?;
+            let io: Box<dyn UpstreamIo> = match connector {
+                Some(connector) => {
+                    let config = connector
+                        .configure()
+                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                    let tls = tokio_openssl::connect(config, host(&address), tcp)
+                        .await
+                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                    Box::new(tls)
+                }
+                None => Box::new(tcp),
+            };
+            Ok((io, password))
+        })
+    };
+
+    Client::from_state(
+        settings.client_id().to_string(),
+        settings.username().map(str::to_string),
+        None,
+        Box::new(io_source),
+        settings.max_reconnect_back_off(),
+        settings.keep_alive(),
+    )
+}
+
+/// Returns the host name of an upstream broker address to verify its certificate against.
+fn host(address: &str) -> &str {
+    let host = address.rsplitn(2, ':').nth(1).unwrap_or(address);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// Handles events the local broker sends to the bridge session.
+async fn local(
+    mut events: UnboundedReceiver<Message>,
+    client_id: ClientId,
+    rules: Arc<Vec<Rule>>,
+    mut broker_handle: BrokerHandle,
+    mut qos0: Sender<proto::Publication>,
+    qos12: UnboundedSender<Pending>,
+    echoes: Echoes,
+) -> Result<(), Error> {
+    while let Some(message) = events.recv().await {
+        let event = match message {
+            Message::Client(_, event) => event,
+            Message::System(_) => continue,
+        };
+
+        let reply = match event {
+            ClientEvent::ConnAck(connack) => match connack.return_code {
+                proto::ConnectReturnCode::Accepted => {
+                    info!("bridge {} connected to the broker", client_id);
+                    subscribe(&rules)
+                }
+                proto::ConnectReturnCode::Refused(reason) => {
+                    return Err(Error::BridgeRefused(client_id.to_string(), reason));
+                }
+            },
+            ClientEvent::SubAck(suback) => {
+                debug!("bridge {} subscribed: {:?}", client_id, suback.qos);
+                None
+            }
+            ClientEvent::PublishTo(Publish::QoS0(id, publish)) => {
+                if let Some(publication) = to_remote(&rules, &echoes, publish) {
+                    if qos0.try_send(publication).is_err() {
+                        warn!("bridge {} dropped QoS 0 publication", client_id);
+                    }
+                }
+                Some(ClientEvent::PubAck0(id))
+            }
+            ClientEvent::PublishTo(Publish::QoS12(_, publish)) => {
+                let packet_identifier_dup_qos = publish.packet_identifier_dup_qos;
+                let publication = to_remote(&rules, &echoes, publish);
+                if qos12
+                    .send((publication, packet_identifier_dup_qos))
+                    .is_err()
+                {
+                    debug!("bridge {} stopped forwarding publications", client_id);
+                }
+                None
+            }
+            ClientEvent::PubRec(pubrec) => Some(ClientEvent::PubRel(proto::PubRel {
+                packet_identifier: pubrec.packet_identifier,
+            })),
+            ClientEvent::PubRel(pubrel) => Some(ClientEvent::PubComp(proto::PubComp {
+                packet_identifier: pubrel.packet_identifier,
+            })),
+            ClientEvent::PubAck(_) | ClientEvent::PubComp(_) => None,
+            ClientEvent::Disconnect(_) | ClientEvent::DropConnection => {
+                debug!("broker dropped bridge {} connection", client_id);
+                break;
+            }
+            event => {
+                debug!("ignoring event for bridge {}: {:?}", client_id, event);
+                None
+            }
+        };
+
+        if let Some(event) = reply {
+            broker_handle
+                .send(Message::Client(client_id.clone(), event))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn subscribe(rules: &[Rule]) -> Option<ClientEvent> {
+    let subscribe_to = rules
+        .iter()
+        .filter(|rule| rule.settings.direction().is_out())
+        .map(|rule| proto::SubscribeTo {
+            topic_filter: rule.settings.local_filter(),
+            qos: rule.settings.qos(),
+        })
+        .collect::<Vec<_>>();
+
+    if subscribe_to.is_empty() {
+        return None;
+    }
+
+    proto::PacketIdentifier::new(1).map(|packet_identifier| {
+        ClientEvent::Subscribe(proto::Subscribe {
+            packet_identifier,
+            subscribe_to,
+        })
+    })
+}
+
+/// A local QoS 1 or 2 publication waiting to be forwarded upstream.
+///
+/// The publication is `None` if it isn't forwarded, but still has to be acknowledged.
+type Pending = (Option<proto::Publication>, proto::PacketIdentifierDupQoS);
+
+/// Acknowledges a publication to the local broker on behalf of the bridge session.
+async fn ack(
+    client_id: &ClientId,
+    broker_handle: &mut BrokerHandle,
+    packet_identifier_dup_qos: proto::PacketIdentifierDupQoS,
+) {
+    let event = match packet_identifier_dup_qos {
+        proto::PacketIdentifierDupQoS::AtMostOnce => return,
+        proto::PacketIdentifierDupQoS::AtLeastOnce(packet_identifier, _) => {
+            ClientEvent::PubAck(proto::PubAck { packet_identifier })
+        }
+        proto::PacketIdentifierDupQoS::ExactlyOnce(packet_identifier, _) => {
+            ClientEvent::PubRec(proto::PubRec { packet_identifier })
+        }
+    };
+
+    let message = Message::Client(client_id.clone(), event);
+    if let Err(e) = broker_handle.send(message).await {
+        warn!(message = "failed to acknowledge bridged publication", error = %e);
+    }
+}
+
+/// Rewrites a local publication to the one sent upstream.
+///
+/// Returns `None` if no rule forwards the publication upstream or
+/// the publication has been forwarded from the upstream broker.
+fn to_remote(
+    rules: &[Rule],
+    echoes: &Echoes,
+    publish: proto::Publish,
+) -> Option<proto::Publication> {
+    let topic_name = rules
+        .iter()
+        .find_map(|rule| rule.to_remote(&publish.topic_name))?;
+
+    if echoes.take(&publish.topic_name, &publish.payload) {
+        return None;
+    }
+
+    Some(proto::Publication {
+        topic_name,
+        qos: qos(publish.packet_identifier_dup_qos),
+        retain: publish.retain,
+        payload: publish.payload,
+        properties: proto::Properties::default(),
+    })
+}
+
+/// Publishes local QoS 1 and 2 publications to the upstream broker one by one,
+/// so that they are sent in the order the local broker delivered them.
+///
+/// The local broker is acknowledged once the upstream broker has acknowledged
+/// a publication, so it stays in the bridge session while the upstream is offline.
+/// If a publication can't be forwarded, the bridge connection is dropped and
+/// the broker sends the unacknowledged publications again once the bridge restarts.
+async fn forward(
+    mut pending: UnboundedReceiver<Pending>,
+    mut publish_handle: PublishHandle,
+    client_id: ClientId,
+    mut broker_handle: BrokerHandle,
+) {
+    while let Some((publication, packet_identifier_dup_qos)) = pending.recv().await {
+        if let Some(publication) = publication {
+            debug!("forwarding publication to {}", publication.topic_name);
+            if let Err(e) = publish_handle.publish(publication).await {
+                warn!(message = "failed to forward publication upstream. dropping bridge connection", error = %e);
+                let message = Message::Client(client_id.clone(), ClientEvent::DropConnection);
+                if let Err(e) = broker_handle.send(message).await {
+                    warn!(message = "failed to drop bridge connection", error = %e);
+                }
+                return;
+            }
+        }
+
+        ack(&client_id, &mut broker_handle, packet_identifier_dup_qos).await;
+    }
+}
+
+/// Publishes local QoS 0 publications to the upstream broker one by one,
+/// so that they are sent in the order the local broker delivered them.
+async fn forward_qos0(
+    mut pending: Receiver<proto::Publication>,
+    mut publish_handle: PublishHandle,
+) {
+    while let Some(publication) = pending.recv().await {
+        debug!("forwarding publication to {}", publication.topic_name);
+        if let Err(e) = publish_handle.publish(publication).await {
+            warn!(message = "failed to forward publication upstream", error = %e);
+        }
+    }
+}
+
+/// Publishes publications received from the upstream broker to the local one.
+async fn upstream(
+    mut client: UpstreamClient,
+    client_id: ClientId,
+    rules: Arc<Vec<Rule>>,
+    mut broker_handle: BrokerHandle,
+    echoes: Echoes,
+) {
+    let mut next_packet_identifier = 0_u16;
+    while let Some(event) = client.next().await {
+        match event {
+            Ok(Event::NewConnection { reset_session }) => {
+                info!(
+                    "bridge {} connected upstream. session reset: {}",
+                    client_id, reset_session
+                );
+            }
+            Ok(Event::SubscriptionUpdates(updates)) => {
+                debug!("bridge {} subscriptions updated: {:?}", client_id, updates);
+            }
+            Ok(Event::Publication(publication)) => {
+                let ReceivedPublication {
+                    topic_name,
+                    qos,
+                    retain,
+                    payload,
+                    ..
+                } = publication;
+
+                let local_topic_name = rules.iter().find_map(|rule| rule.to_local(&topic_name));
+                let topic_name = if let Some(topic_name) = local_topic_name {
+                    topic_name
+                } else {
+                    debug!("dropping publication from upstream to {}", topic_name);
+                    continue;
+                };
+
+                if rules
+                    .iter()
+                    .any(|rule| rule.to_remote(&topic_name).is_some())
+                {
+                    echoes.insert(topic_name.clone(), payload.clone());
+                }
+
+                let mut packet_identifier = || {
+                    next_packet_identifier = next_packet_identifier.wrapping_add(1).max(1);
+                    proto::PacketIdentifier::new(next_packet_identifier)
+                        .expect("packet identifier is never zero")
+                };
+                let packet_identifier_dup_qos = match qos {
+                    proto::QoS::AtMostOnce => proto::PacketIdentifierDupQoS::AtMostOnce,
+                    proto::QoS::AtLeastOnce => {
+                        proto::PacketIdentifierDupQoS::AtLeastOnce(packet_identifier(), false)
+                    }
+                    proto::QoS::ExactlyOnce => {
+                        proto::PacketIdentifierDupQoS::ExactlyOnce(packet_identifier(), false)
+                    }
+                };
+
+                let publish = proto::Publish {
+                    packet_identifier_dup_qos,
+                    retain,
+                    topic_name,
+                    payload,
+                    properties: proto::Properties::default(),
+                };
+                let message = Message::Client(client_id.clone(), ClientEvent::PublishFrom(publish));
+                if let Err(e) = broker_handle.send(message).await {
+                    warn!(message = "failed to publish bridged publication", error = %e);
+                }
+            }
+            Err(e) => {
+                warn!(message = "bridge upstream connection error", error = %e);
+            }
+        }
+    }
+}
+
+fn qos(packet_identifier_dup_qos: proto::PacketIdentifierDupQoS) -> proto::QoS {
+    match packet_identifier_dup_qos {
+        proto::PacketIdentifierDupQoS::AtMostOnce => proto::QoS::AtMostOnce,
+        proto::PacketIdentifierDupQoS::AtLeastOnce(..) => proto::QoS::AtLeastOnce,
+        proto::PacketIdentifierDupQoS::ExactlyOnce(..) => proto::QoS::ExactlyOnce,
+    }
+}
+
+struct Rule {
+    local: TopicFilter,
+    remote: TopicFilter,
+    settings: TopicRule,
+}
+
+impl Rule {
+    fn new(settings: TopicRule) -> Result<Self, Error> {
+        Ok(Self {
+            local: settings.local_filter().parse()?,
+            remote: settings.remote_filter().parse()?,
+            settings,
+        })
+    }
+
+    /// Rewrites a local topic name to the upstream one.
+    fn to_remote(&self, topic_name: &str) -> Option<String> {
+        let prefix = self.settings.local_prefix();
+        if self.settings.direction().is_out()
+            && topic_name.starts_with(prefix)
+            && self.local.matches(topic_name)
+        {
+            Some(format!(
+                "{}{}",
+                self.settings.remote_prefix(),
+                &topic_name[prefix.len()..]
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Rewrites an upstream topic name to the local one.
+    fn to_local(&self, topic_name: &str) -> Option<String> {
+        let prefix = self.settings.remote_prefix();
+        if self.settings.direction().is_in()
+            && topic_name.starts_with(prefix)
+            && self.remote.matches(topic_name)
+        {
+            Some(format!(
+                "{}{}",
+                self.settings.local_prefix(),
+                &topic_name[prefix.len()..]
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Publications forwarded from the upstream broker to the local one.
+#[derive(Clone, Default)]
+struct Echoes(Arc<Mutex<VecDeque<(String, Bytes)>>>);
+
+impl Echoes {
+    fn insert(&self, topic_name: String, payload: Bytes) {
+        let mut echoes = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if echoes.len() >= MAX_ECHOES {
+            echoes.pop_front();
+        }
+        echoes.push_back((topic_name, payload));
+    }
+
+    /// Removes a publication if it was forwarded from the upstream broker.
+    fn take(&self, topic_name: &str, payload: &Bytes) -> bool {
+        let mut echoes = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        echoes
+            .iter()
+            .position(|(t, p)| t == topic_name && p == payload)
+            .and_then(|position| echoes.remove(position))
+            .is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use serde_json::json;
+
+    use super::{host, Echoes, Rule};
+    use crate::configuration::TopicRule;
+
+    fn rule(value: serde_json::Value) -> Rule {
+        let settings: TopicRule = serde_json::from_value(value).unwrap();
+        Rule::new(settings).unwrap()
+    }
+
+    #[test]
+    fn it_rewrites_topic_prefixes() {
+        let rule = rule(json!({
+            "pattern": "telemetry/#",
+            "direction": "both",
+            "qos": 1,
+            "local_prefix": "local/",
+            "remote_prefix": "devices/child/"
+        }));
+
+        assert_eq!(
+            rule.to_remote("local/telemetry/temp"),
+            Some("devices/child/telemetry/temp".to_string())
+        );
+        assert_eq!(
+            rule.to_local("devices/child/telemetry/temp"),
+            Some("local/telemetry/temp".to_string())
+        );
+        assert_eq!(rule.to_remote("telemetry/temp"), None);
+        assert_eq!(rule.to_local("devices/other/telemetry/temp"), None);
+    }
+
+    #[test]
+    fn it_forwards_only_configured_direction() {
+        let rule = rule(json!({
+            "pattern": "commands/+",
+            "direction": "in",
+            "qos": 0
+        }));
+
+        assert_eq!(
+            rule.to_local("commands/reboot"),
+            Some("commands/reboot".to_string())
+        );
+        assert_eq!(rule.to_remote("commands/reboot"), None);
+    }
+
+    #[test]
+    fn it_takes_host_of_address() {
+        assert_eq!(host("parent:8883"), "parent");
+        assert_eq!(host("[::1]:8883"), "::1");
+        assert_eq!(host("parent"), "parent");
+    }
+
+    #[test]
+    fn it_takes_echo_once() {
+        let echoes = Echoes::default();
+        echoes.insert("commands/reboot".into(), Bytes::from("now"));
+
+        assert!(!echoes.take("commands/reboot", &Bytes::from("later")));
+        assert!(echoes.take("commands/reboot", &Bytes::from("now")));
+        assert!(!echoes.take("commands/reboot", &Bytes::from("now")));
+    }
+}
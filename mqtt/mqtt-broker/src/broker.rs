@@ -1,24 +1,36 @@
-use std::collections::HashMap;
-use std::convert::TryInto;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::{TryFrom, TryInto};
 use std::panic;
+use std::time::{Duration, SystemTime};
 
 use mqtt3::proto;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info, span, warn, Level};
 
+use crate::admin::{AdminError, AdminRequest, SessionInfo, SessionStatus};
 use crate::auth::{
     Activity, Authenticator, Authorizer, Credentials, DefaultAuthenticator, DefaultAuthorizer,
     Operation,
 };
-use crate::session::{ConnectedSession, Session, SessionState};
+use crate::bridge::BRIDGE_CLIENT_ID_PREFIX;
+use crate::rate_limit::RateLimiter;
+use crate::session::{ConnectedSession, Session, SessionConfig, SessionState};
 use crate::state_change::StateChange;
+use crate::stats::{Stats, StatsHandle};
+use crate::subscription::TopicFilter;
+use crate::subscription_trie::SubscriptionTrie;
+use crate::wal::Journal;
 use crate::{
-    subscription::Subscription, AuthId, ClientEvent, ClientId, ConnReq, Error, Message, SystemEvent,
+    subscription::{is_shared, Subscription},
+    AuthId, BrokerConfig, ClientEvent, ClientId, ConnReq, Error, Message, SystemEvent,
 };
 use tokio::sync::mpsc::{self, Receiver, Sender};
 
 static EXPECTED_PROTOCOL_NAME: &str = mqtt3::PROTOCOL_NAME;
-const EXPECTED_PROTOCOL_LEVEL: u8 = mqtt3::PROTOCOL_LEVEL;
+const SUPPORTED_PROTOCOL_LEVELS: [u8; 2] = [mqtt3::PROTOCOL_LEVEL, mqtt3::PROTOCOL_LEVEL_V5];
+
+/// The number of topic aliases an MQTT 5 client may use when publishing to the broker.
+pub(crate) const TOPIC_ALIAS_MAXIMUM: u16 = 64;
 
 macro_rules! try_send {
     ($session:expr, $msg:expr) => {{
@@ -32,9 +44,15 @@ pub struct Broker<N, Z> {
     sender: Sender<Message>,
     messages: Receiver<Message>,
     sessions: HashMap<ClientId, Session>,
-    retained: HashMap<String, proto::Publication>,
+    subscriptions: SubscriptionTrie,
+    retained: HashMap<String, RetainedPublication>,
     authenticator: N,
     authorizer: Z,
+    config: BrokerConfig,
+    journal: Option<Journal>,
+    stats: Stats,
+    shared_cursors: HashMap<String, usize>,
+    rate_limiters: HashMap<ClientId, RateLimiter>,
 
     #[cfg(feature = "__internal_broker_callbacks")]
     pub on_publish: Option<tokio::sync::mpsc::UnboundedSender<std::time::Duration>>,
@@ -49,6 +67,14 @@ where
         BrokerHandle(self.sender.clone())
     }
 
+    pub fn stats_handle(&self) -> StatsHandle {
+        self.stats.handle()
+    }
+
+    pub(crate) fn config(&self) -> &BrokerConfig {
+        &self.config
+    }
+
     pub async fn run(mut self) -> Result<BrokerState, Error> {
         while let Some(message) = self.messages.recv().await {
             match message {
@@ -71,6 +97,51 @@ where
                             }
                             break;
                         }
+                        SystemEvent::SessionCleanup => {
+                            debug!("removing expired offline sessions and retained messages...");
+                            if let Err(e) = self.process_session_cleanup() {
+                                warn!(message = "an error occurred removing expired sessions", error = %e);
+                            }
+                        }
+                        SystemEvent::PublishStats => {
+                            debug!("publishing broker statistics...");
+                            if let Err(e) = self.process_publish_stats() {
+                                warn!(message = "an error occurred publishing statistics", error = %e);
+                            }
+                        }
+                        SystemEvent::JournalFlush => {
+                            debug!("flushing journal...");
+                            self.flush_journal(true);
+                        }
+                        SystemEvent::JournalCheckpoint => {
+                            debug!("checkpointing journal...");
+                            if let Some(journal) = &mut self.journal {
+                                journal.checkpoint();
+                            }
+                            self.flush_journal(true);
+                        }
+                        SystemEvent::JournalPersisted(id) => {
+                            if let Some(journal) = &mut self.journal {
+                                let acks = journal.persisted(id);
+                                self.send_acks(acks);
+                            }
+                            self.flush_journal(false);
+                        }
+                        SystemEvent::JournalFailed => {
+                            error!("journal failed. QoS 1 and QoS 2 publications are refused from now on");
+                            if let Some(journal) = &mut self.journal {
+                                let client_ids = journal.fail();
+                                self.drop_unacked(client_ids);
+                            }
+                        }
+                        SystemEvent::Admin(request) => {
+                            debug!("handling admin request...");
+                            self.process_admin(request);
+                        }
+                        SystemEvent::ConfigUpdate(config) => {
+                            info!("applying configuration update...");
+                            self.process_config_update(config);
+                        }
                         SystemEvent::StateSnapshot(mut handle) => {
                             let state = self.snapshot();
                             let _guard = span.enter();
@@ -92,11 +163,18 @@ where
 
     fn snapshot(&self) -> BrokerState {
         let retained = self.retained.clone();
+        let now = SystemTime::now();
         let sessions = self
             .sessions
             .values()
             .filter_map(|session| match session {
-                Session::Persistent(c) => Some(c.state().clone()),
+                // A connected client is seen at the time of the snapshot,
+                // so its session doesn't expire right after a restart.
+                Session::Persistent(c) => {
+                    let mut state = c.state().clone();
+                    state.set_last_seen(now);
+                    Some(state)
+                }
                 Session::Offline(o) => Some(o.state().clone()),
                 _ => None,
             })
@@ -128,8 +206,11 @@ where
         event: ClientEvent,
     ) -> Result<(), Error> {
         debug!("incoming: {:?}", event);
+        let changes_session = changes_session(&event);
+        let was_persisted = self.sessions.get(&client_id).map_or(false, is_persisted);
+
         let result = match event {
-            ClientEvent::ConnReq(connreq) => self.process_connect(client_id, connreq),
+            ClientEvent::ConnReq(connreq) => self.process_connect(client_id.clone(), connreq),
             ClientEvent::ConnAck(_) => {
                 info!("broker received CONNACK, ignoring");
                 Ok(())
@@ -170,9 +251,53 @@ where
             warn!(message = "error processing message", %e);
         }
 
+        if let Some(journal) = &mut self.journal {
+            let is_persisted = self.sessions.get(&client_id).map_or(false, is_persisted);
+            if changes_session && (was_persisted || is_persisted) {
+                journal.session_changed(&client_id);
+            }
+        }
+        self.flush_journal(false);
+
         Ok(())
     }
 
+    /// Sends the changes recorded by the journal to the journal writer.
+    ///
+    /// Unless `force` is set, this only happens when the journal asks for it.
+    fn flush_journal(&mut self, force: bool) {
+        if let Some(journal) = &mut self.journal {
+            if force || journal.should_flush() {
+                let client_ids = journal.flush(&self.sessions, &self.retained);
+                self.drop_unacked(client_ids);
+            }
+        }
+    }
+
+    /// Disconnects the clients waiting for acknowledgements that can't be persisted anymore.
+    fn drop_unacked(&mut self, client_ids: Vec<ClientId>) {
+        for client_id in client_ids {
+            warn!(
+                "dropping {} as its publications can't be persisted",
+                client_id
+            );
+            if let Err(e) = self.drop_connection(&client_id) {
+                warn!(message = "error dropping connection", error = %e);
+            }
+        }
+    }
+
+    fn send_acks(&mut self, acks: Vec<(ClientId, ClientEvent)>) {
+        for (client_id, event) in acks {
+            match self.sessions.get_mut(&client_id) {
+                Some(Session::Offline(_)) | None => {
+                    debug!("no connected session for {}", client_id);
+                }
+                Some(session) => try_send!(session, event),
+            }
+        }
+    }
+
     fn process_shutdown(&mut self) -> Result<(), Error> {
         let mut sessions = vec![];
         let client_ids = self.sessions.keys().cloned().collect::<Vec<ClientId>>();
@@ -200,6 +325,7 @@ where
                 let ack = proto::ConnAck {
                     session_present: false,
                     return_code: proto::ConnectReturnCode::Refused($reason),
+                    properties: proto::Properties::default(),
                 };
 
                 debug!("sending connack with: {:?}", ack.return_code);
@@ -235,7 +361,7 @@ where
         // with a CONNACK return code 0x01 (unacceptable protocol level)
         // and then disconnect the Client if the Protocol Level is not supported
         // by the Server.
-        if connreq.connect().protocol_level != EXPECTED_PROTOCOL_LEVEL {
+        if !SUPPORTED_PROTOCOL_LEVELS.contains(&connreq.connect().protocol_level) {
             warn!(
                 "invalid protocol level received from client: {}",
                 connreq.connect().protocol_level
@@ -244,35 +370,54 @@ where
             return Ok(());
         }
 
+        // Bridges connect in-process with their auth id already set, so a client
+        // with a bridge client id connecting over the network is refused
+        // instead of taking over the bridge session.
+        if client_id.as_str().starts_with(BRIDGE_CLIENT_ID_PREFIX) && connreq.auth_id().is_none() {
+            warn!("client id {} is reserved for bridges", client_id);
+            refuse_connection!(proto::ConnectionRefusedReason::IdentifierRejected);
+            return Ok(());
+        }
+
         // [MQTT-3.1.4-3] - The Server MAY check that the contents of the CONNECT
         // Packet meet any further restrictions and MAY perform authentication
         // and authorization checks. If any of these checks fail, it SHOULD send an
         // appropriate CONNACK response with a non-zero return code as described in
         // section 3.2 and it MUST close the Network Connection.
-        let credentials = connreq.certificate().map_or(
-            Credentials::Basic(
-                connreq.connect().username.clone(),
-                connreq.connect().password.clone(),
-            ),
-            |certificate| Credentials::ClientCertificate(certificate.clone()),
-        );
-        let auth_id = match self.authenticator.authenticate(credentials) {
-            Ok(Some(auth_id)) => {
-                debug!(
-                    "client {} successfully authenticated: {}",
-                    client_id, auth_id
-                );
-                auth_id
-            }
-            Ok(None) => {
-                warn!("unable to authenticate client: {}", client_id);
-                refuse_connection!(proto::ConnectionRefusedReason::BadUserNameOrPassword);
-                return Ok(());
-            }
-            Err(e) => {
-                warn!(message = "error authenticating client: {}", error = %e);
-                refuse_connection!(proto::ConnectionRefusedReason::ServerUnavailable);
-                return Ok(());
+        let auth_id = if let Some(auth_id) = connreq.auth_id() {
+            debug!("client {} is already authenticated: {}", client_id, auth_id);
+            auth_id.clone()
+        } else {
+            let credentials = match (connreq.certificate(), connreq.peer_credentials()) {
+                (Some(certificate), _) => Credentials::ClientCertificate(certificate.clone()),
+                (None, Some(peer_credentials)) => Credentials::PeerCredentials(
+                    connreq.connect().username.clone(),
+                    *peer_credentials,
+                ),
+                (None, None) => Credentials::Basic(
+                    connreq.connect().username.clone(),
+                    connreq.connect().password.clone(),
+                ),
+            };
+
+            match self.authenticator.authenticate(credentials) {
+                Ok(Some(auth_id)) => {
+                    debug!(
+                        "client {} successfully authenticated: {}",
+                        client_id, auth_id
+                    );
+                    auth_id
+                }
+                Ok(None) => {
+                    warn!("unable to authenticate client: {}", client_id);
+                    refuse_connection!(proto::ConnectionRefusedReason::BadUserNameOrPassword);
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!(message = "error authenticating client: {}", error = %e);
+                    refuse_connection!(proto::ConnectionRefusedReason::ServerUnavailable);
+                    return Ok(());
+                }
             }
         };
 
@@ -295,10 +440,37 @@ where
             }
         }
 
+        // MQTT 5 clients learn the negotiated limits and any assigned client id from the CONNACK
+        let connack_properties = if connreq.connect().protocol_level == mqtt3::PROTOCOL_LEVEL_V5 {
+            let assigned_client_identifier = match connreq.connect().client_id {
+                proto::ClientId::ServerGenerated => Some(client_id.to_string()),
+                _ => None,
+            };
+            let maximum_packet_size = self
+                .config
+                .limits()
+                .max_packet_size()
+                .map(|size| u32::try_from(size).unwrap_or(u32::MAX));
+            connack_properties_v5(assigned_client_identifier, maximum_packet_size)
+        } else {
+            proto::Properties::default()
+        };
+
+        let rate_limits = self
+            .config
+            .limits()
+            .rate_limits_for(client_id.as_str(), Some(&auth_id));
+        let max_message_size = self.config.session().messages().max_message_size();
+        let rate_limiter = self.rate_limiters.entry(client_id.clone()).or_default();
+        rate_limiter.set_limits(rate_limits, max_message_size);
+        connreq.handle().rate_limiter().assign(rate_limiter.clone());
+
         // Process the CONNECT packet after it has been validated
         // TODO - fix ConnAck return_code != accepted to not add session to sessions map
         match self.open_session(auth_id, connreq)? {
-            OpenSession::OpenedSession(ack, events) => {
+            OpenSession::OpenedSession(mut ack, events) => {
+                ack.properties = connack_properties;
+
                 // Send ConnAck on new session
                 let session = self
                     .get_session_mut(&client_id)
@@ -311,7 +483,9 @@ where
 
                 self.publish_all(StateChange::new_connection_change(&self.sessions).try_into()?)?;
             }
-            OpenSession::DuplicateSession(mut old_session, ack) => {
+            OpenSession::DuplicateSession(mut old_session, mut ack) => {
+                ack.properties = connack_properties;
+
                 // Drop the old connection
                 old_session.send(ClientEvent::DropConnection)?;
 
@@ -366,6 +540,201 @@ where
         Ok(())
     }
 
+    fn process_publish_stats(&mut self) -> Result<(), Error> {
+        let mut clients_connected = 0;
+        let mut clients_offline = 0;
+        let mut queued_messages = 0;
+        let mut inflight_messages = 0;
+
+        for session in self.sessions.values_mut() {
+            match session {
+                Session::Transient(_) | Session::Persistent(_) => clients_connected += 1,
+                Session::Offline(_) => clients_offline += 1,
+                Session::Disconnecting(_) => (),
+            }
+
+            if let Some(state) = session.state_mut() {
+                queued_messages += state.queued_count();
+                inflight_messages += state.inflight_count();
+
+                let (sent, dropped) = state.take_message_counts();
+                self.stats.messages_sent(sent);
+                self.stats.messages_dropped(dropped);
+            }
+        }
+
+        let (stats, previous) = self.stats.sample(
+            clients_connected,
+            clients_offline,
+            self.retained.len(),
+            queued_messages,
+            inflight_messages,
+        );
+
+        for publication in stats.publications(previous.as_ref()) {
+            self.publish_all(publication)?;
+        }
+
+        Ok(())
+    }
+
+    fn process_session_cleanup(&mut self) -> Result<(), Error> {
+        // A full rate limiter is no different from a new one, unless it is in use.
+        let sessions = &self.sessions;
+        self.rate_limiters.retain(|client_id, rate_limiter| {
+            let connected = matches!(
+                sessions.get(client_id),
+                Some(Session::Transient(_)) | Some(Session::Persistent(_))
+            );
+            connected || !rate_limiter.is_full()
+        });
+
+        let now = SystemTime::now();
+        self.remove_expired_retained(now);
+
+        let expiration = self.config.session().expiration();
+        let expired = self
+            .sessions
+            .iter()
+            .filter_map(|(client_id, session)| match session {
+                Session::Offline(offline) => {
+                    match now.duration_since(offline.state().last_seen()) {
+                        Ok(idle) if idle >= expiration => Some(client_id.clone()),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        for client_id in &expired {
+            info!("removing expired offline session for {}", client_id);
+            self.sessions.remove(client_id);
+            self.update_subscriptions(client_id);
+            if let Some(journal) = &mut self.journal {
+                journal.session_changed(client_id);
+            }
+            self.publish_all(StateChange::new_subscription_change(client_id, None).try_into()?)?;
+        }
+        self.publish_all(StateChange::new_session_change(&self.sessions).try_into()?)?;
+
+        Ok(())
+    }
+
+    /// Replaces the broker configuration, applying the new session and rate limits
+    /// to every existing session without disconnecting its client.
+    fn process_config_update(&mut self, config: BrokerConfig) {
+        self.config = config;
+
+        let max_message_size = self.config.session().messages().max_message_size();
+        for (client_id, rate_limiter) in &self.rate_limiters {
+            let auth_id = self
+                .sessions
+                .get(client_id)
+                .and_then(|session| session.auth_id().ok());
+            let limits = self
+                .config
+                .limits()
+                .rate_limits_for(client_id.as_str(), auth_id);
+            rate_limiter.set_limits(limits, max_message_size);
+        }
+
+        for session in self.sessions.values_mut() {
+            let client_id = session.client_id().clone();
+            let auth_id = session.auth_id().ok().cloned();
+
+            if let Some(state) = session.state_mut() {
+                let config = SessionConfig::for_client(&self.config, &client_id, auth_id.as_ref());
+                state.set_config(config);
+            }
+        }
+    }
+
+    fn process_admin(&mut self, request: AdminRequest) {
+        // the requester may have given up on the response, which is fine
+        match request {
+            AdminRequest::Sessions(reply) => {
+                let _ = reply.send(self.session_infos());
+            }
+            AdminRequest::DeleteSession(client_id, reply) => {
+                let _ = reply.send(self.delete_session(&client_id));
+            }
+            AdminRequest::ClearRetained(topic_filter, reply) => {
+                let _ = reply.send(self.clear_retained(&topic_filter));
+            }
+        }
+    }
+
+    fn session_infos(&self) -> Vec<SessionInfo> {
+        let mut infos = self
+            .sessions
+            .iter()
+            .filter_map(|(client_id, session)| {
+                let status = match session {
+                    Session::Transient(_) | Session::Persistent(_) => SessionStatus::Connected,
+                    Session::Offline(_) => SessionStatus::Offline,
+                    Session::Disconnecting(_) => return None,
+                };
+                let state = session.state()?;
+                let subscriptions = state
+                    .subscriptions()
+                    .iter()
+                    .map(|(topic_filter, sub)| (topic_filter.clone(), u8::from(*sub.max_qos())))
+                    .collect();
+                Some(SessionInfo::new(
+                    client_id.clone(),
+                    status,
+                    subscriptions,
+                    state.queued_count(),
+                    state.inflight_count(),
+                ))
+            })
+            .collect::<Vec<_>>();
+        infos.sort_by(|a, b| a.client_id().as_str().cmp(b.client_id().as_str()));
+        infos
+    }
+
+    fn delete_session(&mut self, client_id: &ClientId) -> Result<(), AdminError> {
+        match self.sessions.get(client_id) {
+            Some(Session::Offline(_)) => (),
+            Some(Session::Disconnecting(_)) | None => {
+                return Err(AdminError::NoSession(client_id.clone()))
+            }
+            Some(_) => return Err(AdminError::SessionConnected(client_id.clone())),
+        }
+
+        info!("removing offline session for {}", client_id);
+        self.sessions.remove(client_id);
+        self.update_subscriptions(client_id);
+        if let Some(journal) = &mut self.journal {
+            journal.session_changed(client_id);
+        }
+        self.publish_all(StateChange::new_subscription_change(client_id, None).try_into()?)?;
+        self.publish_all(StateChange::new_session_change(&self.sessions).try_into()?)?;
+        Ok(())
+    }
+
+    fn clear_retained(&mut self, topic_filter: &str) -> Result<usize, AdminError> {
+        let filter = topic_filter.parse::<TopicFilter>()?;
+        let count = self.retained.len();
+        let journal = &mut self.journal;
+        self.retained.retain(|topic, _| {
+            let matches = filter.matches(topic);
+            if matches {
+                info!("removing retained message for topic \"{}\"", topic);
+                if let Some(journal) = journal {
+                    journal.retained_changed(topic);
+                }
+            }
+            !matches
+        });
+        Ok(count - self.retained.len())
+    }
+
     fn process_close_session(&mut self, client_id: &ClientId) -> Result<(), Error> {
         debug!("handling close session...");
         if let Some(session) = self.close_session(client_id)? {
@@ -410,11 +779,14 @@ where
             debug!("no session for {}", client_id);
             return Ok(());
         };
+        self.update_subscriptions(client_id);
 
         // Handle retained messages
+        self.remove_expired_retained(SystemTime::now());
         let publications = self
             .retained
             .values()
+            .map(RetainedPublication::publication)
             .filter(|p| {
                 subscriptions
                     .iter()
@@ -424,14 +796,29 @@ where
             .collect::<Vec<proto::Publication>>();
 
         if let Some(session) = self.sessions.get_mut(client_id) {
+            let mut queue_full = false;
             for mut publication in publications {
                 publication.retain = true;
-                publish_to(&self.authorizer, session, &publication)?;
+                match publish_to(&self.authorizer, session, None, &publication) {
+                    Err(Error::SessionQueueFull) => {
+                        queue_full = true;
+                        break;
+                    }
+                    result => result?,
+                }
             }
 
             let change =
                 StateChange::new_subscription_change(client_id, Some(&session)).try_into()?;
             self.publish_all(change)?;
+
+            if queue_full {
+                info!(
+                    "dropping connection for {} due to a full session queue",
+                    client_id
+                );
+                self.drop_connection(client_id)?;
+            }
         } else {
             debug!("no session for {}", client_id);
         }
@@ -451,6 +838,7 @@ where
 
                 let change =
                     StateChange::new_subscription_change(client_id, Some(&session)).try_into()?;
+                self.update_subscriptions(client_id);
                 self.publish_all(change)?;
 
                 Ok(())
@@ -491,16 +879,31 @@ where
         client_id: &ClientId,
         publish: proto::Publish,
     ) -> Result<(), Error> {
+        if self.journal.as_ref().map_or(false, Journal::is_failed)
+            && publish.packet_identifier_dup_qos != proto::PacketIdentifierDupQoS::AtMostOnce
+        {
+            warn!(
+                "client {} published with QoS > 0 which can't be persisted anymore",
+                client_id
+            );
+            return self.drop_connection(client_id);
+        }
+
         let operation = Operation::new_publish(publish.clone());
         if let Some(session) = self.sessions.get_mut(client_id) {
             let activity = Activity::new(session.auth_id()?.clone(), client_id.clone(), operation);
             match self.authorizer.authorize(activity) {
                 Ok(true) => {
                     debug!("client {} successfully authorized", client_id);
+                    self.stats.message_received();
                     let (maybe_publication, maybe_event) = session.handle_publish(publish)?;
 
+                    // Acknowledge the publication only once it is persisted
                     if let Some(event) = maybe_event {
-                        session.send(event)?;
+                        match &mut self.journal {
+                            Some(journal) => journal.defer(client_id.clone(), event),
+                            None => session.send(event)?,
+                        }
                     }
 
                     if let Some(publication) = maybe_publication {
@@ -650,7 +1053,8 @@ where
                 let (new_session, events, session_present) =
                     if let proto::ClientId::IdWithExistingSession(_) = connreq.connect().client_id {
                         debug!("moving offline session to online for {}", client_id);
-                        if let Ok((state, events)) = offline.into_online() {
+                        let config = self.session_config(&client_id, &auth_id);
+                        if let Ok((state, events)) = offline.into_online(config) {
                             let new_session = Session::new_persistent(auth_id, connreq, state);
                             (new_session, events, true)
                         } else {
@@ -660,15 +1064,17 @@ where
                         }
                     } else {
                         info!("cleaning offline session for {}", client_id);
-                        let new_session = Session::new_transient(auth_id, connreq);
+                        let config = self.session_config(&client_id, &auth_id);
+                        let new_session = Session::new_transient(auth_id, connreq, config);
                         (new_session, vec![], false)
                     };
 
-                self.sessions.insert(client_id, new_session);
+                self.sessions.insert(client_id.clone(), new_session);
 
                 let ack = proto::ConnAck {
                     session_present,
                     return_code: proto::ConnectReturnCode::Accepted,
+                    properties: proto::Properties::default(),
                 };
 
                 OpenSession::OpenedSession(ack, events)
@@ -682,11 +1088,13 @@ where
                     connreq.connect().client_id
                 {
                     info!("creating new persistent session for {}", client_id);
-                    let state = SessionState::new(client_id.clone());
+                    let config = self.session_config(&client_id, &auth_id);
+                    let state = SessionState::new(client_id.clone(), config);
                     Session::new_persistent(auth_id, connreq, state)
                 } else {
                     info!("creating new transient session for {}", client_id);
-                    Session::new_transient(auth_id, connreq)
+                    let config = self.session_config(&client_id, &auth_id);
+                    Session::new_transient(auth_id, connreq, config)
                 };
 
                 let subscription_change =
@@ -697,6 +1105,7 @@ where
                 let ack = proto::ConnAck {
                     session_present: false,
                     return_code: proto::ConnectReturnCode::Accepted,
+                    properties: proto::Properties::default(),
                 };
                 let events = vec![];
 
@@ -706,6 +1115,7 @@ where
                 OpenSession::OpenedSession(ack, events)
             }
         };
+        self.update_subscriptions(&client_id);
 
         Ok(session)
     }
@@ -739,7 +1149,7 @@ where
             );
 
             let client_id = connreq.client_id().clone();
-            let (auth_id_, state, _will, handle) = current_connected.into_parts();
+            let (auth_id_, mut state, _will, handle) = current_connected.into_parts();
             let old_session = Session::new_disconnecting(auth_id_, client_id.clone(), None, handle);
             let (new_session, session_present) =
                 if let proto::ClientId::IdWithExistingSession(_) = connreq.connect().client_id {
@@ -747,11 +1157,13 @@ where
                         "moving persistent session to this connection for {}",
                         client_id
                     );
+                    state.set_config(self.session_config(&client_id, &auth_id));
                     let new_session = Session::new_persistent(auth_id, connreq, state);
                     (new_session, true)
                 } else {
                     info!("cleaning session for {}", client_id);
-                    let new_session = Session::new_transient(auth_id, connreq);
+                    let config = self.session_config(&client_id, &auth_id);
+                    let new_session = Session::new_transient(auth_id, connreq, config);
                     (new_session, false)
                 };
 
@@ -759,12 +1171,26 @@ where
             let ack = proto::ConnAck {
                 session_present,
                 return_code: proto::ConnectReturnCode::Accepted,
+                properties: proto::Properties::default(),
             };
 
             OpenSession::DuplicateSession(old_session, ack)
         }
     }
 
+    /// Keeps the subscription index in line with the session of a client.
+    fn update_subscriptions(&mut self, client_id: &ClientId) {
+        let subscriptions = self
+            .sessions
+            .get(client_id)
+            .and_then(Session::subscriptions);
+        self.subscriptions.update(client_id, subscriptions);
+    }
+
+    fn session_config(&self, client_id: &ClientId, auth_id: &AuthId) -> SessionConfig {
+        SessionConfig::for_client(&self.config, client_id, Some(auth_id))
+    }
+
     fn close_session(&mut self, client_id: &ClientId) -> Result<Option<Session>, Error> {
         let new_session = match self.sessions.remove(client_id) {
             Some(Session::Transient(connected)) => {
@@ -775,7 +1201,11 @@ where
                     StateChange::new_subscription_change(client_id, None).try_into()?,
                 )?;
 
-                let (auth_id, _state, will, handle) = connected.into_parts();
+                let (auth_id, mut state, will, handle) = connected.into_parts();
+                let (sent, dropped) = state.take_message_counts();
+                self.stats.messages_sent(sent);
+                self.stats.messages_dropped(dropped);
+                self.redeliver_shared(client_id, &mut state)?;
                 Some(Session::new_disconnecting(
                     auth_id,
                     client_id.clone(),
@@ -791,7 +1221,9 @@ where
                 info!("moving persistent session to offline for {}", client_id);
                 self.publish_all(StateChange::new_connection_change(&self.sessions).try_into()?)?;
 
-                let (auth_id, state, will, handle) = connected.into_parts();
+                let (auth_id, mut state, will, handle) = connected.into_parts();
+                state.set_last_seen(SystemTime::now());
+                self.redeliver_shared(client_id, &mut state)?;
                 let new_session = Session::new_offline(state);
                 self.sessions.insert(client_id.clone(), new_session);
                 Some(Session::new_disconnecting(
@@ -809,10 +1241,54 @@ where
             }
             _ => None,
         };
+        self.update_subscriptions(client_id);
 
         Ok(new_session)
     }
 
+    fn store_retained(&mut self, publication: proto::Publication) {
+        let now = SystemTime::now();
+        if !self.retained.contains_key(&publication.topic_name) {
+            let max_count = self.config.retained_messages().max_count() as usize;
+            if self.retained.len() >= max_count {
+                self.remove_expired_retained(now);
+            }
+
+            if self.retained.len() >= max_count {
+                warn!(
+                    "retained messages limit of {} reached. dropping retained message for topic \"{}\"",
+                    max_count, publication.topic_name
+                );
+                return;
+            }
+
+            info!(
+                "new retained message for topic \"{}\"",
+                publication.topic_name
+            );
+        }
+
+        self.retained.insert(
+            publication.topic_name.clone(),
+            RetainedPublication::new(publication, now),
+        );
+    }
+
+    fn remove_expired_retained(&mut self, now: SystemTime) {
+        let expiration = self.config.retained_messages().expiration();
+        let journal = &mut self.journal;
+        self.retained.retain(|topic, retained| {
+            let expired = retained.is_expired(expiration, now);
+            if expired {
+                info!("retained message for topic \"{}\" expired", topic);
+                if let Some(journal) = journal {
+                    journal.retained_changed(topic);
+                }
+            }
+            !expired
+        });
+    }
+
     fn publish_all(&mut self, mut publication: proto::Publication) -> Result<(), Error> {
         if publication.retain {
             // [MQTT-3.3.1-6]. If the Server receives a QoS 0 message with the
@@ -830,15 +1306,11 @@ where
                 );
                 self.retained.remove(&publication.topic_name);
             } else {
-                let maybe_retained = self
-                    .retained
-                    .insert(publication.topic_name.to_owned(), publication.clone());
-                if maybe_retained.is_none() {
-                    info!(
-                        "new retained message for topic \"{}\"",
-                        publication.topic_name
-                    );
-                }
+                self.store_retained(publication.clone());
+            }
+
+            if let Some(journal) = &mut self.journal {
+                journal.retained_changed(&publication.topic_name);
             }
         }
 
@@ -848,11 +1320,161 @@ where
         // This will not happen here.
         publication.retain = false;
 
-        for session in self.sessions.values_mut() {
-            if let Err(e) = publish_to(&self.authorizer, session, &publication) {
+        let (subscribers, shares) = self.subscribers(&publication.topic_name, None);
+
+        let mut full_sessions = vec![];
+        for client_id in subscribers {
+            let session = match self.sessions.get_mut(&client_id) {
+                Some(session) => session,
+                None => continue,
+            };
+            if let Some(journal) = &mut self.journal {
+                if is_persisted(session) {
+                    journal.session_changed(&client_id);
+                }
+            }
+
+            match publish_to(&self.authorizer, session, None, &publication) {
+                Ok(()) => (),
+                Err(Error::SessionQueueFull) => full_sessions.push(client_id),
+                Err(e) => warn!(message = "error processing message", error = %e),
+            }
+        }
+
+        for (topic_filter, members) in shares {
+            if let Some(client_id) = self.publish_shared(&topic_filter, members, &publication) {
+                full_sessions.push(client_id);
+            }
+        }
+
+        for client_id in full_sessions {
+            info!(
+                "dropping connection for {} due to a full session queue",
+                client_id
+            );
+            self.drop_connection(&client_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Looks up the clients subscribed to a topic name, leaving out the
+    /// `excluded` client.
+    ///
+    /// Members of shared subscriptions are grouped by that subscription,
+    /// apart from the clients with a matching non-shared subscription.
+    fn subscribers(
+        &self,
+        topic_name: &str,
+        excluded: Option<&ClientId>,
+    ) -> (HashSet<ClientId>, BTreeMap<String, Vec<ClientId>>) {
+        let mut subscribers = HashSet::new();
+        let mut shares = BTreeMap::new();
+        for (client_id, topic_filter) in self.subscriptions.matches(topic_name) {
+            if excluded == Some(client_id) {
+                continue;
+            }
+
+            if is_shared(topic_filter) {
+                shares
+                    .entry(topic_filter.to_owned())
+                    .or_insert_with(Vec::new)
+                    .push(client_id.clone());
+            } else {
+                subscribers.insert(client_id.clone());
+            }
+        }
+        (subscribers, shares)
+    }
+
+    /// Delivers a publication to a single member of a shared subscription.
+    ///
+    /// Members are picked round-robin among the connected ones, falling back
+    /// to the offline ones if no member is connected. Returns the client of
+    /// the chosen member if its session queue is full.
+    fn publish_shared(
+        &mut self,
+        topic_filter: &str,
+        mut members: Vec<ClientId>,
+        publication: &proto::Publication,
+    ) -> Option<ClientId> {
+        let sessions = &self.sessions;
+        let connected = |client_id: &ClientId| {
+            sessions.get(client_id).map_or(
+                false,
+                |session| matches!(session, Session::Transient(_) | Session::Persistent(_)),
+            )
+        };
+        if members.iter().any(connected) {
+            members.retain(connected);
+        }
+        if members.is_empty() {
+            return None;
+        }
+        members.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+        let cursor = self
+            .shared_cursors
+            .entry(topic_filter.to_owned())
+            .or_default();
+        let client_id = members.swap_remove(*cursor % members.len());
+        *cursor = cursor.wrapping_add(1);
+
+        let session = self.sessions.get_mut(&client_id)?;
+        if let Some(journal) = &mut self.journal {
+            if is_persisted(session) {
+                journal.session_changed(&client_id);
+            }
+        }
+        match publish_to(&self.authorizer, session, Some(topic_filter), publication) {
+            Ok(()) => None,
+            Err(Error::SessionQueueFull) => Some(client_id),
+            Err(e) => {
                 warn!(message = "error processing message", error = %e);
+                None
             }
         }
+    }
+
+    /// Hands the publications a closing session holds for its shared
+    /// subscriptions over to the other members of each share.
+    fn redeliver_shared(
+        &mut self,
+        client_id: &ClientId,
+        state: &mut SessionState,
+    ) -> Result<(), Error> {
+        let sessions = &self.sessions;
+        let shared = state.take_shared_publications(|topic_filter| {
+            sessions.iter().any(|(id, session)| {
+                id != client_id
+                    && session.subscriptions().map_or(false, |subscriptions| {
+                        subscriptions.contains_key(topic_filter)
+                    })
+            })
+        });
+
+        let mut full_sessions = vec![];
+        for (topic_filter, publication) in shared {
+            debug!(
+                "redelivering message for shared subscription {} of {}",
+                topic_filter, client_id
+            );
+            let (_, mut shares) = self.subscribers(&publication.topic_name, Some(client_id));
+            let members = shares.remove(&topic_filter);
+            if let Some(members) = members {
+                if let Some(full) = self.publish_shared(&topic_filter, members, &publication) {
+                    full_sessions.push(full);
+                }
+            }
+        }
+
+        for client_id in full_sessions {
+            info!(
+                "dropping connection for {} due to a full session queue",
+                client_id
+            );
+            self.drop_connection(&client_id)?;
+        }
 
         Ok(())
     }
@@ -881,18 +1503,22 @@ where
 
     for auth in auth_results {
         let ack_qos = match auth {
-            Ok((true, subscribe_to)) => match session.subscribe_to(subscribe_to) {
-                Ok((qos, subscription)) => {
-                    if let Some(subscription) = subscription {
-                        subscriptions.push(subscription);
+            Ok((true, subscribe_to)) => {
+                // Retained messages are not sent for a new shared subscription
+                let shared = is_shared(&subscribe_to.topic_filter);
+                match session.subscribe_to(subscribe_to) {
+                    Ok((qos, subscription)) => {
+                        if let Some(subscription) = subscription.filter(|_| !shared) {
+                            subscriptions.push(subscription);
+                        }
+                        qos
+                    }
+                    Err(e) => {
+                        warn!(message="error subscribing to a topic: {}", error = %e);
+                        proto::SubAckQos::Failure
                     }
-                    qos
-                }
-                Err(e) => {
-                    warn!(message="error subscribing to a topic: {}", error = %e);
-                    proto::SubAckQos::Failure
                 }
-            },
+            }
             Ok((false, subscribe_to)) => {
                 debug!(
                     "client {} not allowed to subscribe to topic {} qos {}",
@@ -918,9 +1544,33 @@ where
     Ok((suback, subscriptions))
 }
 
+/// Returns `true` if the session is persisted by the journal.
+fn is_persisted(session: &Session) -> bool {
+    matches!(session, Session::Persistent(_) | Session::Offline(_))
+}
+
+/// Returns `true` if processing the event can change the persisted state of the client session.
+fn changes_session(event: &ClientEvent) -> bool {
+    match event {
+        ClientEvent::ConnAck(_)
+        | ClientEvent::PingReq(_)
+        | ClientEvent::PingResp(_)
+        | ClientEvent::SubAck(_)
+        | ClientEvent::UnsubAck(_)
+        | ClientEvent::PublishTo(_) => false,
+        // only QoS 2 publications are kept by the publisher session until released
+        ClientEvent::PublishFrom(publish) => matches!(
+            publish.packet_identifier_dup_qos,
+            proto::PacketIdentifierDupQoS::ExactlyOnce(..)
+        ),
+        _ => true,
+    }
+}
+
 fn publish_to<Z>(
     authorizer: &Z,
     session: &mut Session,
+    shared: Option<&str>,
     publication: &proto::Publication,
 ) -> Result<(), Error>
 where
@@ -934,7 +1584,11 @@ where
 
     match authorizer.authorize(activity) {
         Ok(true) => {
-            if let Some(event) = session.publish_to(&publication)? {
+            let event = match shared {
+                Some(topic_filter) => session.publish_shared_to(topic_filter, &publication)?,
+                None => session.publish_to(&publication)?,
+            };
+            if let Some(event) = event {
                 session.send(event)?
             }
         }
@@ -951,18 +1605,68 @@ where
     Ok(())
 }
 
+fn connack_properties_v5(
+    assigned_client_identifier: Option<String>,
+    maximum_packet_size: Option<u32>,
+) -> proto::Properties {
+    proto::Properties {
+        topic_alias_maximum: Some(TOPIC_ALIAS_MAXIMUM),
+        assigned_client_identifier,
+        maximum_packet_size,
+        shared_subscription_available: Some(1),
+        ..proto::Properties::default()
+    }
+}
+
+/// A retained publication along with the time it was retained by the broker.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RetainedPublication {
+    publication: proto::Publication,
+    retained_at: SystemTime,
+}
+
+impl RetainedPublication {
+    pub fn new(publication: proto::Publication, retained_at: SystemTime) -> Self {
+        Self {
+            publication,
+            retained_at,
+        }
+    }
+
+    pub fn publication(&self) -> &proto::Publication {
+        &self.publication
+    }
+
+    pub fn retained_at(&self) -> SystemTime {
+        self.retained_at
+    }
+
+    /// Returns `true` if the publication has been retained for at least `expiration`.
+    /// A retention time in the future (e.g. after a clock change) is never expired.
+    pub fn is_expired(&self, expiration: Duration, now: SystemTime) -> bool {
+        matches!(now.duration_since(self.retained_at), Ok(age) if age >= expiration)
+    }
+
+    pub fn into_parts(self) -> (proto::Publication, SystemTime) {
+        (self.publication, self.retained_at)
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct BrokerState {
-    retained: HashMap<String, proto::Publication>,
+    retained: HashMap<String, RetainedPublication>,
     sessions: Vec<SessionState>,
 }
 
 impl BrokerState {
-    pub fn new(retained: HashMap<String, proto::Publication>, sessions: Vec<SessionState>) -> Self {
+    pub fn new(
+        retained: HashMap<String, RetainedPublication>,
+        sessions: Vec<SessionState>,
+    ) -> Self {
         Self { retained, sessions }
     }
 
-    pub fn into_parts(self) -> (HashMap<String, proto::Publication>, Vec<SessionState>) {
+    pub fn into_parts(self) -> (HashMap<String, RetainedPublication>, Vec<SessionState>) {
         (self.retained, self.sessions)
     }
 }
@@ -971,6 +1675,8 @@ pub struct BrokerBuilder<N, Z> {
     state: Option<BrokerState>,
     authenticator: N,
     authorizer: Z,
+    config: BrokerConfig,
+    journal: Option<Journal>,
 }
 
 impl Default for BrokerBuilder<DefaultAuthenticator, DefaultAuthorizer> {
@@ -979,6 +1685,8 @@ impl Default for BrokerBuilder<DefaultAuthenticator, DefaultAuthorizer> {
             state: None,
             authenticator: DefaultAuthenticator,
             authorizer: DefaultAuthorizer,
+            config: BrokerConfig::default(),
+            journal: None,
         }
     }
 }
@@ -996,6 +1704,8 @@ where
             state: self.state,
             authenticator,
             authorizer: self.authorizer,
+            config: self.config,
+            journal: self.journal,
         }
     }
 
@@ -1007,6 +1717,8 @@ where
             state: self.state,
             authenticator: self.authenticator,
             authorizer,
+            config: self.config,
+            journal: self.journal,
         }
     }
 
@@ -1015,28 +1727,54 @@ where
         self
     }
 
-    pub fn build(self) -> Broker<N, Z> {
-        let (retained, sessions) = match self.state {
+    pub fn config(mut self, config: BrokerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Persists every change to the broker state through the journal.
+    pub fn journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    pub fn build(self) -> Broker<N, Z> {
+        let config = &self.config;
+        let (retained, sessions) = match self.state {
             Some(state) => {
                 let sessions = state
                     .sessions
                     .into_iter()
-                    .map(|s| (s.client_id().clone(), Session::new_offline(s)))
+                    .map(|mut s| {
+                        s.set_config(SessionConfig::for_client(config, s.client_id(), None));
+                        (s.client_id().clone(), Session::new_offline(s))
+                    })
                     .collect::<HashMap<ClientId, Session>>();
                 (state.retained, sessions)
             }
             None => (HashMap::default(), HashMap::default()),
         };
 
+        let mut subscriptions = SubscriptionTrie::default();
+        for (client_id, session) in &sessions {
+            subscriptions.update(client_id, session.subscriptions());
+        }
+
         let (sender, messages) = mpsc::channel(1024);
 
         Broker {
             sender,
             messages,
             sessions,
+            subscriptions,
             retained,
             authenticator: self.authenticator,
             authorizer: self.authorizer,
+            config: self.config,
+            journal: self.journal,
+            stats: Stats::default(),
+            shared_cursors: HashMap::new(),
+            rate_limiters: HashMap::new(),
 
             #[cfg(feature = "__internal_broker_callbacks")]
             on_publish: None,
@@ -1066,7 +1804,8 @@ pub struct NoSessionError;
 
 #[cfg(test)]
 pub(crate) mod tests {
-    use std::time::Duration;
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime};
 
     use bytes::Bytes;
     use futures_util::future::FutureExt;
@@ -1075,14 +1814,18 @@ pub(crate) mod tests {
     use uuid::Uuid;
 
     use mqtt3::{proto, PROTOCOL_LEVEL, PROTOCOL_NAME};
+    use tempfile::TempDir;
 
-    use super::OpenSession;
+    use super::{OpenSession, TOPIC_ALIAS_MAXIMUM};
     use crate::{
-        auth::{Activity, AuthenticateError, AuthorizeError, Operation},
-        broker::{BrokerBuilder, BrokerHandle},
+        auth::{Activity, AuthenticateError, AuthorizeError, Credentials, Operation},
+        broker::{BrokerBuilder, BrokerHandle, BrokerState, RetainedPublication},
         error::Error,
-        session::Session,
-        AuthId, ClientEvent, ClientId, ConnReq, ConnectionHandle, Message, Publish,
+        journal,
+        session::{Session, SessionConfig, SessionState},
+        AdminError, AdminHandle, AuthId, BrokerConfig, ClientEvent, ClientId, ConnReq,
+        ConnectionHandle, Message, PeerCredentials, Persist, Publish, SessionStatus, SystemEvent,
+        WalPersistor,
     };
 
     pub fn connection_handle() -> ConnectionHandle {
@@ -1100,6 +1843,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         }
     }
 
@@ -1112,6 +1856,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         }
     }
 
@@ -1134,6 +1879,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
         let connect2 = proto::Connect {
             username: None,
@@ -1143,6 +1889,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
         let id = Uuid::new_v4();
         let (tx1, mut rx1) = mpsc::unbounded_channel();
@@ -1197,6 +1944,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
         let connect2 = proto::Connect {
             username: None,
@@ -1206,6 +1954,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
         let (tx1, mut rx1) = mpsc::unbounded_channel();
         let (tx2, mut rx2) = mpsc::unbounded_channel();
@@ -1265,6 +2014,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: "AMQP".to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
         let (tx1, mut rx1) = mpsc::unbounded_channel();
         let conn1 = ConnectionHandle::from_sender(tx1);
@@ -1304,6 +2054,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: 0x3,
+            properties: proto::Properties::default(),
         };
         let (tx1, mut rx1) = mpsc::unbounded_channel();
         let conn1 = ConnectionHandle::from_sender(tx1);
@@ -1335,6 +2086,56 @@ pub(crate) mod tests {
         assert_matches!(rx1.recv().await, None)
     }
 
+    #[tokio::test]
+    async fn test_v5_connect_receives_connack_properties() {
+        let broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let mut broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let connect1 = proto::Connect {
+            username: None,
+            password: None,
+            will: None,
+            client_id: proto::ClientId::ServerGenerated,
+            keep_alive: Duration::default(),
+            protocol_name: PROTOCOL_NAME.to_string(),
+            protocol_level: mqtt3::PROTOCOL_LEVEL_V5,
+            properties: proto::Properties::default(),
+        };
+        let (tx1, mut rx1) = mpsc::unbounded_channel();
+        let conn1 = ConnectionHandle::from_sender(tx1);
+        let client_id = ClientId::from("generated".to_string());
+        let req1 = ConnReq::new(client_id.clone(), connect1, None, conn1);
+
+        broker_handle
+            .send(Message::Client(
+                client_id.clone(),
+                ClientEvent::ConnReq(req1),
+            ))
+            .await
+            .unwrap();
+
+        match rx1.recv().await {
+            Some(Message::Client(_, ClientEvent::ConnAck(ack))) => {
+                assert_eq!(ack.return_code, proto::ConnectReturnCode::Accepted);
+                assert_eq!(
+                    ack.properties.assigned_client_identifier,
+                    Some("generated".to_string())
+                );
+                assert_eq!(
+                    ack.properties.topic_alias_maximum,
+                    Some(TOPIC_ALIAS_MAXIMUM)
+                );
+                assert_eq!(ack.properties.shared_subscription_available, Some(1));
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+
     #[tokio::test]
     async fn test_connect_auth_succeeded() {
         let broker = BrokerBuilder::default()
@@ -1353,6 +2154,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
 
         let (tx1, mut rx1) = mpsc::unbounded_channel();
@@ -1378,6 +2180,131 @@ pub(crate) mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_connect_auth_with_peer_credentials() {
+        let broker = BrokerBuilder::default()
+            .authenticator(|credentials| match credentials {
+                Credentials::PeerCredentials(Some(username), peer)
+                    if username == "module" && peer.pid() == Some(42) && peer.uid() == 1000 =>
+                {
+                    Ok(Some(AuthId::from_identity(username)))
+                }
+                _ => Ok(None),
+            })
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let mut broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let connect1 = proto::Connect {
+            username: Some("module".to_string()),
+            password: None,
+            will: None,
+            client_id: proto::ClientId::IdWithCleanSession("blah".to_string()),
+            keep_alive: Duration::default(),
+            protocol_name: PROTOCOL_NAME.to_string(),
+            protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
+        };
+
+        let (tx1, mut rx1) = mpsc::unbounded_channel();
+        let conn1 = ConnectionHandle::from_sender(tx1);
+        let client_id = ClientId::from("blah".to_string());
+        let req1 = ConnReq::new(client_id.clone(), connect1, None, conn1)
+            .with_peer_credentials(Some(PeerCredentials::new(Some(42), 1000, 1000)));
+
+        broker_handle
+            .send(Message::Client(
+                client_id.clone(),
+                ClientEvent::ConnReq(req1),
+            ))
+            .await
+            .unwrap();
+
+        assert_matches!(
+            rx1.recv().await,
+            Some(Message::Client(_, ClientEvent::ConnAck(proto::ConnAck {
+                return_code:
+                    proto::ConnectReturnCode::Accepted,
+                ..
+            })))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_auth_id_skips_authenticator() {
+        let broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(None))
+            .authorizer(|activity: Activity| {
+                Ok(*activity.auth_id() == AuthId::from_identity("$bridge/upstream"))
+            })
+            .build();
+
+        let mut broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let connect1 = transient_connect("blah".to_string());
+        let (tx1, mut rx1) = mpsc::unbounded_channel();
+        let conn1 = ConnectionHandle::from_sender(tx1);
+        let client_id = ClientId::from("blah".to_string());
+        let req1 = ConnReq::new(client_id.clone(), connect1, None, conn1)
+            .with_auth_id(Some(AuthId::from_identity("$bridge/upstream")));
+
+        broker_handle
+            .send(Message::Client(
+                client_id.clone(),
+                ClientEvent::ConnReq(req1),
+            ))
+            .await
+            .unwrap();
+
+        assert_matches!(
+            rx1.recv().await,
+            Some(Message::Client(_, ClientEvent::ConnAck(proto::ConnAck {
+                return_code:
+                    proto::ConnectReturnCode::Accepted,
+                ..
+            })))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_reserved_bridge_client_id() {
+        let broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let mut broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let connect1 = transient_connect("$bridge/upstream".to_string());
+        let (tx1, mut rx1) = mpsc::unbounded_channel();
+        let conn1 = ConnectionHandle::from_sender(tx1);
+        let client_id = ClientId::from("$bridge/upstream".to_string());
+        let req1 = ConnReq::new(client_id.clone(), connect1, None, conn1);
+
+        broker_handle
+            .send(Message::Client(
+                client_id.clone(),
+                ClientEvent::ConnReq(req1),
+            ))
+            .await
+            .unwrap();
+
+        assert_matches!(
+            rx1.recv().await,
+            Some(Message::Client(_, ClientEvent::ConnAck(proto::ConnAck {
+                return_code:
+                    proto::ConnectReturnCode::Refused(
+                        proto::ConnectionRefusedReason::IdentifierRejected
+                    ),
+                ..
+            })))
+        );
+    }
+
     #[tokio::test]
     async fn test_connect_unknown_client() {
         let broker = BrokerBuilder::default()
@@ -1396,6 +2323,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
 
         let (tx1, mut rx1) = mpsc::unbounded_channel();
@@ -1446,6 +2374,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
 
         let (tx1, mut rx1) = mpsc::unbounded_channel();
@@ -1496,6 +2425,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
 
         let (tx1, mut rx1) = mpsc::unbounded_channel();
@@ -1546,6 +2476,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
 
         let (tx1, mut rx1) = mpsc::unbounded_channel();
@@ -1885,6 +2816,7 @@ pub(crate) mod tests {
             retain: true,
             topic_name: "/foo/bar".to_string(),
             payload: Bytes::new(),
+            properties: proto::Properties::default(),
         };
 
         let message = Message::Client(client_id.clone(), ClientEvent::PublishFrom(publish));
@@ -1897,6 +2829,334 @@ pub(crate) mod tests {
         assert_matches!(rx.recv().await, None)
     }
 
+    #[tokio::test]
+    async fn test_journal_defers_puback_until_persisted() {
+        let dir = TempDir::new().unwrap();
+        let (journal, writer) = journal(WalPersistor::new(dir.path()), 100);
+
+        let broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some("client-a".into())))
+            .authorizer(|_| Ok(true))
+            .journal(journal)
+            .build();
+
+        let mut broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let (client_id, mut rx) = connect_client("pub", &mut broker_handle).await.unwrap();
+
+        let publish = proto::Publish {
+            packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtLeastOnce(
+                proto::PacketIdentifier::new(1).unwrap(),
+                false,
+            ),
+            retain: true,
+            topic_name: "/foo/bar".to_string(),
+            payload: Bytes::from("hello"),
+            properties: proto::Properties::default(),
+        };
+
+        let message = Message::Client(client_id.clone(), ClientEvent::PublishFrom(publish));
+        broker_handle.send(message).await.unwrap();
+
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        assert_matches!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        tokio::spawn(writer.run(broker_handle.clone()));
+
+        assert_matches!(
+            rx.recv().await,
+            Some(Message::Client(_, ClientEvent::PubAck(_)))
+        );
+
+        let state = WalPersistor::new(dir.path())
+            .load()
+            .await
+            .unwrap()
+            .expect("state");
+        let (retained, sessions) = state.into_parts();
+        assert_eq!(
+            retained["/foo/bar"].publication().payload,
+            Bytes::from("hello")
+        );
+        assert_eq!(sessions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_journal_failure_drops_unacked_and_refuses_qos1() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"not a directory").unwrap();
+        let (journal, writer) = journal(WalPersistor::new(file.join("wal")), 100);
+
+        let broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some("client-a".into())))
+            .authorizer(|_| Ok(true))
+            .journal(journal)
+            .build();
+
+        let mut broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let publish = proto::Publish {
+            packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtLeastOnce(
+                proto::PacketIdentifier::new(1).unwrap(),
+                false,
+            ),
+            retain: false,
+            topic_name: "/foo/bar".to_string(),
+            payload: Bytes::from("hello"),
+            properties: proto::Properties::default(),
+        };
+
+        let (client_id, mut rx) = connect_client("pub", &mut broker_handle).await.unwrap();
+        let message = Message::Client(client_id, ClientEvent::PublishFrom(publish.clone()));
+        broker_handle.send(message).await.unwrap();
+
+        assert!(writer.run(broker_handle.clone()).await.is_err());
+        assert_matches!(
+            rx.recv().await,
+            Some(Message::Client(_, ClientEvent::DropConnection))
+        );
+
+        let (client_id, mut rx) = connect_client("pub2", &mut broker_handle).await.unwrap();
+        let message = Message::Client(client_id, ClientEvent::PublishFrom(publish));
+        broker_handle.send(message).await.unwrap();
+
+        assert_matches!(
+            rx.recv().await,
+            Some(Message::Client(_, ClientEvent::DropConnection))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_journal_records_only_persisted_session_changes() {
+        fn subscribe() -> ClientEvent {
+            ClientEvent::Subscribe(proto::Subscribe {
+                packet_identifier: proto::PacketIdentifier::new(1).unwrap(),
+                subscribe_to: vec![proto::SubscribeTo {
+                    topic_filter: "/foo".to_string(),
+                    qos: proto::QoS::AtLeastOnce,
+                }],
+            })
+        }
+
+        let dir = TempDir::new().unwrap();
+        let (journal, _writer) = journal(WalPersistor::new(dir.path()), 100);
+
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .journal(journal)
+            .build();
+
+        let persistent = ClientId::from("persistent");
+        let transient = ClientId::from("transient");
+        let (tx, _rx) = mpsc::unbounded_channel();
+        for (client_id, connect) in &[
+            (
+                &persistent,
+                persistent_connect as fn(String) -> proto::Connect,
+            ),
+            (&transient, transient_connect),
+        ] {
+            let connect = connect(client_id.to_string());
+            let handle = ConnectionHandle::from_sender(tx.clone());
+            let req = ConnReq::new((*client_id).clone(), connect, None, handle);
+            broker
+                .process_message((*client_id).clone(), ClientEvent::ConnReq(req))
+                .unwrap();
+        }
+        broker.flush_journal(true);
+
+        for client_id in &[&persistent, &transient] {
+            let ping = ClientEvent::PingReq(proto::PingReq);
+            broker.process_message((*client_id).clone(), ping).unwrap();
+        }
+        broker
+            .process_message(transient.clone(), subscribe())
+            .unwrap();
+        let journal = broker.journal.as_ref().unwrap();
+        assert!(!journal.is_session_changed(&persistent));
+        assert!(!journal.is_session_changed(&transient));
+
+        broker
+            .process_message(persistent.clone(), subscribe())
+            .unwrap();
+        let journal = broker.journal.as_ref().unwrap();
+        assert!(journal.is_session_changed(&persistent));
+    }
+
+    #[tokio::test]
+    async fn test_publish_stats() {
+        let broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let stats_handle = broker.stats_handle();
+        let mut broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let (client_id, mut rx) = connect_client("client_a", &mut broker_handle)
+            .await
+            .unwrap();
+        send_subscribe(
+            &mut broker_handle,
+            &mut rx,
+            client_id,
+            &["$SYS/broker/clients/connected"],
+        )
+        .await;
+
+        broker_handle
+            .send(Message::System(SystemEvent::PublishStats))
+            .await
+            .unwrap();
+
+        match rx.recv().await {
+            Some(Message::Client(_, ClientEvent::PublishTo(Publish::QoS0(_, publish)))) => {
+                assert_eq!(publish.topic_name, "$SYS/broker/clients/connected");
+                assert_eq!(publish.payload, Bytes::from("1"));
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+
+        let stats = stats_handle.get();
+        assert_eq!(stats.clients_connected(), 1);
+        assert_eq!(stats.sessions(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_shared_subscription_round_robin_and_redelivery() {
+        let broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let mut broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let (member_a, mut rx_a) = connect_client("member_a", &mut broker_handle)
+            .await
+            .unwrap();
+        send_subscribe(&mut broker_handle, &mut rx_a, member_a, &["$share/g/foo"]).await;
+        let (member_b, mut rx_b) = connect_client("member_b", &mut broker_handle)
+            .await
+            .unwrap();
+        send_subscribe(&mut broker_handle, &mut rx_b, member_b, &["$share/g/foo"]).await;
+        let (publisher, mut rx_pub) = connect_client("publisher", &mut broker_handle)
+            .await
+            .unwrap();
+
+        for id in 1..=3 {
+            let publish = proto::Publish {
+                packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtLeastOnce(
+                    proto::PacketIdentifier::new(id).unwrap(),
+                    false,
+                ),
+                retain: false,
+                topic_name: "foo".to_string(),
+                payload: Bytes::from(id.to_string()),
+                properties: proto::Properties::default(),
+            };
+            let message = Message::Client(publisher.clone(), ClientEvent::PublishFrom(publish));
+            broker_handle.send(message).await.unwrap();
+            assert_matches!(
+                rx_pub.recv().await,
+                Some(Message::Client(_, ClientEvent::PubAck(_)))
+            );
+        }
+
+        // members take turns
+        assert_eq!(recv_publish_payload(&mut rx_a).await, Bytes::from("1"));
+        assert_eq!(recv_publish_payload(&mut rx_b).await, Bytes::from("2"));
+        assert_eq!(recv_publish_payload(&mut rx_a).await, Bytes::from("3"));
+        assert_matches!(rx_b.try_recv(), Err(TryRecvError::Empty));
+
+        // unacked messages go to the remaining member
+        disconnect_client("member_a", &mut broker_handle).await;
+        assert_eq!(recv_publish_payload(&mut rx_b).await, Bytes::from("1"));
+        assert_eq!(recv_publish_payload(&mut rx_b).await, Bytes::from("3"));
+    }
+
+    #[tokio::test]
+    async fn test_admin_sessions_and_retained() {
+        let broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let mut broker_handle = broker.handle();
+        let mut admin_handle = AdminHandle::new(broker.handle());
+        tokio::spawn(broker.run().map(drop));
+
+        let (client_a, mut rx_a) = connect_client("client_a", &mut broker_handle)
+            .await
+            .unwrap();
+        send_subscribe(&mut broker_handle, &mut rx_a, client_a.clone(), &["foo/#"]).await;
+        let (client_b, _rx_b) = connect_client("client_b", &mut broker_handle)
+            .await
+            .unwrap();
+        disconnect_client("client_b", &mut broker_handle).await;
+
+        let publish = proto::Publish {
+            packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtMostOnce,
+            retain: true,
+            topic_name: "retained/topic".to_string(),
+            payload: Bytes::from("hello"),
+            properties: proto::Properties::default(),
+        };
+        let message = Message::Client(client_a.clone(), ClientEvent::PublishFrom(publish));
+        broker_handle.send(message).await.unwrap();
+
+        let sessions = admin_handle.sessions().await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].client_id(), &client_a);
+        assert_eq!(sessions[0].status(), SessionStatus::Connected);
+        assert_eq!(sessions[0].subscriptions()["foo/#"], 1);
+        assert_eq!(sessions[1].client_id(), &client_b);
+        assert_eq!(sessions[1].status(), SessionStatus::Offline);
+
+        assert_matches!(
+            admin_handle.delete_session(client_a.clone()).await,
+            Err(AdminError::SessionConnected(_))
+        );
+        admin_handle.delete_session(client_b.clone()).await.unwrap();
+        assert_matches!(
+            admin_handle.delete_session(client_b).await,
+            Err(AdminError::NoSession(_))
+        );
+        assert_eq!(admin_handle.sessions().await.unwrap().len(), 1);
+
+        assert_eq!(
+            admin_handle
+                .clear_retained("other/#".to_string())
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            admin_handle
+                .clear_retained("retained/+".to_string())
+                .await
+                .unwrap(),
+            1
+        );
+        assert_matches!(
+            admin_handle
+                .clear_retained("bad/#/filter".to_string())
+                .await,
+            Err(AdminError::Broker(Error::InvalidTopicFilter(_)))
+        );
+
+        admin_handle.disconnect(client_a).await.unwrap();
+        assert_matches!(
+            rx_a.recv().await,
+            Some(Message::Client(_, ClientEvent::DropConnection))
+        );
+    }
+
     #[tokio::test]
     async fn test_subscribe_client_has_no_permissions() {
         let broker = BrokerBuilder::default()
@@ -1986,6 +3246,7 @@ pub(crate) mod tests {
             retain: true,
             topic_name: "/foo/bar".to_string(),
             payload: Bytes::new(),
+            properties: proto::Properties::default(),
         };
 
         let message = Message::Client(pub_id.clone(), ClientEvent::PublishFrom(publish));
@@ -2038,6 +3299,7 @@ pub(crate) mod tests {
                     retain: true,
                     topic_name: "$edgehub/connected".to_owned(),
                     payload: "[\"client_a\"]".into(),
+                    properties: proto::Properties::default(),
                 }
             );
         } else {
@@ -2224,6 +3486,272 @@ pub(crate) mod tests {
         check_notify_received(&mut a_rx, &["foo", "bar", "baz"]).await;
     }
 
+    #[test]
+    fn test_retained_messages_max_count() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .config(broker_config(2, "60d", "60d"))
+            .build();
+
+        broker.store_retained(retained_publication("/foo/a", "a"));
+        broker.store_retained(retained_publication("/foo/b", "b"));
+        broker.store_retained(retained_publication("/foo/c", "c"));
+
+        assert_eq!(2, broker.retained.len());
+        assert!(!broker.retained.contains_key("/foo/c"));
+
+        // replacing an existing retained message is allowed when the limit is reached
+        broker.store_retained(retained_publication("/foo/a", "a2"));
+
+        assert_eq!(2, broker.retained.len());
+        assert_eq!(
+            Bytes::from("a2"),
+            broker.retained["/foo/a"].publication().payload
+        );
+    }
+
+    #[test]
+    fn test_retained_messages_evicts_expired_when_full() {
+        let mut retained = HashMap::new();
+        retained.insert(
+            "/foo/old".to_string(),
+            RetainedPublication::new(
+                retained_publication("/foo/old", "old"),
+                SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60),
+            ),
+        );
+
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .state(BrokerState::new(retained, vec![]))
+            .config(broker_config(1, "1d", "60d"))
+            .build();
+
+        broker.store_retained(retained_publication("/foo/new", "new"));
+
+        assert_eq!(1, broker.retained.len());
+        assert!(broker.retained.contains_key("/foo/new"));
+    }
+
+    #[tokio::test]
+    async fn test_retained_messages_expired_not_sent() {
+        let mut retained = HashMap::new();
+        retained.insert(
+            "/foo/old".to_string(),
+            RetainedPublication::new(
+                retained_publication("/foo/old", "old"),
+                SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60),
+            ),
+        );
+        retained.insert(
+            "/foo/new".to_string(),
+            RetainedPublication::new(retained_publication("/foo/new", "new"), SystemTime::now()),
+        );
+
+        let broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some("client-a".into())))
+            .authorizer(|_| Ok(true))
+            .state(BrokerState::new(retained, vec![]))
+            .config(broker_config(1000, "1d", "60d"))
+            .build();
+
+        let mut broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let (sub_id, mut sub_rx) = connect_client("sub", &mut broker_handle).await.unwrap();
+
+        let subscribe = proto::Subscribe {
+            packet_identifier: proto::PacketIdentifier::new(1).unwrap(),
+            subscribe_to: vec![proto::SubscribeTo {
+                topic_filter: "/foo/+".to_string(),
+                qos: proto::QoS::AtMostOnce,
+            }],
+        };
+
+        let message = Message::Client(sub_id, ClientEvent::Subscribe(subscribe));
+        broker_handle.send(message).await.unwrap();
+
+        assert_matches!(
+            sub_rx.recv().await,
+            Some(Message::Client(_, ClientEvent::SubAck(_)))
+        );
+        assert_matches!(
+            sub_rx.recv().await,
+            Some(Message::Client(_, ClientEvent::PublishTo(Publish::QoS0(_, publish)))) if publish.topic_name == "/foo/new"
+        );
+        assert_matches!(sub_rx.try_recv(), Err(TryRecvError::Empty))
+    }
+
+    #[test]
+    fn test_session_cleanup_removes_expired_offline_sessions() {
+        let mut expired = SessionState::new("expired".into(), SessionConfig::default());
+        expired.set_last_seen(SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60));
+        let active = SessionState::new("active".into(), SessionConfig::default());
+
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .state(BrokerState::new(HashMap::new(), vec![expired, active]))
+            .config(broker_config(1000, "60d", "1d"))
+            .build();
+
+        broker.process_session_cleanup().unwrap();
+
+        assert_eq!(1, broker.sessions.len());
+        assert_matches!(broker.sessions[&"active".into()], Session::Offline(_));
+    }
+
+    #[test]
+    fn test_session_cleanup_removes_expired_retained_messages() {
+        let mut retained = HashMap::new();
+        retained.insert(
+            "/foo/old".to_string(),
+            RetainedPublication::new(
+                retained_publication("/foo/old", "old"),
+                SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60),
+            ),
+        );
+        retained.insert(
+            "/foo/new".to_string(),
+            RetainedPublication::new(retained_publication("/foo/new", "new"), SystemTime::now()),
+        );
+
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .state(BrokerState::new(retained, vec![]))
+            .config(broker_config(1000, "1d", "60d"))
+            .build();
+
+        broker.process_session_cleanup().unwrap();
+
+        assert_eq!(1, broker.retained.len());
+        assert!(broker.retained.contains_key("/foo/new"));
+    }
+
+    #[test]
+    fn test_session_cleanup_keeps_connected_sessions() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .config(broker_config(1000, "60d", "0s"))
+            .build();
+
+        let client_id = ClientId::from("id1");
+        let connect = persistent_connect("id1".into());
+        let req = ConnReq::new(client_id.clone(), connect, None, connection_handle());
+        broker.open_session(AuthId::Anonymous, req).unwrap();
+
+        broker.process_session_cleanup().unwrap();
+        assert_matches!(broker.sessions[&client_id], Session::Persistent(_));
+
+        broker.close_session(&client_id).unwrap();
+        broker.process_session_cleanup().unwrap();
+        assert_eq!(0, broker.sessions.len());
+    }
+
+    #[test]
+    fn test_snapshot_refreshes_last_seen_of_connected_sessions() {
+        let mut state = SessionState::new("id1".into(), SessionConfig::default());
+        state.set_last_seen(SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60));
+
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .state(BrokerState::new(HashMap::new(), vec![state]))
+            .config(broker_config(1000, "60d", "1d"))
+            .build();
+
+        let before = SystemTime::now();
+        let client_id = ClientId::from("id1");
+        let connect = persistent_connect("id1".into());
+        let req = ConnReq::new(client_id, connect, None, connection_handle());
+        broker.open_session(AuthId::Anonymous, req).unwrap();
+
+        let (_, sessions) = broker.snapshot().into_parts();
+        assert_eq!(1, sessions.len());
+        assert!(sessions[0].last_seen() >= before);
+    }
+
+    #[test]
+    fn test_config_update_applies_to_existing_sessions() {
+        let offline = SessionState::new("offline".into(), SessionConfig::default());
+
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .state(BrokerState::new(HashMap::new(), vec![offline]))
+            .build();
+
+        let client_id = ClientId::from("id1");
+        let connect = persistent_connect("id1".into());
+        let req = ConnReq::new(client_id.clone(), connect, None, connection_handle());
+        broker.open_session(AuthId::Anonymous, req).unwrap();
+
+        let max_inflight_messages =
+            |session: &Session| session.state().map(|s| s.config().max_inflight_messages());
+        let offline = ClientId::from("offline");
+        assert_eq!(
+            max_inflight_messages(&broker.sessions[&client_id]),
+            Some(10)
+        );
+        assert_eq!(max_inflight_messages(&broker.sessions[&offline]), Some(10));
+
+        broker.process_config_update(broker_config(5, "60d", "1d"));
+
+        assert_matches!(broker.sessions[&client_id], Session::Persistent(_));
+        assert_eq!(
+            max_inflight_messages(&broker.sessions[&client_id]),
+            Some(16)
+        );
+        assert_eq!(max_inflight_messages(&broker.sessions[&offline]), Some(16));
+        assert_eq!(broker.config.retained_messages().max_count(), 5);
+        assert_eq!(
+            broker.config.session().expiration(),
+            Duration::from_secs(24 * 60 * 60)
+        );
+    }
+
+    fn retained_publication(topic_name: &str, payload: &'static str) -> proto::Publication {
+        proto::Publication {
+            topic_name: topic_name.to_string(),
+            qos: proto::QoS::AtMostOnce,
+            retain: true,
+            payload: Bytes::from(payload),
+            properties: proto::Properties::default(),
+        }
+    }
+
+    fn broker_config(
+        retained_max_count: u32,
+        retained_expiration: &str,
+        session_expiration: &str,
+    ) -> BrokerConfig {
+        let config = serde_json::json!({
+            "transports": [],
+            "inflight_messages": {
+                "max_count": 16
+            },
+            "retained_messages": {
+                "max_count": retained_max_count,
+                "expiration": retained_expiration
+            },
+            "session": {
+                "expiration": session_expiration,
+                "messages": {
+                    "max_message_size": "256kb",
+                    "max_count": 1000,
+                    "max_total_space": "16mb",
+                    "when_full": "drop_new"
+                }
+            }
+        });
+
+        serde_json::from_value(config).expect("broker config")
+    }
+
     async fn connect_client(
         client_id: &str,
         broker_handle: &mut BrokerHandle,
@@ -2308,6 +3836,15 @@ pub(crate) mod tests {
         );
     }
 
+    async fn recv_publish_payload(rx: &mut UnboundedReceiver<Message>) -> Bytes {
+        match rx.recv().await {
+            Some(Message::Client(_, ClientEvent::PublishTo(Publish::QoS12(_, publish)))) => {
+                publish.payload
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+
     async fn check_notify_received(rx: &mut UnboundedReceiver<Message>, expected: &[&str]) {
         if let Some(Message::Client(
             _,
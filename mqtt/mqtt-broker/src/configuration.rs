@@ -6,12 +6,15 @@ use std::time::Duration;
 
 use config::{Config, ConfigError, File, FileFormat};
 use lazy_static::lazy_static;
+use mqtt3::proto;
 use regex::Regex;
 use serde::{Deserialize, Deserializer};
 
+use crate::AuthId;
+
 pub const DEFAULTS: &str = include_str!("../config/default.json");
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Transport {
     Tcp {
@@ -20,10 +23,27 @@ pub enum Transport {
     Tls {
         address: String,
         certificate: PathBuf,
+        #[serde(default)]
+        client_ca_bundle: Option<PathBuf>,
+    },
+    Ws {
+        address: String,
+    },
+    Wss {
+        address: String,
+        certificate: PathBuf,
+        #[serde(default)]
+        client_ca_bundle: Option<PathBuf>,
+    },
+    Unix {
+        path: PathBuf,
+        /// File mode of the socket as an octal string, e.g. `"0660"`.
+        #[serde(default, deserialize_with = "file_mode_opt")]
+        permissions: Option<u32>,
     },
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum QueueFullAction {
     DropNew,
@@ -31,19 +51,179 @@ pub enum QueueFullAction {
     Disconnect,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct InflightMessages {
     max_count: u32,
+    #[serde(default)]
+    overrides: Vec<InflightMessagesOverride>,
+}
+
+impl InflightMessages {
+    pub fn max_count(&self) -> u32 {
+        self.max_count
+    }
+
+    /// Returns the inflight messages limit for a given client.
+    ///
+    /// An override matching the client id takes precedence over
+    /// an override matching the auth id.
+    pub fn max_count_for(&self, client_id: &str, auth_id: Option<&AuthId>) -> u32 {
+        let by_client_id = self
+            .overrides
+            .iter()
+            .find(|o| o.client_id.as_deref() == Some(client_id));
+
+        let by_auth_id = || {
+            self.overrides.iter().find(|o| match (&o.auth_id, auth_id) {
+                (Some(id), Some(AuthId::Identity(identity))) => id == identity,
+                _ => false,
+            })
+        };
+
+        by_client_id
+            .or_else(by_auth_id)
+            .map_or(self.max_count, |o| o.max_count)
+    }
+}
+
+/// Inflight messages limit for a specific client id or auth id.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct InflightMessagesOverride {
+    client_id: Option<String>,
+    auth_id: Option<String>,
+    max_count: u32,
+}
+
+/// Action taken when a client publishes faster than its rate limits allow.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitExceededAction {
+    /// Stop reading from the connection until the client is within its limits again.
+    Throttle,
+    Disconnect,
+}
+
+impl Default for LimitExceededAction {
+    fn default() -> Self {
+        LimitExceededAction::Throttle
+    }
+}
+
+/// Limits applied to packets received from clients.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Limits {
+    #[serde(default, deserialize_with = "humansize_opt")]
+    max_packet_size: Option<u64>,
+    messages_per_second: Option<u32>,
+    #[serde(default, deserialize_with = "humansize_opt")]
+    bytes_per_second: Option<u64>,
+    #[serde(default)]
+    when_exceeded: LimitExceededAction,
+    #[serde(default)]
+    overrides: Vec<LimitsOverride>,
+}
+
+impl Limits {
+    /// Maximum size of a packet received from a client, if any.
+    pub fn max_packet_size(&self) -> Option<u64> {
+        self.max_packet_size
+    }
+
+    /// Returns the rate limits for a given client.
+    ///
+    /// An override matching the client id takes precedence over
+    /// an override matching the auth id.
+    pub fn rate_limits_for(&self, client_id: &str, auth_id: Option<&AuthId>) -> RateLimits {
+        let by_client_id = self
+            .overrides
+            .iter()
+            .find(|o| o.client_id.as_deref() == Some(client_id));
+
+        let by_auth_id = || {
+            self.overrides.iter().find(|o| match (&o.auth_id, auth_id) {
+                (Some(id), Some(AuthId::Identity(identity))) => id == identity,
+                _ => false,
+            })
+        };
+
+        let (messages_per_second, bytes_per_second) = by_client_id
+            .or_else(by_auth_id)
+            .map_or((self.messages_per_second, self.bytes_per_second), |o| {
+                (o.messages_per_second, o.bytes_per_second)
+            });
+
+        RateLimits {
+            messages_per_second,
+            bytes_per_second,
+            when_exceeded: self.when_exceeded,
+        }
+    }
+}
+
+/// Rate limits for a specific client id or auth id.
+///
+/// An override replaces both rates, a missing rate means no limit.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LimitsOverride {
+    client_id: Option<String>,
+    auth_id: Option<String>,
+    messages_per_second: Option<u32>,
+    #[serde(default, deserialize_with = "humansize_opt")]
+    bytes_per_second: Option<u64>,
+}
+
+/// Rate limits which apply to a single client.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimits {
+    messages_per_second: Option<u32>,
+    bytes_per_second: Option<u64>,
+    when_exceeded: LimitExceededAction,
 }
 
-#[derive(Debug, Deserialize)]
+impl RateLimits {
+    pub fn new(
+        messages_per_second: Option<u32>,
+        bytes_per_second: Option<u64>,
+        when_exceeded: LimitExceededAction,
+    ) -> Self {
+        Self {
+            messages_per_second,
+            bytes_per_second,
+            when_exceeded,
+        }
+    }
+
+    pub fn messages_per_second(&self) -> Option<u32> {
+        self.messages_per_second
+    }
+
+    pub fn bytes_per_second(&self) -> Option<u64> {
+        self.bytes_per_second
+    }
+
+    pub fn when_exceeded(&self) -> LimitExceededAction {
+        self.when_exceeded
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct RetainedMessages {
     max_count: u32,
     #[serde(with = "humantime_serde")]
     expiration: Duration,
 }
 
-#[derive(Debug, Deserialize)]
+impl RetainedMessages {
+    pub fn max_count(&self) -> u32 {
+        self.max_count
+    }
+
+    pub fn expiration(&self) -> Duration {
+        self.expiration
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct SessionMessages {
     #[serde(deserialize_with = "humansize")]
     max_message_size: u64,
@@ -53,7 +233,25 @@ pub struct SessionMessages {
     when_full: QueueFullAction,
 }
 
-#[derive(Debug, Deserialize)]
+impl SessionMessages {
+    pub fn max_message_size(&self) -> u64 {
+        self.max_message_size
+    }
+
+    pub fn max_count(&self) -> u32 {
+        self.max_count
+    }
+
+    pub fn max_total_space(&self) -> u64 {
+        self.max_total_space
+    }
+
+    pub fn when_full(&self) -> QueueFullAction {
+        self.when_full
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct SessionPersistence {
     file_path: String,
     #[serde(with = "humantime_serde")]
@@ -61,26 +259,322 @@ pub struct SessionPersistence {
     unsaved_message_count: u32,
 }
 
-#[derive(Debug, Deserialize)]
+impl SessionPersistence {
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    pub fn time_interval(&self) -> Duration {
+        self.time_interval
+    }
+
+    pub fn unsaved_message_count(&self) -> u32 {
+        self.unsaved_message_count
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Session {
     #[serde(with = "humantime_serde")]
     expiration: Duration,
+    #[serde(with = "humantime_serde", default = "default_cleanup_interval")]
+    cleanup_interval: Duration,
     messages: SessionMessages,
 }
 
-#[derive(Debug, Deserialize)]
+impl Session {
+    pub fn expiration(&self) -> Duration {
+        self.expiration
+    }
+
+    /// Interval at which expired offline sessions and retained messages are removed.
+    pub fn cleanup_interval(&self) -> Duration {
+        self.cleanup_interval
+    }
+
+    pub fn messages(&self) -> &SessionMessages {
+        &self.messages
+    }
+}
+
+/// Settings for the `$SYS` statistics and the Prometheus metrics endpoint.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Statistics {
+    #[serde(with = "humantime_serde", default = "default_statistics_interval")]
+    interval: Duration,
+    metrics_address: Option<String>,
+}
+
+impl Statistics {
+    /// Interval at which statistics are published. Zero disables them.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Address to serve the `/metrics` endpoint on, if any.
+    pub fn metrics_address(&self) -> Option<&str> {
+        self.metrics_address.as_deref()
+    }
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Self {
+            interval: default_statistics_interval(),
+            metrics_address: None,
+        }
+    }
+}
+
+/// Settings for the local admin interface.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Admin {
+    #[serde(default = "default_admin_address")]
+    address: String,
+}
+
+impl Admin {
+    /// Address to serve the admin interface on, either a loopback `host:port` or `unix://<path>`.
+    ///
+    /// Defaults to the unix socket `/var/run/mqttd/admin.sock`.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Authorization {
+    policy_file: PathBuf,
+    #[serde(with = "humantime_serde")]
+    reload_interval: Duration,
+}
+
+impl Authorization {
+    pub fn policy_file(&self) -> &Path {
+        &self.policy_file
+    }
+
+    pub fn reload_interval(&self) -> Duration {
+        self.reload_interval
+    }
+}
+
+/// Clients are authenticated with username and password against a password file,
+/// other credentials such as client certificates are refused.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Authentication {
+    password_file: PathBuf,
+}
+
+impl Authentication {
+    pub fn password_file(&self) -> &Path {
+        &self.password_file
+    }
+}
+
+/// Direction in which publications matching a bridge topic rule are forwarded.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// From the upstream broker to the local one.
+    In,
+    /// From the local broker to the upstream one.
+    Out,
+    Both,
+}
+
+impl Direction {
+    pub fn is_in(self) -> bool {
+        self != Direction::Out
+    }
+
+    pub fn is_out(self) -> bool {
+        self != Direction::In
+    }
+}
+
+/// Topics forwarded by a bridge.
+///
+/// A publication to `{local_prefix}{topic}` on the local broker
+/// is forwarded as `{remote_prefix}{topic}` to the upstream broker and
+/// vice versa, where `topic` matches `pattern`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TopicRule {
+    pattern: String,
+    direction: Direction,
+    #[serde(deserialize_with = "qos")]
+    qos: proto::QoS,
+    #[serde(default)]
+    local_prefix: String,
+    #[serde(default)]
+    remote_prefix: String,
+}
+
+impl TopicRule {
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn qos(&self) -> proto::QoS {
+        self.qos
+    }
+
+    pub fn local_prefix(&self) -> &str {
+        &self.local_prefix
+    }
+
+    pub fn remote_prefix(&self) -> &str {
+        &self.remote_prefix
+    }
+
+    pub fn local_filter(&self) -> String {
+        format!("{}{}", self.local_prefix, self.pattern)
+    }
+
+    pub fn remote_filter(&self) -> String {
+        format!("{}{}", self.remote_prefix, self.pattern)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BridgeSettings {
+    name: String,
+    address: String,
+    #[serde(default)]
+    client_id: Option<String>,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    tls: bool,
+    #[serde(default)]
+    ca_bundle: Option<PathBuf>,
+    #[serde(with = "humantime_serde", default = "default_keep_alive")]
+    keep_alive: Duration,
+    #[serde(with = "humantime_serde", default = "default_max_reconnect_back_off")]
+    max_reconnect_back_off: Duration,
+    topics: Vec<TopicRule>,
+}
+
+impl BridgeSettings {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Client id used to connect upstream. Defaults to the bridge name.
+    pub fn client_id(&self) -> &str {
+        self.client_id.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+
+    /// Whether the connection to the upstream broker is secured with TLS.
+    pub fn tls(&self) -> bool {
+        self.tls
+    }
+
+    /// CA certificates to trust in addition to the system ones
+    /// when verifying the upstream broker certificate.
+    pub fn ca_bundle(&self) -> Option<&Path> {
+        self.ca_bundle.as_deref()
+    }
+
+    pub fn keep_alive(&self) -> Duration {
+        self.keep_alive
+    }
+
+    pub fn max_reconnect_back_off(&self) -> Duration {
+        self.max_reconnect_back_off
+    }
+
+    pub fn topics(&self) -> &[TopicRule] {
+        &self.topics
+    }
+}
+
+fn default_keep_alive() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_max_reconnect_back_off() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_cleanup_interval() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+fn default_admin_address() -> String {
+    "unix:///var/run/mqttd/admin.sock".to_string()
+}
+
+fn default_statistics_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct BrokerConfig {
     transports: Vec<Transport>,
     inflight_messages: InflightMessages,
     retained_messages: RetainedMessages,
     session: Session,
+    #[serde(default)]
+    limits: Limits,
+    #[serde(default)]
+    statistics: Statistics,
     persistence: Option<SessionPersistence>,
+    authentication: Option<Authentication>,
+    authorization: Option<Authorization>,
+    admin: Option<Admin>,
+    #[serde(default)]
+    bridges: Vec<BridgeSettings>,
 }
 
 impl BrokerConfig {
     pub fn transports(&self) -> &Vec<Transport> {
         &self.transports
     }
+
+    pub fn inflight_messages(&self) -> &InflightMessages {
+        &self.inflight_messages
+    }
+
+    pub fn retained_messages(&self) -> &RetainedMessages {
+        &self.retained_messages
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+}
+
+impl Default for BrokerConfig {
+    fn default() -> Self {
+        Self::new().expect("default configuration must be valid")
+    }
 }
 
 pub fn humansize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
@@ -108,6 +602,40 @@ where
     Ok(base * multiplier)
 }
 
+fn humansize_opt<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: FromStr + Mul<Output = T> + From<u32>,
+    D: Deserializer<'de>,
+{
+    humansize(deserializer).map(Some)
+}
+
+fn qos<'de, D>(deserializer: D) -> Result<proto::QoS, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match u8::deserialize(deserializer)? {
+        0 => Ok(proto::QoS::AtMostOnce),
+        1 => Ok(proto::QoS::AtLeastOnce),
+        2 => Ok(proto::QoS::ExactlyOnce),
+        qos => Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Unsigned(qos.into()),
+            &"0, 1 or 2",
+        )),
+    }
+}
+
+fn file_mode_opt<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    match u32::from_str_radix(&s, 8) {
+        Ok(mode) if mode <= 0o777 => Ok(Some(mode)),
+        _ => Err(error::<D>(&s, "an octal file mode, e.g. '0660'")),
+    }
+}
+
 fn get_multiplier<'de, T, D>(str: &str) -> Result<T, D::Error>
 where
     T: From<u32>,
@@ -124,6 +652,10 @@ where
     Ok(result.into())
 }
 
+fn invalid(message: &str) -> ConfigError {
+    ConfigError::Message(message.to_string())
+}
+
 fn error<'de, D>(unexpected: &str, expected: &str) -> D::Error
 where
     D: Deserializer<'de>,
@@ -136,7 +668,7 @@ impl BrokerConfig {
         let mut s = Config::new();
         s.merge(File::from_str(DEFAULTS, FileFormat::Json))?;
 
-        s.try_into()
+        s.try_into().and_then(Self::validate)
     }
 
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
@@ -144,12 +676,50 @@ impl BrokerConfig {
         s.merge(File::from_str(DEFAULTS, FileFormat::Json))?;
         s.merge(File::from(path.as_ref()))?;
 
-        s.try_into()
+        s.try_into().and_then(Self::validate)
+    }
+
+    /// Checks the limits which can't be enforced by deserialization alone.
+    fn validate(self) -> Result<Self, ConfigError> {
+        let inflight = self.inflight_messages();
+        if inflight.max_count() == 0 || inflight.overrides.iter().any(|o| o.max_count == 0) {
+            return Err(invalid(
+                "inflight_messages.max_count must be greater than 0",
+            ));
+        }
+
+        let messages = self.session.messages();
+        if messages.max_count() == 0 {
+            return Err(invalid("session.messages.max_count must be greater than 0"));
+        }
+        if messages.max_total_space() < messages.max_message_size() {
+            return Err(invalid(
+                "session.messages.max_total_space must not be less than max_message_size",
+            ));
+        }
+
+        Ok(self)
     }
 
     pub fn persistence(&self) -> Option<&SessionPersistence> {
         self.persistence.as_ref()
     }
+
+    pub fn authentication(&self) -> Option<&Authentication> {
+        self.authentication.as_ref()
+    }
+
+    pub fn authorization(&self) -> Option<&Authorization> {
+        self.authorization.as_ref()
+    }
+
+    pub fn admin(&self) -> Option<&Admin> {
+        self.admin.as_ref()
+    }
+
+    pub fn bridges(&self) -> &[BridgeSettings] {
+        &self.bridges
+    }
 }
 
 #[cfg(test)]
@@ -164,7 +734,13 @@ mod tests {
     use serde_json::json;
     use test_case::test_case;
 
-    use crate::configuration::{humansize, BrokerConfig};
+    use mqtt3::proto::QoS;
+
+    use crate::configuration::{
+        humansize, Admin, BrokerConfig, Direction, LimitExceededAction, RateLimits, TopicRule,
+        Transport,
+    };
+    use crate::AuthId;
 
     #[test]
     fn it_loads_defaults() {
@@ -174,6 +750,10 @@ mod tests {
             settings.retained_messages.expiration,
             Duration::from_secs(60 * 24 * 60 * 60)
         );
+        assert_eq!(
+            settings.session().cleanup_interval(),
+            Duration::from_secs(60 * 60)
+        );
     }
 
     #[test]
@@ -187,6 +767,184 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_loads_inflight_messages_overrides() {
+        let settings = BrokerConfig::from_file(Path::new("test/config_inflight_overrides.json"))
+            .expect("should be able to create instance from configuration file");
+
+        let inflight = settings.inflight_messages();
+        assert_eq!(inflight.max_count(), 20);
+        assert_eq!(inflight.max_count_for("unknown", None), 20);
+        assert_eq!(inflight.max_count_for("Bridge", None), 1000);
+        assert_eq!(inflight.max_count_for("bridge", None), 20);
+        assert_eq!(
+            inflight.max_count_for("sensor", Some(&AuthId::from("sensors"))),
+            2
+        );
+        assert_eq!(
+            inflight.max_count_for("Bridge", Some(&AuthId::from("sensors"))),
+            1000
+        );
+        assert_eq!(
+            inflight.max_count_for("sensor", Some(&AuthId::Anonymous)),
+            20
+        );
+    }
+
+    #[test]
+    fn it_loads_limits() {
+        let settings = BrokerConfig::from_file(Path::new("test/config_limits.json"))
+            .expect("should be able to create instance from configuration file");
+
+        let limits = settings.limits();
+        assert_eq!(limits.max_packet_size(), Some(300 * 1024));
+        assert_eq!(
+            limits.rate_limits_for("sensor", None),
+            RateLimits::new(Some(100), Some(64 * 1024), LimitExceededAction::Disconnect)
+        );
+        assert_eq!(
+            limits.rate_limits_for("bridge", Some(&AuthId::from("sensors"))),
+            RateLimits::new(None, None, LimitExceededAction::Disconnect)
+        );
+        assert_eq!(
+            limits.rate_limits_for("sensor", Some(&AuthId::from("sensors"))),
+            RateLimits::new(Some(10), None, LimitExceededAction::Disconnect)
+        );
+
+        let limits = BrokerConfig::default().limits().clone();
+        assert_eq!(limits.max_packet_size(), None);
+        assert_eq!(
+            limits.rate_limits_for("sensor", None),
+            RateLimits::new(None, None, LimitExceededAction::Throttle)
+        );
+    }
+
+    #[test]
+    fn it_loads_authorization() {
+        let settings = BrokerConfig::from_file(Path::new("test/config_authorization.json"))
+            .expect("should be able to create instance from configuration file");
+
+        let authorization = settings
+            .authorization()
+            .expect("authorization is configured");
+        assert_eq!(
+            authorization.policy_file(),
+            Path::new("/etc/mqttd/policy.json")
+        );
+        assert_eq!(authorization.reload_interval(), Duration::from_secs(30));
+        assert!(BrokerConfig::default().authorization().is_none());
+    }
+
+    #[test]
+    fn it_loads_authentication() {
+        let settings = BrokerConfig::from_file(Path::new("test/config_authentication.json"))
+            .expect("should be able to create instance from configuration file");
+
+        let authentication = settings
+            .authentication()
+            .expect("authentication is configured");
+        assert_eq!(
+            authentication.password_file(),
+            Path::new("/etc/mqttd/passwd")
+        );
+        assert!(BrokerConfig::default().authentication().is_none());
+    }
+
+    #[test]
+    fn it_loads_bridges() {
+        let settings = BrokerConfig::from_file(Path::new("test/config_bridge.json"))
+            .expect("should be able to create instance from configuration file");
+
+        let bridge = &settings.bridges()[0];
+        assert_eq!(bridge.name(), "upstream");
+        assert_eq!(bridge.address(), "parent:8883");
+        assert_eq!(bridge.client_id(), "edge-child");
+        assert!(bridge.tls());
+        assert_eq!(
+            bridge.ca_bundle(),
+            Some(Path::new("/etc/mqttd/parent-ca.pem"))
+        );
+        assert_eq!(bridge.keep_alive(), Duration::from_secs(60));
+        assert_eq!(bridge.max_reconnect_back_off(), Duration::from_secs(10));
+
+        let rule = &bridge.topics()[0];
+        assert_eq!(rule.direction(), Direction::Out);
+        assert_eq!(rule.qos(), QoS::AtLeastOnce);
+        assert_eq!(rule.local_filter(), "telemetry/#");
+        assert_eq!(rule.remote_filter(), "devices/edge-child/telemetry/#");
+
+        let rule = &bridge.topics()[1];
+        assert_eq!(rule.direction(), Direction::Both);
+        assert_eq!(rule.qos(), QoS::AtMostOnce);
+        assert_eq!(rule.local_filter(), "local/commands/+");
+
+        assert!(BrokerConfig::default().bridges().is_empty());
+    }
+
+    #[test]
+    fn it_refuses_bridge_with_invalid_qos() {
+        let rule = serde_json::from_value::<TopicRule>(json!({
+            "pattern": "#",
+            "direction": "in",
+            "qos": 3
+        }));
+
+        assert!(rule.is_err());
+    }
+
+    #[test]
+    fn it_loads_persistence() {
+        let settings = BrokerConfig::from_file(Path::new("test/config_persistence.json"))
+            .expect("should be able to create instance from configuration file");
+
+        let persistence = settings.persistence().expect("persistence is configured");
+        assert_eq!(persistence.file_path(), "/var/lib/mqttd/wal");
+        assert_eq!(persistence.time_interval(), Duration::from_secs(2));
+        assert_eq!(persistence.unsaved_message_count(), 100);
+    }
+
+    #[test]
+    fn it_loads_statistics() {
+        let settings = BrokerConfig::from_file(Path::new("test/config_statistics.json"))
+            .expect("should be able to create instance from configuration file");
+
+        let statistics = settings.statistics();
+        assert_eq!(statistics.interval(), Duration::from_secs(30));
+        assert_eq!(statistics.metrics_address(), Some("0.0.0.0:9600"));
+
+        let statistics = BrokerConfig::default().statistics().clone();
+        assert_eq!(statistics.interval(), Duration::from_secs(10));
+        assert_eq!(statistics.metrics_address(), None);
+    }
+
+    #[test]
+    fn it_loads_admin() {
+        let settings = BrokerConfig::from_file(Path::new("test/config_admin.json"))
+            .expect("should be able to create instance from configuration file");
+
+        let admin = settings.admin().expect("admin");
+        assert_eq!(admin.address(), "unix:///var/run/mqttd/admin.sock");
+
+        let admin: Admin = serde_json::from_str(r#"{"address": "localhost:9601"}"#).unwrap();
+        assert_eq!(admin.address(), "localhost:9601");
+
+        assert!(BrokerConfig::default().admin().is_none());
+    }
+
+    #[test]
+    fn it_loads_unix_transport() {
+        let settings = BrokerConfig::from_file(Path::new("test/config_unix.json"))
+            .expect("should be able to create instance from configuration file");
+
+        assert_eq!(
+            settings.transports(),
+            &vec![Transport::Unix {
+                path: "/var/run/mqttd/mqttd.sock".into(),
+                permissions: Some(0o660),
+            }]
+        );
+    }
+
     #[test]
     fn it_refuses_persistence_with_no_file_path() {
         let settings = BrokerConfig::from_file(Path::new("test/config_no_file_path.json"));
@@ -194,6 +952,21 @@ mod tests {
         assert_matches!(settings, Err(_err));
     }
 
+    #[test]
+    fn it_refuses_zero_inflight_messages() {
+        let settings =
+            BrokerConfig::from_file(Path::new("test/config_zero_inflight_messages.json"));
+
+        assert_matches!(settings, Err(_err));
+    }
+
+    #[test]
+    fn it_refuses_zero_queue_limits() {
+        let settings = BrokerConfig::from_file(Path::new("test/config_zero_queue_limits.json"));
+
+        assert_matches!(settings, Err(_err));
+    }
+
     #[test]
     fn it_type_mismatch_fails() {
         let settings = BrokerConfig::from_file(Path::new("test/config_bad_value_type.json"));
@@ -1,4 +1,3 @@
-use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -21,8 +20,9 @@ use mqtt_edgehub::{
     translate_outgoing_publish,
 };
 
-use crate::broker::BrokerHandle;
-use crate::transport::GetPeerCertificate;
+use crate::broker::{BrokerHandle, TOPIC_ALIAS_MAXIMUM};
+use crate::rate_limit::{Acquire, ConnectionLimiter};
+use crate::transport::{Address, GetPeerCertificate, GetPeerCredentials};
 use crate::{Certificate, ClientEvent, ClientId, ConnReq, Error, Message, Publish};
 
 lazy_static! {
@@ -40,17 +40,27 @@ const KEEPALIVE_MULT: f32 = 1.5;
 pub struct ConnectionHandle {
     id: Uuid,
     sender: UnboundedSender<Message>,
+    rate_limiter: ConnectionLimiter,
 }
 
 impl ConnectionHandle {
     pub(crate) fn new(id: Uuid, sender: UnboundedSender<Message>) -> Self {
-        Self { id, sender }
+        Self {
+            id,
+            sender,
+            rate_limiter: ConnectionLimiter::default(),
+        }
     }
 
     pub fn from_sender(sender: UnboundedSender<Message>) -> Self {
         Self::new(Uuid::new_v4(), sender)
     }
 
+    /// Limits the rate of publications received on this connection.
+    pub fn rate_limiter(&self) -> &ConnectionLimiter {
+        &self.rate_limiter
+    }
+
     pub fn send(&mut self, message: Message) -> Result<(), Error> {
         self.sender
             .send(message)
@@ -76,19 +86,29 @@ impl PartialEq for ConnectionHandle {
 /// Starts two tasks (sending and receiving)
 pub async fn process<I>(
     io: I,
-    remote_addr: SocketAddr,
+    remote_addr: Address,
     mut broker_handle: BrokerHandle,
+    max_packet_size: Option<usize>,
 ) -> Result<(), Error>
 where
-    I: AsyncRead + AsyncWrite + GetPeerCertificate<Certificate = Certificate> + Unpin,
+    I: AsyncRead
+        + AsyncWrite
+        + GetPeerCertificate<Certificate = Certificate>
+        + GetPeerCredentials
+        + Unpin,
 {
     let certificate = io.peer_certificate()?;
+    let peer_credentials = io.peer_credentials()?;
 
     let mut timeout = TimeoutStream::new(io);
     timeout.set_read_timeout(Some(*DEFAULT_TIMEOUT));
     timeout.set_write_timeout(Some(*DEFAULT_TIMEOUT));
 
-    let mut codec = Framed::new(timeout, PacketCodec::default());
+    let mut codec = PacketCodec::default().with_topic_alias_maximum(TOPIC_ALIAS_MAXIMUM);
+    if let Some(max_packet_size) = max_packet_size {
+        codec = codec.with_max_packet_size(max_packet_size);
+    }
+    let mut codec = Framed::new(timeout, codec);
 
     // [MQTT-3.1.0-1] - After a Network Connection is established by a Client to a Server,
     // the first Packet sent from the Client to the Server MUST be a CONNECT Packet.
@@ -103,6 +123,7 @@ where
             let client_id = client_id(&connect.client_id);
             let (sender, events) = mpsc::unbounded_channel();
             let connection_handle = ConnectionHandle::from_sender(sender);
+            let rate_limiter = connection_handle.rate_limiter().clone();
             let span = span!(Level::INFO, "connection", client_id=%client_id, remote_addr=%remote_addr, connection=%connection_handle);
 
             // async block to attach instrumentation context
@@ -124,7 +145,12 @@ where
                     codec.get_mut().set_read_timeout(Some(keep_alive));
                 }
 
-                let req = ConnReq::new(client_id.clone(), connect, certificate, connection_handle);
+                if let Some(peer_credentials) = &peer_credentials {
+                    debug!("peer credentials: {:?}", peer_credentials);
+                }
+
+                let req = ConnReq::new(client_id.clone(), connect, certificate, connection_handle)
+                    .with_peer_credentials(peer_credentials);
                 let event = ClientEvent::ConnReq(req);
                 let message = Message::Client(client_id.clone(), event);
                 broker_handle.send(message).await?;
@@ -132,7 +158,7 @@ where
                 // Start up the processing tasks
                 let (outgoing, incoming) = codec.split();
                 let incoming_task =
-                    incoming_task(client_id.clone(), incoming, broker_handle.clone());
+                    incoming_task(client_id.clone(), incoming, broker_handle.clone(), rate_limiter);
                 let outgoing_task = outgoing_task(client_id.clone(), events, outgoing, broker_handle.clone());
                 pin_mut!(incoming_task);
                 pin_mut!(outgoing_task);
@@ -208,6 +234,7 @@ async fn incoming_task<S>(
     client_id: ClientId,
     mut incoming: S,
     mut broker: BrokerHandle,
+    rate_limiter: ConnectionLimiter,
 ) -> Result<(), Error>
 where
     S: Stream<Item = Result<Packet, DecodeError>> + Unpin,
@@ -237,6 +264,20 @@ where
                     Packet::PubAck(puback) => ClientEvent::PubAck(puback),
                     Packet::PubComp(pubcomp) => ClientEvent::PubComp(pubcomp),
                     Packet::Publish(publish) => {
+                        // Not reading further packets until the client is within its limits
+                        // throttles it through the transport flow control.
+                        match rate_limiter.acquire(publish.payload.len()) {
+                            Acquire::Ready => (),
+                            Acquire::Wait(wait) => {
+                                debug!("rate limit reached. throttling connection for {:?}", wait);
+                                tokio::time::delay_for(wait).await;
+                            }
+                            Acquire::Exceeded => {
+                                warn!("publication exceeds client limits, dropping connection");
+                                return Err(Error::RateLimitExceeded);
+                            }
+                        }
+
                         let publish = translate_incoming_publish(&client_id.0, publish);
                         ClientEvent::PublishFrom(publish)
                     }
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use mqtt3::proto::Packet;
+use mqtt3::proto::{self, Packet};
 use thiserror::Error;
 
 use crate::Message;
@@ -37,6 +37,12 @@ pub enum Error {
     #[error("Provided topic filter is invalid: {0}")]
     InvalidTopicFilter(String),
 
+    #[error("Session queue is full.")]
+    SessionQueueFull,
+
+    #[error("Client exceeded its rate or message size limits.")]
+    RateLimitExceeded,
+
     #[error("All packet identifiers are exhausted.")]
     PacketIdentifiersExhausted,
 
@@ -50,11 +56,20 @@ pub enum Error {
     Persist(#[from] crate::persist::PersistError),
 
     #[error("Unable to obtain peer certificate.")]
-    PeerCertificate(#[source] native_tls::Error),
+    PeerCertificate(#[source] openssl::error::ErrorStack),
+
+    #[error("Unable to obtain peer credentials.")]
+    PeerCredentials(#[source] std::io::Error),
 
     #[error("Unable to start broker.")]
     InitializeBroker(#[from] InitializeBrokerError),
 
+    #[error("Bridge {0} was refused by the broker: {1:?}")]
+    BridgeRefused(String, proto::ConnectionRefusedReason),
+
+    #[error("An error occurred setting up bridge {0} client.")]
+    BridgeClient(String, #[source] Box<dyn std::error::Error + Send + Sync>),
+
     #[error("An error occurred when constructing state change: {0}")]
     StateChange(#[from] serde_json::Error),
 }
@@ -78,8 +93,17 @@ pub enum InitializeBrokerError {
     LoadIdentity(PathBuf, #[source] std::io::Error),
 
     #[error("An error occurred  decoding identity content.")]
-    DecodeIdentity(#[source] native_tls::Error),
+    DecodeIdentity(#[source] openssl::error::ErrorStack),
 
     #[error("An error occurred  bootstrapping TLS")]
-    Tls(#[source] native_tls::Error),
+    Tls(#[source] openssl::error::ErrorStack),
+
+    #[error("An error occurred loading authorization policy.")]
+    LoadPolicy(#[source] crate::auth::PolicyError),
+
+    #[error("An error occurred loading password file.")]
+    LoadPasswordFile(#[source] crate::auth::PasswordFileError),
+
+    #[error("An error occurred loading client certificate CA bundle from file {0}.")]
+    LoadClientCaBundle(PathBuf, #[source] openssl::error::ErrorStack),
 }
@@ -16,32 +16,50 @@ use std::sync::Arc;
 use mqtt3::proto;
 use serde::{Deserialize, Serialize};
 
+mod admin;
 mod auth;
+mod bridge;
 mod broker;
 mod configuration;
 mod connection;
 mod error;
 mod persist;
+mod rate_limit;
 mod server;
 mod session;
 mod snapshot;
 mod state_change;
+mod stats;
 mod subscription;
+mod subscription_trie;
 mod transport;
+mod wal;
 
-pub use crate::auth::{AuthId, Authenticator, Authorizer, Certificate};
-pub use crate::broker::{Broker, BrokerBuilder, BrokerHandle, BrokerState};
-pub use crate::configuration::BrokerConfig;
+pub use crate::admin::{AdminError, AdminHandle, AdminRequest, SessionInfo, SessionStatus};
+pub use crate::auth::{
+    Activity, AuthId, AuthenticateError, Authenticator, AuthorizeError, Authorizer, Certificate,
+    CertificateError, Credentials, PasswordAuthenticator, PasswordFile, PasswordFileError,
+    PeerCredentials, Policy, PolicyAuthorizer, PolicyError,
+};
+pub use crate::bridge::Bridge;
+pub use crate::broker::{Broker, BrokerBuilder, BrokerHandle, BrokerState, RetainedPublication};
+pub use crate::configuration::{
+    BridgeSettings, BrokerConfig, Direction, LimitExceededAction, Limits, QueueFullAction,
+    RateLimits, TopicRule, Transport as TransportConfig,
+};
 pub use crate::connection::ConnectionHandle;
 pub use crate::error::{Error, InitializeBrokerError};
 pub use crate::persist::{
     FileFormat, FilePersistor, NullPersistor, Persist, PersistError, VersionedFileFormat,
 };
+pub use crate::rate_limit::{Acquire, ConnectionLimiter, RateLimiter};
 pub use crate::server::Server;
-pub use crate::session::SessionState;
+pub use crate::session::{SessionConfig, SessionState};
 pub use crate::snapshot::{Snapshotter, StateSnapshotHandle};
+pub use crate::stats::{BrokerStats, StatsHandle};
 pub use crate::subscription::{Segment, Subscription, TopicFilter};
-pub use crate::transport::TransportBuilder;
+pub use crate::transport::{Address, TransportBuilder, WsStream, MQTT_SUBPROTOCOL};
+pub use crate::wal::{journal, Journal, JournalWriter, WalPersistor};
 
 #[cfg(any(test, feature = "proptest"))]
 pub mod proptest;
@@ -71,6 +89,8 @@ pub struct ConnReq {
     client_id: ClientId,
     connect: proto::Connect,
     certificate: Option<Certificate>,
+    peer_credentials: Option<PeerCredentials>,
+    auth_id: Option<AuthId>,
     handle: ConnectionHandle,
 }
 
@@ -85,10 +105,28 @@ impl ConnReq {
             client_id,
             connect,
             certificate,
+            peer_credentials: None,
+            auth_id: None,
             handle,
         }
     }
 
+    /// Attaches credentials of a peer process connected over unix domain socket.
+    #[must_use]
+    pub fn with_peer_credentials(mut self, peer_credentials: Option<PeerCredentials>) -> Self {
+        self.peer_credentials = peer_credentials;
+        self
+    }
+
+    /// Marks a connection opened by the broker process itself as already authenticated.
+    ///
+    /// The authenticator is not consulted for such connections, authorization still applies.
+    #[must_use]
+    pub fn with_auth_id(mut self, auth_id: Option<AuthId>) -> Self {
+        self.auth_id = auth_id;
+        self
+    }
+
     pub fn client_id(&self) -> &ClientId {
         &self.client_id
     }
@@ -105,6 +143,14 @@ impl ConnReq {
         self.certificate.as_ref()
     }
 
+    pub fn peer_credentials(&self) -> Option<&PeerCredentials> {
+        self.peer_credentials.as_ref()
+    }
+
+    pub fn auth_id(&self) -> Option<&AuthId> {
+        self.auth_id.as_ref()
+    }
+
     pub fn handle_mut(&mut self) -> &mut ConnectionHandle {
         &mut self.handle
     }
@@ -185,7 +231,14 @@ pub enum ClientEvent {
 pub enum SystemEvent {
     Shutdown,
     StateSnapshot(StateSnapshotHandle),
-    // ConfigUpdate,
+    SessionCleanup,
+    PublishStats,
+    JournalFlush,
+    JournalCheckpoint,
+    JournalPersisted(u64),
+    JournalFailed,
+    Admin(AdminRequest),
+    ConfigUpdate(BrokerConfig),
 }
 
 #[derive(Debug)]
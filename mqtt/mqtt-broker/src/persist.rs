@@ -8,6 +8,7 @@ use std::os::unix::fs::symlink;
 #[cfg(windows)]
 use std::os::windows::fs::symlink_file;
 use std::path::PathBuf;
+use std::time::SystemTime;
 
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -24,6 +25,7 @@ use crate::session::SessionState;
 use crate::subscription::Subscription;
 use crate::BrokerState;
 use crate::ClientId;
+use crate::RetainedPublication;
 
 /// sets the number of past states to save - 2 means we save the current and the pervious
 const STATE_DEFAULT_PREVIOUS_COUNT: usize = 2;
@@ -110,19 +112,21 @@ impl<F> FilePersistor<F> {
 
 #[derive(Deserialize, Serialize)]
 enum VersionedState {
-    V1(ConsolidatedState),
+    V1(ConsolidatedStateV1),
+    V2(ConsolidatedState),
 }
 
 impl From<BrokerState> for VersionedState {
     fn from(state: BrokerState) -> Self {
-        VersionedState::V1(state.into())
+        VersionedState::V2(state.into())
     }
 }
 
 impl From<VersionedState> for BrokerState {
     fn from(state: VersionedState) -> Self {
         match state {
-            VersionedState::V1(state) => state.into(),
+            VersionedState::V1(state) => ConsolidatedState::from(state).into(),
+            VersionedState::V2(state) => state.into(),
         }
     }
 }
@@ -173,17 +177,26 @@ impl From<BrokerState> for ConsolidatedState {
                 qos: publication.qos,
                 retain: publication.retain,
                 payload: id,
+                properties: publication.properties,
             }
         };
 
         let retained = retained
             .into_iter()
-            .map(|(topic, publication)| (topic, shrink_payload(publication)))
+            .map(|(topic, retained)| {
+                let (publication, retained_at) = retained.into_parts();
+                let retained = SimplifiedRetainedPublication {
+                    publication: shrink_payload(publication),
+                    retained_at,
+                };
+                (topic, retained)
+            })
             .collect();
 
         let sessions = sessions
             .into_iter()
             .map(|session| {
+                let last_seen = session.last_seen();
                 let (client_id, subscriptions, waiting_to_be_sent) = session.into_parts();
 
                 #[allow(clippy::redundant_closure)] // removing closure leads to borrow error
@@ -196,6 +209,7 @@ impl From<BrokerState> for ConsolidatedState {
                     client_id,
                     subscriptions,
                     waiting_to_be_sent,
+                    last_seen,
                 }
             })
             .collect();
@@ -231,11 +245,16 @@ impl From<ConsolidatedState> for BrokerState {
                 .get(&publication.payload)
                 .expect("corrupted data")
                 .clone(),
+            properties: publication.properties,
         };
 
         let retained = retained
             .into_iter()
-            .map(|(topic, publication)| (topic, expand_payload(publication)))
+            .map(|(topic, retained)| {
+                let publication = expand_payload(retained.publication);
+                let retained = RetainedPublication::new(publication, retained.retained_at);
+                (topic, retained)
+            })
             .collect();
 
         #[allow(clippy::redundant_closure)] // removing closure leads to borrow error
@@ -247,11 +266,13 @@ impl From<ConsolidatedState> for BrokerState {
                     .into_iter()
                     .map(|publication| expand_payload(publication))
                     .collect();
-                SessionState::from_parts(
+                let mut state = SessionState::from_parts(
                     session.client_id,
                     session.subscriptions,
                     waiting_to_be_sent,
-                )
+                );
+                state.set_last_seen(session.last_seen);
+                state
             })
             .collect();
 
@@ -259,20 +280,102 @@ impl From<ConsolidatedState> for BrokerState {
     }
 }
 
+impl From<ConsolidatedStateV1> for ConsolidatedState {
+    fn from(state: ConsolidatedStateV1) -> Self {
+        let ConsolidatedStateV1 {
+            payloads,
+            retained,
+            sessions,
+        } = state;
+
+        // V1 did not keep track of retention time nor of when clients were last seen,
+        // so retained messages and sessions loaded from it start aging from the moment
+        // they are loaded. It did not keep MQTT 5 properties either.
+        let now = SystemTime::now();
+        let retained = retained
+            .into_iter()
+            .map(|(topic, publication)| {
+                let retained = SimplifiedRetainedPublication {
+                    publication: publication.into(),
+                    retained_at: now,
+                };
+                (topic, retained)
+            })
+            .collect();
+
+        let sessions = sessions
+            .into_iter()
+            .map(|session| ConsolidatedSession {
+                client_id: session.client_id,
+                subscriptions: session.subscriptions,
+                waiting_to_be_sent: session
+                    .waiting_to_be_sent
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+                last_seen: now,
+            })
+            .collect();
+
+        ConsolidatedState {
+            payloads,
+            retained,
+            sessions,
+        }
+    }
+}
+
+impl From<SimplifiedPublicationV1> for SimplifiedPublication {
+    fn from(publication: SimplifiedPublicationV1) -> Self {
+        SimplifiedPublication {
+            topic_name: publication.topic_name,
+            qos: publication.qos,
+            retain: publication.retain,
+            payload: publication.payload,
+            properties: crate::proto::Properties::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct ConsolidatedStateV1 {
+    #[serde(serialize_with = "serialize_payloads")]
+    #[serde(deserialize_with = "deserialize_payloads")]
+    payloads: HashMap<u64, Bytes>,
+    retained: HashMap<String, SimplifiedPublicationV1>,
+    sessions: Vec<ConsolidatedSessionV1>,
+}
+
 #[derive(Deserialize, Serialize)]
 struct ConsolidatedState {
     #[serde(serialize_with = "serialize_payloads")]
     #[serde(deserialize_with = "deserialize_payloads")]
     payloads: HashMap<u64, Bytes>,
-    retained: HashMap<String, SimplifiedPublication>,
+    retained: HashMap<String, SimplifiedRetainedPublication>,
     sessions: Vec<ConsolidatedSession>,
 }
 
+#[derive(Deserialize, Serialize)]
+struct ConsolidatedSessionV1 {
+    client_id: ClientId,
+    subscriptions: HashMap<String, Subscription>,
+    waiting_to_be_sent: Vec<SimplifiedPublicationV1>,
+}
+
 #[derive(Deserialize, Serialize)]
 struct ConsolidatedSession {
     client_id: ClientId,
     subscriptions: HashMap<String, Subscription>,
     waiting_to_be_sent: Vec<SimplifiedPublication>,
+    last_seen: SystemTime,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SimplifiedPublicationV1 {
+    topic_name: String,
+    qos: crate::proto::QoS,
+    retain: bool,
+    payload: u64,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -281,6 +384,13 @@ struct SimplifiedPublication {
     qos: crate::proto::QoS,
     retain: bool,
     payload: u64,
+    properties: crate::proto::Properties,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SimplifiedRetainedPublication {
+    publication: SimplifiedPublication,
+    retained_at: SystemTime,
 }
 
 fn serialize_payloads<S>(payloads: &HashMap<u64, Bytes>, serializer: S) -> Result<S::Ok, S::Error>
@@ -507,6 +617,12 @@ pub enum PersistError {
     #[error("failed to open file {0}")]
     FileOpen(PathBuf, #[source] Option<std::io::Error>),
 
+    #[error("failed to read file {0}")]
+    FileRead(PathBuf, #[source] Option<std::io::Error>),
+
+    #[error("failed to write file {0}")]
+    FileWrite(PathBuf, #[source] Option<std::io::Error>),
+
     #[error("failed to rename file {0} to {}")]
     FileRename(PathBuf, PathBuf, #[source] Option<std::io::Error>),
 
@@ -537,15 +653,25 @@ pub enum PersistError {
 
 #[cfg(test)]
 pub(crate) mod tests {
+    use std::collections::HashMap;
     use std::io::Cursor;
+    use std::iter::FromIterator;
 
+    use bytes::Bytes;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
     use proptest::prelude::*;
     use tempfile::TempDir;
 
+    use mqtt3::proto::QoS;
+
     use crate::{
-        persist::{ConsolidatedState, FileFormat, FilePersistor, Persist, VersionedFileFormat},
+        persist::{
+            ConsolidatedSessionV1, ConsolidatedState, ConsolidatedStateV1, FileFormat,
+            FilePersistor, Persist, SimplifiedPublicationV1, VersionedFileFormat, VersionedState,
+        },
         proptest::arb_broker_state,
-        BrokerState,
+        BrokerState, ClientId,
     };
 
     proptest! {
@@ -564,6 +690,7 @@ pub(crate) mod tests {
             prop_assert_eq!(expected_sessions.len(), result_sessions.len());
             for i in 0..expected_sessions.len(){
                 prop_assert_eq!(expected_sessions[i].clone().into_parts(), result_sessions[i].clone().into_parts());
+                prop_assert_eq!(expected_sessions[i].last_seen(), result_sessions[i].last_seen());
             }
         }
 
@@ -583,10 +710,42 @@ pub(crate) mod tests {
             prop_assert_eq!(expected_sessions.len(), result_sessions.len());
             for i in 0..expected_sessions.len(){
                 prop_assert_eq!(expected_sessions[i].clone().into_parts(), result_sessions[i].clone().into_parts());
+                prop_assert_eq!(expected_sessions[i].last_seen(), result_sessions[i].last_seen());
             }
         }
     }
 
+    #[test]
+    fn load_v1() {
+        let publication = || SimplifiedPublicationV1 {
+            topic_name: "topic".to_string(),
+            qos: QoS::AtLeastOnce,
+            retain: true,
+            payload: 0,
+        };
+        let state = VersionedState::V1(ConsolidatedStateV1 {
+            payloads: HashMap::from_iter(vec![(0, Bytes::from("payload"))]),
+            retained: HashMap::from_iter(vec![("topic".to_string(), publication())]),
+            sessions: vec![ConsolidatedSessionV1 {
+                client_id: ClientId::from("client"),
+                subscriptions: HashMap::new(),
+                waiting_to_be_sent: vec![publication()],
+            }],
+        });
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        bincode::serialize_into(&mut encoder, &state).unwrap();
+        let buffer = encoder.finish().unwrap();
+
+        let state = VersionedFileFormat.load(Cursor::new(buffer)).unwrap();
+
+        let (retained, sessions) = state.into_parts();
+        assert_eq!(retained["topic"].publication().payload, "payload");
+        assert_eq!(sessions.len(), 1);
+        let (client_id, _, waiting_to_be_sent) = sessions[0].clone().into_parts();
+        assert_eq!(client_id, ClientId::from("client"));
+        assert_eq!(waiting_to_be_sent[0].payload, "payload");
+    }
+
     #[tokio::test]
     async fn filepersistor_smoketest() {
         let tmp_dir = TempDir::new().unwrap();
@@ -1,22 +1,23 @@
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use bytes::Bytes;
 use mqtt3::proto;
 use proptest::{
     bool,
     collection::{hash_map, hash_set, vec, vec_deque},
-    num,
+    num, option,
     prelude::*,
 };
 
 use crate::{
     session::{IdentifiersInUse, PacketIdentifiers},
-    BrokerState, ClientId, Publish, Segment, SessionState, Subscription, TopicFilter,
+    BrokerState, ClientId, Publish, RetainedPublication, Segment, SessionState, Subscription,
+    TopicFilter,
 };
 
 prop_compose! {
     pub fn arb_broker_state()(
-        retained in hash_map(arb_topic(), arb_publication(), 0..20),
+        retained in hash_map(arb_topic(), arb_retained_publication(), 0..20),
         sessions in vec(arb_session_state(), 0..10),
     ) -> BrokerState {
         BrokerState::new(retained, sessions)
@@ -52,6 +53,7 @@ prop_compose! {
         waiting_to_be_acked in hash_map(arb_packet_identifier(), arb_publish(), 0..10),
         waiting_to_be_acked_qos0 in hash_map(arb_packet_identifier(), arb_publish(), 0..10),
         waiting_to_be_completed in hash_set(arb_packet_identifier(), 0..10),
+        last_seen in arb_system_time(),
     ) -> SessionState {
         SessionState::from_state_parts(
             client_id,
@@ -63,6 +65,7 @@ prop_compose! {
             waiting_to_be_acked,
             waiting_to_be_acked_qos0,
             waiting_to_be_completed,
+            last_seen,
         )
     }
 }
@@ -80,6 +83,7 @@ prop_compose! {
             keep_alive: Duration::from_secs(1),
             protocol_name: mqtt3::PROTOCOL_NAME.into(),
             protocol_level: mqtt3::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         }
     }
 }
@@ -177,16 +181,46 @@ prop_compose! {
         qos in arb_qos(),
         retain in proptest::bool::ANY,
         payload in arb_payload(),
+        properties in arb_properties(),
     ) -> proto::Publication {
         proto::Publication {
             topic_name,
             qos,
             retain,
             payload,
+            properties,
         }
     }
 }
 
+prop_compose! {
+    pub fn arb_properties()(
+        message_expiry_interval in option::of(num::u32::ANY),
+        content_type in option::of("\\PC{0,32}"),
+        user_properties in vec(("\\PC{0,32}", "\\PC{0,32}"), 0..4),
+    ) -> proto::Properties {
+        proto::Properties {
+            message_expiry_interval,
+            content_type,
+            user_properties,
+            ..proto::Properties::default()
+        }
+    }
+}
+
+prop_compose! {
+    pub fn arb_retained_publication()(
+        publication in arb_publication(),
+        retained_at in arb_system_time(),
+    ) -> RetainedPublication {
+        RetainedPublication::new(publication, retained_at)
+    }
+}
+
+pub fn arb_system_time() -> impl Strategy<Value = SystemTime> {
+    (0..=u64::from(u32::max_value())).prop_map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+}
+
 pub fn arb_pidq() -> impl Strategy<Value = proto::PacketIdentifierDupQoS> {
     prop_oneof![
         Just(proto::PacketIdentifierDupQoS::AtMostOnce),
@@ -209,6 +243,7 @@ prop_compose! {
             retain,
             topic_name,
             payload,
+            properties: proto::Properties::default(),
         }
     }
 }
@@ -0,0 +1,323 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::configuration::{LimitExceededAction, RateLimits};
+
+/// Outcome of asking a rate limiter for permission to process a message.
+#[derive(Debug, PartialEq)]
+pub enum Acquire {
+    /// The message is within limits.
+    Ready,
+
+    /// The message is within limits once the client has waited for the given time.
+    Wait(Duration),
+
+    /// The message exceeds the limits or the maximum message size
+    /// and the client should be disconnected.
+    Exceeded,
+}
+
+/// Limits the rate of messages and bytes a client publishes
+/// and the size of each message.
+///
+/// The broker keeps a limiter for every client id and configures it once
+/// the client is authenticated and on configuration updates. The limiter
+/// outlives the connections of the client, so a client can't get a fresh
+/// set of tokens by reconnecting.
+/// A limiter which has not been configured allows everything.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimiter(Arc<Mutex<Buckets>>);
+
+/// The rate limiter of the client on a connection.
+///
+/// The connection asks it before forwarding a publication to the broker,
+/// and the broker assigns it the limiter of the client once the client is
+/// authenticated. A connection without a limiter allows everything.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionLimiter(Arc<Mutex<Option<RateLimiter>>>);
+
+#[derive(Debug, Default)]
+struct Buckets {
+    messages: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+    when_exceeded: LimitExceededAction,
+    max_message_size: Option<u64>,
+}
+
+impl RateLimiter {
+    /// Sets the limits, keeping the tokens left in the buckets.
+    pub fn set_limits(&self, limits: RateLimits, max_message_size: u64) {
+        let now = Instant::now();
+        if let Ok(mut buckets) = self.0.lock() {
+            let messages = limits.messages_per_second().map(f64::from);
+            buckets.messages = TokenBucket::with_rate(buckets.messages.take(), messages, now);
+            let bytes = limits.bytes_per_second().map(as_f64);
+            buckets.bytes = TokenBucket::with_rate(buckets.bytes.take(), bytes, now);
+            buckets.when_exceeded = limits.when_exceeded();
+            buckets.max_message_size = Some(max_message_size);
+        }
+    }
+
+    /// Returns `true` if the buckets are full, which makes the limiter
+    /// no different from a new one.
+    pub fn is_full(&self) -> bool {
+        let now = Instant::now();
+        match self.0.lock() {
+            Ok(mut buckets) => {
+                let Buckets {
+                    messages, bytes, ..
+                } = &mut *buckets;
+                messages.iter_mut().chain(bytes.iter_mut()).all(|bucket| {
+                    bucket.refill(now);
+                    bucket.tokens >= bucket.rate
+                })
+            }
+            Err(_) => true,
+        }
+    }
+
+    /// Takes the tokens for a single message with a payload of a given size.
+    pub fn acquire(&self, size: usize) -> Acquire {
+        let mut buckets = match self.0.lock() {
+            Ok(buckets) => buckets,
+            Err(_) => return Acquire::Ready,
+        };
+        buckets.acquire(size as u64, Instant::now())
+    }
+}
+
+impl ConnectionLimiter {
+    pub fn assign(&self, limiter: RateLimiter) {
+        if let Ok(mut current) = self.0.lock() {
+            *current = Some(limiter);
+        }
+    }
+
+    /// Takes the tokens for a single message with a payload of a given size.
+    pub fn acquire(&self, size: usize) -> Acquire {
+        let limiter = match self.0.lock() {
+            Ok(limiter) => limiter.clone(),
+            Err(_) => None,
+        };
+        limiter.map_or(Acquire::Ready, |limiter| limiter.acquire(size))
+    }
+}
+
+impl Buckets {
+    fn acquire(&mut self, size: u64, now: Instant) -> Acquire {
+        if self.max_message_size.map_or(false, |max| size > max) {
+            return Acquire::Exceeded;
+        }
+
+        let mut buckets = Vec::with_capacity(2);
+        if let Some(messages) = &mut self.messages {
+            buckets.push((messages, 1.0));
+        }
+        if let Some(bytes) = &mut self.bytes {
+            let mut amount = as_f64(size);
+            // Without going into debt, a bucket never holds more than a second
+            // worth of bytes, so a bigger message costs the whole bucket.
+            if self.when_exceeded == LimitExceededAction::Disconnect {
+                amount = amount.min(bytes.rate);
+            }
+            buckets.push((bytes, amount));
+        }
+
+        for (bucket, _) in &mut buckets {
+            bucket.refill(now);
+        }
+
+        match self.when_exceeded {
+            LimitExceededAction::Disconnect => {
+                if buckets
+                    .iter()
+                    .any(|(bucket, amount)| bucket.tokens < *amount)
+                {
+                    return Acquire::Exceeded;
+                }
+                for (bucket, amount) in buckets {
+                    bucket.take(amount);
+                }
+                Acquire::Ready
+            }
+            LimitExceededAction::Throttle => {
+                let wait = buckets
+                    .into_iter()
+                    .map(|(bucket, amount)| bucket.take(amount))
+                    .max()
+                    .unwrap_or_default();
+                if wait == Duration::default() {
+                    Acquire::Ready
+                } else {
+                    Acquire::Wait(wait)
+                }
+            }
+        }
+    }
+}
+
+/// A bucket holding up to one second worth of tokens, refilled at a constant rate.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate,
+            updated: now,
+        }
+    }
+
+    /// Changes the rate of a bucket, keeping the tokens left in it.
+    fn with_rate(bucket: Option<Self>, rate: Option<f64>, now: Instant) -> Option<Self> {
+        let rate = rate?;
+        let bucket = match bucket {
+            Some(mut bucket) => {
+                bucket.refill(now);
+                bucket.rate = rate;
+                bucket.tokens = bucket.tokens.min(rate);
+                bucket
+            }
+            None => Self::new(rate, now),
+        };
+        Some(bucket)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.updated = now;
+    }
+
+    /// Takes tokens going into debt if there are not enough of them,
+    /// and returns the time it takes to pay the debt off.
+    fn take(&mut self, amount: f64) -> Duration {
+        self.tokens -= amount;
+        if self.tokens >= 0.0 || self.rate <= 0.0 {
+            Duration::default()
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn as_f64(value: u64) -> f64 {
+    value as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Acquire, Buckets, ConnectionLimiter, RateLimiter, TokenBucket};
+    use crate::configuration::{LimitExceededAction, RateLimits};
+
+    fn buckets(messages: f64, bytes: f64, when_exceeded: LimitExceededAction) -> Buckets {
+        let now = Instant::now();
+        Buckets {
+            messages: Some(TokenBucket::new(messages, now)),
+            bytes: Some(TokenBucket::new(bytes, now)),
+            when_exceeded,
+            max_message_size: Some(1024),
+        }
+    }
+
+    #[test]
+    fn it_throttles_messages_over_the_rate() {
+        let mut buckets = buckets(2.0, 1000.0, LimitExceededAction::Throttle);
+        let now = Instant::now();
+
+        assert_eq!(buckets.acquire(10, now), Acquire::Ready);
+        assert_eq!(buckets.acquire(10, now), Acquire::Ready);
+        assert_eq!(
+            buckets.acquire(10, now),
+            Acquire::Wait(Duration::from_millis(500))
+        );
+
+        // the debt is paid off after waiting
+        let now = now + Duration::from_millis(500);
+        assert_eq!(
+            buckets.acquire(10, now),
+            Acquire::Wait(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn it_throttles_bytes_over_the_rate() {
+        let mut buckets = buckets(100.0, 1000.0, LimitExceededAction::Throttle);
+        let now = Instant::now();
+
+        assert_eq!(buckets.acquire(600, now), Acquire::Ready);
+        assert_eq!(
+            buckets.acquire(600, now),
+            Acquire::Wait(Duration::from_millis(200))
+        );
+    }
+
+    #[test]
+    fn it_reports_exceeded_limits_without_taking_tokens() {
+        let mut buckets = buckets(1.0, 1000.0, LimitExceededAction::Disconnect);
+        let now = Instant::now();
+
+        assert_eq!(buckets.acquire(10, now), Acquire::Ready);
+        assert_eq!(buckets.acquire(10, now), Acquire::Exceeded);
+
+        let now = now + Duration::from_secs(1);
+        assert_eq!(buckets.acquire(10, now), Acquire::Ready);
+    }
+
+    #[test]
+    fn it_caps_the_cost_of_messages_bigger_than_the_byte_rate() {
+        let mut buckets = buckets(100.0, 1000.0, LimitExceededAction::Disconnect);
+        let now = Instant::now();
+
+        assert_eq!(buckets.acquire(1024, now), Acquire::Ready);
+        assert_eq!(buckets.acquire(10, now), Acquire::Exceeded);
+
+        let now = now + Duration::from_secs(1);
+        assert_eq!(buckets.acquire(1024, now), Acquire::Ready);
+    }
+
+    #[test]
+    fn it_shares_tokens_between_connections_of_a_client() {
+        let limiter = RateLimiter::default();
+        let limits = RateLimits::new(Some(1), None, LimitExceededAction::Disconnect);
+        limiter.set_limits(limits, 1024);
+
+        let first = ConnectionLimiter::default();
+        assert_eq!(first.acquire(10), Acquire::Ready);
+        first.assign(limiter.clone());
+        assert_eq!(first.acquire(10), Acquire::Ready);
+        assert!(!limiter.is_full());
+
+        limiter.set_limits(limits, 1024);
+        let second = ConnectionLimiter::default();
+        second.assign(limiter);
+        assert_eq!(second.acquire(10), Acquire::Exceeded);
+    }
+
+    #[test]
+    fn it_reports_messages_over_the_maximum_size() {
+        let mut buckets = buckets(100.0, 10_000.0, LimitExceededAction::Throttle);
+        let now = Instant::now();
+
+        assert_eq!(buckets.acquire(1024, now), Acquire::Ready);
+        assert_eq!(buckets.acquire(1025, now), Acquire::Exceeded);
+    }
+
+    #[test]
+    fn it_allows_everything_without_limits() {
+        let mut buckets = Buckets::default();
+        let now = Instant::now();
+
+        for _ in 0..1000 {
+            assert_eq!(buckets.acquire(1_000_000, now), Acquire::Ready);
+        }
+    }
+}
@@ -1,8 +1,11 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::future::Future;
+use std::hash::Hash;
 
 use futures_util::future::{self, Either, FutureExt};
-use futures_util::pin_mut;
-use futures_util::stream::StreamExt;
+use futures_util::select;
+use futures_util::stream::{self, FuturesUnordered, Stream, StreamExt};
 use tokio::{net::ToSocketAddrs, sync::oneshot};
 use tracing::{debug, error, info, span, warn, Level};
 use tracing_futures::Instrument;
@@ -38,116 +41,207 @@ where
         A: ToSocketAddrs,
         F: Future<Output = ()> + Unpin,
         I: IntoIterator<Item = TransportBuilder<A>>,
+    {
+        let transports = transports.into_iter().enumerate().collect();
+        self.serve_with_reload(transports, stream::pending(), shutdown_signal)
+            .await
+    }
+
+    /// Serves the broker the same way as [`serve`] does, replacing the set of
+    /// listeners every time `updates` yields a new one.
+    ///
+    /// Listeners are identified by a key. Listeners whose key is missing from
+    /// an update stop accepting connections, listeners with a new key start
+    /// and the rest keep running. New listeners start only once the removed
+    /// ones have stopped, so that they can bind the addresses released by them.
+    /// Established connections are not affected.
+    ///
+    /// Unlike the initial listeners, a listener started by an update which
+    /// fails to bind or stops accepting connections doesn't shut down the server.
+    ///
+    /// [`serve`]: #method.serve
+    pub async fn serve_with_reload<K, A, F, U>(
+        self,
+        transports: Vec<(K, TransportBuilder<A>)>,
+        updates: U,
+        shutdown_signal: F,
+    ) -> Result<BrokerState, Error>
+    where
+        K: Eq + Hash,
+        A: ToSocketAddrs,
+        F: Future<Output = ()> + Unpin,
+        U: Stream<Item = Vec<(K, TransportBuilder<A>)>> + Unpin,
     {
         let Server { broker } = self;
         let mut handle = broker.handle();
-        let broker_task = tokio::spawn(broker.run());
-
-        let mut incoming_tasks = Vec::new();
-        let mut shutdown_handles = Vec::new();
-        for transport in transports {
-            let (itx, irx) = oneshot::channel::<()>();
-            shutdown_handles.push(itx);
-
-            let incoming_task = incoming_task(transport, handle.clone(), irx.map(drop));
-
-            let incoming_task = Box::pin(incoming_task);
+        let max_packet_size = broker
+            .config()
+            .limits()
+            .max_packet_size()
+            .map(|size| usize::try_from(size).unwrap_or(usize::MAX));
+        let mut broker_task = tokio::spawn(broker.run()).fuse();
+        let mut shutdown_signal = shutdown_signal.fuse();
+        let mut updates = updates.fuse();
+
+        let mut next_id = 0;
+        let mut listeners = HashMap::new();
+        let mut incoming_tasks = FuturesUnordered::new();
+        // listeners removed by an update which haven't stopped yet
+        let mut stopping = HashSet::new();
+        // listeners added by an update waiting for the removed ones to stop
+        let mut pending = vec![];
+        for (key, transport) in transports {
+            next_id += 1;
+            let (listener, incoming_task) =
+                listen(next_id, false, transport, handle.clone(), max_packet_size);
+            listeners.insert(key, listener);
             incoming_tasks.push(incoming_task);
         }
 
-        pin_mut!(broker_task);
+        let broker_state = loop {
+            let event = select! {
+                _ = shutdown_signal => Event::Shutdown,
+                broker_state = broker_task => Event::BrokerExited(broker_state),
+                (id, result) = incoming_tasks.select_next_some() => Event::Stopped(id, result),
+                transports = updates.select_next_some() => Event::Update(transports),
+            };
 
-        let incoming_tasks = future::select_all(incoming_tasks);
-        let main_task = future::select(broker_task, incoming_tasks);
+            match event {
+                Event::Shutdown => {
+                    info!("server received shutdown signal");
 
-        // Handle shutdown
-        match future::select(shutdown_signal, main_task).await {
-            Either::Left((_, tasks)) => {
-                info!("server received shutdown signal");
+                    // shutdown the incoming loop
+                    info!("shutting down accept loop...");
 
-                // shutdown the incoming loop
-                info!("shutting down accept loop...");
+                    debug!("sending stop signal for every protocol head");
+                    send_shutdown(listeners.drain().map(|(_, listener)| listener.shutdown));
+                    stopped(&mut incoming_tasks).await;
 
-                debug!("sending stop signal for every protocol head");
-                send_shutdown(shutdown_handles);
+                    debug!("sending Shutdown message to broker");
+                    handle.send(Message::System(SystemEvent::Shutdown)).await?;
+                    break broker_task.await;
+                }
+                Event::BrokerExited(broker_state) => {
+                    warn!("broker exited before accept loop");
 
-                match tasks.await {
-                    Either::Right(((result, _index, unfinished_incoming_tasks), broker_task)) => {
-                        // wait until the rest of incoming_tasks finished
-                        let mut results = vec![result];
-                        results.extend(future::join_all(unfinished_incoming_tasks).await);
+                    debug!("sending stop signal for the rest of protocol heads");
+                    send_shutdown(listeners.drain().map(|(_, listener)| listener.shutdown));
+                    stopped(&mut incoming_tasks).await;
 
-                        for e in results.into_iter().filter_map(Result::err) {
-                            warn!(message = "failed to shutdown protocol head", error=%e);
+                    break broker_state;
+                }
+                Event::Stopped(id, result) => {
+                    let reloaded = listeners
+                        .values()
+                        .find(|listener| listener.id == id)
+                        .map(|listener| listener.reloaded);
+                    listeners.retain(|_, listener| listener.id != id);
+                    stopping.remove(&id);
+
+                    match reloaded {
+                        // the listener was stopped by an update
+                        None => {
+                            if let Err(e) = result {
+                                warn!(message = "failed to shutdown protocol head", error=%e);
+                            }
                         }
+                        Some(true) => {
+                            if let Err(e) = result {
+                                warn!(message = "an error occurred in the accept loop", error=%e);
+                            }
+                        }
+                        Some(false) => {
+                            debug!("sending Shutdown message to broker");
 
-                        debug!("sending Shutdown message to broker");
-                        handle.send(Message::System(SystemEvent::Shutdown)).await?;
-                        broker_task.await?
-                    }
-                    Either::Left((broker_state, incoming_tasks)) => {
-                        warn!("broker exited before accept loop");
-
-                        // wait until either of incoming_tasks finished
-                        let (result, _index, unfinished_incoming_tasks) = incoming_tasks.await;
+                            if let Err(e) = &result {
+                                error!(message = "an error occurred in the accept loop", error=%e);
+                            }
 
-                        // wait until the rest of incoming_tasks finished
-                        let mut results = vec![result];
-                        results.extend(future::join_all(unfinished_incoming_tasks).await);
+                            debug!("sending stop signal for the rest of protocol heads");
+                            send_shutdown(listeners.drain().map(|(_, listener)| listener.shutdown));
+                            stopped(&mut incoming_tasks).await;
 
-                        for e in results.into_iter().filter_map(Result::err) {
-                            warn!(message = "failed to shutdown protocol head", error=%e);
+                            handle.send(Message::System(SystemEvent::Shutdown)).await?;
+                            break broker_task.await;
                         }
-
-                        broker_state?
                     }
                 }
-            }
-            Either::Right((either, _)) => match either {
-                Either::Right(((result, index, unfinished_incoming_tasks), broker_task)) => {
-                    debug!("sending Shutdown message to broker");
-
-                    if let Err(e) = &result {
-                        error!(message = "an error occurred in the accept loop", error=%e);
+                Event::Update(transports) => {
+                    info!("updating listeners...");
+                    let mut stale = std::mem::take(&mut listeners);
+                    pending.clear();
+                    for (key, transport) in transports {
+                        if let Some(listener) = stale.remove(&key) {
+                            listeners.insert(key, listener);
+                        } else {
+                            pending.push((key, transport));
+                        }
                     }
 
-                    debug!("sending stop signal for the rest of protocol heads");
-                    shutdown_handles.remove(index);
-                    send_shutdown(shutdown_handles);
-
-                    let mut results = vec![result];
-                    results.extend(future::join_all(unfinished_incoming_tasks).await);
-
-                    handle.send(Message::System(SystemEvent::Shutdown)).await?;
-
-                    let broker_state = broker_task.await;
-
-                    for e in results.into_iter().filter_map(Result::err) {
-                        warn!(message = "failed to shutdown protocol head", error=%e);
-                    }
+                    debug!("sending stop signal for removed protocol heads");
+                    stopping.extend(stale.values().map(|listener| listener.id));
+                    send_shutdown(stale.drain().map(|(_, listener)| listener.shutdown));
+                }
+            }
 
-                    broker_state?
+            if stopping.is_empty() && !pending.is_empty() {
+                debug!("starting added protocol heads");
+                for (key, transport) in pending.drain(..) {
+                    next_id += 1;
+                    let (listener, incoming_task) =
+                        listen(next_id, true, transport, handle.clone(), max_packet_size);
+                    listeners.insert(key, listener);
+                    incoming_tasks.push(incoming_task);
                 }
-                Either::Left((broker_state, incoming_tasks)) => {
-                    warn!("broker exited before accept loop");
+            }
+        };
 
-                    debug!("sending stop signal for the rest of protocol heads");
-                    send_shutdown(shutdown_handles);
+        broker_state?
+    }
+}
 
-                    // wait until either of incoming_tasks finished
-                    let (result, _index, unfinished_incoming_tasks) = incoming_tasks.await;
+enum Event<S, T> {
+    Shutdown,
+    BrokerExited(S),
+    Stopped(u64, Result<(), Error>),
+    Update(T),
+}
 
-                    // wait until the rest of incoming_tasks finished
-                    let mut results = vec![result];
-                    results.extend(future::join_all(unfinished_incoming_tasks).await);
+struct Listener {
+    id: u64,
+    reloaded: bool,
+    shutdown: oneshot::Sender<()>,
+}
 
-                    for e in results.into_iter().filter_map(Result::err) {
-                        warn!(message = "failed to shutdown protocol head", error=%e);
-                    }
+fn listen<A>(
+    id: u64,
+    reloaded: bool,
+    transport: TransportBuilder<A>,
+    handle: BrokerHandle,
+    max_packet_size: Option<usize>,
+) -> (Listener, impl Future<Output = (u64, Result<(), Error>)>)
+where
+    A: ToSocketAddrs,
+{
+    let (itx, irx) = oneshot::channel::<()>();
+    let listener = Listener {
+        id,
+        reloaded,
+        shutdown: itx,
+    };
+
+    let incoming_task = incoming_task(transport, handle, max_packet_size, irx.map(drop));
+    (listener, incoming_task.map(move |result| (id, result)))
+}
 
-                    broker_state?
-                }
-            },
+/// Waits until every protocol head has stopped.
+async fn stopped<T>(incoming_tasks: &mut FuturesUnordered<T>)
+where
+    T: Future<Output = (u64, Result<(), Error>)>,
+{
+    while let Some((_, result)) = incoming_tasks.next().await {
+        if let Err(e) = result {
+            warn!(message = "failed to shutdown protocol head", error=%e);
         }
     }
 }
@@ -166,6 +260,7 @@ where
 async fn incoming_task<A, F>(
     transport: TransportBuilder<A>,
     handle: BrokerHandle,
+    max_packet_size: Option<usize>,
     mut shutdown_signal: F,
 ) -> Result<(), Error>
 where
@@ -191,9 +286,10 @@ where
                 let broker_handle = handle.clone();
                 let span = span.clone();
                 tokio::spawn(async move {
-                    if let Err(e) = connection::process(stream, peer, broker_handle)
-                        .instrument(span)
-                        .await
+                    if let Err(e) =
+                        connection::process(stream, peer, broker_handle, max_packet_size)
+                            .instrument(span)
+                            .await
                     {
                         warn!(message = "failed to process connection", error=%e);
                     }
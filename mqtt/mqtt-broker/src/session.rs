@@ -1,4 +1,6 @@
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
 use std::{cmp, fmt, mem};
 
 use mqtt3::proto;
@@ -7,10 +9,93 @@ use serde::ser::SerializeTuple;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tracing::{debug, warn};
 
-use crate::subscription::Subscription;
+use crate::configuration::{BrokerConfig, QueueFullAction};
+use crate::subscription::{self, is_shared, Subscription};
 use crate::{AuthId, ClientEvent, ClientId, ConnReq, ConnectionHandle, Error, Message, Publish};
 
-const MAX_INFLIGHT_MESSAGES: usize = 16;
+const DEFAULT_MAX_INFLIGHT_MESSAGES: usize = 16;
+
+/// Limits applied to the messages in flight and to the queue of messages
+/// waiting to be sent to a session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionConfig {
+    max_inflight_messages: usize,
+    max_message_size: u64,
+    max_queued_messages: usize,
+    max_queued_size: u64,
+    when_full: QueueFullAction,
+}
+
+impl SessionConfig {
+    pub fn new(
+        max_inflight_messages: usize,
+        max_message_size: u64,
+        max_queued_messages: usize,
+        max_queued_size: u64,
+        when_full: QueueFullAction,
+    ) -> Self {
+        Self {
+            max_inflight_messages,
+            max_message_size,
+            max_queued_messages,
+            max_queued_size,
+            when_full,
+        }
+    }
+
+    /// Creates a session config for a given client, taking into account
+    /// inflight messages limit overrides.
+    pub fn for_client(
+        config: &BrokerConfig,
+        client_id: &ClientId,
+        auth_id: Option<&AuthId>,
+    ) -> Self {
+        let messages = config.session().messages();
+        let max_inflight_messages = config
+            .inflight_messages()
+            .max_count_for(client_id.as_str(), auth_id);
+
+        Self::new(
+            max_inflight_messages as usize,
+            messages.max_message_size(),
+            messages.max_count() as usize,
+            messages.max_total_space(),
+            messages.when_full(),
+        )
+    }
+
+    pub fn max_inflight_messages(&self) -> usize {
+        self.max_inflight_messages
+    }
+
+    pub fn max_message_size(&self) -> u64 {
+        self.max_message_size
+    }
+
+    pub fn max_queued_messages(&self) -> usize {
+        self.max_queued_messages
+    }
+
+    pub fn max_queued_size(&self) -> u64 {
+        self.max_queued_size
+    }
+
+    pub fn when_full(&self) -> QueueFullAction {
+        self.when_full
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_MAX_INFLIGHT_MESSAGES,
+            u64::max_value(),
+            usize::max_value(),
+            u64::max_value(),
+            QueueFullAction::DropNew,
+        )
+    }
+}
 
 #[derive(Debug)]
 pub struct ConnectedSession {
@@ -109,11 +194,19 @@ impl ConnectedSession {
         self.state.publish_to(publication)
     }
 
+    pub fn publish_shared_to(
+        &mut self,
+        topic_filter: &str,
+        publication: proto::Publication,
+    ) -> Result<Option<ClientEvent>, Error> {
+        self.state.publish_shared_to(topic_filter, publication)
+    }
+
     pub fn subscribe_to(
         &mut self,
         subscribe_to: proto::SubscribeTo,
     ) -> Result<(proto::SubAckQos, Option<Subscription>), Error> {
-        match subscribe_to.topic_filter.parse() {
+        match subscription::parse_topic_filter(&subscribe_to.topic_filter).map(|(_, f)| f) {
             Ok(filter) => {
                 let proto::SubscribeTo { topic_filter, qos } = subscribe_to;
 
@@ -171,13 +264,43 @@ impl OfflineSession {
         &mut self,
         publication: proto::Publication,
     ) -> Result<Option<ClientEvent>, Error> {
-        self.state.queue_publish(publication)?;
-        Ok(None)
+        let result = self.state.queue_publish(publication);
+        self.queued(result)
+    }
+
+    pub fn publish_shared_to(
+        &mut self,
+        topic_filter: &str,
+        publication: proto::Publication,
+    ) -> Result<Option<ClientEvent>, Error> {
+        let result = self.state.queue_shared(topic_filter, publication);
+        self.queued(result)
+    }
+
+    fn queued(&mut self, result: Result<(), Error>) -> Result<Option<ClientEvent>, Error> {
+        match result {
+            // There is no connection to drop for an offline session,
+            // so the new message is dropped instead.
+            Err(Error::SessionQueueFull) => {
+                warn!(
+                    "queue is full for offline session {}. dropping message",
+                    self.state.client_id
+                );
+                self.state.messages_dropped += 1;
+                Ok(None)
+            }
+            result => result.map(|()| None),
+        }
     }
 
-    pub fn into_online(self) -> Result<(SessionState, Vec<ClientEvent>), Error> {
-        let mut events = Vec::with_capacity(MAX_INFLIGHT_MESSAGES);
+    pub fn into_online(
+        self,
+        config: SessionConfig,
+    ) -> Result<(SessionState, Vec<ClientEvent>), Error> {
         let OfflineSession { mut state } = self;
+        state.config = config;
+        state.last_seen = SystemTime::now();
+        let mut events = Vec::with_capacity(state.config.max_inflight_messages);
 
         // Handle the outstanding QoS 1 and QoS 2 packets
         for (id, publish) in &state.waiting_to_be_acked {
@@ -276,6 +399,189 @@ impl DisconnectingSession {
     }
 }
 
+/// Queue of publications waiting to be sent to a session.
+///
+/// Keeps a running total of the queued payload sizes, so that the queue limits
+/// can be checked without going through the whole queue.
+///
+/// The queue also counts the publications pushed to its back and popped from
+/// its front, so that the journal only writes the changes since the last time
+/// it has seen the queue. Any other change gives the queue a new `id`, which
+/// makes the journal write the whole queue again.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(
+    from = "VecDeque<proto::Publication>",
+    into = "VecDeque<proto::Publication>"
+)]
+struct PublicationQueue {
+    publications: VecDeque<proto::Publication>,
+    size: u64,
+    id: u64,
+    pushed: u64,
+    popped: u64,
+}
+
+static NEXT_QUEUE_ID: AtomicU64 = AtomicU64::new(0);
+
+fn next_queue_id() -> u64 {
+    NEXT_QUEUE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+impl PublicationQueue {
+    fn len(&self) -> usize {
+        self.publications.len()
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    #[cfg(test)]
+    fn iter(&self) -> impl Iterator<Item = &proto::Publication> {
+        self.publications.iter()
+    }
+
+    fn push_back(&mut self, publication: proto::Publication) {
+        self.size += payload_size(&publication);
+        self.pushed += 1;
+        self.publications.push_back(publication);
+    }
+
+    fn pop_front(&mut self) -> Option<proto::Publication> {
+        let publication = self.publications.pop_front()?;
+        self.size -= payload_size(&publication);
+        self.popped += 1;
+        Some(publication)
+    }
+
+    fn take(&mut self) -> VecDeque<proto::Publication> {
+        self.size = 0;
+        self.popped += self.publications.len() as u64;
+        mem::take(&mut self.publications)
+    }
+
+    /// Returns the changes of the queue since `cursor` and the cursor to the current state.
+    fn delta(&self, cursor: Option<&QueueCursor>) -> (QueueDelta, QueueCursor) {
+        let delta = match cursor {
+            Some(cursor) if cursor.id == self.id => {
+                // Publications pushed since the cursor which are popped already
+                // were never written, so only the written ones are popped.
+                #[allow(clippy::cast_possible_truncation)]
+                let popped = cmp::min((self.popped - cursor.popped) as usize, cursor.len);
+                #[allow(clippy::cast_possible_truncation)]
+                let pushed = cmp::min((self.pushed - cursor.pushed) as usize, self.len());
+                QueueDelta::Update {
+                    popped,
+                    pushed: self
+                        .publications
+                        .iter()
+                        .skip(self.len() - pushed)
+                        .cloned()
+                        .collect(),
+                }
+            }
+            _ => QueueDelta::Replace(self.publications.iter().cloned().collect()),
+        };
+
+        let cursor = QueueCursor {
+            id: self.id,
+            pushed: self.pushed,
+            popped: self.popped,
+            len: self.len(),
+        };
+        (delta, cursor)
+    }
+
+    fn apply(&mut self, delta: QueueDelta) {
+        match delta {
+            QueueDelta::Replace(publications) => {
+                *self = VecDeque::from(publications).into();
+            }
+            QueueDelta::Update { popped, pushed } => {
+                for _ in 0..popped {
+                    self.pop_front();
+                }
+                for publication in pushed {
+                    self.push_back(publication);
+                }
+            }
+        }
+    }
+}
+
+impl Default for PublicationQueue {
+    fn default() -> Self {
+        VecDeque::new().into()
+    }
+}
+
+impl Clone for PublicationQueue {
+    fn clone(&self) -> Self {
+        self.publications.clone().into()
+    }
+}
+
+impl PartialEq for PublicationQueue {
+    fn eq(&self, other: &Self) -> bool {
+        self.publications == other.publications
+    }
+}
+
+impl From<VecDeque<proto::Publication>> for PublicationQueue {
+    fn from(publications: VecDeque<proto::Publication>) -> Self {
+        let size = publications.iter().map(payload_size).sum();
+        Self {
+            publications,
+            size,
+            id: next_queue_id(),
+            pushed: 0,
+            popped: 0,
+        }
+    }
+}
+
+impl From<PublicationQueue> for VecDeque<proto::Publication> {
+    fn from(queue: PublicationQueue) -> Self {
+        queue.publications
+    }
+}
+
+/// The state of a session queue the journal has written.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct QueueCursor {
+    id: u64,
+    pushed: u64,
+    popped: u64,
+    len: usize,
+}
+
+/// Changes of a session queue written by the journal.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub(crate) enum QueueDelta {
+    /// The queue has been replaced with these publications.
+    Replace(Vec<proto::Publication>),
+
+    /// Publications have been popped from the front of the queue
+    /// and pushed to its back.
+    Update {
+        popped: usize,
+        pushed: Vec<proto::Publication>,
+    },
+}
+
+impl QueueDelta {
+    pub(crate) fn is_empty(&self) -> bool {
+        match self {
+            Self::Replace(_) => false,
+            Self::Update { popped, pushed } => *popped == 0 && pushed.is_empty(),
+        }
+    }
+}
+
+fn payload_size(publication: &proto::Publication) -> u64 {
+    publication.payload.len() as u64
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SessionState {
     client_id: ClientId,
@@ -283,7 +589,7 @@ pub struct SessionState {
     packet_identifiers: PacketIdentifiers,
     packet_identifiers_qos0: PacketIdentifiers,
 
-    waiting_to_be_sent: VecDeque<proto::Publication>,
+    waiting_to_be_sent: PublicationQueue,
 
     // for incoming messages - QoS2
     waiting_to_be_released: HashMap<proto::PacketIdentifier, proto::Publish>,
@@ -292,21 +598,40 @@ pub struct SessionState {
     waiting_to_be_acked: HashMap<proto::PacketIdentifier, Publish>,
     waiting_to_be_acked_qos0: HashMap<proto::PacketIdentifier, Publish>,
     waiting_to_be_completed: HashSet<proto::PacketIdentifier>,
+
+    // last time the client was seen - used to expire offline sessions
+    last_seen: SystemTime,
+
+    // messages sent and dropped since the broker last took the counts
+    #[serde(skip)]
+    messages_sent: u64,
+    #[serde(skip)]
+    messages_dropped: u64,
+
+    #[serde(skip)]
+    config: SessionConfig,
 }
 
 impl SessionState {
-    pub fn new(client_id: ClientId) -> Self {
+    pub fn new(client_id: ClientId, config: SessionConfig) -> Self {
         Self {
             client_id,
             subscriptions: HashMap::new(),
             packet_identifiers: PacketIdentifiers::default(),
             packet_identifiers_qos0: PacketIdentifiers::default(),
 
-            waiting_to_be_sent: VecDeque::new(),
+            waiting_to_be_sent: PublicationQueue::default(),
             waiting_to_be_acked: HashMap::new(),
             waiting_to_be_acked_qos0: HashMap::new(),
             waiting_to_be_released: HashMap::new(),
             waiting_to_be_completed: HashSet::new(),
+
+            last_seen: SystemTime::now(),
+
+            messages_sent: 0,
+            messages_dropped: 0,
+
+            config,
         }
     }
 
@@ -314,6 +639,79 @@ impl SessionState {
         &self.client_id
     }
 
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    /// Returns a copy of the state without the queue of publications waiting to be sent,
+    /// along with the changes of this queue since `cursor`.
+    ///
+    /// The journal writes the queue changes separately, so that a change of
+    /// a session doesn't rewrite all the publications queued for it.
+    pub(crate) fn journal_entry(
+        &self,
+        cursor: Option<&QueueCursor>,
+    ) -> (Self, QueueDelta, QueueCursor) {
+        let state = Self {
+            client_id: self.client_id.clone(),
+            subscriptions: self.subscriptions.clone(),
+            packet_identifiers: self.packet_identifiers.clone(),
+            packet_identifiers_qos0: self.packet_identifiers_qos0.clone(),
+
+            waiting_to_be_sent: PublicationQueue::default(),
+            waiting_to_be_acked: self.waiting_to_be_acked.clone(),
+            waiting_to_be_acked_qos0: self.waiting_to_be_acked_qos0.clone(),
+            waiting_to_be_released: self.waiting_to_be_released.clone(),
+            waiting_to_be_completed: self.waiting_to_be_completed.clone(),
+
+            last_seen: self.last_seen,
+
+            messages_sent: 0,
+            messages_dropped: 0,
+
+            config: self.config.clone(),
+        };
+        let (delta, cursor) = self.waiting_to_be_sent.delta(cursor);
+        (state, delta, cursor)
+    }
+
+    /// Moves the queue of publications waiting to be sent from a previous state.
+    pub(crate) fn keep_queue(&mut self, previous: Self) {
+        self.waiting_to_be_sent = previous.waiting_to_be_sent;
+    }
+
+    pub(crate) fn apply_queue_delta(&mut self, delta: QueueDelta) {
+        self.waiting_to_be_sent.apply(delta);
+    }
+
+    pub fn set_config(&mut self, config: SessionConfig) {
+        self.config = config;
+    }
+
+    pub fn last_seen(&self) -> SystemTime {
+        self.last_seen
+    }
+
+    pub fn queued_count(&self) -> usize {
+        self.waiting_to_be_sent.len()
+    }
+
+    pub fn inflight_count(&self) -> usize {
+        self.waiting_to_be_acked.len() + self.waiting_to_be_acked_qos0.len()
+    }
+
+    /// Returns the number of messages sent and dropped since the last call.
+    pub fn take_message_counts(&mut self) -> (u64, u64) {
+        let counts = (self.messages_sent, self.messages_dropped);
+        self.messages_sent = 0;
+        self.messages_dropped = 0;
+        counts
+    }
+
+    pub fn set_last_seen(&mut self, last_seen: SystemTime) {
+        self.last_seen = last_seen;
+    }
+
     pub fn subscriptions(&self) -> &HashMap<String, Subscription> {
         &self.subscriptions
     }
@@ -332,7 +730,7 @@ impl SessionState {
 
     pub fn queue_publish(&mut self, publication: proto::Publication) -> Result<(), Error> {
         if let Some(publication) = self.filter(publication) {
-            self.waiting_to_be_sent.push_back(publication);
+            self.enqueue(publication)?;
         }
         Ok(())
     }
@@ -344,18 +742,110 @@ impl SessionState {
         publication: proto::Publication,
     ) -> Result<Option<ClientEvent>, Error> {
         if let Some(publication) = self.filter(publication) {
-            if self.allowed_to_send() {
-                let event = self.prepare_to_send(&publication)?;
-                Ok(Some(event))
-            } else {
-                self.waiting_to_be_sent.push_back(publication);
-                Ok(None)
-            }
+            self.send_or_enqueue(publication)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Queues a publication delivered to this session through its shared
+    /// subscription `topic_filter`.
+    pub fn queue_shared(
+        &mut self,
+        topic_filter: &str,
+        publication: proto::Publication,
+    ) -> Result<(), Error> {
+        if let Some(publication) = self.filter_shared(topic_filter, publication) {
+            self.enqueue(publication)?;
+        }
+        Ok(())
+    }
+
+    /// Takes a publication delivered to this session through its shared
+    /// subscription `topic_filter`, like `publish_to` does for the other
+    /// subscriptions.
+    pub fn publish_shared_to(
+        &mut self,
+        topic_filter: &str,
+        publication: proto::Publication,
+    ) -> Result<Option<ClientEvent>, Error> {
+        if let Some(publication) = self.filter_shared(topic_filter, publication) {
+            self.send_or_enqueue(publication)
         } else {
             Ok(None)
         }
     }
 
+    /// Removes the publications this session holds only because of one of
+    /// its shared subscriptions, so they can be delivered to another member
+    /// of the share.
+    ///
+    /// This covers QoS 1 and QoS 2 publications that were sent but not yet
+    /// acknowledged, as well as queued ones. Only shared subscriptions for
+    /// which `has_other_members` returns true are considered.
+    pub fn take_shared_publications<F>(
+        &mut self,
+        has_other_members: F,
+    ) -> Vec<(String, proto::Publication)>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut shared = self
+            .subscriptions
+            .keys()
+            .filter(|topic_filter| is_shared(topic_filter) && has_other_members(topic_filter))
+            .cloned()
+            .collect::<Vec<_>>();
+        if shared.is_empty() {
+            return Vec::new();
+        }
+        shared.sort();
+
+        let subscriptions = &self.subscriptions;
+        let shared_for = |topic_name: &str| {
+            let not_shared = subscriptions.iter().any(|(topic_filter, sub)| {
+                !is_shared(topic_filter) && sub.filter().matches(topic_name)
+            });
+            if not_shared {
+                None
+            } else {
+                shared
+                    .iter()
+                    .find(|topic_filter| subscriptions[*topic_filter].filter().matches(topic_name))
+                    .cloned()
+            }
+        };
+
+        let mut unacked = self
+            .waiting_to_be_acked
+            .iter()
+            .filter_map(|(id, publish)| match publish {
+                Publish::QoS12(_, publish) => {
+                    shared_for(&publish.topic_name).map(|topic_filter| (*id, topic_filter))
+                }
+                Publish::QoS0(..) => None,
+            })
+            .collect::<Vec<_>>();
+        unacked.sort_unstable_by_key(|(id, _)| *id);
+
+        let mut taken = Vec::new();
+        for (id, topic_filter) in unacked {
+            if let Some(Publish::QoS12(_, publish)) = self.waiting_to_be_acked.remove(&id) {
+                self.packet_identifiers.discard(id);
+                taken.push((topic_filter, into_publication(publish)));
+            }
+        }
+
+        for publication in self.waiting_to_be_sent.take() {
+            match shared_for(&publication.topic_name) {
+                Some(topic_filter) => taken.push((topic_filter, publication)),
+                None => self.waiting_to_be_sent.push_back(publication),
+            }
+        }
+
+        taken
+    }
+
     pub fn handle_publish(
         &mut self,
         publish: proto::Publish,
@@ -367,6 +857,7 @@ impl SessionState {
                     qos: proto::QoS::AtMostOnce,
                     retain: publish.retain,
                     payload: publish.payload,
+                    properties: publish.properties,
                 };
                 (Some(publication), None)
             }
@@ -376,6 +867,7 @@ impl SessionState {
                     qos: proto::QoS::AtLeastOnce,
                     retain: publish.retain,
                     payload: publish.payload,
+                    properties: publish.properties,
                 };
                 let puback = proto::PubAck { packet_identifier };
                 let event = ClientEvent::PubAck(puback);
@@ -414,6 +906,7 @@ impl SessionState {
                 qos: proto::QoS::ExactlyOnce,
                 retain: publish.retain,
                 payload: publish.payload,
+                properties: publish.properties,
             });
         Ok(publication)
     }
@@ -459,13 +952,89 @@ impl SessionState {
         let num_inflight = self.waiting_to_be_acked.len()
             + self.waiting_to_be_acked_qos0.len()
             + self.waiting_to_be_completed.len();
-        num_inflight < MAX_INFLIGHT_MESSAGES
+        num_inflight < self.config.max_inflight_messages
+    }
+
+    /// Adds a publication to the queue of messages waiting to be sent,
+    /// applying the configured queue limits.
+    ///
+    /// Returns `Error::SessionQueueFull` if the queue is full and the
+    /// configured action is to disconnect the client.
+    fn enqueue(&mut self, publication: proto::Publication) -> Result<(), Error> {
+        let size = payload_size(&publication);
+        if size > self.config.max_message_size || size > self.config.max_queued_size {
+            warn!(
+                "message of {} bytes exceeds queue limits for {}. dropping message",
+                size, self.client_id
+            );
+            self.messages_dropped += 1;
+            return Ok(());
+        }
+
+        if self.is_queue_full(size) {
+            match self.config.when_full {
+                QueueFullAction::DropNew => {
+                    warn!("queue is full for {}. dropping new message", self.client_id);
+                    self.messages_dropped += 1;
+                    return Ok(());
+                }
+                QueueFullAction::DropOld => {
+                    debug!(
+                        "queue is full for {}. dropping old messages",
+                        self.client_id
+                    );
+                    while self.is_queue_full(size) && self.waiting_to_be_sent.pop_front().is_some()
+                    {
+                        self.messages_dropped += 1;
+                    }
+
+                    // Only reachable with a queue that can't hold any message.
+                    if self.is_queue_full(size) {
+                        warn!("queue is full for {}. dropping new message", self.client_id);
+                        self.messages_dropped += 1;
+                        return Ok(());
+                    }
+                }
+                QueueFullAction::Disconnect => {
+                    warn!("queue is full for {}", self.client_id);
+                    return Err(Error::SessionQueueFull);
+                }
+            }
+        }
+
+        self.waiting_to_be_sent.push_back(publication);
+        Ok(())
+    }
+
+    fn is_queue_full(&self, size: u64) -> bool {
+        self.waiting_to_be_sent.len() >= self.config.max_queued_messages
+            || self.waiting_to_be_sent.size() + size > self.config.max_queued_size
+    }
+
+    fn send_or_enqueue(
+        &mut self,
+        publication: proto::Publication,
+    ) -> Result<Option<ClientEvent>, Error> {
+        if self.allowed_to_send() {
+            let event = self.prepare_to_send(&publication)?;
+            Ok(Some(event))
+        } else {
+            self.enqueue(publication)?;
+            Ok(None)
+        }
     }
 
+    /// Applies the non-shared subscriptions of the session to a publication.
+    ///
+    /// Shared subscriptions are left out, as the broker picks a single member
+    /// of each share to deliver to.
     fn filter(&self, mut publication: proto::Publication) -> Option<proto::Publication> {
         self.subscriptions
-            .values()
-            .filter(|sub| sub.filter().matches(&publication.topic_name))
+            .iter()
+            .filter(|(topic_filter, sub)| {
+                !is_shared(topic_filter) && sub.filter().matches(&publication.topic_name)
+            })
+            .map(|(_, sub)| sub)
             .fold(None, |acc, sub| {
                 acc.map(|qos| cmp::max(qos, cmp::min(*sub.max_qos(), publication.qos)))
                     .or_else(|| Some(cmp::min(*sub.max_qos(), publication.qos)))
@@ -476,6 +1045,20 @@ impl SessionState {
             })
     }
 
+    fn filter_shared(
+        &self,
+        topic_filter: &str,
+        mut publication: proto::Publication,
+    ) -> Option<proto::Publication> {
+        self.subscriptions
+            .get(topic_filter)
+            .filter(|sub| sub.filter().matches(&publication.topic_name))
+            .map(move |sub| {
+                publication.qos = cmp::min(*sub.max_qos(), publication.qos);
+                publication
+            })
+    }
+
     fn prepare_to_send(&mut self, publication: &proto::Publication) -> Result<ClientEvent, Error> {
         let publish = match publication.qos {
             proto::QoS::AtMostOnce => {
@@ -485,6 +1068,7 @@ impl SessionState {
                     retain: publication.retain,
                     topic_name: publication.topic_name.to_owned(),
                     payload: publication.payload.to_owned(),
+                    properties: publication.properties.clone(),
                 };
                 Publish::QoS0(id, packet)
             }
@@ -497,6 +1081,7 @@ impl SessionState {
                     retain: publication.retain,
                     topic_name: publication.topic_name.to_owned(),
                     payload: publication.payload.to_owned(),
+                    properties: publication.properties.clone(),
                 };
                 Publish::QoS12(id, packet)
             }
@@ -509,10 +1094,12 @@ impl SessionState {
                     retain: publication.retain,
                     topic_name: publication.topic_name.to_owned(),
                     payload: publication.payload.to_owned(),
+                    properties: publication.properties.clone(),
                 };
                 Publish::QoS12(id, packet)
             }
         };
+        self.messages_sent += 1;
 
         let event = match publish {
             Publish::QoS0(id, publish) => {
@@ -536,7 +1123,11 @@ impl SessionState {
         HashMap<String, Subscription>,
         VecDeque<proto::Publication>,
     ) {
-        (self.client_id, self.subscriptions, self.waiting_to_be_sent)
+        (
+            self.client_id,
+            self.subscriptions,
+            self.waiting_to_be_sent.into(),
+        )
     }
 
     pub fn from_parts(
@@ -550,15 +1141,37 @@ impl SessionState {
             packet_identifiers: PacketIdentifiers::default(),
             packet_identifiers_qos0: PacketIdentifiers::default(),
 
-            waiting_to_be_sent,
+            waiting_to_be_sent: waiting_to_be_sent.into(),
             waiting_to_be_acked: HashMap::new(),
             waiting_to_be_acked_qos0: HashMap::new(),
             waiting_to_be_released: HashMap::new(),
             waiting_to_be_completed: HashSet::new(),
+
+            last_seen: SystemTime::now(),
+
+            messages_sent: 0,
+            messages_dropped: 0,
+
+            config: SessionConfig::default(),
         }
     }
 }
 
+fn into_publication(publish: proto::Publish) -> proto::Publication {
+    let qos = match publish.packet_identifier_dup_qos {
+        proto::PacketIdentifierDupQoS::AtMostOnce => proto::QoS::AtMostOnce,
+        proto::PacketIdentifierDupQoS::AtLeastOnce(..) => proto::QoS::AtLeastOnce,
+        proto::PacketIdentifierDupQoS::ExactlyOnce(..) => proto::QoS::ExactlyOnce,
+    };
+    proto::Publication {
+        topic_name: publish.topic_name,
+        qos,
+        retain: publish.retain,
+        payload: publish.payload,
+        properties: publish.properties,
+    }
+}
+
 #[cfg(any(test, feature = "proptest"))]
 #[allow(clippy::too_many_arguments)]
 impl SessionState {
@@ -572,17 +1185,24 @@ impl SessionState {
         waiting_to_be_acked: HashMap<proto::PacketIdentifier, Publish>,
         waiting_to_be_acked_qos0: HashMap<proto::PacketIdentifier, Publish>,
         waiting_to_be_completed: HashSet<proto::PacketIdentifier>,
+        last_seen: SystemTime,
     ) -> Self {
         Self {
             client_id,
             subscriptions,
             packet_identifiers,
             packet_identifiers_qos0,
-            waiting_to_be_sent,
+            waiting_to_be_sent: waiting_to_be_sent.into(),
             waiting_to_be_acked,
             waiting_to_be_acked_qos0,
             waiting_to_be_released,
             waiting_to_be_completed,
+            last_seen,
+
+            messages_sent: 0,
+            messages_dropped: 0,
+
+            config: SessionConfig::default(),
         }
     }
 }
@@ -596,8 +1216,8 @@ pub enum Session {
 }
 
 impl Session {
-    pub fn new_transient(auth_id: AuthId, connreq: ConnReq) -> Self {
-        let state = SessionState::new(connreq.client_id().clone());
+    pub fn new_transient(auth_id: AuthId, connreq: ConnReq, config: SessionConfig) -> Self {
+        let state = SessionState::new(connreq.client_id().clone(), config);
         let (connect, handle) = connreq.into_parts();
         let connected = ConnectedSession::new(auth_id, state, connect.will, handle);
         Self::Transient(connected)
@@ -652,14 +1272,25 @@ impl Session {
     }
 
     pub fn subscriptions(&self) -> Option<&HashMap<String, Subscription>> {
-        let state = match self {
+        self.state().map(SessionState::subscriptions)
+    }
+
+    pub fn state(&self) -> Option<&SessionState> {
+        match self {
             Self::Transient(connected) => Some(connected.state()),
             Self::Persistent(connected) => Some(connected.state()),
             Self::Offline(offline) => Some(offline.state()),
             Self::Disconnecting(_) => None,
-        };
+        }
+    }
 
-        state.map(SessionState::subscriptions)
+    pub fn state_mut(&mut self) -> Option<&mut SessionState> {
+        match self {
+            Self::Transient(connected) => Some(&mut connected.state),
+            Self::Persistent(connected) => Some(&mut connected.state),
+            Self::Offline(offline) => Some(&mut offline.state),
+            Self::Disconnecting(_) => None,
+        }
     }
 
     pub fn handle_publish(
@@ -740,6 +1371,25 @@ impl Session {
         }
     }
 
+    pub fn publish_shared_to(
+        &mut self,
+        topic_filter: &str,
+        publication: &proto::Publication,
+    ) -> Result<Option<ClientEvent>, Error> {
+        match self {
+            Self::Transient(connected) => {
+                connected.publish_shared_to(topic_filter, publication.to_owned())
+            }
+            Self::Persistent(connected) => {
+                connected.publish_shared_to(topic_filter, publication.to_owned())
+            }
+            Self::Offline(offline) => {
+                offline.publish_shared_to(topic_filter, publication.to_owned())
+            }
+            Self::Disconnecting(_) => Err(Error::SessionOffline),
+        }
+    }
+
     pub fn subscribe_to(
         &mut self,
         subscribe_to: proto::SubscribeTo,
@@ -909,7 +1559,7 @@ impl Default for PacketIdentifiers {
 
 #[cfg(test)]
 pub(crate) mod tests {
-    use std::time::Duration;
+    use std::{collections::VecDeque, time::Duration};
 
     use matches::assert_matches;
     use tokio::sync::mpsc;
@@ -919,8 +1569,8 @@ pub(crate) mod tests {
 
     use crate::{
         auth::AuthId,
-        session::{PacketIdentifiers, Session, SessionState},
-        ClientId, ConnReq, ConnectionHandle, Error,
+        session::{PacketIdentifiers, QueueDelta, Session, SessionConfig, SessionState},
+        ClientId, ConnReq, ConnectionHandle, Error, QueueFullAction,
     };
 
     fn connection_handle() -> ConnectionHandle {
@@ -938,6 +1588,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: PROTOCOL_NAME.to_string(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         }
     }
 
@@ -949,7 +1600,7 @@ pub(crate) mod tests {
         let handle1 = connection_handle();
         let req1 = ConnReq::new(client_id, connect1, None, handle1);
         let auth_id = "auth-id1".into();
-        let mut session = Session::new_transient(auth_id, req1);
+        let mut session = Session::new_transient(auth_id, req1, SessionConfig::default());
         let subscribe_to = proto::SubscribeTo {
             topic_filter: "topic/new".to_string(),
             qos: proto::QoS::AtMostOnce,
@@ -999,7 +1650,7 @@ pub(crate) mod tests {
         let handle1 = connection_handle();
         let req1 = ConnReq::new(client_id, connect1, None, handle1);
         let auth_id = "auth-id1".into();
-        let mut session = Session::new_transient(auth_id, req1);
+        let mut session = Session::new_transient(auth_id, req1, SessionConfig::default());
         let subscribe_to = proto::SubscribeTo {
             topic_filter: "topic/#/#".to_string(),
             qos: proto::QoS::AtMostOnce,
@@ -1019,7 +1670,7 @@ pub(crate) mod tests {
         let handle1 = connection_handle();
         let req1 = ConnReq::new(client_id, connect1, None, handle1);
         let auth_id = AuthId::Anonymous;
-        let mut session = Session::new_transient(auth_id, req1);
+        let mut session = Session::new_transient(auth_id, req1, SessionConfig::default());
 
         let subscribe_to = proto::SubscribeTo {
             topic_filter: "topic/new".to_string(),
@@ -1065,11 +1716,211 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn test_publish_to_respects_inflight_limit() {
+        let id = "id1".to_string();
+        let client_id = ClientId::from(id.clone());
+        let connect1 = transient_connect(id);
+        let handle1 = connection_handle();
+        let req1 = ConnReq::new(client_id, connect1, None, handle1);
+        let auth_id = AuthId::Anonymous;
+        let config = SessionConfig::new(
+            2,
+            u64::max_value(),
+            usize::max_value(),
+            u64::max_value(),
+            QueueFullAction::DropNew,
+        );
+        let mut session = Session::new_transient(auth_id, req1, config);
+
+        let subscribe_to = proto::SubscribeTo {
+            topic_filter: "topic/new".to_string(),
+            qos: proto::QoS::AtLeastOnce,
+        };
+        session.subscribe_to(subscribe_to).unwrap();
+
+        let publication = proto::Publication {
+            topic_name: "topic/new".to_string(),
+            qos: proto::QoS::AtLeastOnce,
+            retain: false,
+            payload: "payload".into(),
+            properties: proto::Properties::default(),
+        };
+
+        assert_matches!(session.publish_to(&publication), Ok(Some(_)));
+        assert_matches!(session.publish_to(&publication), Ok(Some(_)));
+        assert_matches!(session.publish_to(&publication), Ok(None));
+
+        match &session {
+            Session::Transient(connected) => {
+                assert_eq!(1, connected.state.waiting_to_be_sent.len());
+            }
+            _ => panic!("not transient"),
+        }
+    }
+
+    #[test]
+    fn test_drop_old_with_no_room_in_queue() {
+        let id = "id1".to_string();
+        let client_id = ClientId::from(id.clone());
+        let connect1 = transient_connect(id);
+        let handle1 = connection_handle();
+        let req1 = ConnReq::new(client_id, connect1, None, handle1);
+        let auth_id = AuthId::Anonymous;
+        let config = SessionConfig::new(1, 1024, 0, 1024, QueueFullAction::DropOld);
+        let mut session = Session::new_transient(auth_id, req1, config);
+
+        let subscribe_to = proto::SubscribeTo {
+            topic_filter: "topic/new".to_string(),
+            qos: proto::QoS::AtLeastOnce,
+        };
+        session.subscribe_to(subscribe_to).unwrap();
+
+        let publication = proto::Publication {
+            topic_name: "topic/new".to_string(),
+            qos: proto::QoS::AtLeastOnce,
+            retain: false,
+            payload: "payload".into(),
+            properties: proto::Properties::default(),
+        };
+
+        assert_matches!(session.publish_to(&publication), Ok(Some(_)));
+        assert_matches!(session.publish_to(&publication), Ok(None));
+
+        match &session {
+            Session::Transient(connected) => {
+                assert_eq!(0, connected.state.waiting_to_be_sent.len());
+                assert_eq!(0, connected.state.waiting_to_be_sent.size());
+            }
+            _ => panic!("not transient"),
+        }
+    }
+
+    #[test]
+    fn test_journal_entry_returns_queue_delta() {
+        fn publication(payload: &str) -> proto::Publication {
+            proto::Publication {
+                topic_name: "topic/new".to_string(),
+                qos: proto::QoS::AtLeastOnce,
+                retain: false,
+                payload: payload.to_string().into(),
+                properties: proto::Properties::default(),
+            }
+        }
+
+        let mut state = SessionState::new(ClientId::from("id1"), SessionConfig::default());
+        state.waiting_to_be_sent.push_back(publication("1"));
+        state.waiting_to_be_sent.push_back(publication("2"));
+
+        let (entry, delta, cursor) = state.journal_entry(None);
+        assert_eq!(0, entry.waiting_to_be_sent.len());
+        assert_eq!(
+            delta,
+            QueueDelta::Replace(vec![publication("1"), publication("2")])
+        );
+
+        state.waiting_to_be_sent.pop_front();
+        state.waiting_to_be_sent.push_back(publication("3"));
+        state.waiting_to_be_sent.push_back(publication("4"));
+        state.waiting_to_be_sent.pop_front();
+
+        let (_, delta, cursor) = state.journal_entry(Some(&cursor));
+        assert_eq!(
+            delta,
+            QueueDelta::Update {
+                popped: 2,
+                pushed: vec![publication("3"), publication("4")],
+            }
+        );
+
+        let (_, delta, cursor) = state.journal_entry(Some(&cursor));
+        assert!(delta.is_empty());
+
+        state.waiting_to_be_sent = VecDeque::from(vec![publication("0")]).into();
+        let (_, delta, _) = state.journal_entry(Some(&cursor));
+        assert_eq!(delta, QueueDelta::Replace(vec![publication("0")]));
+    }
+
+    #[test]
+    fn test_shared_subscription() {
+        let id = "id1".to_string();
+        let client_id = ClientId::from(id.clone());
+        let connect1 = transient_connect(id);
+        let handle1 = connection_handle();
+        let req1 = ConnReq::new(client_id, connect1, None, handle1);
+        let auth_id = AuthId::Anonymous;
+        let config = SessionConfig::new(
+            1,
+            u64::max_value(),
+            usize::max_value(),
+            u64::max_value(),
+            QueueFullAction::DropNew,
+        );
+        let mut session = Session::new_transient(auth_id, req1, config);
+
+        for topic_filter in &["$share/group/shared/+", "other"] {
+            let subscribe_to = proto::SubscribeTo {
+                topic_filter: (*topic_filter).to_string(),
+                qos: proto::QoS::AtLeastOnce,
+            };
+            let (ack, _) = session.subscribe_to(subscribe_to).unwrap();
+            assert_eq!(ack, proto::SubAckQos::Success(proto::QoS::AtLeastOnce));
+        }
+
+        let publication = |topic_name: &str, payload: &str| proto::Publication {
+            topic_name: topic_name.to_string(),
+            qos: proto::QoS::ExactlyOnce,
+            retain: false,
+            payload: payload.to_string().into(),
+            properties: proto::Properties::default(),
+        };
+
+        // only delivered through the shared path
+        let event = session.publish_to(&publication("shared/a", "0")).unwrap();
+        assert!(event.is_none());
+
+        for payload in &["1", "2"] {
+            session
+                .publish_shared_to("$share/group/shared/+", &publication("shared/a", payload))
+                .unwrap();
+        }
+        session.publish_to(&publication("other", "3")).unwrap();
+
+        let mut state = match &session {
+            Session::Transient(connected) => connected.state.clone(),
+            _ => panic!("not transient"),
+        };
+        assert_eq!(1, state.waiting_to_be_acked.len());
+        assert_eq!(2, state.waiting_to_be_sent.len());
+
+        assert!(state.clone().take_shared_publications(|_| false).is_empty());
+
+        let taken = state.take_shared_publications(|_| true);
+        let taken = taken
+            .iter()
+            .map(|(topic_filter, publication)| {
+                assert_eq!(topic_filter, "$share/group/shared/+");
+                assert_eq!(publication.qos, proto::QoS::AtLeastOnce);
+                publication.payload.clone()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(taken, vec!["1", "2"]);
+
+        assert!(state.waiting_to_be_acked.is_empty());
+        let queued = state
+            .waiting_to_be_sent
+            .iter()
+            .map(|publication| publication.payload.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(queued, vec!["3"]);
+    }
+
     #[test]
     fn test_offline_subscribe_to() {
         let id = "id1".to_string();
         let client_id = ClientId::from(id);
-        let mut session = Session::new_offline(SessionState::new(client_id));
+        let mut session =
+            Session::new_offline(SessionState::new(client_id, SessionConfig::default()));
 
         let subscribe_to = proto::SubscribeTo {
             topic_filter: "topic/new".to_string(),
@@ -1083,7 +1934,8 @@ pub(crate) mod tests {
     fn test_offline_unsubscribe() {
         let id = "id1".to_string();
         let client_id = ClientId::from(id);
-        let mut session = Session::new_offline(SessionState::new(client_id));
+        let mut session =
+            Session::new_offline(SessionState::new(client_id, SessionConfig::default()));
 
         let unsubscribe = proto::Unsubscribe {
             packet_identifier: proto::PacketIdentifier::new(24).unwrap(),
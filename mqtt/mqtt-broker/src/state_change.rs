@@ -70,6 +70,7 @@ impl<'a> TryFrom<StateChange<'a>> for proto::Publication {
                     qos: STATE_CHANGE_QOS,
                     retain: true,
                     payload,
+                    properties: proto::Properties::default(),
                 }
             }
             StateChange::Connections(connections) => proto::Publication {
@@ -77,12 +78,14 @@ impl<'a> TryFrom<StateChange<'a>> for proto::Publication {
                 qos: STATE_CHANGE_QOS,
                 retain: true,
                 payload: serde_json::to_string(&connections)?.into(),
+                properties: proto::Properties::default(),
             },
             StateChange::Sessions(sessions) => proto::Publication {
                 topic_name: "$edgehub/sessions".to_owned(),
                 qos: STATE_CHANGE_QOS,
                 retain: true,
                 payload: serde_json::to_string(&sessions)?.into(),
+                properties: proto::Properties::default(),
             },
         })
     }
@@ -406,6 +409,7 @@ mod tests {
             qos,
             retain,
             payload,
+            ..
         } = publication;
 
         assert_eq!(&topic_name, topic);
@@ -0,0 +1,353 @@
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use mqtt3::proto;
+
+const STATS_QOS: proto::QoS = proto::QoS::AtMostOnce;
+
+/// A point-in-time view of the broker, published to `$SYS/broker/...` topics
+/// and exposed as Prometheus metrics.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BrokerStats {
+    uptime: Duration,
+    clients_connected: usize,
+    clients_offline: usize,
+    sessions: usize,
+    retained_messages: usize,
+    queued_messages: usize,
+    inflight_messages: usize,
+    messages_received: u64,
+    messages_sent: u64,
+    messages_dropped: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Gauge,
+    Counter,
+}
+
+struct Metric {
+    topic: &'static str,
+    name: &'static str,
+    help: &'static str,
+    kind: Kind,
+    value: u64,
+}
+
+impl BrokerStats {
+    pub fn uptime(&self) -> Duration {
+        self.uptime
+    }
+
+    pub fn clients_connected(&self) -> usize {
+        self.clients_connected
+    }
+
+    pub fn clients_offline(&self) -> usize {
+        self.clients_offline
+    }
+
+    pub fn sessions(&self) -> usize {
+        self.sessions
+    }
+
+    pub fn retained_messages(&self) -> usize {
+        self.retained_messages
+    }
+
+    pub fn queued_messages(&self) -> usize {
+        self.queued_messages
+    }
+
+    pub fn inflight_messages(&self) -> usize {
+        self.inflight_messages
+    }
+
+    pub fn messages_received(&self) -> u64 {
+        self.messages_received
+    }
+
+    pub fn messages_sent(&self) -> u64 {
+        self.messages_sent
+    }
+
+    pub fn messages_dropped(&self) -> u64 {
+        self.messages_dropped
+    }
+
+    /// Returns the `$SYS` publications for these stats.
+    ///
+    /// Message rates are computed against the `previous` stats, if any.
+    pub fn publications(&self, previous: Option<&BrokerStats>) -> Vec<proto::Publication> {
+        let mut publications = self
+            .metrics()
+            .into_iter()
+            .map(|metric| publication(metric.topic, metric.value.to_string()))
+            .collect::<Vec<_>>();
+
+        if let Some(previous) = previous {
+            let elapsed = self.uptime.checked_sub(previous.uptime).unwrap_or_default();
+            if elapsed > Duration::default() {
+                let rate = |current: u64, previous: u64| {
+                    #[allow(clippy::cast_precision_loss)]
+                    let delta = current.saturating_sub(previous) as f64;
+                    format!("{:.2}", delta / elapsed.as_secs_f64())
+                };
+
+                publications.push(publication(
+                    "$SYS/broker/load/messages/received",
+                    rate(self.messages_received, previous.messages_received),
+                ));
+                publications.push(publication(
+                    "$SYS/broker/load/messages/sent",
+                    rate(self.messages_sent, previous.messages_sent),
+                ));
+            }
+        }
+
+        publications
+    }
+
+    /// Formats the stats in the Prometheus text exposition format.
+    pub fn prometheus(&self) -> String {
+        let mut text = String::new();
+        for metric in self.metrics() {
+            let kind = match metric.kind {
+                Kind::Gauge => "gauge",
+                Kind::Counter => "counter",
+            };
+            // writing to a String cannot fail
+            let _ = writeln!(text, "# HELP {} {}", metric.name, metric.help);
+            let _ = writeln!(text, "# TYPE {} {}", metric.name, kind);
+            let _ = writeln!(text, "{} {}", metric.name, metric.value);
+        }
+        text
+    }
+
+    fn metrics(&self) -> Vec<Metric> {
+        vec![
+            Metric {
+                topic: "$SYS/broker/uptime",
+                name: "mqtt_broker_uptime_seconds",
+                help: "Time since the broker started.",
+                kind: Kind::Gauge,
+                value: self.uptime.as_secs(),
+            },
+            Metric {
+                topic: "$SYS/broker/clients/connected",
+                name: "mqtt_broker_clients_connected",
+                help: "Number of connected clients.",
+                kind: Kind::Gauge,
+                value: self.clients_connected as u64,
+            },
+            Metric {
+                topic: "$SYS/broker/clients/offline",
+                name: "mqtt_broker_clients_offline",
+                help: "Number of persistent sessions without a connected client.",
+                kind: Kind::Gauge,
+                value: self.clients_offline as u64,
+            },
+            Metric {
+                topic: "$SYS/broker/clients/total",
+                name: "mqtt_broker_sessions",
+                help: "Number of sessions, connected or not.",
+                kind: Kind::Gauge,
+                value: self.sessions as u64,
+            },
+            Metric {
+                topic: "$SYS/broker/retained/count",
+                name: "mqtt_broker_retained_messages",
+                help: "Number of retained messages.",
+                kind: Kind::Gauge,
+                value: self.retained_messages as u64,
+            },
+            Metric {
+                topic: "$SYS/broker/messages/queued",
+                name: "mqtt_broker_queued_messages",
+                help: "Number of messages waiting in session queues.",
+                kind: Kind::Gauge,
+                value: self.queued_messages as u64,
+            },
+            Metric {
+                topic: "$SYS/broker/messages/inflight",
+                name: "mqtt_broker_inflight_messages",
+                help: "Number of messages sent to clients and not yet acknowledged.",
+                kind: Kind::Gauge,
+                value: self.inflight_messages as u64,
+            },
+            Metric {
+                topic: "$SYS/broker/messages/received",
+                name: "mqtt_broker_messages_received_total",
+                help: "Number of messages published by clients.",
+                kind: Kind::Counter,
+                value: self.messages_received,
+            },
+            Metric {
+                topic: "$SYS/broker/messages/sent",
+                name: "mqtt_broker_messages_sent_total",
+                help: "Number of messages sent to clients.",
+                kind: Kind::Counter,
+                value: self.messages_sent,
+            },
+            Metric {
+                topic: "$SYS/broker/messages/dropped",
+                name: "mqtt_broker_messages_dropped_total",
+                help: "Number of messages dropped because a session queue was full.",
+                kind: Kind::Counter,
+                value: self.messages_dropped,
+            },
+        ]
+    }
+}
+
+fn publication(topic: &str, payload: String) -> proto::Publication {
+    proto::Publication {
+        topic_name: topic.to_owned(),
+        qos: STATS_QOS,
+        retain: true,
+        payload: payload.into(),
+        properties: proto::Properties::default(),
+    }
+}
+
+/// Gives access to the latest stats sampled by the broker.
+#[derive(Clone, Debug, Default)]
+pub struct StatsHandle(Arc<Mutex<BrokerStats>>);
+
+impl StatsHandle {
+    pub fn get(&self) -> BrokerStats {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn set(&self, stats: BrokerStats) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = stats;
+    }
+}
+
+/// Counters kept by the broker between samples.
+#[derive(Debug)]
+pub(crate) struct Stats {
+    started: Instant,
+    messages_received: u64,
+    messages_sent: u64,
+    messages_dropped: u64,
+    last: Option<BrokerStats>,
+    handle: StatsHandle,
+}
+
+impl Stats {
+    pub(crate) fn handle(&self) -> StatsHandle {
+        self.handle.clone()
+    }
+
+    pub(crate) fn message_received(&mut self) {
+        self.messages_received += 1;
+    }
+
+    pub(crate) fn messages_sent(&mut self, count: u64) {
+        self.messages_sent += count;
+    }
+
+    pub(crate) fn messages_dropped(&mut self, count: u64) {
+        self.messages_dropped += count;
+    }
+
+    /// Records a new sample, returning it along with the previous one.
+    pub(crate) fn sample(
+        &mut self,
+        clients_connected: usize,
+        clients_offline: usize,
+        retained_messages: usize,
+        queued_messages: usize,
+        inflight_messages: usize,
+    ) -> (BrokerStats, Option<BrokerStats>) {
+        let stats = BrokerStats {
+            uptime: self.started.elapsed(),
+            clients_connected,
+            clients_offline,
+            sessions: clients_connected + clients_offline,
+            retained_messages,
+            queued_messages,
+            inflight_messages,
+            messages_received: self.messages_received,
+            messages_sent: self.messages_sent,
+            messages_dropped: self.messages_dropped,
+        };
+
+        self.handle.set(stats.clone());
+        let previous = self.last.replace(stats.clone());
+        (stats, previous)
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            messages_received: 0,
+            messages_sent: 0,
+            messages_dropped: 0,
+            last: None,
+            handle: StatsHandle::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::BrokerStats;
+
+    fn stats(uptime: u64, messages_received: u64) -> BrokerStats {
+        BrokerStats {
+            uptime: Duration::from_secs(uptime),
+            clients_connected: 2,
+            clients_offline: 1,
+            sessions: 3,
+            messages_received,
+            ..BrokerStats::default()
+        }
+    }
+
+    #[test]
+    fn it_formats_prometheus_text() {
+        let text = stats(10, 5).prometheus();
+
+        assert!(text.contains("# TYPE mqtt_broker_clients_connected gauge\n"));
+        assert!(text.contains("\nmqtt_broker_clients_connected 2\n"));
+        assert!(text.contains("# TYPE mqtt_broker_messages_received_total counter\n"));
+        assert!(text.contains("\nmqtt_broker_messages_received_total 5\n"));
+        assert!(text.contains("\nmqtt_broker_uptime_seconds 10\n"));
+    }
+
+    #[test]
+    fn it_publishes_rates_against_previous_stats() {
+        let previous = stats(10, 5);
+        let current = stats(20, 25);
+
+        let publications = current.publications(None);
+        assert!(publications
+            .iter()
+            .all(|p| !p.topic_name.starts_with("$SYS/broker/load")));
+
+        let publications = current.publications(Some(&previous));
+        let rate = publications
+            .iter()
+            .find(|p| p.topic_name == "$SYS/broker/load/messages/received")
+            .expect("rate");
+        assert_eq!(rate.payload, "2.00");
+        assert!(rate.retain);
+
+        let total = publications
+            .iter()
+            .find(|p| p.topic_name == "$SYS/broker/clients/total")
+            .expect("total");
+        assert_eq!(total.payload, "3");
+    }
+}
@@ -10,6 +10,7 @@ const NUL_CHAR: char = '\0';
 const TOPIC_SEPARATOR: char = '/';
 static MULTILEVEL_WILDCARD: &str = "#";
 static SINGLELEVEL_WILDCARD: &str = "+";
+static SHARED_SUBSCRIPTION_PREFIX: &str = "$share/";
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Subscription {
@@ -31,6 +32,40 @@ impl Subscription {
     }
 }
 
+/// Returns true if the topic filter names a shared subscription.
+pub fn is_shared(topic_filter: &str) -> bool {
+    topic_filter.starts_with(SHARED_SUBSCRIPTION_PREFIX)
+}
+
+/// Parses a topic filter from a SUBSCRIBE packet.
+///
+/// A shared subscription `$share/<group>/<filter>` yields the share name
+/// along with the filter that is matched against topic names.
+pub fn parse_topic_filter(topic_filter: &str) -> Result<(Option<&str>, TopicFilter), Error> {
+    if !is_shared(topic_filter) {
+        return Ok((None, topic_filter.parse()?));
+    }
+
+    // [MQTT-4.8.2-1] - A ShareName MUST be at least one character long and
+    // MUST NOT contain "/", "+" or "#". It MUST be followed by a "/" and a
+    // Topic Filter.
+    let shared = &topic_filter[SHARED_SUBSCRIPTION_PREFIX.len()..];
+    let mut parts = shared.splitn(2, TOPIC_SEPARATOR);
+    match (parts.next(), parts.next()) {
+        (Some(group), Some(filter))
+            if !group.is_empty()
+                && !group.contains(MULTILEVEL_WILDCARD)
+                && !group.contains(SINGLELEVEL_WILDCARD) =>
+        {
+            let filter = filter
+                .parse()
+                .map_err(|_| Error::InvalidTopicFilter(topic_filter.to_owned()))?;
+            Ok((Some(group), filter))
+        }
+        _ => Err(Error::InvalidTopicFilter(topic_filter.to_owned())),
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct TopicFilter {
     segments: Vec<Segment>,
@@ -47,6 +82,10 @@ impl TopicFilter {
         }
     }
 
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
     pub fn matches(&self, topic_name: &str) -> bool {
         let mut segments = self.segments.iter();
         let mut levels = topic_name.split(TOPIC_SEPARATOR);
@@ -81,6 +120,69 @@ impl TopicFilter {
         }
         true
     }
+
+    /// Returns `true` if every topic matched by `other` is also matched by this filter.
+    ///
+    /// Unlike `matches`, wildcards in `other` are treated as wildcards,
+    /// e.g. `devices/+` covers `devices/device-1` but not `devices/#`.
+    pub fn covers(&self, other: &TopicFilter) -> bool {
+        let mut segments = self.segments.iter();
+        let mut others = other.segments.iter();
+
+        // Topics starting with `$` are not matched by a leading wildcard.
+        match (self.segments.first(), other.segments.first()) {
+            (Some(Segment::MultiLevelWildcard), Some(Segment::Level(l)))
+            | (Some(Segment::SingleLevelWildcard), Some(Segment::Level(l)))
+                if l.starts_with('$') =>
+            {
+                return false
+            }
+            _ => (),
+        }
+
+        loop {
+            match (segments.next(), others.next()) {
+                (Some(Segment::MultiLevelWildcard), _) => return true,
+                (Some(Segment::SingleLevelWildcard), Some(Segment::Level(_)))
+                | (Some(Segment::SingleLevelWildcard), Some(Segment::SingleLevelWildcard)) => (),
+                (Some(Segment::Level(s)), Some(Segment::Level(o))) if s == o => (),
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Returns `true` if at least one topic is matched by both this filter and `other`.
+    pub fn intersects(&self, other: &TopicFilter) -> bool {
+        let mut segments = self.segments.iter();
+        let mut others = other.segments.iter();
+
+        // Topics starting with `$` are not matched by a leading wildcard.
+        match (self.segments.first(), other.segments.first()) {
+            (Some(Segment::MultiLevelWildcard), Some(Segment::Level(l)))
+            | (Some(Segment::SingleLevelWildcard), Some(Segment::Level(l)))
+            | (Some(Segment::Level(l)), Some(Segment::MultiLevelWildcard))
+            | (Some(Segment::Level(l)), Some(Segment::SingleLevelWildcard))
+                if l.starts_with('$') =>
+            {
+                return false
+            }
+            _ => (),
+        }
+
+        loop {
+            match (segments.next(), others.next()) {
+                (Some(Segment::MultiLevelWildcard), _) | (_, Some(Segment::MultiLevelWildcard)) => {
+                    return true
+                }
+                (Some(Segment::SingleLevelWildcard), Some(_))
+                | (Some(_), Some(Segment::SingleLevelWildcard)) => (),
+                (Some(Segment::Level(s)), Some(Segment::Level(o))) if s == o => (),
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -165,7 +267,7 @@ pub(crate) mod tests {
 
     use proptest::prelude::*;
 
-    use crate::subscription::{Segment, TopicFilter};
+    use crate::subscription::{parse_topic_filter, Segment, TopicFilter};
 
     fn filter(segments: Vec<Segment>) -> TopicFilter {
         TopicFilter::new(segments)
@@ -239,6 +341,30 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn shared_topic_filter() {
+        let (group, filter) = parse_topic_filter("$share/group/sport/+").unwrap();
+        assert_eq!(Some("group"), group);
+        assert_eq!(TopicFilter::from_str("sport/+").unwrap(), filter);
+
+        let (group, filter) = parse_topic_filter("sport/#").unwrap();
+        assert_eq!(None, group);
+        assert_eq!(TopicFilter::from_str("sport/#").unwrap(), filter);
+
+        let cases = vec![
+            "$share/group",
+            "$share/group/",
+            "$share//sport",
+            "$share/gr+oup/sport",
+            "$share/gr#oup/sport",
+            "$share/group/sport/#/ranking",
+        ];
+
+        for case in &cases {
+            assert!(parse_topic_filter(case).is_err(), "{}", case);
+        }
+    }
+
     proptest! {
         #[test]
         fn display_roundtrip(filter in crate::proptest::arb_topic_filter()) {
@@ -273,4 +399,66 @@ pub(crate) mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_covers() {
+        let cases = vec![
+            ("#", "blah/#", true),
+            ("#", "+/blah", true),
+            ("blah/#", "blah", true),
+            ("blah/#", "blah/+/blah2", true),
+            ("blah/+", "blah/blah1", true),
+            ("blah/+", "blah/+", true),
+            ("blah/+", "blah/#", false),
+            ("blah/+", "blah/+/blah2", false),
+            ("blah/+", "blah", false),
+            ("blah/blah1", "blah/+", false),
+            ("blah/blah1", "blah/blah1", true),
+            ("blah/blah1", "blah/blah2", false),
+            ("#", "$SYS/blah", false),
+            ("+/blah", "$SYS/blah", false),
+            ("$SYS/#", "$SYS/blah", true),
+        ];
+
+        for (filter, other, expected) in &cases {
+            let parsed = TopicFilter::from_str(filter).unwrap();
+            let other_parsed = TopicFilter::from_str(other).unwrap();
+            assert_eq!(
+                *expected,
+                parsed.covers(&other_parsed),
+                "filter \"{}\" covers \"{}\"",
+                filter,
+                other
+            );
+        }
+    }
+
+    #[test]
+    fn test_intersects() {
+        let cases = vec![
+            ("#", "blah/blah1", true),
+            ("blah/#", "blah", true),
+            ("blah/+", "blah/#", true),
+            ("blah/+", "+/blah1", true),
+            ("blah/+", "blah/+/blah2", false),
+            ("blah/blah1", "blah/blah2", false),
+            ("blah/blah1", "blah", false),
+            ("#", "$SYS/blah", false),
+            ("$SYS/blah", "+/blah", false),
+            ("$SYS/#", "$SYS/blah", true),
+        ];
+
+        for (filter, other, expected) in &cases {
+            let parsed = TopicFilter::from_str(filter).unwrap();
+            let other_parsed = TopicFilter::from_str(other).unwrap();
+            assert_eq!(
+                *expected,
+                parsed.intersects(&other_parsed),
+                "filter \"{}\" intersects \"{}\"",
+                filter,
+                other
+            );
+            assert_eq!(*expected, other_parsed.intersects(&parsed));
+        }
+    }
 }
@@ -0,0 +1,295 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::subscription::{Segment, Subscription, TopicFilter};
+use crate::ClientId;
+
+const TOPIC_SEPARATOR: char = '/';
+
+/// An index of the subscriptions of all sessions, keyed on the segments of
+/// their topic filters.
+///
+/// Looking up the subscribers of a topic name only walks the branches that
+/// can match it, instead of checking every subscription of every session.
+#[derive(Debug, Default)]
+pub(crate) struct SubscriptionTrie {
+    root: Node,
+    clients: HashMap<ClientId, HashMap<String, TopicFilter>>,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct Entry {
+    client_id: ClientId,
+    topic_filter: String,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    levels: HashMap<String, Node>,
+    single_level: Option<Box<Node>>,
+    // subscribers of a filter ending with a multi-level wildcard at this node
+    multi_level: HashSet<Entry>,
+    // subscribers of a filter ending at this node
+    subscribers: HashSet<Entry>,
+}
+
+impl SubscriptionTrie {
+    /// Brings the index in line with the current subscriptions of a client.
+    ///
+    /// `None` removes all the subscriptions of the client.
+    pub(crate) fn update(
+        &mut self,
+        client_id: &ClientId,
+        subscriptions: Option<&HashMap<String, Subscription>>,
+    ) {
+        let mut indexed = self.clients.remove(client_id).unwrap_or_default();
+
+        indexed.retain(|topic_filter, filter| {
+            let current = subscriptions
+                .and_then(|subscriptions| subscriptions.get(topic_filter))
+                .map(Subscription::filter);
+            if current == Some(filter) {
+                true
+            } else {
+                let entry = Entry::new(client_id, topic_filter);
+                self.root.remove(filter.segments(), &entry);
+                false
+            }
+        });
+
+        for (topic_filter, subscription) in subscriptions.into_iter().flatten() {
+            if !indexed.contains_key(topic_filter) {
+                let entry = Entry::new(client_id, topic_filter);
+                self.root.insert(subscription.filter().segments(), entry);
+                indexed.insert(topic_filter.clone(), subscription.filter().clone());
+            }
+        }
+
+        if !indexed.is_empty() {
+            self.clients.insert(client_id.clone(), indexed);
+        }
+    }
+
+    /// Returns the client and the key of every subscription matching the topic name.
+    pub(crate) fn matches<'a>(&'a self, topic_name: &str) -> Vec<(&'a ClientId, &'a str)> {
+        let levels = topic_name.split(TOPIC_SEPARATOR).collect::<Vec<_>>();
+
+        // [MQTT-4.7.2-1] - The Server MUST NOT match Topic Filters starting
+        // with a wildcard character with Topic Names beginning with a $ character.
+        let system = levels.first().map_or(false, |level| level.starts_with('$'));
+
+        let mut entries = Vec::new();
+        self.root.matches(&levels, !system, &mut entries);
+        entries
+            .into_iter()
+            .map(|entry| (&entry.client_id, entry.topic_filter.as_str()))
+            .collect()
+    }
+}
+
+impl Entry {
+    fn new(client_id: &ClientId, topic_filter: &str) -> Self {
+        Self {
+            client_id: client_id.clone(),
+            topic_filter: topic_filter.to_owned(),
+        }
+    }
+}
+
+impl Node {
+    fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+            && self.single_level.is_none()
+            && self.multi_level.is_empty()
+            && self.subscribers.is_empty()
+    }
+
+    fn insert(&mut self, segments: &[Segment], entry: Entry) {
+        match segments.split_first() {
+            Some((Segment::Level(level), rest)) => self
+                .levels
+                .entry(level.clone())
+                .or_default()
+                .insert(rest, entry),
+            Some((Segment::SingleLevelWildcard, rest)) => self
+                .single_level
+                .get_or_insert_with(Box::default)
+                .insert(rest, entry),
+            Some((Segment::MultiLevelWildcard, _)) => {
+                self.multi_level.insert(entry);
+            }
+            None => {
+                self.subscribers.insert(entry);
+            }
+        }
+    }
+
+    fn remove(&mut self, segments: &[Segment], entry: &Entry) {
+        match segments.split_first() {
+            Some((Segment::Level(level), rest)) => {
+                if let Some(node) = self.levels.get_mut(level) {
+                    node.remove(rest, entry);
+                    if node.is_empty() {
+                        self.levels.remove(level);
+                    }
+                }
+            }
+            Some((Segment::SingleLevelWildcard, rest)) => {
+                if let Some(node) = &mut self.single_level {
+                    node.remove(rest, entry);
+                    if node.is_empty() {
+                        self.single_level = None;
+                    }
+                }
+            }
+            Some((Segment::MultiLevelWildcard, _)) => {
+                self.multi_level.remove(entry);
+            }
+            None => {
+                self.subscribers.remove(entry);
+            }
+        }
+    }
+
+    fn matches<'a>(&'a self, levels: &[&str], wildcards: bool, entries: &mut Vec<&'a Entry>) {
+        // A multi-level wildcard also matches the parent level,
+        // so "sport/#" matches "sport"
+        if wildcards {
+            entries.extend(&self.multi_level);
+        }
+
+        match levels.split_first() {
+            Some((level, rest)) => {
+                if let Some(node) = self.levels.get(*level) {
+                    node.matches(rest, true, entries);
+                }
+                if wildcards {
+                    if let Some(node) = &self.single_level {
+                        node.matches(rest, true, entries);
+                    }
+                }
+            }
+            None => entries.extend(&self.subscribers),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use mqtt3::proto;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use super::SubscriptionTrie;
+    use crate::subscription::{Subscription, TopicFilter};
+    use crate::ClientId;
+
+    fn subscriptions(topic_filters: &[&str]) -> HashMap<String, Subscription> {
+        topic_filters
+            .iter()
+            .map(|topic_filter| {
+                let filter = topic_filter.parse().unwrap();
+                let subscription = Subscription::new(filter, proto::QoS::AtLeastOnce);
+                ((*topic_filter).to_owned(), subscription)
+            })
+            .collect()
+    }
+
+    fn matches(trie: &SubscriptionTrie, topic_name: &str) -> Vec<(String, String)> {
+        let mut matches = trie
+            .matches(topic_name)
+            .into_iter()
+            .map(|(client_id, topic_filter)| (client_id.to_string(), topic_filter.to_owned()))
+            .collect::<Vec<_>>();
+        matches.sort();
+        matches
+    }
+
+    #[test]
+    fn it_matches_subscribers() {
+        let mut trie = SubscriptionTrie::default();
+        trie.update(
+            &ClientId::from("a"),
+            Some(&subscriptions(&["sport/tennis/+", "sport/#", "#"])),
+        );
+        trie.update(
+            &ClientId::from("b"),
+            Some(&subscriptions(&["sport/tennis/player1", "+/+"])),
+        );
+
+        assert_eq!(
+            matches(&trie, "sport/tennis/player1"),
+            vec![
+                ("a".to_owned(), "#".to_owned()),
+                ("a".to_owned(), "sport/#".to_owned()),
+                ("a".to_owned(), "sport/tennis/+".to_owned()),
+                ("b".to_owned(), "sport/tennis/player1".to_owned()),
+            ]
+        );
+        assert_eq!(
+            matches(&trie, "sport"),
+            vec![
+                ("a".to_owned(), "#".to_owned()),
+                ("a".to_owned(), "sport/#".to_owned()),
+            ]
+        );
+        assert_eq!(
+            matches(&trie, "sport/tennis"),
+            vec![
+                ("a".to_owned(), "#".to_owned()),
+                ("a".to_owned(), "sport/#".to_owned()),
+                ("b".to_owned(), "+/+".to_owned()),
+            ]
+        );
+        assert!(matches(&trie, "$SYS/broker").is_empty());
+    }
+
+    #[test]
+    fn it_removes_subscribers() {
+        let mut trie = SubscriptionTrie::default();
+        let client_id = ClientId::from("a");
+        trie.update(&client_id, Some(&subscriptions(&["a/+", "a/b"])));
+        trie.update(&client_id, Some(&subscriptions(&["a/b"])));
+
+        assert!(matches(&trie, "a/c").is_empty());
+        assert_eq!(
+            matches(&trie, "a/b"),
+            vec![("a".to_owned(), "a/b".to_owned())]
+        );
+
+        trie.update(&client_id, None);
+        assert!(matches(&trie, "a/b").is_empty());
+        assert!(trie.root.is_empty());
+        assert!(trie.clients.is_empty());
+    }
+
+    proptest! {
+        #[test]
+        fn it_matches_like_topic_filters(
+            topic_filters in vec("[ab+](/[ab+]?){0,3}(/#)?|#|/[ab]", 1..10),
+            topic_name in "(\\$s|[ab]?)(/[ab]?){0,3}",
+        ) {
+            let client_id = ClientId::from("client");
+            let topic_filters = topic_filters.iter().map(String::as_str).collect::<Vec<_>>();
+            let subscriptions = subscriptions(&topic_filters);
+
+            let mut trie = SubscriptionTrie::default();
+            trie.update(&client_id, Some(&subscriptions));
+
+            let mut expected = subscriptions
+                .keys()
+                .filter(|topic_filter| {
+                    topic_filter
+                        .parse::<TopicFilter>()
+                        .unwrap()
+                        .matches(&topic_name)
+                })
+                .map(|topic_filter| ("client".to_owned(), topic_filter.clone()))
+                .collect::<Vec<_>>();
+            expected.sort();
+
+            prop_assert_eq!(matches(&trie, &topic_name), expected);
+        }
+    }
+}
@@ -0,0 +1,659 @@
+#[cfg(unix)]
+mod unix;
+mod websocket;
+
+use std::{
+    convert::TryFrom,
+    future::Future,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, BufMut};
+use core::mem::MaybeUninit;
+use futures::stream::FuturesUnordered;
+use openssl::{
+    pkcs12::Pkcs12,
+    ssl::{SslAcceptor, SslMethod, SslVerifyMode},
+    x509::{X509Name, X509Ref},
+};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    stream::Stream,
+};
+use tokio_openssl::{HandshakeError, SslStream};
+use tracing::{debug, error, info, warn};
+
+use crate::configuration::Transport as TransportConfig;
+use crate::{Certificate, Error, InitializeBrokerError, PeerCredentials};
+
+pub use websocket::{WsStream, MQTT_SUBPROTOCOL};
+
+pub enum TransportBuilder<A> {
+    Tcp(A),
+    Tls(A, SslAcceptor),
+    Ws(A),
+    Wss(A, SslAcceptor),
+    #[cfg(unix)]
+    Unix(PathBuf, Option<u32>),
+}
+
+impl<A> TransportBuilder<A>
+where
+    A: ToSocketAddrs,
+{
+    pub async fn build(self) -> Result<Transport, InitializeBrokerError> {
+        match self {
+            TransportBuilder::Tcp(addr) => Transport::new_tcp(addr).await,
+            TransportBuilder::Tls(addr, acceptor) => Transport::new_tls(addr, acceptor).await,
+            TransportBuilder::Ws(addr) => Transport::new_ws(addr).await,
+            TransportBuilder::Wss(addr, acceptor) => Transport::new_wss(addr, acceptor).await,
+            #[cfg(unix)]
+            TransportBuilder::Unix(path, permissions) => Transport::new_unix(&path, permissions),
+        }
+    }
+}
+
+impl TryFrom<TransportConfig> for TransportBuilder<String> {
+    type Error = InitializeBrokerError;
+
+    fn try_from(transport: TransportConfig) -> Result<Self, Self::Error> {
+        match transport {
+            TransportConfig::Tcp { address } => Ok(Self::Tcp(address)),
+            TransportConfig::Tls {
+                address,
+                certificate,
+                client_ca_bundle,
+            } => Ok(Self::Tls(
+                address,
+                tls_acceptor(&certificate, client_ca_bundle.as_deref())?,
+            )),
+            TransportConfig::Ws { address } => Ok(Self::Ws(address)),
+            TransportConfig::Wss {
+                address,
+                certificate,
+                client_ca_bundle,
+            } => Ok(Self::Wss(
+                address,
+                tls_acceptor(&certificate, client_ca_bundle.as_deref())?,
+            )),
+            #[cfg(unix)]
+            TransportConfig::Unix { path, permissions } => Ok(Self::Unix(path, permissions)),
+            #[cfg(not(unix))]
+            TransportConfig::Unix { path, .. } => {
+                Err(InitializeBrokerError::BindServer(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!(
+                        "unix domain socket {} is not supported on this platform",
+                        path.display()
+                    ),
+                )))
+            }
+        }
+    }
+}
+
+/// Creates TLS acceptor for a server identity stored in PKCS#12 file.
+///
+/// When a CA bundle is provided, clients are asked for a certificate
+/// which must be issued by one of the CAs in the bundle. Clients that
+/// don't present a certificate are still accepted and are expected to
+/// authenticate with username and password.
+fn tls_acceptor(
+    certificate: &Path,
+    client_ca_bundle: Option<&Path>,
+) -> Result<SslAcceptor, InitializeBrokerError> {
+    info!("Loading identity from {}", certificate.display());
+    let cert_buffer = std::fs::read(certificate)
+        .map_err(|e| InitializeBrokerError::LoadIdentity(certificate.to_path_buf(), e))?;
+
+    let identity = Pkcs12::from_der(&cert_buffer)
+        .and_then(|pkcs12| pkcs12.parse(""))
+        .map_err(InitializeBrokerError::DecodeIdentity)?;
+
+    let mut builder =
+        SslAcceptor::mozilla_intermediate(SslMethod::tls()).map_err(InitializeBrokerError::Tls)?;
+    builder
+        .set_private_key(&identity.pkey)
+        .map_err(InitializeBrokerError::Tls)?;
+    builder
+        .set_certificate(&identity.cert)
+        .map_err(InitializeBrokerError::Tls)?;
+    for cert in identity.chain.into_iter().flatten() {
+        builder
+            .add_extra_chain_cert(cert)
+            .map_err(InitializeBrokerError::Tls)?;
+    }
+
+    if let Some(client_ca_bundle) = client_ca_bundle {
+        info!(
+            "Loading client certificate CA bundle from {}",
+            client_ca_bundle.display()
+        );
+        let load_ca_bundle_err =
+            |e| InitializeBrokerError::LoadClientCaBundle(client_ca_bundle.to_path_buf(), e);
+
+        builder
+            .set_ca_file(client_ca_bundle)
+            .map_err(load_ca_bundle_err)?;
+        builder.set_client_ca_list(
+            X509Name::load_client_ca_file(client_ca_bundle).map_err(load_ca_bundle_err)?,
+        );
+        builder.set_verify(SslVerifyMode::PEER);
+    }
+
+    Ok(builder.build())
+}
+
+pub enum Transport {
+    Tcp(TcpListener),
+    Tls(TcpListener, SslAcceptor),
+    Ws(TcpListener),
+    Wss(TcpListener, SslAcceptor),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Transport {
+    async fn new_tcp<A>(addr: A) -> Result<Self, InitializeBrokerError>
+    where
+        A: ToSocketAddrs,
+    {
+        let tcp = TcpListener::bind(addr)
+            .await
+            .map_err(InitializeBrokerError::BindServer)?;
+
+        Ok(Transport::Tcp(tcp))
+    }
+
+    async fn new_tls<A>(addr: A, acceptor: SslAcceptor) -> Result<Self, InitializeBrokerError>
+    where
+        A: ToSocketAddrs,
+    {
+        let tcp = TcpListener::bind(addr)
+            .await
+            .map_err(InitializeBrokerError::BindServer)?;
+
+        Ok(Transport::Tls(tcp, acceptor))
+    }
+
+    async fn new_ws<A>(addr: A) -> Result<Self, InitializeBrokerError>
+    where
+        A: ToSocketAddrs,
+    {
+        let tcp = TcpListener::bind(addr)
+            .await
+            .map_err(InitializeBrokerError::BindServer)?;
+
+        Ok(Transport::Ws(tcp))
+    }
+
+    async fn new_wss<A>(addr: A, acceptor: SslAcceptor) -> Result<Self, InitializeBrokerError>
+    where
+        A: ToSocketAddrs,
+    {
+        let tcp = TcpListener::bind(addr)
+            .await
+            .map_err(InitializeBrokerError::BindServer)?;
+
+        Ok(Transport::Wss(tcp, acceptor))
+    }
+
+    #[cfg(unix)]
+    fn new_unix(path: &Path, permissions: Option<u32>) -> Result<Self, InitializeBrokerError> {
+        let listener = unix::bind(path, permissions).map_err(InitializeBrokerError::BindServer)?;
+
+        Ok(Transport::Unix(listener))
+    }
+
+    pub fn incoming(self) -> Incoming {
+        match self {
+            Self::Tcp(listener) => Incoming::Tcp(IncomingTcp::new(listener)),
+            Self::Tls(listener, acceptor) => Incoming::Tls(IncomingTls::new(listener, acceptor)),
+            Self::Ws(listener) => Incoming::Ws(IncomingWs::new(listener, None)),
+            Self::Wss(listener, acceptor) => {
+                Incoming::Ws(IncomingWs::new(listener, Some(acceptor)))
+            }
+            #[cfg(unix)]
+            Self::Unix(listener) => Incoming::Unix(unix::IncomingUnix::new(listener)),
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<Address, InitializeBrokerError> {
+        let addr = match self {
+            Self::Tcp(listener) => listener.local_addr().map(Address::Socket),
+            Self::Tls(listener, _) => listener.local_addr().map(Address::Socket),
+            Self::Ws(listener) => listener.local_addr().map(Address::Socket),
+            Self::Wss(listener, _) => listener.local_addr().map(Address::Socket),
+            #[cfg(unix)]
+            Self::Unix(listener) => listener
+                .local_addr()
+                .map(|addr| Address::Unix(addr.as_pathname().map(Path::to_path_buf))),
+        };
+        addr.map_err(InitializeBrokerError::ConnectionLocalAddress)
+    }
+}
+
+/// Address of either end of a client connection.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Address {
+    Socket(SocketAddr),
+
+    /// Unix domain socket path. Client sockets are usually unnamed.
+    Unix(Option<PathBuf>),
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Socket(addr) => write!(f, "{}", addr),
+            Self::Unix(Some(path)) => write!(f, "unix:{}", path.display()),
+            Self::Unix(None) => write!(f, "unix:(unnamed)"),
+        }
+    }
+}
+
+type HandshakeFuture =
+    Pin<Box<dyn Future<Output = Result<SslStream<TcpStream>, HandshakeError<TcpStream>>> + Send>>;
+
+type WsHandshakeFuture = Pin<
+    Box<
+        dyn Future<Output = Result<StreamSelector, Box<dyn std::error::Error + Send + Sync>>>
+            + Send,
+    >,
+>;
+
+pub enum Incoming {
+    Tcp(IncomingTcp),
+    Tls(IncomingTls),
+    Ws(IncomingWs),
+    #[cfg(unix)]
+    Unix(unix::IncomingUnix),
+}
+
+impl Stream for Incoming {
+    type Item = std::io::Result<StreamSelector>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            Self::Tcp(incoming) => Pin::new(incoming).poll_next(cx),
+            Self::Tls(incoming) => Pin::new(incoming).poll_next(cx),
+            Self::Ws(incoming) => Pin::new(incoming).poll_next(cx),
+            #[cfg(unix)]
+            Self::Unix(incoming) => Pin::new(incoming).poll_next(cx),
+        }
+    }
+}
+
+pub struct IncomingTcp {
+    listener: TcpListener,
+}
+
+impl IncomingTcp {
+    fn new(listener: TcpListener) -> Self {
+        Self { listener }
+    }
+}
+
+impl Stream for IncomingTcp {
+    type Item = std::io::Result<StreamSelector>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.listener.poll_accept(cx) {
+            Poll::Ready(Ok((tcp, _))) => match tcp.set_nodelay(true) {
+                Ok(()) => {
+                    debug!("TCP: Accepted connection from client");
+                    Poll::Ready(Some(Ok(StreamSelector::Tcp(tcp))))
+                }
+                Err(err) => {
+                    warn!(
+                        "TCP: Dropping client because failed to setup TCP properties: {}",
+                        err
+                    );
+                    Poll::Ready(Some(Err(err)))
+                }
+            },
+            Poll::Ready(Err(err)) => {
+                error!(
+                    "TCP: Dropping client that failed to completely establish a TCP connection: {}",
+                    err
+                );
+                Poll::Ready(Some(Err(err)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub struct IncomingTls {
+    listener: TcpListener,
+    acceptor: SslAcceptor,
+    connections: FuturesUnordered<HandshakeFuture>,
+}
+
+impl IncomingTls {
+    fn new(listener: TcpListener, acceptor: SslAcceptor) -> Self {
+        Self {
+            listener,
+            acceptor,
+            connections: FuturesUnordered::default(),
+        }
+    }
+}
+
+impl Stream for IncomingTls {
+    type Item = std::io::Result<StreamSelector>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.listener.poll_accept(cx) {
+                Poll::Ready(Ok((stream, _))) => match stream.set_nodelay(true) {
+                    Ok(()) => {
+                        let acceptor = self.acceptor.clone();
+                        self.connections.push(Box::pin(async move {
+                            tokio_openssl::accept(&acceptor, stream).await
+                        }));
+                    }
+                    Err(err) => warn!(
+                        "TCP: Dropping client because failed to setup TCP properties: {}",
+                        err
+                    ),
+                },
+                Poll::Ready(Err(err)) => warn!(
+                    "TCP: Dropping client that failed to completely establish a TCP connection: {}",
+                    err
+                ),
+                Poll::Pending => break,
+            }
+        }
+
+        loop {
+            if self.connections.is_empty() {
+                return Poll::Pending;
+            }
+
+            match Pin::new(&mut self.connections).poll_next(cx) {
+                Poll::Ready(Some(Ok(stream))) => {
+                    debug!("TLS: Accepted connection from client");
+                    return Poll::Ready(Some(Ok(StreamSelector::Tls(stream))));
+                }
+
+                Poll::Ready(Some(Err(err))) => warn!(
+                    "TLS: Dropping client that failed to complete a TLS handshake: {}",
+                    err
+                ),
+
+                Poll::Ready(None) => {
+                    debug!("TLS: Shutting down web server");
+                    return Poll::Ready(None);
+                }
+
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+pub struct IncomingWs {
+    listener: TcpListener,
+    acceptor: Option<SslAcceptor>,
+    connections: FuturesUnordered<WsHandshakeFuture>,
+}
+
+impl IncomingWs {
+    fn new(listener: TcpListener, acceptor: Option<SslAcceptor>) -> Self {
+        Self {
+            listener,
+            acceptor,
+            connections: FuturesUnordered::default(),
+        }
+    }
+}
+
+impl Stream for IncomingWs {
+    type Item = std::io::Result<StreamSelector>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.listener.poll_accept(cx) {
+                Poll::Ready(Ok((stream, _))) => match stream.set_nodelay(true) {
+                    Ok(()) => {
+                        let acceptor = self.acceptor.clone();
+                        self.connections.push(Box::pin(async move {
+                            let stream = match acceptor {
+                                Some(acceptor) => {
+                                    let stream = tokio_openssl::accept(&acceptor, stream).await?;
+                                    StreamSelector::Wss(websocket::accept(stream).await?)
+                                }
+                                None => StreamSelector::Ws(websocket::accept(stream).await?),
+                            };
+                            Ok(stream)
+                        }));
+                    }
+                    Err(err) => warn!(
+                        "TCP: Dropping client because failed to setup TCP properties: {}",
+                        err
+                    ),
+                },
+                Poll::Ready(Err(err)) => warn!(
+                    "TCP: Dropping client that failed to completely establish a TCP connection: {}",
+                    err
+                ),
+                Poll::Pending => break,
+            }
+        }
+
+        loop {
+            if self.connections.is_empty() {
+                return Poll::Pending;
+            }
+
+            match Pin::new(&mut self.connections).poll_next(cx) {
+                Poll::Ready(Some(Ok(stream))) => {
+                    debug!("WS: Accepted connection from client");
+                    return Poll::Ready(Some(Ok(stream)));
+                }
+
+                Poll::Ready(Some(Err(err))) => warn!(
+                    "WS: Dropping client that failed to complete a WebSocket handshake: {}",
+                    err
+                ),
+
+                Poll::Ready(None) => {
+                    debug!("WS: Shutting down web server");
+                    return Poll::Ready(None);
+                }
+
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+pub enum StreamSelector {
+    Tcp(TcpStream),
+    Tls(SslStream<TcpStream>),
+    Ws(WsStream<TcpStream>),
+    Wss(WsStream<SslStream<TcpStream>>),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl StreamSelector {
+    pub fn peer_addr(&self) -> std::io::Result<Address> {
+        match self {
+            StreamSelector::Tcp(stream) => stream.peer_addr().map(Address::Socket),
+            StreamSelector::Tls(stream) => stream.get_ref().peer_addr().map(Address::Socket),
+            StreamSelector::Ws(stream) => stream.get_ref().peer_addr().map(Address::Socket),
+            StreamSelector::Wss(stream) => {
+                stream.get_ref().get_ref().peer_addr().map(Address::Socket)
+            }
+            #[cfg(unix)]
+            StreamSelector::Unix(stream) => stream
+                .peer_addr()
+                .map(|addr| Address::Unix(addr.as_pathname().map(Path::to_path_buf))),
+        }
+    }
+}
+
+pub trait GetPeerCertificate {
+    type Certificate;
+
+    fn peer_certificate(&self) -> Result<Option<Self::Certificate>, Error>;
+}
+
+impl GetPeerCertificate for StreamSelector {
+    type Certificate = Certificate;
+
+    fn peer_certificate(&self) -> Result<Option<Self::Certificate>, Error> {
+        match self {
+            StreamSelector::Tcp(_) | StreamSelector::Ws(_) => Ok(None),
+            StreamSelector::Tls(stream) => peer_certificate(stream),
+            StreamSelector::Wss(stream) => peer_certificate(stream.get_ref()),
+            #[cfg(unix)]
+            StreamSelector::Unix(_) => Ok(None),
+        }
+    }
+}
+
+pub trait GetPeerCredentials {
+    fn peer_credentials(&self) -> Result<Option<PeerCredentials>, Error>;
+}
+
+impl GetPeerCredentials for StreamSelector {
+    fn peer_credentials(&self) -> Result<Option<PeerCredentials>, Error> {
+        match self {
+            StreamSelector::Tcp(_)
+            | StreamSelector::Tls(_)
+            | StreamSelector::Ws(_)
+            | StreamSelector::Wss(_) => Ok(None),
+            #[cfg(unix)]
+            StreamSelector::Unix(stream) => unix::peer_credentials(stream)
+                .map(Some)
+                .map_err(Error::PeerCredentials),
+        }
+    }
+}
+
+fn peer_certificate(stream: &SslStream<TcpStream>) -> Result<Option<Certificate>, Error> {
+    let ssl = stream.ssl();
+    ssl.peer_certificate()
+        .map(|cert| {
+            // on the server side the chain doesn't include the peer certificate itself
+            let chain = ssl
+                .peer_cert_chain()
+                .map(|chain| chain.iter().map(X509Ref::to_der).collect())
+                .transpose()?
+                .unwrap_or_default();
+
+            cert.to_der()
+                .map(|cert| Certificate::from(cert).with_chain(chain))
+        })
+        .transpose()
+        .map_err(Error::PeerCertificate)
+}
+
+impl AsyncRead for StreamSelector {
+    #[inline]
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [MaybeUninit<u8>]) -> bool {
+        match self {
+            StreamSelector::Tcp(stream) => stream.prepare_uninitialized_buffer(buf),
+            StreamSelector::Tls(stream) => stream.prepare_uninitialized_buffer(buf),
+            StreamSelector::Ws(stream) => stream.prepare_uninitialized_buffer(buf),
+            StreamSelector::Wss(stream) => stream.prepare_uninitialized_buffer(buf),
+            #[cfg(unix)]
+            StreamSelector::Unix(stream) => stream.prepare_uninitialized_buffer(buf),
+        }
+    }
+
+    #[inline]
+    fn poll_read_buf<B: BufMut>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            StreamSelector::Tcp(stream) => Pin::new(stream).poll_read_buf(cx, buf),
+            StreamSelector::Tls(stream) => Pin::new(stream).poll_read_buf(cx, buf),
+            StreamSelector::Ws(stream) => Pin::new(stream).poll_read_buf(cx, buf),
+            StreamSelector::Wss(stream) => Pin::new(stream).poll_read_buf(cx, buf),
+            #[cfg(unix)]
+            StreamSelector::Unix(stream) => Pin::new(stream).poll_read_buf(cx, buf),
+        }
+    }
+
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            StreamSelector::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            StreamSelector::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            StreamSelector::Ws(stream) => Pin::new(stream).poll_read(cx, buf),
+            StreamSelector::Wss(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            StreamSelector::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for StreamSelector {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            StreamSelector::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            StreamSelector::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            StreamSelector::Ws(stream) => Pin::new(stream).poll_write(cx, buf),
+            StreamSelector::Wss(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            StreamSelector::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_buf<B: Buf>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            StreamSelector::Tcp(stream) => Pin::new(stream).poll_write_buf(cx, buf),
+            StreamSelector::Tls(stream) => Pin::new(stream).poll_write_buf(cx, buf),
+            StreamSelector::Ws(stream) => Pin::new(stream).poll_write_buf(cx, buf),
+            StreamSelector::Wss(stream) => Pin::new(stream).poll_write_buf(cx, buf),
+            #[cfg(unix)]
+            StreamSelector::Unix(stream) => Pin::new(stream).poll_write_buf(cx, buf),
+        }
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            StreamSelector::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            StreamSelector::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            StreamSelector::Ws(stream) => Pin::new(stream).poll_flush(cx),
+            StreamSelector::Wss(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            StreamSelector::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            StreamSelector::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            StreamSelector::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            StreamSelector::Ws(stream) => Pin::new(stream).poll_shutdown(cx),
+            StreamSelector::Wss(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            StreamSelector::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
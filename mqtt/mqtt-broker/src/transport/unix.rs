@@ -0,0 +1,148 @@
+use std::{
+    fs,
+    future::Future,
+    io,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_util::ready;
+use tokio::{
+    net::{UnixListener, UnixStream},
+    stream::Stream,
+    time::{self, Delay},
+};
+use tracing::{debug, error};
+
+use crate::PeerCredentials;
+
+use super::StreamSelector;
+
+/// Binds a listener to a given path.
+///
+/// A socket file left over from the previous run is removed first,
+/// so that the broker can be restarted without manual clean up.
+/// When `permissions` is set, the socket file is created accessible
+/// to the owner only and gets this mode right after bind.
+pub fn bind(path: &Path, permissions: Option<u32>) -> io::Result<UnixListener> {
+    if fs::symlink_metadata(path).map_or(false, |metadata| metadata.file_type().is_socket()) {
+        debug!("unlinking {}...", path.display());
+        fs::remove_file(path)?;
+        debug!("unlinked {}", path.display());
+    }
+
+    let listener = match permissions {
+        Some(mode) => {
+            let listener = bind_owner_only(path)?;
+            debug!("setting mode {:o} on {}", mode, path.display());
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+            listener
+        }
+        None => UnixListener::bind(path)?,
+    };
+
+    Ok(listener)
+}
+
+/// Binds with a umask that leaves the socket file accessible to the owner only,
+/// so that nobody else can connect before the requested mode is set.
+fn bind_owner_only(path: &Path) -> io::Result<UnixListener> {
+    // umask applies to the whole process, it is restored right after bind
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(umask) };
+    listener
+}
+
+/// How long to wait before accepting again after an accept error.
+///
+/// Errors such as running out of file descriptors don't go away immediately,
+/// so accepting again right away would only spin.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+pub struct IncomingUnix {
+    listener: UnixListener,
+    backoff: Option<Delay>,
+}
+
+impl IncomingUnix {
+    pub fn new(listener: UnixListener) -> Self {
+        Self {
+            listener,
+            backoff: None,
+        }
+    }
+}
+
+impl Stream for IncomingUnix {
+    type Item = io::Result<StreamSelector>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(backoff) = &mut self.backoff {
+                ready!(Pin::new(backoff).poll(cx));
+                self.backoff = None;
+            }
+
+            match self.listener.poll_accept(cx) {
+                Poll::Ready(Ok((stream, _))) => {
+                    debug!("UDS: Accepted connection from client");
+                    return Poll::Ready(Some(Ok(StreamSelector::Unix(stream))));
+                }
+                Poll::Ready(Err(err)) => {
+                    // An accept error doesn't stop the listener,
+                    // it keeps accepting other clients after a backoff.
+                    error!(
+                        "UDS: Dropping client that failed to completely establish a connection: {}",
+                        err
+                    );
+                    self.backoff = Some(time::delay_for(ACCEPT_ERROR_BACKOFF));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn peer_credentials(stream: &UnixStream) -> io::Result<PeerCredentials> {
+    use std::{mem, os::unix::io::AsRawFd};
+
+    use libc::{c_void, getsockopt, socklen_t, ucred, SOL_SOCKET, SO_PEERCRED};
+
+    let mut ucred = ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    let mut ucred_size = mem::size_of::<ucred>() as socklen_t;
+
+    // SO_PEERCRED is used directly instead of tokio's peer_cred()
+    // because the latter doesn't report pid of the peer.
+    let ret = unsafe {
+        getsockopt(
+            stream.as_raw_fd(),
+            SOL_SOCKET,
+            SO_PEERCRED,
+            (&mut ucred as *mut ucred).cast::<c_void>(),
+            &mut ucred_size,
+        )
+    };
+
+    if ret == 0 && ucred_size as usize == mem::size_of::<ucred>() {
+        Ok(PeerCredentials::new(Some(ucred.pid), ucred.uid, ucred.gid))
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn peer_credentials(stream: &UnixStream) -> io::Result<PeerCredentials> {
+    let ucred = stream.peer_cred()?;
+    Ok(PeerCredentials::new(None, ucred.uid, ucred.gid))
+}
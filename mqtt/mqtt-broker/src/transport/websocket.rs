@@ -0,0 +1,164 @@
+use std::{
+    cmp, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes};
+use futures_util::{ready, sink::Sink, stream::Stream};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue, StatusCode},
+        Error as WsError, Message,
+    },
+    WebSocketStream,
+};
+
+/// The WebSocket subprotocol used to carry MQTT packets.
+pub const MQTT_SUBPROTOCOL: &str = "mqtt";
+
+/// Performs the server side of the WebSocket handshake on the given stream.
+///
+/// [MQTT-6.0.0-3] - The client must offer the `mqtt` subprotocol and the
+/// server selects it in its response, otherwise the handshake is refused.
+pub async fn accept<S>(stream: S) -> Result<WsStream<S>, WsError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let stream = tokio_tungstenite::accept_hdr_async(stream, select_subprotocol).await?;
+    Ok(WsStream::new(stream))
+}
+
+#[allow(clippy::needless_pass_by_value)] // signature is defined by tungstenite callback
+fn select_subprotocol(
+    request: &Request,
+    mut response: Response,
+) -> Result<Response, ErrorResponse> {
+    let offers_mqtt = request
+        .headers()
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|protocol| protocol.trim() == MQTT_SUBPROTOCOL);
+
+    if offers_mqtt {
+        response.headers_mut().insert(
+            SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static(MQTT_SUBPROTOCOL),
+        );
+        Ok(response)
+    } else {
+        let mut response = ErrorResponse::new(Some(format!(
+            "{} subprotocol is required",
+            MQTT_SUBPROTOCOL
+        )));
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        Err(response)
+    }
+}
+
+/// Adapts a WebSocket connection to a byte stream, so that MQTT packets can be
+/// framed the same way as on a plain TCP connection.
+///
+/// Every write is sent as a single binary frame, and binary frames are read back
+/// as a contiguous stream of bytes regardless of how packets are split across them.
+#[derive(Debug)]
+pub struct WsStream<S> {
+    inner: WebSocketStream<S>,
+    pending: Bytes,
+}
+
+impl<S> WsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    pub fn new(inner: WebSocketStream<S>) -> Self {
+        Self {
+            inner,
+            pending: Bytes::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.inner.get_ref()
+    }
+}
+
+impl<S> AsyncRead for WsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        while this.pending.is_empty() {
+            match ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                Some(Ok(Message::Binary(data))) => this.pending = data.into(),
+                // [MQTT-6.0.0-1] - MQTT Control Packets must be sent in WebSocket binary data frames.
+                Some(Ok(Message::Text(_))) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "received a WebSocket text data frame",
+                    )))
+                }
+                Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => {}
+                Some(Ok(Message::Close(_))) | Some(Err(WsError::ConnectionClosed)) | None => {
+                    return Poll::Ready(Ok(0))
+                }
+                Some(Err(e)) => return Poll::Ready(Err(into_io_error(e))),
+            }
+        }
+
+        let len = cmp::min(buf.len(), this.pending.len());
+        buf[..len].copy_from_slice(&this.pending[..len]);
+        this.pending.advance(len);
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl<S> AsyncWrite for WsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        ready!(Pin::new(&mut this.inner).poll_ready(cx)).map_err(into_io_error)?;
+        Pin::new(&mut this.inner)
+            .start_send(Message::Binary(buf.to_vec()))
+            .map_err(into_io_error)?;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner)
+            .poll_flush(cx)
+            .map_err(into_io_error)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match ready!(Pin::new(&mut self.get_mut().inner).poll_close(cx)) {
+            Ok(()) | Err(WsError::ConnectionClosed) => Poll::Ready(Ok(())),
+            Err(e) => Poll::Ready(Err(into_io_error(e))),
+        }
+    }
+}
+
+fn into_io_error(e: WsError) -> io::Error {
+    match e {
+        WsError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::Other, e),
+    }
+}
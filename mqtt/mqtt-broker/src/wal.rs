@@ -0,0 +1,981 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use async_trait::async_trait;
+use flate2::Crc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tracing::{debug, error, info, warn};
+
+use crate::persist::{Persist, PersistError};
+use crate::session::{QueueCursor, QueueDelta, Session, SessionState};
+use crate::{
+    BrokerHandle, BrokerState, ClientEvent, ClientId, Message, RetainedPublication, SystemEvent,
+};
+
+static CHECKPOINT_FILE: &str = "checkpoint.dat";
+static CHECKPOINT_TEMP_FILE: &str = "checkpoint.dat.tmp";
+static SEGMENT_PREFIX: &str = "wal-";
+static SEGMENT_EXTENSION: &str = ".log";
+
+/// Segments are closed once they grow past this size.
+const DEFAULT_SEGMENT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The log is compacted into a checkpoint once this many segments were written.
+const DEFAULT_MAX_SEGMENTS: usize = 4;
+
+/// Every batch in a segment is prefixed with its length and CRC32, both little-endian `u32`.
+const FRAME_HEADER_LEN: usize = 8;
+
+/// A mutation of the persisted broker state.
+///
+/// Records carry the whole new value of a session or a retained message:
+/// the last record for a key wins. The only exception is the queue of
+/// publications waiting to be sent to a session. A session record keeps the
+/// queue of the previous one, and `Queue` records carry the queue changes,
+/// so that a busy offline session doesn't rewrite its whole queue every time.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) enum Record {
+    Session(ClientId, Option<SessionState>),
+    Queue(ClientId, QueueDelta),
+    Retained(String, Option<RetainedPublication>),
+}
+
+/// A group of records written to the log with a single sync.
+#[derive(Debug)]
+pub(crate) struct Batch {
+    id: u64,
+    records: Vec<Record>,
+    /// compact the log into a checkpoint once the batch is written
+    checkpoint: bool,
+}
+
+/// Loads/stores the broker state using a write-ahead log.
+///
+/// The log is a sequence of segment files (`wal-<index>.log`) holding batches
+/// of `Record`s. Once `max_segments` segments have been written, the log is
+/// compacted into a checkpoint (`checkpoint.dat`) and the segments are removed.
+/// The checkpoint is a single batch with a record for every session, its queue
+/// and every retained message, so that it keeps the sessions exactly as the
+/// queue changes written after it expect them, publications in flight included.
+///
+/// Loading reads the checkpoint and replays the segments on top of it.
+/// A torn batch at the end of a segment, e.g. after a crash in the middle of
+/// a write, is ignored.
+#[derive(Debug)]
+pub struct WalPersistor {
+    dir: PathBuf,
+    segment_size: u64,
+    max_segments: usize,
+
+    /// the open segment and the number of bytes written to it
+    segment: Option<(File, u64)>,
+    /// index of the open segment, or of the next one if none is open
+    segment_index: u64,
+    /// number of segments written since the last checkpoint
+    segment_count: usize,
+
+    sessions: HashMap<ClientId, SessionState>,
+    retained: HashMap<String, RetainedPublication>,
+}
+
+impl WalPersistor {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            max_segments: DEFAULT_MAX_SEGMENTS,
+            segment: None,
+            segment_index: 0,
+            segment_count: 0,
+            sessions: HashMap::new(),
+            retained: HashMap::new(),
+        }
+    }
+
+    /// Sets the size after which a segment is closed and a new one is started.
+    pub fn with_segment_size(mut self, segment_size: u64) -> Self {
+        self.segment_size = segment_size;
+        self
+    }
+
+    /// Sets the number of segments written before the log is compacted into a checkpoint.
+    ///
+    /// The minimum value is `1`.
+    pub fn with_max_segments(mut self, max_segments: usize) -> Self {
+        self.max_segments = max_segments.max(1);
+        self
+    }
+
+    /// Appends a batch of records to the log and syncs it to disk.
+    pub(crate) fn append(&mut self, records: Vec<Record>) -> Result<(), PersistError> {
+        let frame = encode_frame(&records)?;
+
+        let path = self.segment_path(self.segment_index);
+        if self.segment.is_none() {
+            create_dir(&self.dir)?;
+            debug!("opening segment {}...", path.display());
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| PersistError::FileOpen(path.clone(), Some(e)))?;
+            self.segment = Some((file, 0));
+            self.segment_count += 1;
+        }
+
+        let mut segment_full = false;
+        if let Some((file, written)) = &mut self.segment {
+            file.write_all(&frame)
+                .and_then(|()| file.sync_data())
+                .map_err(|e| PersistError::FileWrite(path, Some(e)))?;
+            *written += frame.len() as u64;
+            segment_full = *written >= self.segment_size;
+        }
+
+        self.apply(records);
+
+        if segment_full {
+            self.close_segment();
+            if self.segment_count >= self.max_segments {
+                self.checkpoint()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the current state to a new checkpoint and removes the segments it covers.
+    fn checkpoint(&mut self) -> Result<(), PersistError> {
+        self.close_segment();
+        create_dir(&self.dir)?;
+
+        let mut records = Vec::with_capacity(2 * self.sessions.len() + self.retained.len());
+        for (client_id, session) in &self.sessions {
+            let (state, queue, _) = session.journal_entry(None);
+            records.push(Record::Session(client_id.clone(), Some(state)));
+            records.push(Record::Queue(client_id.clone(), queue));
+        }
+        for (topic, retained) in &self.retained {
+            records.push(Record::Retained(topic.clone(), Some(retained.clone())));
+        }
+        let frame = encode_frame(&records)?;
+
+        let path = self.dir.join(CHECKPOINT_FILE);
+        let temp_path = self.dir.join(CHECKPOINT_TEMP_FILE);
+        info!(message="writing checkpoint...", file=%path.display());
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&temp_path)
+            .map_err(|e| PersistError::FileOpen(temp_path.clone(), Some(e)))?;
+        file.write_all(&frame)
+            .and_then(|()| file.sync_all())
+            .map_err(|e| PersistError::FileWrite(temp_path.clone(), Some(e)))?;
+        fs::rename(&temp_path, &path)
+            .map_err(|e| PersistError::FileRename(temp_path, path.clone(), Some(e)))?;
+
+        for (index, segment) in self.segments()? {
+            if index < self.segment_index {
+                debug!("removing segment {}...", segment.display());
+                fs::remove_file(&segment)
+                    .map_err(|e| PersistError::FileUnlink(segment.clone(), Some(e)))?;
+            }
+        }
+        self.segment_count = 0;
+
+        info!(message="checkpoint written.", file=%path.display());
+        Ok(())
+    }
+
+    fn load_blocking(&mut self) -> Result<Option<BrokerState>, PersistError> {
+        self.close_segment();
+        self.sessions.clear();
+        self.retained.clear();
+        let mut found = false;
+
+        let path = self.dir.join(CHECKPOINT_FILE);
+        if path.exists() {
+            info!("loading checkpoint from file {}.", path.display());
+            let buffer = read_file(&path)?;
+            let (records, _) = next_frame(&buffer).ok_or(PersistError::Deserialize(None))?;
+            self.apply(records);
+            found = true;
+        }
+
+        let segments = self.segments()?;
+        for (index, segment) in &segments {
+            info!("replaying segment {}.", segment.display());
+            self.replay(segment)?;
+            self.segment_index = index + 1;
+            found = true;
+        }
+        self.segment_count = segments.len();
+
+        if found {
+            let sessions = self.sessions.values().cloned().collect();
+            Ok(Some(BrokerState::new(self.retained.clone(), sessions)))
+        } else {
+            info!("no state found in {}.", self.dir.display());
+            Ok(None)
+        }
+    }
+
+    fn store_blocking(&mut self, state: BrokerState) -> Result<(), PersistError> {
+        let (retained, sessions) = state.into_parts();
+        self.retained = retained;
+        self.sessions = sessions
+            .into_iter()
+            .map(|session| (session.client_id().clone(), session))
+            .collect();
+        self.checkpoint()
+    }
+
+    fn replay(&mut self, path: &Path) -> Result<(), PersistError> {
+        let buffer = read_file(path)?;
+
+        let mut frames = buffer.as_slice();
+        while !frames.is_empty() {
+            if let Some((records, rest)) = next_frame(frames) {
+                self.apply(records);
+                frames = rest;
+            } else {
+                warn!(
+                    "ignoring {} bytes of incomplete or corrupted data at the end of {}",
+                    frames.len(),
+                    path.display()
+                );
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply(&mut self, records: Vec<Record>) {
+        for record in records {
+            match record {
+                Record::Session(client_id, Some(mut session)) => {
+                    if let Some(previous) = self.sessions.remove(&client_id) {
+                        session.keep_queue(previous);
+                    }
+                    self.sessions.insert(client_id, session);
+                }
+                Record::Queue(client_id, delta) => {
+                    if let Some(session) = self.sessions.get_mut(&client_id) {
+                        session.apply_queue_delta(delta);
+                    }
+                }
+                Record::Session(client_id, None) => {
+                    self.sessions.remove(&client_id);
+                }
+                Record::Retained(topic, Some(retained)) => {
+                    self.retained.insert(topic, retained);
+                }
+                Record::Retained(topic, None) => {
+                    self.retained.remove(&topic);
+                }
+            }
+        }
+    }
+
+    fn close_segment(&mut self) {
+        if self.segment.take().is_some() {
+            self.segment_index += 1;
+        }
+    }
+
+    fn segment_path(&self, index: u64) -> PathBuf {
+        self.dir.join(format!(
+            "{}{:020}{}",
+            SEGMENT_PREFIX, index, SEGMENT_EXTENSION
+        ))
+    }
+
+    /// Returns the segments in the log directory ordered by index.
+    fn segments(&self) -> Result<Vec<(u64, PathBuf)>, PersistError> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut segments = fs::read_dir(&self.dir)
+            .map_err(|e| PersistError::ReadDir(self.dir.clone(), Some(e)))?
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with(SEGMENT_PREFIX) && name.ends_with(SEGMENT_EXTENSION) {
+                    let index = &name[SEGMENT_PREFIX.len()..name.len() - SEGMENT_EXTENSION.len()];
+                    index.parse().ok().map(|index| (index, entry.path()))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        segments.sort_unstable_by_key(|(index, _)| *index);
+
+        Ok(segments)
+    }
+
+    /// Runs a blocking operation on the persistor without blocking the executor.
+    ///
+    /// The persistor is put back even if the operation panics or is cancelled,
+    /// so that it keeps the state it has loaded and written so far.
+    async fn blocking<T, F>(&mut self, f: F) -> Result<T, PersistError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, PersistError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let persistor = mem::replace(self, Self::new(self.dir.clone()));
+        let persistor = Arc::new(Mutex::new(persistor));
+
+        let result = tokio::task::spawn_blocking({
+            let persistor = persistor.clone();
+            move || {
+                let mut persistor = persistor.lock().unwrap_or_else(PoisonError::into_inner);
+                f(&mut persistor)
+            }
+        })
+        .await;
+
+        // the operation has either completed or been dropped at this point
+        let mut persistor = persistor.lock().unwrap_or_else(PoisonError::into_inner);
+        mem::swap(self, &mut persistor);
+
+        result.map_err(|e| PersistError::TaskJoin(Some(e)))?
+    }
+}
+
+#[async_trait]
+impl Persist for WalPersistor {
+    type Error = PersistError;
+
+    async fn load(&mut self) -> Result<Option<BrokerState>, Self::Error> {
+        self.blocking(Self::load_blocking).await
+    }
+
+    async fn store(&mut self, state: BrokerState) -> Result<(), Self::Error> {
+        self.blocking(move |persistor| persistor.store_blocking(state))
+            .await
+    }
+}
+
+fn create_dir(dir: &Path) -> Result<(), PersistError> {
+    if !dir.exists() {
+        info!("creating log directory {}", dir.display());
+        fs::create_dir_all(dir).map_err(|e| PersistError::CreateDir(dir.to_path_buf(), Some(e)))?;
+    }
+    Ok(())
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, PersistError> {
+    let mut buffer = vec![];
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut buffer))
+        .map_err(|e| PersistError::FileRead(path.to_path_buf(), Some(e)))?;
+    Ok(buffer)
+}
+
+/// Encodes a batch, prefixed with its length and CRC32.
+fn encode_frame(records: &[Record]) -> Result<Vec<u8>, PersistError> {
+    let payload = bincode::serialize(records).map_err(|e| PersistError::Serialize(Some(e)))?;
+    let mut crc = Crc::new();
+    crc.update(&payload);
+
+    #[allow(clippy::cast_possible_truncation)]
+    let len = payload.len() as u32;
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(&crc.sum().to_le_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// Decodes the batch at the start of `frames`, returning it with the remaining data.
+fn next_frame(frames: &[u8]) -> Option<(Vec<Record>, &[u8])> {
+    if frames.len() < FRAME_HEADER_LEN {
+        return None;
+    }
+
+    let mut len = [0; 4];
+    let mut sum = [0; 4];
+    len.copy_from_slice(&frames[..4]);
+    sum.copy_from_slice(&frames[4..FRAME_HEADER_LEN]);
+    let len = u32::from_le_bytes(len) as usize;
+
+    let frames = &frames[FRAME_HEADER_LEN..];
+    if frames.len() < len {
+        return None;
+    }
+
+    let (payload, rest) = frames.split_at(len);
+    let mut crc = Crc::new();
+    crc.update(payload);
+    if crc.sum() != u32::from_le_bytes(sum) {
+        return None;
+    }
+
+    bincode::deserialize(payload)
+        .ok()
+        .map(|records| (records, rest))
+}
+
+/// Creates a journal for the broker and the writer appending its batches to `persistor`.
+///
+/// The journal collects the sessions and retained messages changed by the broker
+/// and sends their new state to the writer in batches. Acknowledgements for
+/// QoS 1 and QoS 2 publications are held back until the batch with the changes
+/// they caused is synced to disk.
+///
+/// A batch is sent whenever no other batch is being written and an acknowledgement
+/// is waiting, or once `max_unsaved` changes have accumulated. Other changes are
+/// written when the broker receives a `SystemEvent::JournalFlush`, and
+/// a `SystemEvent::JournalCheckpoint` also compacts the log into a checkpoint.
+pub fn journal(persistor: WalPersistor, max_unsaved: usize) -> (Journal, JournalWriter) {
+    let (sender, batches) = mpsc::unbounded_channel();
+
+    let journal = Journal {
+        sender,
+        max_unsaved,
+        unsaved: 0,
+        next_batch: 0,
+        sessions: HashSet::new(),
+        retained: HashSet::new(),
+        queues: HashMap::new(),
+        pending: vec![],
+        in_flight: VecDeque::new(),
+        checkpoint: false,
+        failed: false,
+    };
+    let writer = JournalWriter { persistor, batches };
+    (journal, writer)
+}
+
+/// Tracks the changes made by the broker since the last batch sent to the `JournalWriter`.
+#[derive(Debug)]
+pub struct Journal {
+    sender: UnboundedSender<Batch>,
+    max_unsaved: usize,
+    unsaved: usize,
+    next_batch: u64,
+    sessions: HashSet<ClientId>,
+    retained: HashSet<String>,
+    /// the state of the session queues written so far
+    queues: HashMap<ClientId, QueueCursor>,
+
+    /// acknowledgements waiting for the next batch
+    pending: Vec<(ClientId, ClientEvent)>,
+    /// acknowledgements waiting for a batch sent to the writer
+    in_flight: VecDeque<(u64, Vec<(ClientId, ClientEvent)>)>,
+    /// set when the next batch has to be followed by a checkpoint
+    checkpoint: bool,
+    /// set once changes can no longer be persisted
+    failed: bool,
+}
+
+impl Journal {
+    pub(crate) fn session_changed(&mut self, client_id: &ClientId) {
+        self.unsaved += 1;
+        if !self.sessions.contains(client_id) {
+            self.sessions.insert(client_id.clone());
+        }
+    }
+
+    pub(crate) fn retained_changed(&mut self, topic: &str) {
+        self.unsaved += 1;
+        if !self.retained.contains(topic) {
+            self.retained.insert(topic.to_owned());
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_session_changed(&self, client_id: &ClientId) -> bool {
+        self.sessions.contains(client_id)
+    }
+
+    /// Compacts the log into a checkpoint once the next batch is written.
+    pub(crate) fn checkpoint(&mut self) {
+        self.checkpoint = true;
+    }
+
+    /// Holds back an acknowledgement until the current changes are persisted.
+    pub(crate) fn defer(&mut self, client_id: ClientId, event: ClientEvent) {
+        self.pending.push((client_id, event));
+    }
+
+    pub(crate) fn should_flush(&self) -> bool {
+        !self.failed
+            && (self.unsaved >= self.max_unsaved
+                || (!self.pending.is_empty() && self.in_flight.is_empty()))
+    }
+
+    /// Returns `true` once changes can no longer be persisted.
+    ///
+    /// The broker must not accept QoS 1 and QoS 2 publications anymore,
+    /// as they could never be acknowledged.
+    pub(crate) fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Marks the journal as failed, dropping the acknowledgements waiting to be persisted.
+    ///
+    /// Returns the clients waiting for these acknowledgements.
+    /// They have to be disconnected, so that they publish again once reconnected.
+    pub(crate) fn fail(&mut self) -> Vec<ClientId> {
+        self.failed = true;
+        self.sessions.clear();
+        self.retained.clear();
+        self.queues.clear();
+
+        let mut client_ids = vec![];
+        let acks = self
+            .pending
+            .drain(..)
+            .chain(self.in_flight.drain(..).flat_map(|(_, acks)| acks));
+        for (client_id, _) in acks {
+            if !client_ids.contains(&client_id) {
+                client_ids.push(client_id);
+            }
+        }
+        client_ids
+    }
+
+    /// Sends the changed sessions and retained messages to the writer.
+    ///
+    /// Returns the clients to disconnect if the writer is gone.
+    pub(crate) fn flush(
+        &mut self,
+        sessions: &HashMap<ClientId, Session>,
+        retained: &HashMap<String, RetainedPublication>,
+    ) -> Vec<ClientId> {
+        if self.failed
+            || (self.sessions.is_empty()
+                && self.retained.is_empty()
+                && self.pending.is_empty()
+                && !self.checkpoint)
+        {
+            return vec![];
+        }
+
+        let mut records = Vec::with_capacity(self.sessions.len() + self.retained.len());
+        for client_id in self.sessions.drain() {
+            let state = match sessions.get(&client_id) {
+                Some(Session::Persistent(connected)) => Some(connected.state()),
+                Some(Session::Offline(offline)) => Some(offline.state()),
+                _ => None,
+            };
+
+            if let Some(state) = state {
+                let (state, delta, cursor) = state.journal_entry(self.queues.get(&client_id));
+                self.queues.insert(client_id.clone(), cursor);
+                records.push(Record::Session(client_id.clone(), Some(state)));
+                if !delta.is_empty() {
+                    records.push(Record::Queue(client_id, delta));
+                }
+            } else {
+                self.queues.remove(&client_id);
+                records.push(Record::Session(client_id, None));
+            }
+        }
+        for topic in self.retained.drain() {
+            let publication = retained.get(&topic).cloned();
+            records.push(Record::Retained(topic, publication));
+        }
+
+        let id = self.next_batch;
+        self.next_batch += 1;
+        self.unsaved = 0;
+        let acks = mem::take(&mut self.pending);
+        let checkpoint = mem::take(&mut self.checkpoint);
+
+        debug!("sending batch {} with {} records", id, records.len());
+        self.in_flight.push_back((id, acks));
+        let batch = Batch {
+            id,
+            records,
+            checkpoint,
+        };
+        if self.sender.send(batch).is_err() {
+            error!("journal writer is gone. changes are no longer persisted");
+            return self.fail();
+        }
+
+        vec![]
+    }
+
+    /// Returns the acknowledgements released by the batch `id` being persisted.
+    pub(crate) fn persisted(&mut self, id: u64) -> Vec<(ClientId, ClientEvent)> {
+        let mut acks = vec![];
+        while let Some((batch, _)) = self.in_flight.front() {
+            if *batch > id {
+                break;
+            }
+            if let Some((_, batch_acks)) = self.in_flight.pop_front() {
+                acks.extend(batch_acks);
+            }
+        }
+        acks
+    }
+}
+
+/// Appends the batches sent by the `Journal` to a `WalPersistor`.
+#[derive(Debug)]
+pub struct JournalWriter {
+    persistor: WalPersistor,
+    batches: UnboundedReceiver<Batch>,
+}
+
+impl JournalWriter {
+    /// Runs until the broker stops, returning the persistor to store the final state with.
+    pub async fn run(self, mut broker_handle: BrokerHandle) -> Result<WalPersistor, PersistError> {
+        let JournalWriter {
+            mut persistor,
+            mut batches,
+        } = self;
+
+        info!("starting journal writer...");
+        while let Some(Batch {
+            id,
+            records,
+            checkpoint,
+        }) = batches.recv().await
+        {
+            let result = persistor
+                .blocking(move |persistor| {
+                    persistor.append(records)?;
+                    if checkpoint {
+                        persistor.checkpoint()?;
+                    }
+                    Ok(())
+                })
+                .await;
+            if let Err(e) = result {
+                error!(message = "failed to persist batch", id, error = %e);
+                let message = Message::System(SystemEvent::JournalFailed);
+                if let Err(e) = broker_handle.send(message).await {
+                    debug!(message = "broker is gone", error = %e);
+                }
+                return Err(e);
+            }
+
+            debug!("batch {} persisted", id);
+            let message = Message::System(SystemEvent::JournalPersisted(id));
+            if let Err(e) = broker_handle.send(message).await {
+                debug!(message = "broker is gone", error = %e);
+                break;
+            }
+        }
+
+        info!("journal writer stopped.");
+        Ok(persistor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::time::SystemTime;
+
+    use bytes::Bytes;
+    use mqtt3::proto;
+    use tempfile::TempDir;
+
+    use super::{Record, WalPersistor};
+    use crate::persist::Persist;
+    use crate::session::{QueueDelta, SessionState};
+    use crate::{
+        ClientEvent, ClientId, Publish, QueueFullAction, RetainedPublication, SessionConfig,
+        Subscription,
+    };
+
+    fn retained(topic: &str, payload: &'static str) -> Record {
+        let publication = proto::Publication {
+            topic_name: topic.to_string(),
+            qos: proto::QoS::AtLeastOnce,
+            retain: true,
+            payload: Bytes::from(payload),
+            properties: proto::Properties::default(),
+        };
+        Record::Retained(
+            topic.to_string(),
+            Some(RetainedPublication::new(publication, SystemTime::now())),
+        )
+    }
+
+    fn session(client_id: &str) -> Record {
+        let client_id = ClientId::from(client_id);
+        let state = SessionState::new(client_id.clone(), SessionConfig::default());
+        Record::Session(client_id, Some(state))
+    }
+
+    fn payloads(retained: &HashMap<String, RetainedPublication>) -> HashMap<String, Bytes> {
+        retained
+            .iter()
+            .map(|(topic, retained)| (topic.clone(), retained.publication().payload.clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn it_replays_log_on_load() {
+        let dir = TempDir::new().unwrap();
+
+        let mut persistor = WalPersistor::new(dir.path());
+        assert!(persistor.load().await.unwrap().is_none());
+
+        persistor
+            .append(vec![retained("a", "1"), session("client1")])
+            .unwrap();
+        persistor
+            .append(vec![
+                retained("a", "2"),
+                retained("b", "1"),
+                Record::Session(ClientId::from("client1"), None),
+                session("client2"),
+            ])
+            .unwrap();
+
+        let state = WalPersistor::new(dir.path())
+            .load()
+            .await
+            .unwrap()
+            .expect("state");
+        let (retained, sessions) = state.into_parts();
+
+        let expected = vec![("a", "2"), ("b", "1")]
+            .into_iter()
+            .map(|(topic, payload)| (topic.to_string(), Bytes::from(payload)))
+            .collect();
+        assert_eq!(payloads(&retained), expected);
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].client_id(), &ClientId::from("client2"));
+    }
+
+    #[tokio::test]
+    async fn it_replays_queue_changes_on_load() {
+        fn publication(payload: &'static str) -> proto::Publication {
+            proto::Publication {
+                topic_name: "a".to_string(),
+                qos: proto::QoS::AtLeastOnce,
+                retain: false,
+                payload: Bytes::from(payload),
+                properties: proto::Properties::default(),
+            }
+        }
+
+        let dir = TempDir::new().unwrap();
+        let client_id = ClientId::from("client1");
+
+        let mut persistor = WalPersistor::new(dir.path());
+        persistor
+            .append(vec![
+                session("client1"),
+                Record::Queue(
+                    client_id.clone(),
+                    QueueDelta::Replace(vec![publication("1"), publication("2")]),
+                ),
+            ])
+            .unwrap();
+        persistor
+            .append(vec![
+                session("client1"),
+                Record::Queue(
+                    client_id,
+                    QueueDelta::Update {
+                        popped: 1,
+                        pushed: vec![publication("3")],
+                    },
+                ),
+            ])
+            .unwrap();
+
+        let state = WalPersistor::new(dir.path())
+            .load()
+            .await
+            .unwrap()
+            .expect("state");
+        let (_, mut sessions) = state.into_parts();
+        assert_eq!(sessions.len(), 1);
+
+        let (_, _, queue) = sessions.remove(0).into_parts();
+        let payloads = queue
+            .into_iter()
+            .map(|publication| publication.payload)
+            .collect::<Vec<_>>();
+        assert_eq!(payloads, vec![Bytes::from("2"), Bytes::from("3")]);
+    }
+
+    #[tokio::test]
+    async fn it_replays_queue_changes_on_top_of_checkpoint() {
+        fn publication(payload: &'static str) -> proto::Publication {
+            proto::Publication {
+                topic_name: "a".to_string(),
+                qos: proto::QoS::AtLeastOnce,
+                retain: false,
+                payload: Bytes::from(payload),
+                properties: proto::Properties::default(),
+            }
+        }
+
+        fn records(state: &SessionState, delta: QueueDelta) -> Vec<Record> {
+            let client_id = state.client_id().clone();
+            vec![
+                Record::Session(client_id.clone(), Some(state.clone())),
+                Record::Queue(client_id, delta),
+            ]
+        }
+
+        let dir = TempDir::new().unwrap();
+        let config = SessionConfig::new(
+            1,
+            u64::max_value(),
+            usize::max_value(),
+            u64::max_value(),
+            QueueFullAction::DropNew,
+        );
+        let mut state = SessionState::new(ClientId::from("client1"), config);
+        state.update_subscription(
+            "a".to_string(),
+            Subscription::new("a".parse().unwrap(), proto::QoS::AtLeastOnce),
+        );
+
+        let mut inflight = vec![];
+        for payload in &["1", "2", "3"] {
+            if let Some(ClientEvent::PublishTo(Publish::QoS12(id, _))) =
+                state.publish_to(publication(payload)).unwrap()
+            {
+                inflight.push(id);
+            }
+        }
+        assert_eq!(inflight.len(), 1);
+
+        let mut persistor = WalPersistor::new(dir.path());
+        let (entry, delta, cursor) = state.journal_entry(None);
+        persistor.append(records(&entry, delta)).unwrap();
+        persistor.checkpoint().unwrap();
+
+        let puback = proto::PubAck {
+            packet_identifier: inflight[0],
+        };
+        state.handle_puback(&puback).unwrap();
+        let (entry, delta, _) = state.journal_entry(Some(&cursor));
+        persistor.append(records(&entry, delta)).unwrap();
+
+        let loaded = WalPersistor::new(dir.path())
+            .load()
+            .await
+            .unwrap()
+            .expect("state");
+        let (_, mut sessions) = loaded.into_parts();
+        assert_eq!(sessions.len(), 1);
+
+        let session = sessions.remove(0);
+        assert_eq!(session.inflight_count(), state.inflight_count());
+        assert_eq!(session.into_parts().2, state.into_parts().2);
+    }
+
+    #[tokio::test]
+    async fn it_keeps_state_when_blocking_operation_panics() {
+        let dir = TempDir::new().unwrap();
+
+        let mut persistor = WalPersistor::new(dir.path());
+        persistor.append(vec![retained("a", "1")]).unwrap();
+
+        let result: Result<(), _> = persistor.blocking(|_| panic!("operation failed")).await;
+        assert!(result.is_err());
+        assert!(persistor.retained.contains_key("a"));
+    }
+
+    #[tokio::test]
+    async fn it_compacts_segments_into_checkpoint() {
+        let dir = TempDir::new().unwrap();
+
+        let mut persistor = WalPersistor::new(dir.path())
+            .with_segment_size(1)
+            .with_max_segments(2);
+        persistor.append(vec![retained("a", "1")]).unwrap();
+        assert_eq!(persistor.segments().unwrap().len(), 1);
+
+        persistor.append(vec![retained("b", "1")]).unwrap();
+        assert!(persistor.segments().unwrap().is_empty());
+        assert!(dir.path().join(super::CHECKPOINT_FILE).exists());
+
+        persistor.append(vec![retained("a", "2")]).unwrap();
+        assert_eq!(persistor.segments().unwrap().len(), 1);
+
+        let state = WalPersistor::new(dir.path())
+            .load()
+            .await
+            .unwrap()
+            .expect("state");
+        let (retained, _) = state.into_parts();
+
+        let expected = vec![("a", "2"), ("b", "1")]
+            .into_iter()
+            .map(|(topic, payload)| (topic.to_string(), Bytes::from(payload)))
+            .collect();
+        assert_eq!(payloads(&retained), expected);
+    }
+
+    #[tokio::test]
+    async fn it_ignores_torn_batch_at_end_of_segment() {
+        let dir = TempDir::new().unwrap();
+
+        let mut persistor = WalPersistor::new(dir.path());
+        persistor.append(vec![retained("a", "1")]).unwrap();
+        persistor.append(vec![retained("b", "1")]).unwrap();
+
+        let path = persistor.segment_path(0);
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&[0xff])
+            .unwrap();
+
+        let state = WalPersistor::new(dir.path())
+            .load()
+            .await
+            .unwrap()
+            .expect("state");
+        let (retained, _) = state.into_parts();
+
+        assert_eq!(retained.len(), 1);
+        assert!(retained.contains_key("a"));
+    }
+
+    #[tokio::test]
+    async fn it_stores_checkpoint_and_removes_segments() {
+        let dir = TempDir::new().unwrap();
+
+        let mut persistor = WalPersistor::new(dir.path());
+        persistor.append(vec![retained("a", "1")]).unwrap();
+
+        let state = persistor.load().await.unwrap().expect("state");
+        persistor.store(state).await.unwrap();
+        assert!(persistor.segments().unwrap().is_empty());
+
+        persistor.append(vec![retained("b", "1")]).unwrap();
+
+        let state = WalPersistor::new(dir.path())
+            .load()
+            .await
+            .unwrap()
+            .expect("state");
+        let (retained, _) = state.into_parts();
+        assert_eq!(retained.len(), 2);
+    }
+}
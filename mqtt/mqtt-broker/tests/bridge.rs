@@ -0,0 +1,219 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use serde_json::json;
+
+use mqtt3::proto::{ClientId, QoS};
+use mqtt_broker::{AuthId, Bridge, BridgeSettings, BrokerBuilder, BrokerHandle, TransportBuilder};
+
+use common::TestClientBuilder;
+
+mod common;
+
+fn start_bridge(address: &str, broker_handle: BrokerHandle) {
+    let settings: BridgeSettings = serde_json::from_value(json!({
+        "name": "upstream",
+        "address": address,
+        "client_id": "child",
+        "max_reconnect_back_off": "1s",
+        "topics": [
+            {
+                "pattern": "telemetry/#",
+                "direction": "out",
+                "qos": 1,
+                "remote_prefix": "devices/child/"
+            },
+            {
+                "pattern": "commands/#",
+                "direction": "in",
+                "qos": 1,
+                "local_prefix": "local/",
+                "remote_prefix": "devices/child/"
+            }
+        ]
+    }))
+    .expect("invalid bridge settings");
+
+    let bridge = Bridge::new(settings, broker_handle).expect("unable to create bridge");
+    tokio::spawn(bridge.run());
+}
+
+/// Scenario:
+/// - Local client publishes a retained message to a bridged topic.
+/// - Upstream client publishes a retained message to a bridged topic.
+/// - Both messages are forwarded with topic prefixes rewritten.
+#[tokio::test]
+async fn bridge_forwards_publications_both_ways() {
+    let upstream_broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .build();
+    let upstream_server_handle = common::start_server(upstream_broker);
+
+    let local_broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .build();
+    let local_broker_handle = local_broker.handle();
+    let local_server_handle = common::start_server(local_broker);
+
+    let mut upstream_client = TestClientBuilder::new(upstream_server_handle.address())
+        .client_id(ClientId::IdWithCleanSession("upstream-client".into()))
+        .build();
+    upstream_client
+        .subscribe("devices/child/telemetry/#", QoS::AtLeastOnce)
+        .await;
+    upstream_client.subscriptions().recv().await;
+
+    let mut local_client = TestClientBuilder::new(local_server_handle.address())
+        .client_id(ClientId::IdWithCleanSession("local-client".into()))
+        .build();
+    local_client
+        .subscribe("local/commands/#", QoS::AtLeastOnce)
+        .await;
+    local_client.subscriptions().recv().await;
+
+    local_client
+        .publish_qos1("telemetry/temperature", "42", true)
+        .await;
+    upstream_client
+        .publish_qos1("devices/child/commands/reboot", "now", true)
+        .await;
+
+    start_bridge(&upstream_server_handle.address(), local_broker_handle);
+
+    let publication = upstream_client
+        .publications()
+        .recv()
+        .await
+        .expect("no publication received");
+    assert_eq!(
+        publication.topic_name,
+        "devices/child/telemetry/temperature"
+    );
+    assert_eq!(publication.payload, Bytes::from("42"));
+
+    let publication = local_client
+        .publications()
+        .recv()
+        .await
+        .expect("no publication received");
+    assert_eq!(publication.topic_name, "local/commands/reboot");
+    assert_eq!(publication.payload, Bytes::from("now"));
+
+    upstream_client.shutdown().await;
+    local_client.shutdown().await;
+}
+
+/// Scenario:
+/// - Local client publishes several messages to a bridged topic.
+/// - The messages are forwarded upstream in the order they were published.
+#[tokio::test]
+async fn bridge_forwards_publications_in_order() {
+    let upstream_broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .build();
+    let upstream_server_handle = common::start_server(upstream_broker);
+
+    let local_broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .build();
+    let local_broker_handle = local_broker.handle();
+    let local_server_handle = common::start_server(local_broker);
+
+    let mut upstream_client = TestClientBuilder::new(upstream_server_handle.address())
+        .client_id(ClientId::IdWithCleanSession("upstream-client".into()))
+        .build();
+    upstream_client
+        .subscribe("devices/child/telemetry/#", QoS::AtLeastOnce)
+        .await;
+    upstream_client.subscriptions().recv().await;
+
+    start_bridge(&upstream_server_handle.address(), local_broker_handle);
+    // wait for the bridge to subscribe to the local broker
+    tokio::time::delay_for(Duration::from_secs(1)).await;
+
+    let mut local_client = TestClientBuilder::new(local_server_handle.address())
+        .client_id(ClientId::IdWithCleanSession("local-client".into()))
+        .build();
+    for i in 0..20 {
+        local_client
+            .publish_qos1("telemetry/temperature", i.to_string(), false)
+            .await;
+    }
+
+    for i in 0..20 {
+        let publication = upstream_client
+            .publications()
+            .recv()
+            .await
+            .expect("no publication received");
+        assert_eq!(publication.payload, Bytes::from(i.to_string()));
+    }
+
+    upstream_client.shutdown().await;
+    local_client.shutdown().await;
+}
+
+/// Scenario:
+/// - Bridge starts while the upstream broker is offline.
+/// - Local client publishes a message to a bridged topic.
+/// - Upstream broker starts.
+/// - The message stored by the bridge is forwarded once it reconnects.
+#[tokio::test]
+async fn bridge_stores_publications_while_upstream_is_offline() {
+    let address = {
+        let listener = std::net::TcpListener::bind("localhost:0").expect("unable to bind");
+        format!(
+            "localhost:{}",
+            listener.local_addr().expect("no local address").port()
+        )
+    };
+
+    let local_broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .build();
+    start_bridge(&address, local_broker.handle());
+    let local_server_handle = common::start_server(local_broker);
+
+    let mut local_client = TestClientBuilder::new(local_server_handle.address())
+        .client_id(ClientId::IdWithCleanSession("local-client".into()))
+        .build();
+    local_client
+        .publish_qos1("telemetry/temperature", "42", true)
+        .await;
+
+    let upstream_broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .build();
+    let upstream_server_handle = common::start_server_with_transport(upstream_broker, {
+        let address = address.clone();
+        |_| TransportBuilder::Tcp(address)
+    });
+
+    let mut upstream_client = TestClientBuilder::new(address)
+        .client_id(ClientId::IdWithCleanSession("upstream-client".into()))
+        .build();
+    upstream_client
+        .subscribe("devices/child/telemetry/#", QoS::AtLeastOnce)
+        .await;
+
+    let publication = upstream_client
+        .publications()
+        .recv()
+        .await
+        .expect("no publication received");
+    assert_eq!(
+        publication.topic_name,
+        "devices/child/telemetry/temperature"
+    );
+    assert_eq!(publication.payload, Bytes::from("42"));
+
+    upstream_client.shutdown().await;
+    local_client.shutdown().await;
+    drop(upstream_server_handle);
+}
@@ -1,13 +1,17 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
 
-use proptest::{prop_oneof, proptest, strategy::Strategy};
+use bytes::Bytes;
+use proptest::{prop_oneof, proptest, strategy::Just, strategy::Strategy};
+use serde_json::json;
 use tokio::sync::mpsc::{self, UnboundedReceiver};
 use uuid::Uuid;
 
 use mqtt3::proto;
 use mqtt_broker::{
-    proptest::{arb_client_id_weighted, arb_connect, arb_subscribe, arb_unsubscribe},
-    AuthId, BrokerBuilder, ClientEvent, ClientId, ConnReq, ConnectionHandle, Message,
+    proptest::{arb_client_id_weighted, arb_connect, arb_payload, arb_subscribe, arb_unsubscribe},
+    AuthId, BrokerBuilder, BrokerConfig, ClientEvent, ClientId, ConnReq, ConnectionHandle, Message,
+    QueueFullAction,
 };
 
 proptest! {
@@ -35,6 +39,28 @@ proptest! {
     }
 }
 
+proptest! {
+    /// Model based test to check whether broker applies configured session queue limits
+    /// to messages queued for an offline persistent session.
+    ///
+    /// A randomly generated sequence of publications is sent to an offline session
+    /// and processed by both: broker and a model of the session queue which applies the same
+    /// limits and `QueueFullAction`. In the end, the test verifies that both the broker and
+    /// its model contain the same queued messages.
+    #[test]
+    fn broker_enforces_session_queue_limits(
+        limits in arb_queue_limits(),
+        payloads in proptest::collection::vec(arb_payload(), 1..50)
+    ) {
+    tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(test_broker_enforces_session_queue_limits(limits, payloads));
+    }
+}
+
 async fn test_broker_manages_sessions(events: impl IntoIterator<Item = BrokerEvent>) {
     let mut broker = BrokerBuilder::default()
         .authenticator(|_| Ok(Some(AuthId::Anonymous)))
@@ -74,6 +100,204 @@ async fn test_broker_manages_sessions(events: impl IntoIterator<Item = BrokerEve
     assert!(model.sessions.is_empty());
 }
 
+async fn test_broker_enforces_session_queue_limits(
+    limits: QueueLimits,
+    payloads: impl IntoIterator<Item = Bytes>,
+) {
+    let mut broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .config(limits.to_config())
+        .build();
+
+    let subscriber_id = ClientId::from("subscriber");
+    let (tx, _rx) = mpsc::unbounded_channel();
+    process_connect(
+        &mut broker,
+        &subscriber_id,
+        persistent_connect("subscriber"),
+        tx,
+    );
+    broker
+        .process_message(
+            subscriber_id.clone(),
+            ClientEvent::Subscribe(subscribe("topic", proto::QoS::AtLeastOnce)),
+        )
+        .expect("process message");
+    broker
+        .process_message(
+            subscriber_id.clone(),
+            ClientEvent::Disconnect(proto::Disconnect),
+        )
+        .expect("process message");
+
+    let publisher_id = ClientId::from("publisher");
+    let (tx, _rx) = mpsc::unbounded_channel();
+    process_connect(
+        &mut broker,
+        &publisher_id,
+        transient_connect("publisher"),
+        tx,
+    );
+
+    let mut model = QueueModel::new(limits);
+    for payload in payloads {
+        broker
+            .process_message(
+                publisher_id.clone(),
+                ClientEvent::PublishFrom(publish_qos0("topic", payload.clone())),
+            )
+            .expect("process message");
+
+        model.enqueue(payload);
+    }
+
+    let (_retained, sessions) = broker.clone_state().into_parts();
+    let (_, _, queued) = sessions
+        .into_iter()
+        .map(|session| session.into_parts())
+        .find(|(client_id, _, _)| *client_id == subscriber_id)
+        .expect("subscriber session");
+
+    let queued = queued
+        .into_iter()
+        .map(|publication| publication.payload)
+        .collect::<VecDeque<_>>();
+    assert_eq!(queued, model.queue);
+}
+
+#[test]
+fn broker_disconnects_client_when_session_queue_is_full() {
+    let limits = QueueLimits {
+        max_message_size: 1024,
+        max_count: 2,
+        max_total_space: 1024,
+        when_full: QueueFullAction::Disconnect,
+    };
+    let mut broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .config(limits.to_config())
+        .build();
+
+    let subscriber_id = ClientId::from("subscriber");
+    let (tx, mut subscriber_rx) = mpsc::unbounded_channel();
+    process_connect(
+        &mut broker,
+        &subscriber_id,
+        persistent_connect("subscriber"),
+        tx,
+    );
+    broker
+        .process_message(
+            subscriber_id.clone(),
+            ClientEvent::Subscribe(subscribe("topic", proto::QoS::AtLeastOnce)),
+        )
+        .expect("process message");
+
+    let publisher_id = ClientId::from("publisher");
+    let (tx, _rx) = mpsc::unbounded_channel();
+    process_connect(
+        &mut broker,
+        &publisher_id,
+        transient_connect("publisher"),
+        tx,
+    );
+
+    // subscriber never acknowledges messages, so the inflight window fills up
+    // first and then the session queue.
+    for _ in 0..100 {
+        broker
+            .process_message(
+                publisher_id.clone(),
+                ClientEvent::PublishFrom(publish_qos0("topic", Bytes::from("message"))),
+            )
+            .expect("process message");
+    }
+
+    let mut delivered = 0;
+    loop {
+        match subscriber_rx.try_recv() {
+            Ok(Message::Client(_, ClientEvent::ConnAck(_)))
+            | Ok(Message::Client(_, ClientEvent::SubAck(_))) => (),
+            Ok(Message::Client(_, ClientEvent::PublishTo(_))) => delivered += 1,
+            Ok(Message::Client(_, ClientEvent::DropConnection)) => break,
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    assert!(delivered > 0);
+
+    // session is offline but keeps the queued messages
+    let (_retained, sessions) = broker.clone_state().into_parts();
+    let (_, _, queued) = sessions
+        .into_iter()
+        .map(|session| session.into_parts())
+        .find(|(client_id, _, _)| *client_id == subscriber_id)
+        .expect("subscriber session");
+    assert_eq!(queued.len(), 2);
+}
+
+fn process_connect<N, Z>(
+    broker: &mut mqtt_broker::Broker<N, Z>,
+    client_id: &ClientId,
+    connect: proto::Connect,
+    tx: mpsc::UnboundedSender<Message>,
+) where
+    N: mqtt_broker::Authenticator + Send + 'static,
+    Z: mqtt_broker::Authorizer + Send + 'static,
+{
+    let connreq = ConnReq::new(
+        client_id.clone(),
+        connect,
+        None,
+        ConnectionHandle::from_sender(tx),
+    );
+    broker
+        .process_message(client_id.clone(), ClientEvent::ConnReq(connreq))
+        .expect("process message");
+}
+
+fn transient_connect(id: &str) -> proto::Connect {
+    proto::Connect {
+        username: None,
+        password: None,
+        will: None,
+        client_id: proto::ClientId::IdWithCleanSession(id.into()),
+        keep_alive: Duration::default(),
+        protocol_name: mqtt3::PROTOCOL_NAME.into(),
+        protocol_level: mqtt3::PROTOCOL_LEVEL,
+        properties: proto::Properties::default(),
+    }
+}
+
+fn persistent_connect(id: &str) -> proto::Connect {
+    proto::Connect {
+        client_id: proto::ClientId::IdWithExistingSession(id.into()),
+        ..transient_connect(id)
+    }
+}
+
+fn subscribe(topic_filter: &str, qos: proto::QoS) -> proto::Subscribe {
+    proto::Subscribe {
+        packet_identifier: proto::PacketIdentifier::new(1).unwrap(),
+        subscribe_to: vec![proto::SubscribeTo {
+            topic_filter: topic_filter.into(),
+            qos,
+        }],
+    }
+}
+
+fn publish_qos0(topic_name: &str, payload: Bytes) -> proto::Publish {
+    proto::Publish {
+        packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtMostOnce,
+        retain: false,
+        topic_name: topic_name.into(),
+        payload,
+        properties: proto::Properties::default(),
+    }
+}
+
 fn into_events(
     event: BrokerEvent,
     clients: &mut Vec<UnboundedReceiver<Message>>,
@@ -127,6 +351,88 @@ fn client_id(client_id: &proto::ClientId) -> ClientId {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct QueueLimits {
+    max_message_size: u64,
+    max_count: u32,
+    max_total_space: u64,
+    when_full: QueueFullAction,
+}
+
+impl QueueLimits {
+    fn to_config(self) -> BrokerConfig {
+        let when_full = match self.when_full {
+            QueueFullAction::DropNew => "drop_new",
+            QueueFullAction::DropOld => "drop_old",
+            QueueFullAction::Disconnect => "disconnect",
+        };
+
+        let config = json!({
+            "transports": [],
+            "inflight_messages": {
+                "max_count": 16
+            },
+            "retained_messages": {
+                "max_count": 1000,
+                "expiration": "60d"
+            },
+            "session": {
+                "expiration": "60d",
+                "messages": {
+                    "max_message_size": format!("{}b", self.max_message_size),
+                    "max_count": self.max_count,
+                    "max_total_space": format!("{}b", self.max_total_space),
+                    "when_full": when_full
+                }
+            }
+        });
+
+        serde_json::from_value(config).expect("broker config")
+    }
+}
+
+/// Simplified model of a session queue of messages waiting to be sent.
+#[derive(Debug)]
+struct QueueModel {
+    limits: QueueLimits,
+    queue: VecDeque<Bytes>,
+}
+
+impl QueueModel {
+    fn new(limits: QueueLimits) -> Self {
+        Self {
+            limits,
+            queue: VecDeque::new(),
+        }
+    }
+
+    fn enqueue(&mut self, payload: Bytes) {
+        let size = payload.len() as u64;
+        if size > self.limits.max_message_size || size > self.limits.max_total_space {
+            return;
+        }
+
+        while self.is_full(size) {
+            match self.limits.when_full {
+                QueueFullAction::DropOld => {
+                    self.queue.pop_front();
+                }
+                // there is no connection to drop for an offline session
+                QueueFullAction::DropNew | QueueFullAction::Disconnect => return,
+            }
+        }
+
+        self.queue.push_back(payload);
+    }
+
+    fn is_full(&self, size: u64) -> bool {
+        let total: u64 = self.queue.iter().map(|p| p.len() as u64).sum();
+        self.queue.len() >= self.limits.max_count as usize
+            || total + size > self.limits.max_total_space
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum BrokerEvent {
     ConnReq(ClientId, proto::Connect),
@@ -253,6 +559,7 @@ impl ModelSession {
     }
 }
 
+#[allow(clippy::large_enum_variant)]
 enum ModelEventIn {
     ConnReq(proto::Connect),
     Disconnect(proto::Disconnect),
@@ -279,3 +586,24 @@ pub fn arb_broker_event() -> impl Strategy<Value = BrokerEvent> {
         arb_client_id_weighted().prop_map(|id| BrokerEvent::DropConnection(client_id(&id))),
     ]
 }
+
+pub fn arb_queue_limits() -> impl Strategy<Value = QueueLimits> {
+    (
+        1_u64..1024,
+        1_u32..20,
+        1_u64..4096,
+        prop_oneof![
+            Just(QueueFullAction::DropNew),
+            Just(QueueFullAction::DropOld),
+            Just(QueueFullAction::Disconnect),
+        ],
+    )
+        .prop_map(
+            |(max_message_size, max_count, max_total_space, when_full)| QueueLimits {
+                max_message_size,
+                max_count,
+                max_total_space,
+                when_full,
+            },
+        )
+}
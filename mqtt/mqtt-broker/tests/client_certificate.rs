@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use matches::assert_matches;
+use openssl::{
+    asn1::Asn1Time,
+    bn::BigNum,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    rsa::Rsa,
+    ssl::{SslAcceptor, SslConnector, SslMethod, SslVerifyMode},
+    x509::{
+        extension::{BasicConstraints, ExtendedKeyUsage, SubjectAlternativeName},
+        store::X509StoreBuilder,
+        X509NameBuilder, X509,
+    },
+};
+use tokio::io::AsyncReadExt;
+
+use mqtt3::{
+    proto::{ClientId, ConnAck, Connect, ConnectReturnCode, Packet, Properties},
+    PROTOCOL_LEVEL, PROTOCOL_NAME,
+};
+use mqtt_broker::{AuthId, BrokerBuilder, Credentials, TransportBuilder};
+
+use common::PacketStream;
+
+mod common;
+
+#[tokio::test]
+async fn client_certificate_passed_to_authenticator() {
+    let (ca, ca_key) = issue_ca("test-ca");
+    let (client, client_key) = issue("device-1", &ca, &ca_key, Usage::Client);
+    let thumbprint = hex(&client.digest(MessageDigest::sha256()).unwrap());
+
+    let broker = BrokerBuilder::default()
+        .authenticator(move |credentials| match credentials {
+            Credentials::ClientCertificate(certificate)
+                if certificate.thumbprint() == thumbprint =>
+            {
+                let common_name = certificate.common_name().unwrap();
+                Ok(common_name.map(AuthId::from_identity))
+            }
+            _ => Ok(None),
+        })
+        .authorizer(|_| Ok(true))
+        .build();
+
+    let acceptor = acceptor(&ca, &ca_key);
+    let server_handle =
+        common::start_server_with_transport(broker, |addr| TransportBuilder::Tls(addr, acceptor));
+
+    let mut connector = connector(&ca);
+    connector.set_certificate(&client).unwrap();
+    connector.set_private_key(&client_key).unwrap();
+
+    let mut client = PacketStream::open_tls(&server_handle.address(), connector).await;
+    client.send_connect(connect(None, None)).await;
+
+    assert_eq!(
+        client.next().await,
+        Some(connack(ConnectReturnCode::Accepted))
+    );
+}
+
+#[tokio::test]
+async fn client_without_certificate_uses_basic_credentials() {
+    let (ca, ca_key) = issue_ca("test-ca");
+
+    let broker = BrokerBuilder::default()
+        .authenticator(|credentials| match credentials {
+            Credentials::Basic(Some(username), Some(password))
+                if username == "username" && password == "password" =>
+            {
+                Ok(Some(AuthId::from_identity(username)))
+            }
+            _ => Ok(None),
+        })
+        .authorizer(|_| Ok(true))
+        .build();
+
+    let acceptor = acceptor(&ca, &ca_key);
+    let server_handle =
+        common::start_server_with_transport(broker, |addr| TransportBuilder::Tls(addr, acceptor));
+
+    let connector = connector(&ca);
+
+    let mut client = PacketStream::open_tls(&server_handle.address(), connector).await;
+    client
+        .send_connect(connect(Some("username"), Some("password")))
+        .await;
+
+    assert_eq!(
+        client.next().await,
+        Some(connack(ConnectReturnCode::Accepted))
+    );
+}
+
+#[tokio::test]
+async fn client_certificate_issued_by_unknown_ca_rejected() {
+    let (ca, ca_key) = issue_ca("test-ca");
+    let (unknown_ca, unknown_ca_key) = issue_ca("unknown-ca");
+    let (client, client_key) = issue("device-1", &unknown_ca, &unknown_ca_key, Usage::Client);
+
+    let broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .build();
+
+    let acceptor = acceptor(&ca, &ca_key);
+    let server_handle =
+        common::start_server_with_transport(broker, |addr| TransportBuilder::Tls(addr, acceptor));
+
+    let mut connector = connector(&ca);
+    connector.set_certificate(&client).unwrap();
+    connector.set_private_key(&client_key).unwrap();
+
+    // with TLS 1.3 client considers handshake completed before the server
+    // validates client certificate, so the server rejection may only be
+    // observed on the first read.
+    let tcp_stream = common::connect_tcp(server_handle.address()).await;
+    if let Ok(mut tls_stream) = tokio_openssl::connect(connector, "localhost", tcp_stream).await {
+        let mut buf = [0; 1];
+        let read = tls_stream.read(&mut buf).await;
+        assert_matches!(read, Ok(0) | Err(_));
+    }
+}
+
+fn connect(username: Option<&str>, password: Option<&str>) -> Connect {
+    Connect {
+        client_id: ClientId::IdWithCleanSession("test-client".into()),
+        username: username.map(Into::into),
+        password: password.map(Into::into),
+        will: None,
+        keep_alive: Duration::from_secs(30),
+        protocol_name: PROTOCOL_NAME.into(),
+        protocol_level: PROTOCOL_LEVEL,
+        properties: Properties::default(),
+    }
+}
+
+fn connack(return_code: ConnectReturnCode) -> Packet {
+    Packet::ConnAck(ConnAck {
+        return_code,
+        session_present: false,
+        properties: Properties::default(),
+    })
+}
+
+fn acceptor(ca: &X509, ca_key: &PKey<Private>) -> SslAcceptor {
+    let (server, server_key) = issue("localhost", ca, ca_key, Usage::Server);
+
+    let mut store = X509StoreBuilder::new().unwrap();
+    store.add_cert(ca.clone()).unwrap();
+
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+    builder.set_certificate(&server).unwrap();
+    builder.set_private_key(&server_key).unwrap();
+    builder.set_verify_cert_store(store.build()).unwrap();
+    builder.set_verify(SslVerifyMode::PEER);
+    builder.build()
+}
+
+fn connector(ca: &X509) -> openssl::ssl::ConnectConfiguration {
+    let mut store = X509StoreBuilder::new().unwrap();
+    store.add_cert(ca.clone()).unwrap();
+
+    let mut builder = SslConnector::builder(SslMethod::tls()).unwrap();
+    builder.set_verify_cert_store(store.build()).unwrap();
+    builder.build().configure().unwrap()
+}
+
+enum Usage {
+    Server,
+    Client,
+}
+
+fn issue_ca(common_name: &str) -> (X509, PKey<Private>) {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+    let mut builder = cert_builder(common_name, &key);
+    builder
+        .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+        .unwrap();
+    builder.set_issuer_name(&name(common_name)).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+    (builder.build(), key)
+}
+
+fn issue(
+    common_name: &str,
+    ca: &X509,
+    ca_key: &PKey<Private>,
+    usage: Usage,
+) -> (X509, PKey<Private>) {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+    let mut builder = cert_builder(common_name, &key);
+    builder.set_issuer_name(ca.subject_name()).unwrap();
+    match usage {
+        Usage::Server => {
+            let san = SubjectAlternativeName::new()
+                .dns(common_name)
+                .build(&builder.x509v3_context(Some(ca), None))
+                .unwrap();
+            builder.append_extension(san).unwrap();
+            builder
+                .append_extension(ExtendedKeyUsage::new().server_auth().build().unwrap())
+                .unwrap();
+        }
+        Usage::Client => {
+            builder
+                .append_extension(ExtendedKeyUsage::new().client_auth().build().unwrap())
+                .unwrap();
+        }
+    }
+    builder.sign(ca_key, MessageDigest::sha256()).unwrap();
+
+    (builder.build(), key)
+}
+
+fn cert_builder(common_name: &str, key: &PKey<Private>) -> openssl::x509::X509Builder {
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    let serial = BigNum::from_u32(rand_serial()).unwrap();
+    builder
+        .set_serial_number(&serial.to_asn1_integer().unwrap())
+        .unwrap();
+    builder.set_subject_name(&name(common_name)).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    builder
+}
+
+fn name(common_name: &str) -> openssl::x509::X509Name {
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+        .unwrap();
+    name.build()
+}
+
+fn rand_serial() -> u32 {
+    let mut buf = [0; 4];
+    openssl::rand::rand_bytes(&mut buf).unwrap();
+    u32::from_be_bytes(buf) >> 1
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
@@ -1,4 +1,5 @@
 use std::{
+    path::Path,
     sync::atomic::{AtomicU32, Ordering},
     task::{Context, Poll},
     time::{Duration, Instant},
@@ -8,7 +9,11 @@ use bytes::Bytes;
 use futures::{future::select, pin_mut, Stream};
 use futures_util::{sink::SinkExt, FutureExt, StreamExt};
 use lazy_static::lazy_static;
+use openssl::ssl::ConnectConfiguration;
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio::{
+    io::{AsyncRead, AsyncWrite},
     net::{TcpStream, ToSocketAddrs},
     sync::{
         mpsc::{self, UnboundedReceiver},
@@ -17,14 +22,21 @@ use tokio::{
     task::JoinHandle,
 };
 use tokio_io_timeout::TimeoutStream;
+use tokio_openssl::SslStream;
+use tokio_tungstenite::tungstenite::http::{header::SEC_WEBSOCKET_PROTOCOL, Request};
 use tokio_util::codec::Framed;
 
 use mqtt3::{
-    proto::{ClientId, Connect, Packet, PacketCodec, Publication, Publish, QoS, SubscribeTo},
+    proto::{
+        ClientId, Connect, Packet, PacketCodec, Properties, Publication, Publish, QoS, SubscribeTo,
+    },
     Client, Event, PublishError, PublishHandle, ReceivedPublication, ShutdownHandle,
     UpdateSubscriptionHandle, PROTOCOL_LEVEL, PROTOCOL_NAME,
 };
-use mqtt_broker::{Authenticator, Authorizer, Broker, BrokerState, Error, Server};
+use mqtt_broker::{
+    Authenticator, Authorizer, Broker, BrokerState, Error, Server, TransportBuilder, WsStream,
+    MQTT_SUBPROTOCOL,
+};
 
 /// A wrapper on the [`mqtt3::Client`] to help simplify client event loop management.
 #[derive(Debug)]
@@ -59,6 +71,7 @@ impl TestClient {
             qos: QoS::AtMostOnce,
             retain,
             payload: payload.into(),
+            properties: Properties::default(),
         })
         .await
         .expect("couldn't publish")
@@ -75,6 +88,7 @@ impl TestClient {
             qos: QoS::AtLeastOnce,
             retain,
             payload: payload.into(),
+            properties: Properties::default(),
         })
         .await
         .expect("couldn't publish")
@@ -91,6 +105,7 @@ impl TestClient {
             qos: QoS::ExactlyOnce,
             retain,
             payload: payload.into(),
+            properties: Properties::default(),
         })
         .await
         .expect("couldn't publish")
@@ -291,34 +306,16 @@ lazy_static! {
 /// A simple wrapper around TcpStream + PacketCodec to send specific packets
 /// to a broker for more granular integration testing.
 #[derive(Debug)]
-pub struct PacketStream {
-    codec: Framed<TimeoutStream<TcpStream>, PacketCodec>,
+pub struct PacketStream<S = TcpStream> {
+    codec: Framed<TimeoutStream<S>, PacketCodec>,
 }
 
 impl PacketStream {
     /// Creates a client and opens TCP connection to the server.
     /// No MQTT packets are sent at this moment.
     pub async fn open(server_addr: impl ToSocketAddrs) -> Self {
-        // broker may not be available immediately in the test,
-        // so we'll try to connect for some time.
-        let mut result = TcpStream::connect(&server_addr).await;
-        let start_time = Instant::now();
-        while let Err(_) = result {
-            tokio::time::delay_for(Duration::from_millis(100)).await;
-            if start_time.elapsed() > *DEFAULT_TIMEOUT {
-                break;
-            }
-            result = TcpStream::connect(&server_addr).await;
-        }
-
-        let tcp_stream = result.expect("unable to establish tcp connection");
-        let mut timeout = TimeoutStream::new(tcp_stream);
-        timeout.set_read_timeout(Some(*DEFAULT_TIMEOUT));
-        timeout.set_write_timeout(Some(*DEFAULT_TIMEOUT));
-
-        let codec = Framed::new(timeout, PacketCodec::default());
-
-        Self { codec }
+        let tcp_stream = connect_tcp(server_addr).await;
+        Self::from_io(tcp_stream)
     }
 
     /// Creates a client, opens TCP connection to the server,
@@ -339,10 +336,83 @@ impl PacketStream {
                 keep_alive: Duration::from_secs(30),
                 protocol_name: PROTOCOL_NAME.into(),
                 protocol_level: PROTOCOL_LEVEL,
+                properties: Properties::default(),
             })
             .await;
         client
     }
+}
+
+#[allow(dead_code)]
+impl PacketStream<WsStream<TcpStream>> {
+    /// Creates a client and opens WebSocket connection to the server
+    /// using `mqtt` subprotocol. No MQTT packets are sent at this moment.
+    pub async fn open_ws(server_addr: &str) -> Self {
+        let tcp_stream = connect_tcp(server_addr).await;
+
+        let request = Request::get(format!("ws://{}/mqtt", server_addr))
+            .header(SEC_WEBSOCKET_PROTOCOL, MQTT_SUBPROTOCOL)
+            .body(())
+            .expect("unable to build websocket request");
+        let (ws_stream, _) = tokio_tungstenite::client_async(request, tcp_stream)
+            .await
+            .expect("unable to establish websocket connection");
+
+        Self::from_io(WsStream::new(ws_stream))
+    }
+}
+
+#[allow(dead_code)]
+impl PacketStream<SslStream<TcpStream>> {
+    /// Creates a client and opens TLS connection to the server.
+    /// No MQTT packets are sent at this moment.
+    pub async fn open_tls(server_addr: &str, config: ConnectConfiguration) -> Self {
+        let tcp_stream = connect_tcp(server_addr).await;
+
+        let domain = server_addr.split(':').next().unwrap_or_default();
+        let tls_stream = tokio_openssl::connect(config, domain, tcp_stream)
+            .await
+            .expect("unable to establish tls connection");
+
+        Self::from_io(tls_stream)
+    }
+}
+
+#[cfg(unix)]
+#[allow(dead_code)]
+impl PacketStream<UnixStream> {
+    /// Creates a client and opens unix domain socket connection to the server.
+    /// No MQTT packets are sent at this moment.
+    pub async fn open_unix(path: &Path) -> Self {
+        // broker may not be available immediately in the test,
+        // so we'll try to connect for some time.
+        let mut result = UnixStream::connect(path).await;
+        let start_time = Instant::now();
+        while let Err(_) = result {
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+            if start_time.elapsed() > *DEFAULT_TIMEOUT {
+                break;
+            }
+            result = UnixStream::connect(path).await;
+        }
+
+        Self::from_io(result.expect("unable to establish unix domain socket connection"))
+    }
+}
+
+impl<S> PacketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn from_io(io: S) -> Self {
+        let mut timeout = TimeoutStream::new(io);
+        timeout.set_read_timeout(Some(*DEFAULT_TIMEOUT));
+        timeout.set_write_timeout(Some(*DEFAULT_TIMEOUT));
+
+        let codec = Framed::new(timeout, PacketCodec::default());
+
+        Self { codec }
+    }
 
     pub async fn send_connect(&mut self, connect: Connect) {
         self.send_packet(Packet::Connect(connect)).await;
@@ -360,7 +430,10 @@ impl PacketStream {
     }
 }
 
-impl Stream for PacketStream {
+impl<S> Stream for PacketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     type Item = Packet;
 
     fn poll_next(
@@ -377,6 +450,23 @@ impl Stream for PacketStream {
     }
 }
 
+/// Opens TCP connection to the server.
+pub async fn connect_tcp(server_addr: impl ToSocketAddrs) -> TcpStream {
+    // broker may not be available immediately in the test,
+    // so we'll try to connect for some time.
+    let mut result = TcpStream::connect(&server_addr).await;
+    let start_time = Instant::now();
+    while let Err(_) = result {
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        if start_time.elapsed() > *DEFAULT_TIMEOUT {
+            break;
+        }
+        result = TcpStream::connect(&server_addr).await;
+    }
+
+    result.expect("unable to establish tcp connection")
+}
+
 /// Used to control server lifetime during tests. Implements
 /// Drop to cleanup resources after every test.
 pub struct ServerHandle {
@@ -420,6 +510,17 @@ pub fn start_server<N, Z>(broker: Broker<N, Z>) -> ServerHandle
 where
     N: Authenticator + Send + Sync + 'static,
     Z: Authorizer + Send + Sync + 'static,
+{
+    start_server_with_transport(broker, TransportBuilder::Tcp)
+}
+
+/// Starts a test server with a provided broker listening on a transport
+/// built for the server binding and returns shutdown handle, broker task and server binding.
+pub fn start_server_with_transport<N, Z, F>(broker: Broker<N, Z>, transport: F) -> ServerHandle
+where
+    N: Authenticator + Send + Sync + 'static,
+    Z: Authorizer + Send + Sync + 'static,
+    F: FnOnce(String) -> TransportBuilder<String>,
 {
     lazy_static! {
         static ref PORT: AtomicU32 = AtomicU32::new(5555);
@@ -429,7 +530,7 @@ where
     let address: String = format!("localhost:{}", port);
 
     let (shutdown, rx) = oneshot::channel::<()>();
-    let transports = vec![mqtt_broker::TransportBuilder::Tcp(address.clone())];
+    let transports = vec![transport(address.clone())];
     let task = tokio::spawn(Server::from_broker(broker).serve(transports, rx.map(drop)));
 
     ServerHandle {
@@ -1,18 +1,25 @@
 use std::time::Duration;
 
 use bytes::Bytes;
-use futures_util::StreamExt;
+use futures_util::{FutureExt, StreamExt};
 use matches::assert_matches;
 use mqtt3::{
     proto::{
         ClientId, ConnAck, Connect, ConnectReturnCode, ConnectionRefusedReason, Packet,
-        PacketIdentifier, PacketIdentifierDupQoS, PingReq, PubAck, Publication, Publish, QoS,
+        PacketIdentifier, PacketIdentifierDupQoS, PingReq, PingResp, Properties, PubAck,
+        Publication, Publish, QoS, SubAck, SubAckQos, Subscribe, SubscribeTo,
     },
     Event, ReceivedPublication, PROTOCOL_LEVEL, PROTOCOL_NAME,
 };
 
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, oneshot},
+};
+
 use common::{PacketStream, TestClientBuilder};
-use mqtt_broker::{AuthId, BrokerBuilder};
+
+use mqtt_broker::{AuthId, BrokerBuilder, Credentials, Server, TransportBuilder};
 
 mod common;
 
@@ -354,6 +361,7 @@ async fn will_message() {
             qos: QoS::AtLeastOnce,
             retain: false,
             payload: "will_msg_a".into(),
+            properties: Properties::default(),
         })
         .build();
 
@@ -615,6 +623,7 @@ async fn duplicate_connect_packet_connection_dropped() {
             keep_alive: Duration::from_secs(30),
             protocol_name: PROTOCOL_NAME.into(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: Properties::default(),
         })
         .await;
 
@@ -622,7 +631,8 @@ async fn duplicate_connect_packet_connection_dropped() {
         client.next().await,
         Some(Packet::ConnAck(ConnAck {
             return_code: ConnectReturnCode::Accepted,
-            session_present: false
+            session_present: false,
+            properties: Properties::default(),
         }))
     );
 
@@ -635,6 +645,7 @@ async fn duplicate_connect_packet_connection_dropped() {
             keep_alive: Duration::from_secs(30),
             protocol_name: PROTOCOL_NAME.into(),
             protocol_level: PROTOCOL_LEVEL,
+            properties: Properties::default(),
         })
         .await;
 
@@ -660,6 +671,7 @@ async fn wrong_protocol_name_connection_dropped() {
             will: None,
             keep_alive: Duration::from_secs(30),
             protocol_level: PROTOCOL_LEVEL,
+            properties: Properties::default(),
         })
         .await;
 
@@ -685,6 +697,7 @@ async fn wrong_protocol_version_rejected() {
             will: None,
             keep_alive: Duration::from_secs(30),
             protocol_name: PROTOCOL_NAME.into(),
+            properties: Properties::default(),
         })
         .await;
 
@@ -727,6 +740,7 @@ async fn qos1_puback_should_be_in_order() {
             retain: false,
             topic_name: "topic/A".into(),
             payload: Bytes::from("qos 1"),
+            properties: Properties::default(),
         })
         .await;
 
@@ -739,6 +753,7 @@ async fn qos1_puback_should_be_in_order() {
             retain: false,
             topic_name: "topic/A".into(),
             payload: Bytes::from("qos 1"),
+            properties: Properties::default(),
         })
         .await;
 
@@ -751,6 +766,7 @@ async fn qos1_puback_should_be_in_order() {
             retain: false,
             topic_name: "topic/A".into(),
             payload: Bytes::from("qos 1"),
+            properties: Properties::default(),
         })
         .await;
 
@@ -775,3 +791,236 @@ async fn qos1_puback_should_be_in_order() {
         }))
     );
 }
+
+#[tokio::test]
+async fn websocket_pub_sub() {
+    let broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .build();
+
+    let server_handle = common::start_server_with_transport(broker, TransportBuilder::Ws);
+
+    let mut client = PacketStream::open_ws(&server_handle.address()).await;
+    client
+        .send_connect(Connect {
+            client_id: ClientId::IdWithCleanSession("test-client".into()),
+            username: None,
+            password: None,
+            will: None,
+            keep_alive: Duration::from_secs(30),
+            protocol_name: PROTOCOL_NAME.into(),
+            protocol_level: PROTOCOL_LEVEL,
+            properties: Properties::default(),
+        })
+        .await;
+
+    assert_eq!(
+        client.next().await,
+        Some(Packet::ConnAck(ConnAck {
+            return_code: ConnectReturnCode::Accepted,
+            session_present: false,
+            properties: Properties::default(),
+        }))
+    );
+
+    client
+        .send_packet(Packet::Subscribe(Subscribe {
+            packet_identifier: PacketIdentifier::new(1).unwrap(),
+            subscribe_to: vec![SubscribeTo {
+                topic_filter: "topic/A".into(),
+                qos: QoS::AtLeastOnce,
+            }],
+        }))
+        .await;
+
+    assert_eq!(
+        client.next().await,
+        Some(Packet::SubAck(SubAck {
+            packet_identifier: PacketIdentifier::new(1).unwrap(),
+            qos: vec![SubAckQos::Success(QoS::AtLeastOnce)],
+        }))
+    );
+
+    client
+        .send_publish(Publish {
+            packet_identifier_dup_qos: PacketIdentifierDupQoS::AtMostOnce,
+            retain: false,
+            topic_name: "topic/A".into(),
+            payload: Bytes::from("qos 0"),
+            properties: Properties::default(),
+        })
+        .await;
+
+    assert_matches!(
+        client.next().await,
+        Some(Packet::Publish(Publish {
+            packet_identifier_dup_qos: PacketIdentifierDupQoS::AtMostOnce,
+            ref topic_name,
+            ref payload,
+            ..
+        })) if topic_name == "topic/A" && payload == &Bytes::from("qos 0")
+    );
+}
+
+#[tokio::test]
+async fn websocket_without_mqtt_subprotocol_rejected() {
+    let broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .build();
+
+    let server_handle = common::start_server_with_transport(broker, TransportBuilder::Ws);
+
+    let address = server_handle.address();
+    let tcp_stream = common::connect_tcp(&address).await;
+    let result =
+        tokio_tungstenite::client_async(format!("ws://{}/mqtt", address), tcp_stream).await;
+
+    assert!(result.is_err());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn unix_socket_passes_peer_credentials_to_authenticator() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let broker = BrokerBuilder::default()
+        .authenticator(|credentials| match credentials {
+            Credentials::PeerCredentials(Some(username), peer)
+                if peer.pid() == Some(std::process::id() as i32) =>
+            {
+                Ok(Some(AuthId::from_identity(username)))
+            }
+            _ => Ok(None),
+        })
+        .authorizer(|_| Ok(true))
+        .build();
+
+    let dir = tempfile::tempdir().expect("unable to create temp dir");
+    let path = dir.path().join("mqttd.sock");
+
+    let server_path = path.clone();
+    let _server_handle = common::start_server_with_transport(broker, |_| {
+        TransportBuilder::Unix(server_path, Some(0o600))
+    });
+
+    let mut client = PacketStream::open_unix(&path).await;
+    client
+        .send_connect(Connect {
+            client_id: ClientId::IdWithCleanSession("test-client".into()),
+            username: Some("module".into()),
+            password: None,
+            will: None,
+            keep_alive: Duration::from_secs(30),
+            protocol_name: PROTOCOL_NAME.into(),
+            protocol_level: PROTOCOL_LEVEL,
+            properties: Properties::default(),
+        })
+        .await;
+
+    assert_eq!(
+        client.next().await,
+        Some(Packet::ConnAck(ConnAck {
+            return_code: ConnectReturnCode::Accepted,
+            session_present: false,
+            properties: Properties::default(),
+        }))
+    );
+
+    let mode = std::fs::metadata(&path)
+        .expect("unable to read socket metadata")
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+/// Scenario:
+///	- Server starts listening on the first address and a client connects.
+///	- Listeners are replaced with the second address.
+///	- A new client connects to the second address.
+///	- The first client stays connected, while the first address no longer accepts connections.
+#[tokio::test]
+async fn reload_listeners_keeps_connections() {
+    let broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .build();
+
+    let first = "localhost:5900".to_string();
+    let second = "localhost:5901".to_string();
+
+    let (mut updates, rx) = mpsc::channel(1);
+    let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+    let transports = vec![(first.clone(), TransportBuilder::Tcp(first.clone()))];
+    let server =
+        Server::from_broker(broker).serve_with_reload(transports, rx, shutdown_rx.map(drop));
+    let server = tokio::spawn(server);
+
+    let accepted = Some(Packet::ConnAck(ConnAck {
+        return_code: ConnectReturnCode::Accepted,
+        session_present: false,
+        properties: Properties::default(),
+    }));
+
+    let client_id = ClientId::IdWithCleanSession("first-client".into());
+    let mut first_client = PacketStream::connect(client_id, first.clone(), None, None).await;
+    assert_eq!(first_client.next().await, accepted);
+
+    let transports = vec![(second.clone(), TransportBuilder::Tcp(second.clone()))];
+    assert!(updates.send(transports).await.is_ok());
+
+    let client_id = ClientId::IdWithCleanSession("second-client".into());
+    let mut second_client = PacketStream::connect(client_id, second, None, None).await;
+    assert_eq!(second_client.next().await, accepted);
+
+    first_client.send_packet(Packet::PingReq(PingReq)).await;
+    assert_eq!(first_client.next().await, Some(Packet::PingResp(PingResp)));
+
+    assert!(TcpStream::connect(first).await.is_err());
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+/// Scenario:
+///	- Server starts listening on an address.
+///	- Listeners are replaced with a new listener on the same address.
+///	- The new listener binds the address once the old one has stopped and a client connects.
+#[tokio::test]
+async fn reload_listeners_rebinds_released_address() {
+    let broker = BrokerBuilder::default()
+        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+        .authorizer(|_| Ok(true))
+        .build();
+
+    let address = "localhost:5902".to_string();
+
+    let (mut updates, rx) = mpsc::channel(1);
+    let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+    let transports = vec![("old", TransportBuilder::Tcp(address.clone()))];
+    let server =
+        Server::from_broker(broker).serve_with_reload(transports, rx, shutdown_rx.map(drop));
+    let server = tokio::spawn(server);
+
+    let accepted = Some(Packet::ConnAck(ConnAck {
+        return_code: ConnectReturnCode::Accepted,
+        session_present: false,
+        properties: Properties::default(),
+    }));
+
+    let client_id = ClientId::IdWithCleanSession("first-client".into());
+    let mut first_client = PacketStream::connect(client_id, address.clone(), None, None).await;
+    assert_eq!(first_client.next().await, accepted);
+
+    let transports = vec![("new", TransportBuilder::Tcp(address.clone()))];
+    assert!(updates.send(transports).await.is_ok());
+    tokio::time::delay_for(Duration::from_millis(100)).await;
+
+    let client_id = ClientId::IdWithCleanSession("second-client".into());
+    let mut second_client = PacketStream::connect(client_id, address, None, None).await;
+    assert_eq!(second_client.next().await, accepted);
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
@@ -73,6 +73,7 @@ pub fn translate_outgoing_publish(mut publish: proto::Publish) -> proto::Publish
 }
 
 const DEVICE_ID: &str = r"(?P<device_id>.+)";
+const MODULE_ID: &str = r"(?P<module_id>[^/]+)";
 macro_rules! translate_d2c {
     ($(
         $translate_name:ident {
@@ -172,6 +173,14 @@ macro_rules! translate_c2d {
 
 translate_d2c! {
     // Message Translation
+    // Older sdks send module outputs as events with the output name in the `$.on` property,
+    // so these have to be matched before any other events.
+    module_send_output {
+        to_new {
+            format!("devices/{}/modules/{}/messages/events/(?P<props>(?:.*&)?(?:\\$|%24)\\.on=(?P<output>[^&/]+).*)", DEVICE_ID, MODULE_ID),
+            {|captures: regex::Captures<'_>, _| format!("$edgehub/{}/modules/{}/outputs/{}/{}", &captures["device_id"], &captures["module_id"], &captures["output"], &captures["props"])}
+        }
+    },
     device_send_message { // note this may have to be split into 2 patterns for device and modules, depending on how client_id encodes device and module id
         to_new {
             format!("devices/{}/messages/events(?P<path>.*)", DEVICE_ID),
@@ -215,15 +224,27 @@ translate_c2d! {
         }
     },
 
+    // Module-to-Module Translation
+    module_input {
+        to_new {
+            format!("devices/{}/modules/{}/inputs(?P<path>.*)", DEVICE_ID, MODULE_ID),
+            {|captures: regex::Captures<'_>, _| format!("$edgehub/{}/modules/{}/inputs{}", &captures["device_id"], &captures["module_id"], &captures["path"])}
+        },
+        to_old {
+            format!("\\$edgehub/{}/modules/{}/inputs(?P<path>.*)", DEVICE_ID, MODULE_ID),
+            {|captures: regex::Captures<'_>| format!("devices/{}/modules/{}/inputs{}", &captures["device_id"], &captures["module_id"], &captures["path"])}
+        }
+    },
+
     // Twin Translation
     twin_receive_update_from_hub {
         to_new {
-            "\\$iothub/twin/PATCH/properties/desired/(?P<params>.*)",
-            {|captures: regex::Captures<'_>, client_id| format!("$edgehub/{}/twin/desired/{}", client_id, &captures["params"])}
+            "\\$iothub/twin/PATCH/properties/desired(?P<params>/.*)?$",
+            {|captures: regex::Captures<'_>, client_id| format!("$edgehub/{}/twin/desired{}", client_id, captures.name("params").map_or("", |params| params.as_str()))}
         },
         to_old {
-            format!("\\$edgehub/{}/twin/desired/(?P<params>.*)", DEVICE_ID),
-            {|captures: regex::Captures<'_>| format!("$iothub/twin/PATCH/properties/desired/{}", &captures["params"])}
+            format!("\\$edgehub/{}/twin/desired(?P<params>/.*)?$", DEVICE_ID),
+            {|captures: regex::Captures<'_>| format!("$iothub/twin/PATCH/properties/desired{}", captures.name("params").map_or("", |params| params.as_str()))}
         }
     },
     twin_response_from_hub {
@@ -313,6 +334,42 @@ pub(crate) mod tests {
             Some("devices/device_1/modules/client_a/messages/devicebound".to_owned())
         );
 
+        // Module outputs d2c
+        assert_eq!(
+            d2c.translate_to_new(
+                "devices/device_1/modules/client_a/messages/events/%24.on=output_1",
+                "device_1/client_a"
+            ),
+            Some("$edgehub/device_1/modules/client_a/outputs/output_1/%24.on=output_1".to_owned())
+        );
+        assert_eq!(
+            d2c.translate_to_new(
+                "devices/device_1/modules/client_a/messages/events/%24.ct=application%2Fjson&$.on=output_1&a=b",
+                "device_1/client_a"
+            ),
+            Some("$edgehub/device_1/modules/client_a/outputs/output_1/%24.ct=application%2Fjson&$.on=output_1&a=b".to_owned())
+        );
+        assert_eq!(
+            d2c.translate_to_new(
+                "devices/device_1/modules/client_a/messages/events/a=b",
+                "device_1/client_a"
+            ),
+            Some("$edgehub/device_1/modules/client_a/messages/events/a=b".to_owned())
+        );
+
+        // Module inputs c2d
+        assert_eq!(
+            c2d.translate_to_new(
+                "devices/device_1/modules/client_a/inputs/#",
+                "device_1/client_a"
+            ),
+            Some("$edgehub/device_1/modules/client_a/inputs/#".to_owned())
+        );
+        assert_eq!(
+            c2d.translate_to_old("$edgehub/device_1/modules/client_a/inputs/input_1/a=b"),
+            Some("devices/device_1/modules/client_a/inputs/input_1/a=b".to_owned())
+        );
+
         // Twin d2c
         assert_eq!(
             d2c.translate_to_new("$iothub/twin/PATCH/properties/reported/?rid=1", "client_a"),
@@ -333,6 +390,18 @@ pub(crate) mod tests {
             Some("$iothub/twin/PATCH/properties/desired/?rid=1".to_owned())
         );
 
+        assert_eq!(
+            c2d.translate_to_new(
+                "$iothub/twin/PATCH/properties/desired/#",
+                "device_1/client_a"
+            ),
+            Some("$edgehub/device_1/client_a/twin/desired/#".to_owned())
+        );
+        assert_eq!(
+            c2d.translate_to_old("$edgehub/device_1/client_a/twin/desired"),
+            Some("$iothub/twin/PATCH/properties/desired".to_owned())
+        );
+
         assert_eq!(
             c2d.translate_to_new("$iothub/twin/res/#", "client_a"),
             Some("$edgehub/client_a/twin/res/#".to_owned())
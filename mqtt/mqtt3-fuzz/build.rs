@@ -16,6 +16,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
                 session_present: true,
                 return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                properties: Default::default(),
             }),
         ),
         (
@@ -28,11 +29,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     qos: mqtt3::proto::QoS::ExactlyOnce,
                     retain: true,
                     payload: b"\x00\x01\x02\xFF\xFE\xFD"[..].into(),
+                    properties: Default::default(),
                 }),
                 client_id: mqtt3::proto::ClientId::IdWithExistingSession("id".to_string()),
                 keep_alive: std::time::Duration::from_secs(5),
                 protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                 protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: Default::default(),
             }),
         ),
         (
@@ -69,6 +72,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 retain: true,
                 topic_name: "publish-topic".to_string(),
                 payload: b"\x00\x01\x02\xFF\xFE\xFD"[..].into(),
+                properties: Default::default(),
             }),
         ),
         (
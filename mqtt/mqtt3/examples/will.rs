@@ -90,6 +90,7 @@ fn main() {
         qos,
         retain: false,
         payload: payload.into(),
+        properties: mqtt3::proto::Properties::default(),
     };
 
     let mut client = mqtt3::Client::new(
@@ -7,6 +7,9 @@ mod ping;
 mod publish;
 pub use publish::{PublishError, PublishHandle};
 
+mod store;
+pub use store::{FilePublicationStore, PublicationStore};
+
 mod subscriptions;
 pub use subscriptions::{UpdateSubscriptionError, UpdateSubscriptionHandle};
 
@@ -150,6 +153,39 @@ where
         })
     }
 
+    /// Sets a store for publications which the server hasn't acknowledged yet.
+    ///
+    /// Publications left in the store when the client last stopped are sent again with the DUP flag set
+    /// as soon as the client connects. Use this together with [`Client::from_state`] to resume the
+    /// publications of an existing session after a restart.
+    pub fn with_publication_store<S>(mut self, store: S) -> std::io::Result<Self>
+    where
+        S: PublicationStore + 'static,
+    {
+        if let ClientState::Up {
+            packet_identifiers,
+            publish,
+            ..
+        } = &mut self.0
+        {
+            publish.restore(Box::new(store), packet_identifiers)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Sets the maximum number of messages [`PublishHandle::publish`] accepts while the client is not connected.
+    ///
+    /// The messages are sent once the client connects. By default no messages are accepted
+    /// and [`PublishHandle::publish`] waits for the connection.
+    pub fn with_offline_queue(mut self, max_len: usize) -> Self {
+        if let ClientState::Up { publish, .. } = &mut self.0 {
+            publish.set_max_offline_queue_len(max_len);
+        }
+
+        self
+    }
+
     /// Queues a message to be published to the server
     pub fn publish(
         &mut self,
@@ -265,6 +301,8 @@ where
                         std::task::Poll::Ready(None) | std::task::Poll::Pending => (),
                     }
 
+                    publish.poll_offline(cx);
+
                     let connect::Connected {
                         framed,
                         new_connection,
@@ -732,6 +770,13 @@ impl PacketIdentifiers {
         Ok(current)
     }
 
+    /// Marks a packet identifier restored from a previous run of the client as in use.
+    fn restore(&mut self, packet_identifier: crate::proto::PacketIdentifier) {
+        let (block, mask) = self.entry(packet_identifier);
+        *block |= mask;
+        self.previous = packet_identifier;
+    }
+
     fn discard(&mut self, packet_identifier: crate::proto::PacketIdentifier) {
         let (block, mask) = self.entry(packet_identifier);
         *block &= !mask;
@@ -772,6 +817,7 @@ pub enum Error {
     PacketIdentifiersExhausted,
     PingTimer(tokio::time::Error),
     ServerClosedConnection,
+    StorePublication(crate::proto::PacketIdentifier, std::io::Error),
     SubAckDoesNotContainEnoughQoS(crate::proto::PacketIdentifier, usize, usize),
     SubscriptionDowngraded(String, crate::proto::QoS, crate::proto::QoS),
     SubscriptionRejectedByServer,
@@ -791,6 +837,7 @@ impl Error {
     fn is_user_error(&self) -> bool {
         match self {
             Error::EncodePacket(err) => err.is_user_error(),
+            Error::StorePublication(_, _) => true,
             _ => false,
         }
     }
@@ -836,6 +883,9 @@ impl std::fmt::Display for Error {
 			Error::ServerClosedConnection =>
 				write!(f, "connection closed by server"),
 
+			Error::StorePublication(packet_identifier, err) =>
+				write!(f, "could not store PUBLISH {}: {}", packet_identifier, err),
+
 			Error::SubAckDoesNotContainEnoughQoS(packet_identifier, expected, actual) =>
 				write!(f, "Expected SUBACK {} to contain {} QoS's but it actually contained {}", packet_identifier, expected, actual),
 
@@ -864,6 +914,7 @@ impl std::error::Error for Error {
             Error::PacketIdentifiersExhausted => None,
             Error::PingTimer(err) => Some(err),
             Error::ServerClosedConnection => None,
+            Error::StorePublication(_, err) => Some(err),
             Error::SubAckDoesNotContainEnoughQoS(_, _, _) => None,
             Error::SubscriptionDowngraded(_, _, _) => None,
             Error::SubscriptionRejectedByServer => None,
@@ -23,6 +23,12 @@ pub(super) struct State {
         crate::proto::PacketIdentifier,
         (futures_channel::oneshot::Sender<()>, crate::proto::Publish),
     >,
+
+    /// Stores PUBLISH packets sent by us until they're acked or completed, if set
+    store: Option<Box<dyn super::PublicationStore>>,
+
+    /// Maximum number of publish requests accepted while there is no connection to the server
+    max_offline_queue_len: usize,
 }
 
 impl State {
@@ -49,6 +55,7 @@ impl State {
                 match self.waiting_to_be_acked.remove(&packet_identifier) {
                     Some((ack_sender, _)) => {
                         packet_identifiers.discard(packet_identifier);
+                        self.remove_stored(packet_identifier);
 
                         match ack_sender.send(()) {
 						Ok(()) => (),
@@ -63,6 +70,7 @@ impl State {
                 match self.waiting_to_be_completed.remove(&packet_identifier) {
                     Some((ack_sender, _)) => {
                         packet_identifiers.discard(packet_identifier);
+                        self.remove_stored(packet_identifier);
 
                         match ack_sender.send(()) {
 						Ok(()) => (),
@@ -78,6 +86,7 @@ impl State {
                 retain,
                 topic_name,
                 payload,
+                ..
             })) => match packet_identifier_dup_qos {
                 crate::proto::PacketIdentifierDupQoS::AtMostOnce => {
                     publication_received = Some(crate::ReceivedPublication {
@@ -184,6 +193,7 @@ impl State {
                             retain: publication.retain,
                             topic_name: publication.topic_name,
                             payload: publication.payload,
+                            properties: publication.properties,
                         },
                     ));
 
@@ -206,33 +216,36 @@ impl State {
                         }
                     };
 
-                    let packet = crate::proto::Packet::Publish(crate::proto::Publish {
+                    let publish = crate::proto::Publish {
                         packet_identifier_dup_qos:
                             crate::proto::PacketIdentifierDupQoS::AtLeastOnce(
                                 packet_identifier,
-                                false,
+                                true,
                             ),
                         retain: publication.retain,
                         topic_name: publication.topic_name.clone(),
                         payload: publication.payload.clone(),
+                        properties: publication.properties.clone(),
+                    };
+                    let packet = crate::proto::Packet::Publish(crate::proto::Publish {
+                        packet_identifier_dup_qos:
+                            crate::proto::PacketIdentifierDupQoS::AtLeastOnce(
+                                packet_identifier,
+                                false,
+                            ),
+                        ..publish.clone()
                     });
 
-                    self.waiting_to_be_acked.insert(
-                        packet_identifier,
-                        (
-                            ack_sender,
-                            crate::proto::Publish {
-                                packet_identifier_dup_qos:
-                                    crate::proto::PacketIdentifierDupQoS::AtLeastOnce(
-                                        packet_identifier,
-                                        true,
-                                    ),
-                                retain: publication.retain,
-                                topic_name: publication.topic_name,
-                                payload: publication.payload,
-                            },
-                        ),
-                    );
+                    let publish_request = PublishRequest {
+                        publication,
+                        ack_sender,
+                    };
+                    if let Err(err) =
+                        self.insert_waiting_to_be_acked(packet_identifier, publish_request, publish)
+                    {
+                        packet_identifiers.discard(packet_identifier);
+                        return Err(err);
+                    }
 
                     packets_waiting_to_be_sent.push(packet);
                 }
@@ -250,33 +263,36 @@ impl State {
                         }
                     };
 
-                    let packet = crate::proto::Packet::Publish(crate::proto::Publish {
+                    let publish = crate::proto::Publish {
                         packet_identifier_dup_qos:
                             crate::proto::PacketIdentifierDupQoS::ExactlyOnce(
                                 packet_identifier,
-                                false,
+                                true,
                             ),
                         retain: publication.retain,
                         topic_name: publication.topic_name.clone(),
                         payload: publication.payload.clone(),
+                        properties: publication.properties.clone(),
+                    };
+                    let packet = crate::proto::Packet::Publish(crate::proto::Publish {
+                        packet_identifier_dup_qos:
+                            crate::proto::PacketIdentifierDupQoS::ExactlyOnce(
+                                packet_identifier,
+                                false,
+                            ),
+                        ..publish.clone()
                     });
 
-                    self.waiting_to_be_acked.insert(
-                        packet_identifier,
-                        (
-                            ack_sender,
-                            crate::proto::Publish {
-                                packet_identifier_dup_qos:
-                                    crate::proto::PacketIdentifierDupQoS::ExactlyOnce(
-                                        packet_identifier,
-                                        true,
-                                    ),
-                                retain: publication.retain,
-                                topic_name: publication.topic_name,
-                                payload: publication.payload,
-                            },
-                        ),
-                    );
+                    let publish_request = PublishRequest {
+                        publication,
+                        ack_sender,
+                    };
+                    if let Err(err) =
+                        self.insert_waiting_to_be_acked(packet_identifier, publish_request, publish)
+                    {
+                        packet_identifiers.discard(packet_identifier);
+                        return Err(err);
+                    }
 
                     packets_waiting_to_be_sent.push(packet);
                 }
@@ -286,6 +302,93 @@ impl State {
         Ok((packets_waiting_to_be_sent, publication_received))
     }
 
+    /// Accepts publish requests while there is no connection to the server, up to the maximum offline queue length.
+    ///
+    /// The requests are sent once the client is connected again.
+    pub(super) fn poll_offline(&mut self, cx: &mut std::task::Context<'_>) {
+        use futures_core::Stream;
+
+        while self.publish_requests_waiting_to_be_sent.len() < self.max_offline_queue_len {
+            match std::pin::Pin::new(&mut self.publish_request_recv).poll_next(cx) {
+                std::task::Poll::Ready(Some(publish_request)) => self
+                    .publish_requests_waiting_to_be_sent
+                    .push_back(publish_request),
+                std::task::Poll::Ready(None) | std::task::Poll::Pending => break,
+            }
+        }
+    }
+
+    pub(super) fn set_max_offline_queue_len(&mut self, max_offline_queue_len: usize) {
+        self.max_offline_queue_len = max_offline_queue_len;
+    }
+
+    /// Restores the publications in the store as if they were sent by us but not acked yet,
+    /// so that they are sent again with the DUP flag set on the next connection.
+    pub(super) fn restore(
+        &mut self,
+        mut store: Box<dyn super::PublicationStore>,
+        packet_identifiers: &mut super::PacketIdentifiers,
+    ) -> std::io::Result<()> {
+        for publish in store.load()? {
+            let packet_identifier = match publish.packet_identifier_dup_qos {
+                crate::proto::PacketIdentifierDupQoS::AtLeastOnce(packet_identifier, _)
+                | crate::proto::PacketIdentifierDupQoS::ExactlyOnce(packet_identifier, _) => {
+                    packet_identifier
+                }
+                crate::proto::PacketIdentifierDupQoS::AtMostOnce => continue,
+            };
+
+            log::debug!(
+                "restoring PUBLISH {} to topic {:?}",
+                packet_identifier,
+                publish.topic_name
+            );
+            packet_identifiers.restore(packet_identifier);
+
+            // Nobody is waiting for the ack of a publication sent before the client restarted.
+            let (ack_sender, _) = futures_channel::oneshot::channel();
+            self.waiting_to_be_acked
+                .insert(packet_identifier, (ack_sender, publish));
+        }
+
+        self.store = Some(store);
+        Ok(())
+    }
+
+    /// Stores the PUBLISH packet sent for a publish request and waits for the server to ack it.
+    ///
+    /// If the packet can't be stored, the request is put back to be sent again on the next poll.
+    fn insert_waiting_to_be_acked(
+        &mut self,
+        packet_identifier: crate::proto::PacketIdentifier,
+        publish_request: PublishRequest,
+        publish: crate::proto::Publish,
+    ) -> Result<(), super::Error> {
+        if let Some(store) = &mut self.store {
+            if let Err(err) = store.store(&publish) {
+                self.publish_requests_waiting_to_be_sent
+                    .push_front(publish_request);
+                return Err(super::Error::StorePublication(packet_identifier, err));
+            }
+        }
+
+        self.waiting_to_be_acked
+            .insert(packet_identifier, (publish_request.ack_sender, publish));
+        Ok(())
+    }
+
+    fn remove_stored(&mut self, packet_identifier: crate::proto::PacketIdentifier) {
+        if let Some(store) = &mut self.store {
+            if let Err(err) = store.remove(packet_identifier) {
+                log::warn!(
+                    "could not remove PUBLISH {} from store: {}",
+                    packet_identifier,
+                    err
+                );
+            }
+        }
+    }
+
     pub(super) fn new_connection<'a>(
         &'a mut self,
         reset_session: bool,
@@ -356,6 +459,9 @@ impl Default for State {
             waiting_to_be_acked: Default::default(),
             waiting_to_be_released: Default::default(),
             waiting_to_be_completed: Default::default(),
+
+            store: None,
+            max_offline_queue_len: 0,
         }
     }
 }
@@ -432,11 +538,12 @@ impl PublishRequest {
             retain: publication.retain,
             topic_name: publication.topic_name,
             payload: publication.payload,
+            properties: publication.properties,
         };
 
         let mut counter = crate::proto::ByteCounter::new();
         let encode_result = packet
-            .encode(&mut counter)
+            .encode(&mut counter, crate::PROTOCOL_LEVEL)
             .and_then(|()| crate::proto::encode_remaining_length(counter.0, &mut counter));
 
         let publication = crate::proto::Publication {
@@ -444,6 +551,7 @@ impl PublishRequest {
             qos: publication.qos,
             retain: publication.retain,
             payload: packet.payload,
+            properties: packet.properties,
         };
 
         match encode_result {
@@ -455,3 +563,88 @@ impl PublishRequest {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{PublishRequest, State};
+
+    fn publish_request() -> PublishRequest {
+        let (ack_sender, _) = futures_channel::oneshot::channel();
+        PublishRequest::new(
+            crate::proto::Publication {
+                topic_name: "topic1".to_owned(),
+                qos: crate::proto::QoS::AtLeastOnce,
+                retain: false,
+                payload: [0x01, 0x02, 0x03][..].into(),
+                properties: crate::proto::Properties::default(),
+            },
+            ack_sender,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn offline_queue_accepts_publish_requests_up_to_max_len() {
+        let mut cx = std::task::Context::from_waker(futures_util::task::noop_waker_ref());
+
+        let mut state = State::default();
+        for _ in 0..3 {
+            state
+                .publish_request_send
+                .clone()
+                .try_send(publish_request())
+                .unwrap();
+        }
+
+        state.poll_offline(&mut cx);
+        assert_eq!(state.publish_requests_waiting_to_be_sent.len(), 0);
+
+        state.set_max_offline_queue_len(2);
+        state.poll_offline(&mut cx);
+        assert_eq!(state.publish_requests_waiting_to_be_sent.len(), 2);
+    }
+
+    #[derive(Debug)]
+    struct FailingStore;
+
+    impl crate::client::PublicationStore for FailingStore {
+        fn load(&mut self) -> std::io::Result<Vec<crate::proto::Publish>> {
+            Ok(vec![])
+        }
+
+        fn store(&mut self, _: &crate::proto::Publish) -> std::io::Result<()> {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full"))
+        }
+
+        fn remove(&mut self, _: crate::proto::PacketIdentifier) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn store_errors_are_returned_and_publish_request_is_kept() {
+        let mut cx = std::task::Context::from_waker(futures_util::task::noop_waker_ref());
+
+        let mut state = State::default();
+        let mut packet_identifiers = crate::client::PacketIdentifiers::default();
+        state
+            .restore(Box::new(FailingStore), &mut packet_identifiers)
+            .unwrap();
+        state
+            .publish_requests_waiting_to_be_sent
+            .push_back(publish_request());
+
+        let result = state.poll(&mut cx, &mut None, &mut packet_identifiers);
+        assert!(matches!(
+            result,
+            Err(crate::client::Error::StorePublication(_, _))
+        ));
+        assert_eq!(state.publish_requests_waiting_to_be_sent.len(), 1);
+        assert!(state.waiting_to_be_acked.is_empty());
+
+        // the packet identifier is released
+        let (block, mask) =
+            packet_identifiers.entry(crate::proto::PacketIdentifier::new(1).unwrap());
+        assert_eq!(*block & mask, 0);
+    }
+}
@@ -0,0 +1,311 @@
+/// Stores publications sent to the server which the server hasn't acknowledged yet,
+/// so that they can be sent again after the client restarts.
+///
+/// Only publications with QoS 1 and 2 are stored. They are stored exactly as they are sent again,
+/// that is with their packet identifier and the DUP flag set.
+pub trait PublicationStore: std::fmt::Debug + Send {
+    /// Loads all publications stored previously.
+    fn load(&mut self) -> std::io::Result<Vec<crate::proto::Publish>>;
+
+    /// Stores a publication sent to the server, replacing any publication with the same packet identifier.
+    fn store(&mut self, publish: &crate::proto::Publish) -> std::io::Result<()>;
+
+    /// Removes the publication with the given packet identifier after the server acknowledged it.
+    fn remove(&mut self, packet_identifier: crate::proto::PacketIdentifier) -> std::io::Result<()>;
+}
+
+/// A [`PublicationStore`] which keeps publications in a single file.
+///
+/// The file contains the PUBLISH packets as they are sent to the server. It is rewritten
+/// on every change, so this store is meant for clients with a moderate number of messages in flight.
+///
+/// The file is written on a separate thread so that the client isn't blocked by the disk.
+/// A failed write is reported by the next call to [`PublicationStore::store`] or [`PublicationStore::remove`].
+/// Dropping the store waits for the last write to complete.
+#[derive(Debug)]
+pub struct FilePublicationStore {
+    path: std::path::PathBuf,
+    publications: std::collections::BTreeMap<crate::proto::PacketIdentifier, crate::proto::Publish>,
+    writer: Option<FileWriter>,
+}
+
+impl FilePublicationStore {
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        FilePublicationStore {
+            path: path.into(),
+            publications: Default::default(),
+            writer: None,
+        }
+    }
+
+    fn save(&mut self) -> std::io::Result<()> {
+        use tokio_util::codec::Encoder;
+
+        let mut codec: crate::proto::PacketCodec = Default::default();
+        let mut bytes = bytes::BytesMut::new();
+        for publish in self.publications.values() {
+            codec
+                .encode(crate::proto::Packet::Publish(publish.clone()), &mut bytes)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        }
+
+        if self.writer.is_none() {
+            self.writer = Some(FileWriter::new(self.path.clone())?);
+        }
+        match &self.writer {
+            Some(writer) => writer.write(bytes.freeze()),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the error of the last write, if it failed.
+    fn write_error(&self) -> std::io::Result<()> {
+        match &self.writer {
+            Some(writer) => writer.error(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes the contents of a [`FilePublicationStore`] on its own thread.
+#[derive(Debug)]
+struct FileWriter {
+    contents: Option<std::sync::mpsc::Sender<bytes::Bytes>>,
+    error: std::sync::Arc<std::sync::Mutex<Option<std::io::Error>>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl FileWriter {
+    fn new(path: std::path::PathBuf) -> std::io::Result<Self> {
+        let (contents, receiver) = std::sync::mpsc::channel::<bytes::Bytes>();
+        let error: std::sync::Arc<std::sync::Mutex<Option<std::io::Error>>> = Default::default();
+
+        let thread_error = error.clone();
+        let thread = std::thread::Builder::new()
+            .name("mqtt3-publication-store".to_owned())
+            .spawn(move || {
+                while let Ok(mut latest) = receiver.recv() {
+                    // Only the latest contents need to be written.
+                    while let Ok(contents) = receiver.try_recv() {
+                        latest = contents;
+                    }
+
+                    let result = write_file(&path, &latest);
+                    if let Err(err) = &result {
+                        log::warn!(
+                            "could not write publication store {}: {}",
+                            path.display(),
+                            err
+                        );
+                    }
+                    if let Ok(mut error) = thread_error.lock() {
+                        *error = result.err();
+                    }
+                }
+            })?;
+
+        Ok(FileWriter {
+            contents: Some(contents),
+            error,
+            thread: Some(thread),
+        })
+    }
+
+    fn write(&self, contents: bytes::Bytes) -> std::io::Result<()> {
+        match &self.contents {
+            Some(sender) => sender.send(contents).map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::BrokenPipe,
+                    "publication store writer has stopped",
+                )
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn error(&self) -> std::io::Result<()> {
+        match self.error.lock() {
+            Ok(mut error) => error.take().map_or(Ok(()), Err),
+            Err(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "publication store writer has panicked",
+            )),
+        }
+    }
+}
+
+impl Drop for FileWriter {
+    fn drop(&mut self) {
+        // Closing the channel stops the thread once it has written the latest contents.
+        self.contents = None;
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::warn!("publication store writer has panicked");
+            }
+        }
+    }
+}
+
+/// Replaces the file at `path` with `contents`.
+///
+/// The contents are written to a temporary file and synced to disk first,
+/// so that a crash doesn't leave a partially written file behind.
+fn write_file(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut temp_path = path.to_owned().into_os_string();
+    temp_path.push(".tmp");
+
+    let mut file = std::fs::File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&temp_path, path)
+}
+
+impl PublicationStore for FilePublicationStore {
+    fn load(&mut self) -> std::io::Result<Vec<crate::proto::Publish>> {
+        use tokio_util::codec::Decoder;
+
+        self.publications.clear();
+
+        let mut bytes = match std::fs::read(&self.path) {
+            Ok(contents) => bytes::BytesMut::from(&contents[..]),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+
+        let mut codec: crate::proto::PacketCodec = Default::default();
+        while let Some(packet) = codec
+            .decode(&mut bytes)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?
+        {
+            let publish = match packet {
+                crate::proto::Packet::Publish(publish) => publish,
+                packet => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("expected a PUBLISH packet but found {:?}", packet),
+                    ))
+                }
+            };
+
+            match publish.packet_identifier_dup_qos {
+                crate::proto::PacketIdentifierDupQoS::AtLeastOnce(packet_identifier, _)
+                | crate::proto::PacketIdentifierDupQoS::ExactlyOnce(packet_identifier, _) => {
+                    self.publications.insert(packet_identifier, publish);
+                }
+                crate::proto::PacketIdentifierDupQoS::AtMostOnce => {
+                    log::warn!("ignoring stored publication with QoS 0");
+                }
+            }
+        }
+
+        if !bytes.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "publication store file is truncated",
+            ));
+        }
+
+        Ok(self.publications.values().cloned().collect())
+    }
+
+    fn store(&mut self, publish: &crate::proto::Publish) -> std::io::Result<()> {
+        match publish.packet_identifier_dup_qos {
+            crate::proto::PacketIdentifierDupQoS::AtLeastOnce(packet_identifier, _)
+            | crate::proto::PacketIdentifierDupQoS::ExactlyOnce(packet_identifier, _) => {
+                self.write_error()?;
+                self.publications.insert(packet_identifier, publish.clone());
+                self.save()
+            }
+            crate::proto::PacketIdentifierDupQoS::AtMostOnce => Ok(()),
+        }
+    }
+
+    fn remove(&mut self, packet_identifier: crate::proto::PacketIdentifier) -> std::io::Result<()> {
+        match self.publications.remove(&packet_identifier) {
+            Some(_) => {
+                self.save()?;
+                self.write_error()
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FilePublicationStore, PublicationStore};
+
+    fn publish(packet_identifier: u16, topic_name: &str) -> crate::proto::Publish {
+        crate::proto::Publish {
+            packet_identifier_dup_qos: crate::proto::PacketIdentifierDupQoS::AtLeastOnce(
+                crate::proto::PacketIdentifier::new(packet_identifier).unwrap(),
+                true,
+            ),
+            retain: false,
+            topic_name: topic_name.to_owned(),
+            payload: [0x01, 0x02, 0x03][..].into(),
+            properties: crate::proto::Properties::default(),
+        }
+    }
+
+    #[test]
+    fn file_store_keeps_unacknowledged_publications() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("publications");
+
+        let mut store = FilePublicationStore::new(&path);
+        assert_eq!(store.load().unwrap(), vec![]);
+
+        store.store(&publish(1, "topic1")).unwrap();
+        store.store(&publish(2, "topic2")).unwrap();
+        store.store(&publish(3, "topic3")).unwrap();
+        store
+            .remove(crate::proto::PacketIdentifier::new(2).unwrap())
+            .unwrap();
+        drop(store);
+
+        let mut store = FilePublicationStore::new(&path);
+        assert_eq!(
+            store.load().unwrap(),
+            vec![publish(1, "topic1"), publish(3, "topic3")]
+        );
+    }
+
+    #[test]
+    fn file_store_rejects_truncated_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("publications");
+
+        let mut store = FilePublicationStore::new(&path);
+        store.store(&publish(1, "topic1")).unwrap();
+        drop(store);
+
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &contents[..contents.len() - 1]).unwrap();
+
+        let mut store = FilePublicationStore::new(&path);
+        assert!(store.load().is_err());
+    }
+
+    #[test]
+    fn file_store_reports_failed_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing").join("publications");
+
+        let mut store = FilePublicationStore::new(&path);
+        store.store(&publish(1, "topic1")).unwrap();
+
+        // the failure of the first write is reported by one of the next calls
+        let mut failed = false;
+        for packet_identifier in 2..100 {
+            if store.store(&publish(packet_identifier, "topic")).is_err() {
+                failed = true;
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(failed);
+    }
+}
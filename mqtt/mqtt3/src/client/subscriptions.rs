@@ -677,7 +677,7 @@ fn try_append_subscription(
     packet.subscribe_to.push(subscribe_to);
     let mut counter = crate::proto::ByteCounter::new();
     match packet
-        .encode(&mut counter)
+        .encode(&mut counter, crate::PROTOCOL_LEVEL)
         .and_then(|()| crate::proto::encode_remaining_length(counter.0, &mut counter))
     {
         Ok(_) => Ok(()),
@@ -703,7 +703,7 @@ fn try_append_unsubscription(
     packet.unsubscribe_from.push(unsubscribe_from);
     let mut counter = crate::proto::ByteCounter::new();
     match packet
-        .encode(&mut counter)
+        .encode(&mut counter, crate::PROTOCOL_LEVEL)
         .and_then(|()| crate::proto::encode_remaining_length(counter.0, &mut counter))
     {
         Ok(_) => Ok(()),
@@ -25,11 +25,13 @@ pub static PROTOCOL_NAME: &str = "MQTT";
 
 pub const PROTOCOL_LEVEL: u8 = 0x04;
 
+pub const PROTOCOL_LEVEL_V5: u8 = 0x05;
+
 mod client;
 pub use client::{
-    Client, Error, Event, IoSource, PublishError, PublishHandle, ReceivedPublication,
-    ShutdownError, ShutdownHandle, SubscriptionUpdateEvent, UpdateSubscriptionError,
-    UpdateSubscriptionHandle,
+    Client, Error, Event, FilePublicationStore, IoSource, PublicationStore, PublishError,
+    PublishHandle, ReceivedPublication, ShutdownError, ShutdownHandle, SubscriptionUpdateEvent,
+    UpdateSubscriptionError, UpdateSubscriptionHandle,
 };
 
 mod logging_framed;
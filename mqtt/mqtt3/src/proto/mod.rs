@@ -15,6 +15,9 @@ pub use packet::{
     SubscribeTo, UnsubAck, Unsubscribe,
 };
 
+mod properties;
+pub use properties::Properties;
+
 pub(crate) use packet::PacketMeta;
 
 /// The client ID
@@ -83,6 +86,42 @@ impl From<ConnectReturnCode> for u8 {
     }
 }
 
+impl ConnectReturnCode {
+    /// Converts an MQTT 5 CONNACK reason code into a return code.
+    ///
+    /// Ref: 3.2.2.2 Connect Reason Code (MQTT 5.0)
+    pub(crate) fn from_reason_code(code: u8) -> Self {
+        match code {
+            0x00 => ConnectReturnCode::Accepted,
+            0x84 => {
+                ConnectReturnCode::Refused(ConnectionRefusedReason::UnacceptableProtocolVersion)
+            }
+            0x85 => ConnectReturnCode::Refused(ConnectionRefusedReason::IdentifierRejected),
+            0x88 => ConnectReturnCode::Refused(ConnectionRefusedReason::ServerUnavailable),
+            0x86 => ConnectReturnCode::Refused(ConnectionRefusedReason::BadUserNameOrPassword),
+            0x87 => ConnectReturnCode::Refused(ConnectionRefusedReason::NotAuthorized),
+            code => ConnectReturnCode::Refused(ConnectionRefusedReason::Other(code)),
+        }
+    }
+
+    /// Converts this return code into an MQTT 5 CONNACK reason code.
+    ///
+    /// Ref: 3.2.2.2 Connect Reason Code (MQTT 5.0)
+    pub(crate) fn into_reason_code(self) -> u8 {
+        match self {
+            ConnectReturnCode::Accepted => 0x00,
+            ConnectReturnCode::Refused(ConnectionRefusedReason::UnacceptableProtocolVersion) => {
+                0x84
+            }
+            ConnectReturnCode::Refused(ConnectionRefusedReason::IdentifierRejected) => 0x85,
+            ConnectReturnCode::Refused(ConnectionRefusedReason::ServerUnavailable) => 0x88,
+            ConnectReturnCode::Refused(ConnectionRefusedReason::BadUserNameOrPassword) => 0x86,
+            ConnectReturnCode::Refused(ConnectionRefusedReason::NotAuthorized) => 0x87,
+            ConnectReturnCode::Refused(ConnectionRefusedReason::Other(code)) => code,
+        }
+    }
+}
+
 /// A tokio decoder of MQTT-format strings.
 ///
 /// Strings are prefixed with a two-byte big-endian length and are encoded as utf-8.
@@ -280,12 +319,15 @@ impl std::ops::AddAssign<u16> for PacketIdentifier {
 pub enum DecodeError {
     ConnectReservedSet,
     ConnectZeroLengthIdWithExistingSession,
+    DuplicateProperty(u8),
     IncompletePacket,
     Io(std::io::Error),
     PublishDupAtMostOnce,
     NoTopics,
+    PacketTooLarge(usize),
     RemainingLengthTooHigh,
     StringNotUtf8(std::str::Utf8Error),
+    TopicAliasOutOfRange(u16),
     UnrecognizedConnAckFlags(u8),
     UnrecognizedPacket {
         packet_type: u8,
@@ -294,7 +336,10 @@ pub enum DecodeError {
     },
     UnrecognizedProtocolLevel(u8),
     UnrecognizedProtocolName(String),
+    UnrecognizedProperty(u8),
     UnrecognizedQoS(u8),
+    UnrecognizedSubscriptionOptions(u8),
+    UnrecognizedTopicAlias(u16),
     ZeroPacketIdentifier,
 }
 
@@ -308,9 +353,17 @@ impl std::fmt::Display for DecodeError {
                 f,
                 "a zero length client_id was received without the clean session flag set"
             ),
+            DecodeError::DuplicateProperty(identifier) => {
+                write!(f, "property 0x{:02X} is present more than once", identifier)
+            }
             DecodeError::IncompletePacket => write!(f, "packet is truncated"),
             DecodeError::Io(err) => write!(f, "I/O error: {}", err),
             DecodeError::NoTopics => write!(f, "expected at least one topic but there were none"),
+            DecodeError::PacketTooLarge(size) => write!(
+                f,
+                "packet of {} bytes exceeds the maximum packet size",
+                size
+            ),
             DecodeError::PublishDupAtMostOnce => {
                 write!(f, "PUBLISH packet has DUP flag set and QoS 0")
             }
@@ -318,6 +371,9 @@ impl std::fmt::Display for DecodeError {
                 write!(f, "remaining length is too high to be decoded")
             }
             DecodeError::StringNotUtf8(err) => err.fmt(f),
+            DecodeError::TopicAliasOutOfRange(alias) => {
+                write!(f, "topic alias {} is out of range", alias)
+            }
             DecodeError::UnrecognizedConnAckFlags(flags) => {
                 write!(f, "could not parse CONNACK flags 0x{:02X}", flags)
             }
@@ -338,7 +394,16 @@ impl std::fmt::Display for DecodeError {
             DecodeError::UnrecognizedProtocolName(name) => {
                 write!(f, "unexpected protocol name {:?}", name)
             }
+            DecodeError::UnrecognizedProperty(identifier) => {
+                write!(f, "could not identify property 0x{:02X}", identifier)
+            }
             DecodeError::UnrecognizedQoS(qos) => write!(f, "could not parse QoS 0x{:02X}", qos),
+            DecodeError::UnrecognizedSubscriptionOptions(options) => {
+                write!(f, "could not parse subscription options 0x{:02X}", options)
+            }
+            DecodeError::UnrecognizedTopicAlias(alias) => {
+                write!(f, "topic alias {} was not previously defined", alias)
+            }
             DecodeError::ZeroPacketIdentifier => write!(f, "packet identifier is 0"),
         }
     }
@@ -350,17 +415,23 @@ impl std::error::Error for DecodeError {
         match self {
             DecodeError::ConnectReservedSet => None,
             DecodeError::ConnectZeroLengthIdWithExistingSession => None,
+            DecodeError::DuplicateProperty(_) => None,
             DecodeError::IncompletePacket => None,
             DecodeError::Io(err) => Some(err),
             DecodeError::NoTopics => None,
+            DecodeError::PacketTooLarge(_) => None,
             DecodeError::PublishDupAtMostOnce => None,
             DecodeError::RemainingLengthTooHigh => None,
             DecodeError::StringNotUtf8(err) => Some(err),
+            DecodeError::TopicAliasOutOfRange(_) => None,
             DecodeError::UnrecognizedConnAckFlags(_) => None,
             DecodeError::UnrecognizedPacket { .. } => None,
             DecodeError::UnrecognizedProtocolLevel(_) => None,
             DecodeError::UnrecognizedProtocolName(_) => None,
+            DecodeError::UnrecognizedProperty(_) => None,
             DecodeError::UnrecognizedQoS(_) => None,
+            DecodeError::UnrecognizedSubscriptionOptions(_) => None,
+            DecodeError::UnrecognizedTopicAlias(_) => None,
             DecodeError::ZeroPacketIdentifier => None,
         }
     }
@@ -374,6 +445,7 @@ impl From<std::io::Error> for DecodeError {
 
 #[derive(Debug)]
 pub enum EncodeError {
+    BinaryDataTooLarge(usize),
     Io(std::io::Error),
     KeepAliveTooHigh(std::time::Duration),
     RemainingLengthTooHigh(usize),
@@ -385,6 +457,7 @@ impl EncodeError {
     pub fn is_user_error(&self) -> bool {
         #[allow(clippy::match_same_arms)]
         match self {
+            EncodeError::BinaryDataTooLarge(_) => true,
             EncodeError::Io(_) => false,
             EncodeError::KeepAliveTooHigh(_) => true,
             EncodeError::RemainingLengthTooHigh(_) => true,
@@ -397,6 +470,11 @@ impl EncodeError {
 impl std::fmt::Display for EncodeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            EncodeError::BinaryDataTooLarge(len) => write!(
+                f,
+                "binary data of length {} is too large to be encoded",
+                len
+            ),
             EncodeError::Io(err) => write!(f, "I/O error: {}", err),
             EncodeError::KeepAliveTooHigh(keep_alive) => {
                 write!(f, "keep-alive {:?} is too high", keep_alive)
@@ -420,6 +498,7 @@ impl std::error::Error for EncodeError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         #[allow(clippy::match_same_arms)]
         match self {
+            EncodeError::BinaryDataTooLarge(_) => None,
             EncodeError::Io(err) => Some(err),
             EncodeError::KeepAliveTooHigh(_) => None,
             EncodeError::RemainingLengthTooHigh(_) => None,
@@ -442,6 +521,8 @@ pub(crate) trait ByteBuf {
 
     fn put_u16_bytes(&mut self, n: u16);
 
+    fn put_u32_bytes(&mut self, n: u32);
+
     fn put_packet_identifier_bytes(&mut self, packet_identifier: PacketIdentifier) {
         self.put_u16_bytes(packet_identifier.0);
     }
@@ -462,6 +543,10 @@ impl ByteBuf for bytes::BytesMut {
         self.put_u16(n);
     }
 
+    fn put_u32_bytes(&mut self, n: u32) {
+        self.put_u32(n);
+    }
+
     fn put_slice_bytes(&mut self, src: &[u8]) {
         self.put_slice(src);
     }
@@ -486,6 +571,10 @@ impl ByteBuf for ByteCounter {
         self.0 += std::mem::size_of::<u16>();
     }
 
+    fn put_u32_bytes(&mut self, _: u32) {
+        self.0 += std::mem::size_of::<u32>();
+    }
+
     fn put_slice_bytes(&mut self, src: &[u8]) {
         self.0 += src.len();
     }
@@ -496,6 +585,7 @@ trait BufMutExt {
 
     fn try_get_u8(&mut self) -> Result<u8, DecodeError>;
     fn try_get_u16_be(&mut self) -> Result<u16, DecodeError>;
+    fn try_get_u32_be(&mut self) -> Result<u32, DecodeError>;
     fn try_get_packet_identifier(&mut self) -> Result<PacketIdentifier, DecodeError>;
 }
 
@@ -521,6 +611,14 @@ impl BufMutExt for bytes::BytesMut {
         Ok(self.get_u16())
     }
 
+    fn try_get_u32_be(&mut self) -> Result<u32, DecodeError> {
+        if self.len() < std::mem::size_of::<u32>() {
+            return Err(DecodeError::IncompletePacket);
+        }
+
+        Ok(self.get_u32())
+    }
+
     fn try_get_packet_identifier(&mut self) -> Result<PacketIdentifier, DecodeError> {
         if self.len() < std::mem::size_of::<u16>() {
             return Err(DecodeError::IncompletePacket);
@@ -634,4 +732,180 @@ mod tests {
             None
         );
     }
+
+    #[test]
+    fn v5_connect_negotiates_protocol_level() {
+        let mut codec = super::PacketCodec::default();
+        assert_eq!(codec.protocol_level(), crate::PROTOCOL_LEVEL);
+
+        let connect = v5_connect(super::Properties {
+            session_expiry_interval: Some(30),
+            receive_maximum: Some(10),
+            user_properties: vec![("key".to_owned(), "value".to_owned())],
+            ..Default::default()
+        });
+        let packet = roundtrip(
+            &mut super::PacketCodec::default(),
+            &mut codec,
+            connect.clone(),
+        );
+
+        assert_eq!(packet, connect);
+        assert_eq!(codec.protocol_level(), crate::PROTOCOL_LEVEL_V5);
+    }
+
+    #[test]
+    fn v5_publish_roundtrip() {
+        let (mut client, mut server) = v5_codecs();
+
+        let publish = super::Packet::Publish(super::Publish {
+            packet_identifier_dup_qos: super::PacketIdentifierDupQoS::AtLeastOnce(
+                super::PacketIdentifier::new(1).unwrap(),
+                false,
+            ),
+            retain: false,
+            topic_name: "topic".to_owned(),
+            payload: bytes::Bytes::from_static(b"payload"),
+            properties: super::Properties {
+                message_expiry_interval: Some(60),
+                content_type: Some("application/json".to_owned()),
+                user_properties: vec![("key".to_owned(), "value".to_owned())],
+                ..Default::default()
+            },
+        });
+
+        assert_eq!(
+            roundtrip(&mut client, &mut server, publish.clone()),
+            publish
+        );
+    }
+
+    #[test]
+    fn v5_publish_resolves_topic_alias() {
+        let (mut client, mut server) = v5_codecs();
+
+        let publish = |topic_name: &str, topic_alias| {
+            super::Packet::Publish(super::Publish {
+                packet_identifier_dup_qos: super::PacketIdentifierDupQoS::AtMostOnce,
+                retain: false,
+                topic_name: topic_name.to_owned(),
+                payload: bytes::Bytes::from_static(b"payload"),
+                properties: super::Properties {
+                    topic_alias: Some(topic_alias),
+                    ..Default::default()
+                },
+            })
+        };
+
+        for packet in vec![publish("topic", 1), publish("", 1)] {
+            match roundtrip(&mut client, &mut server, packet) {
+                super::Packet::Publish(publish) => {
+                    assert_eq!(publish.topic_name, "topic");
+                    assert_eq!(publish.properties.topic_alias, None);
+                }
+                packet => panic!("unexpected packet {:?}", packet),
+            }
+        }
+
+        let mut bytes = bytes::BytesMut::new();
+        tokio_util::codec::Encoder::encode(&mut client, publish("", 2), &mut bytes).unwrap();
+        let err = tokio_util::codec::Decoder::decode(&mut server, &mut bytes).unwrap_err();
+        if let super::DecodeError::UnrecognizedTopicAlias(2) = err {
+        } else {
+            panic!("{:?}", err);
+        }
+
+        for topic_alias in vec![0, 3] {
+            let mut bytes = bytes::BytesMut::new();
+            tokio_util::codec::Encoder::encode(
+                &mut client,
+                publish("topic", topic_alias),
+                &mut bytes,
+            )
+            .unwrap();
+            let err = tokio_util::codec::Decoder::decode(&mut server, &mut bytes).unwrap_err();
+            match err {
+                super::DecodeError::TopicAliasOutOfRange(alias) if alias == topic_alias => (),
+                err => panic!("{:?}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn v5_unsuback_has_reason_code_per_topic_filter() {
+        let (mut client, mut server) = v5_codecs();
+
+        let packet_identifier = super::PacketIdentifier::new(1).unwrap();
+        let unsubscribe = super::Packet::Unsubscribe(super::Unsubscribe {
+            packet_identifier,
+            unsubscribe_from: vec!["topic/1".to_owned(), "topic/2".to_owned()],
+        });
+        roundtrip(&mut client, &mut server, unsubscribe);
+
+        let mut bytes = bytes::BytesMut::new();
+        let unsuback = super::Packet::UnsubAck(super::UnsubAck { packet_identifier });
+        tokio_util::codec::Encoder::encode(&mut server, unsuback, &mut bytes).unwrap();
+        assert_eq!(&*bytes, &[0xB0, 0x05, 0x00, 0x01, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn max_packet_size_is_enforced() {
+        let publish = |payload: &'static [u8]| {
+            super::Packet::Publish(super::Publish {
+                packet_identifier_dup_qos: super::PacketIdentifierDupQoS::AtMostOnce,
+                retain: false,
+                topic_name: "topic".to_owned(),
+                payload: bytes::Bytes::from_static(payload),
+                properties: Default::default(),
+            })
+        };
+
+        // 2 bytes of fixed header, 7 bytes of topic name and the payload
+        let mut client = super::PacketCodec::default();
+        let mut server = super::PacketCodec::default().with_max_packet_size(16);
+        roundtrip(&mut client, &mut server, publish(b"1234567"));
+
+        let mut bytes = bytes::BytesMut::new();
+        tokio_util::codec::Encoder::encode(&mut client, publish(b"12345678"), &mut bytes).unwrap();
+        bytes.truncate(2);
+        let err = tokio_util::codec::Decoder::decode(&mut server, &mut bytes).unwrap_err();
+        if let super::DecodeError::PacketTooLarge(17) = err {
+        } else {
+            panic!("{:?}", err);
+        }
+    }
+
+    fn v5_connect(properties: super::Properties) -> super::Packet {
+        super::Packet::Connect(super::Connect {
+            username: None,
+            password: None,
+            will: None,
+            client_id: super::ClientId::ServerGenerated,
+            keep_alive: std::time::Duration::from_secs(5),
+            protocol_name: crate::PROTOCOL_NAME.to_owned(),
+            protocol_level: crate::PROTOCOL_LEVEL_V5,
+            properties,
+        })
+    }
+
+    fn v5_codecs() -> (super::PacketCodec, super::PacketCodec) {
+        let mut client = super::PacketCodec::default();
+        let mut server = super::PacketCodec::default().with_topic_alias_maximum(2);
+        roundtrip(&mut client, &mut server, v5_connect(Default::default()));
+        (client, server)
+    }
+
+    fn roundtrip(
+        encoder: &mut super::PacketCodec,
+        decoder: &mut super::PacketCodec,
+        packet: super::Packet,
+    ) -> super::Packet {
+        let mut bytes = bytes::BytesMut::new();
+        tokio_util::codec::Encoder::encode(encoder, packet, &mut bytes).unwrap();
+        let packet = tokio_util::codec::Decoder::decode(decoder, &mut bytes)
+            .unwrap()
+            .unwrap();
+        assert!(bytes.is_empty());
+        packet
+    }
 }
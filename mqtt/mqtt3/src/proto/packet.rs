@@ -1,11 +1,11 @@
-use std::{convert::TryInto, time::Duration};
+use std::{collections::HashMap, convert::TryInto, time::Duration};
 
 use bytes::{Buf, BufMut};
 #[cfg(feature = "serde1")]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tokio_util::codec::Decoder;
 
-use crate::proto::{BufMutExt, ByteBuf};
+use crate::proto::{BufMutExt, ByteBuf, Properties};
 
 /// An MQTT packet
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -58,29 +58,49 @@ pub(crate) trait PacketMeta: Sized {
     /// The packet type for this kind of packet
     const PACKET_TYPE: u8;
 
-    /// Decodes this packet from the given buffer
-    fn decode(flags: u8, src: bytes::BytesMut) -> Result<Self, super::DecodeError>;
+    /// Decodes this packet from the given buffer, using the format of the given protocol level
+    fn decode(
+        flags: u8,
+        src: bytes::BytesMut,
+        protocol_level: u8,
+    ) -> Result<Self, super::DecodeError>;
 
-    /// Encodes the variable header and payload corresponding to this packet into the given buffer.
+    /// Encodes the variable header and payload corresponding to this packet into the given buffer,
+    /// using the format of the given protocol level.
     /// The buffer is expected to already have the packet type and body length encoded into it,
     /// and to have reserved enough space to put the bytes of this packet directly into the buffer.
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, protocol_level: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf;
 }
 
+fn is_v5(protocol_level: u8) -> bool {
+    protocol_level == crate::PROTOCOL_LEVEL_V5
+}
+
 /// Ref: 3.2 CONNACK – Acknowledge connection request
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ConnAck {
     pub session_present: bool,
     pub return_code: super::ConnectReturnCode,
+    pub properties: Properties,
 }
 
 impl PacketMeta for ConnAck {
     const PACKET_TYPE: u8 = 0x20;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 0 || src.len() != (std::mem::size_of::<u8>() + std::mem::size_of::<u8>()) {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        protocol_level: u8,
+    ) -> Result<Self, super::DecodeError> {
+        let min_len = std::mem::size_of::<u8>() + std::mem::size_of::<u8>();
+        let valid_len = if is_v5(protocol_level) {
+            src.len() >= min_len
+        } else {
+            src.len() == min_len
+        };
+        if flags != 0 || !valid_len {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
                 flags,
@@ -97,21 +117,33 @@ impl PacketMeta for ConnAck {
             }
         };
 
-        let return_code: super::ConnectReturnCode = src.get_u8().into();
+        let (return_code, properties) = if is_v5(protocol_level) {
+            let return_code = super::ConnectReturnCode::from_reason_code(src.get_u8());
+            let properties = if src.is_empty() {
+                Properties::default()
+            } else {
+                Properties::decode(&mut src)?
+            };
+            (return_code, properties)
+        } else {
+            (src.get_u8().into(), Properties::default())
+        };
 
         Ok(ConnAck {
             session_present,
             return_code,
+            properties,
         })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, protocol_level: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
         let ConnAck {
             session_present,
             return_code,
+            properties,
         } = self;
         if *session_present {
             dst.put_u8_bytes(0x01);
@@ -119,7 +151,12 @@ impl PacketMeta for ConnAck {
             dst.put_u8_bytes(0x00);
         }
 
-        dst.put_u8_bytes((*return_code).into());
+        if is_v5(protocol_level) {
+            dst.put_u8_bytes(return_code.into_reason_code());
+            properties.encode(dst)?;
+        } else {
+            dst.put_u8_bytes((*return_code).into());
+        }
 
         Ok(())
     }
@@ -135,6 +172,7 @@ pub struct Connect {
     pub keep_alive: Duration,
     pub protocol_name: String,
     pub protocol_level: u8,
+    pub properties: Properties,
 }
 
 impl std::fmt::Debug for Connect {
@@ -146,6 +184,7 @@ impl std::fmt::Debug for Connect {
             .field("keep_alive", &self.keep_alive)
             .field("protocol_name", &self.protocol_name)
             .field("protocol_level", &self.protocol_level)
+            .field("properties", &self.properties)
             .finish()
     }
 }
@@ -153,7 +192,8 @@ impl std::fmt::Debug for Connect {
 impl PacketMeta for Connect {
     const PACKET_TYPE: u8 = 0x10;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    // The protocol level of a CONNECT packet is read from the packet itself
+    fn decode(flags: u8, mut src: bytes::BytesMut, _: u8) -> Result<Self, super::DecodeError> {
         if flags != 0 {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
@@ -178,11 +218,18 @@ impl PacketMeta for Connect {
 
         let keep_alive = Duration::from_secs(u64::from(src.try_get_u16_be()?));
 
+        let properties = if is_v5(protocol_level) {
+            Properties::decode(&mut src)?
+        } else {
+            Properties::default()
+        };
+
         let client_id = super::Utf8StringDecoder::default()
             .decode(&mut src)?
             .ok_or(super::DecodeError::IncompletePacket)?;
         let client_id = if client_id == "" {
-            if connect_flags & 0x02 == 0 {
+            // [MQTT-3.1.3-8] MQTT 5 allows a zero length client id without clean start
+            if connect_flags & 0x02 == 0 && !is_v5(protocol_level) {
                 return Err(super::DecodeError::ConnectZeroLengthIdWithExistingSession);
             }
             super::ClientId::ServerGenerated
@@ -195,6 +242,12 @@ impl PacketMeta for Connect {
         let will = if connect_flags & 0x04 == 0 {
             None
         } else {
+            let properties = if is_v5(protocol_level) {
+                Properties::decode(&mut src)?
+            } else {
+                Properties::default()
+            };
+
             let topic_name = super::Utf8StringDecoder::default()
                 .decode(&mut src)?
                 .ok_or(super::DecodeError::IncompletePacket)?;
@@ -219,6 +272,7 @@ impl PacketMeta for Connect {
                 qos,
                 retain,
                 payload,
+                properties,
             })
         };
 
@@ -250,10 +304,12 @@ impl PacketMeta for Connect {
             keep_alive,
             protocol_name,
             protocol_level,
+            properties,
         })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    // The protocol level of a CONNECT packet is written from the packet itself
+    fn encode<B>(&self, dst: &mut B, _: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -265,6 +321,7 @@ impl PacketMeta for Connect {
             keep_alive,
             protocol_name,
             protocol_level,
+            properties,
         } = self;
 
         super::encode_utf8_str(protocol_name, dst)?;
@@ -306,6 +363,10 @@ impl PacketMeta for Connect {
                 .map_err(|_| super::EncodeError::KeepAliveTooHigh(*keep_alive))?,
         );
 
+        if is_v5(*protocol_level) {
+            properties.encode(dst)?;
+        }
+
         match client_id {
             super::ClientId::ServerGenerated => super::encode_utf8_str("", dst)?,
             super::ClientId::IdWithCleanSession(id)
@@ -313,6 +374,10 @@ impl PacketMeta for Connect {
         }
 
         if let Some(will) = will {
+            if is_v5(*protocol_level) {
+                will.properties.encode(dst)?;
+            }
+
             super::encode_utf8_str(&will.topic_name, dst)?;
 
             let will_len = will.payload.len();
@@ -344,8 +409,13 @@ pub struct Disconnect;
 impl PacketMeta for Disconnect {
     const PACKET_TYPE: u8 = 0xE0;
 
-    fn decode(flags: u8, src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 0 || !src.is_empty() {
+    fn decode(
+        flags: u8,
+        src: bytes::BytesMut,
+        protocol_level: u8,
+    ) -> Result<Self, super::DecodeError> {
+        // MQTT 5 adds an optional reason code and properties, which are ignored
+        if flags != 0 || (!src.is_empty() && !is_v5(protocol_level)) {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
                 flags,
@@ -356,7 +426,7 @@ impl PacketMeta for Disconnect {
         Ok(Disconnect)
     }
 
-    fn encode<B>(&self, _: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, _: &mut B, _: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -371,7 +441,7 @@ pub struct PingReq;
 impl PacketMeta for PingReq {
     const PACKET_TYPE: u8 = 0xC0;
 
-    fn decode(flags: u8, src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(flags: u8, src: bytes::BytesMut, _: u8) -> Result<Self, super::DecodeError> {
         if flags != 0 || !src.is_empty() {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
@@ -383,7 +453,7 @@ impl PacketMeta for PingReq {
         Ok(PingReq)
     }
 
-    fn encode<B>(&self, _: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, _: &mut B, _: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -398,7 +468,7 @@ pub struct PingResp;
 impl PacketMeta for PingResp {
     const PACKET_TYPE: u8 = 0xD0;
 
-    fn decode(flags: u8, src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(flags: u8, src: bytes::BytesMut, _: u8) -> Result<Self, super::DecodeError> {
         if flags != 0 || !src.is_empty() {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
@@ -410,7 +480,7 @@ impl PacketMeta for PingResp {
         Ok(PingResp)
     }
 
-    fn encode<B>(&self, _: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, _: &mut B, _: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -427,8 +497,12 @@ pub struct PubAck {
 impl PacketMeta for PubAck {
     const PACKET_TYPE: u8 = 0x40;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 0 || src.len() != std::mem::size_of::<u16>() {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        protocol_level: u8,
+    ) -> Result<Self, super::DecodeError> {
+        if flags != 0 || !is_valid_ack_len(src.len(), protocol_level) {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
                 flags,
@@ -441,7 +515,7 @@ impl PacketMeta for PubAck {
         Ok(PubAck { packet_identifier })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, _: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -461,8 +535,12 @@ pub struct PubComp {
 impl PacketMeta for PubComp {
     const PACKET_TYPE: u8 = 0x70;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 0 || src.len() != std::mem::size_of::<u16>() {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        protocol_level: u8,
+    ) -> Result<Self, super::DecodeError> {
+        if flags != 0 || !is_valid_ack_len(src.len(), protocol_level) {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
                 flags,
@@ -475,7 +553,7 @@ impl PacketMeta for PubComp {
         Ok(PubComp { packet_identifier })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, _: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -495,12 +573,17 @@ pub struct Publish {
     #[cfg_attr(feature = "serde1", serde(serialize_with = "serialize_bytes"))]
     #[cfg_attr(feature = "serde1", serde(deserialize_with = "deserialize_bytes"))]
     pub payload: bytes::Bytes,
+    pub properties: Properties,
 }
 
 impl PacketMeta for Publish {
     const PACKET_TYPE: u8 = 0x30;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        protocol_level: u8,
+    ) -> Result<Self, super::DecodeError> {
         let dup = (flags & 0x08) != 0;
         let retain = (flags & 0x01) != 0;
 
@@ -526,6 +609,12 @@ impl PacketMeta for Publish {
             qos => return Err(super::DecodeError::UnrecognizedQoS(qos)),
         };
 
+        let properties = if is_v5(protocol_level) {
+            Properties::decode(&mut src)?
+        } else {
+            Properties::default()
+        };
+
         let payload = src.freeze();
 
         Ok(Publish {
@@ -533,10 +622,11 @@ impl PacketMeta for Publish {
             retain,
             topic_name,
             payload,
+            properties,
         })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, protocol_level: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -546,6 +636,7 @@ impl PacketMeta for Publish {
             retain: _,
             topic_name,
             payload,
+            properties,
         } = self;
 
         super::encode_utf8_str(topic_name, dst)?;
@@ -558,6 +649,10 @@ impl PacketMeta for Publish {
             }
         }
 
+        if is_v5(protocol_level) {
+            properties.encode(dst)?;
+        }
+
         dst.put_slice_bytes(&payload);
 
         Ok(())
@@ -574,8 +669,12 @@ pub struct PubRec {
 impl PacketMeta for PubRec {
     const PACKET_TYPE: u8 = 0x50;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 0 || src.len() != std::mem::size_of::<u16>() {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        protocol_level: u8,
+    ) -> Result<Self, super::DecodeError> {
+        if flags != 0 || !is_valid_ack_len(src.len(), protocol_level) {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
                 flags,
@@ -588,7 +687,7 @@ impl PacketMeta for PubRec {
         Ok(PubRec { packet_identifier })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, _: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -608,8 +707,12 @@ pub struct PubRel {
 impl PacketMeta for PubRel {
     const PACKET_TYPE: u8 = 0x60;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 2 || src.len() != std::mem::size_of::<u16>() {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        protocol_level: u8,
+    ) -> Result<Self, super::DecodeError> {
+        if flags != 2 || !is_valid_ack_len(src.len(), protocol_level) {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
                 flags,
@@ -622,7 +725,7 @@ impl PacketMeta for PubRel {
         Ok(PubRel { packet_identifier })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, _: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -642,7 +745,11 @@ pub struct SubAck {
 impl PacketMeta for SubAck {
     const PACKET_TYPE: u8 = 0x90;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        protocol_level: u8,
+    ) -> Result<Self, super::DecodeError> {
         if flags != 0 || src.len() < std::mem::size_of::<u16>() {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
@@ -653,6 +760,10 @@ impl PacketMeta for SubAck {
 
         let packet_identifier = src.get_packet_identifier()?;
 
+        if is_v5(protocol_level) {
+            let _ = Properties::decode(&mut src)?;
+        }
+
         let qos: Result<Vec<_>, _> = src
             .iter()
             .map(|&qos| match qos {
@@ -660,6 +771,8 @@ impl PacketMeta for SubAck {
                 0x01 => Ok(SubAckQos::Success(QoS::AtLeastOnce)),
                 0x02 => Ok(SubAckQos::Success(QoS::ExactlyOnce)),
                 0x80 => Ok(SubAckQos::Failure),
+                // MQTT 5 reason codes 0x80 and above are all failures
+                qos if qos > 0x80 && is_v5(protocol_level) => Ok(SubAckQos::Failure),
                 qos => Err(super::DecodeError::UnrecognizedQoS(qos)),
             })
             .collect();
@@ -675,7 +788,7 @@ impl PacketMeta for SubAck {
         })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, protocol_level: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -686,6 +799,10 @@ impl PacketMeta for SubAck {
 
         dst.put_packet_identifier_bytes(*packet_identifier);
 
+        if is_v5(protocol_level) {
+            Properties::default().encode(dst)?;
+        }
+
         for &qos in qos {
             dst.put_u8_bytes(qos.into());
         }
@@ -704,7 +821,11 @@ pub struct Subscribe {
 impl PacketMeta for Subscribe {
     const PACKET_TYPE: u8 = 0x80;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        protocol_level: u8,
+    ) -> Result<Self, super::DecodeError> {
         if flags != 2 || src.len() < std::mem::size_of::<u16>() {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
@@ -715,13 +836,30 @@ impl PacketMeta for Subscribe {
 
         let packet_identifier = src.get_packet_identifier()?;
 
+        if is_v5(protocol_level) {
+            let _ = Properties::decode(&mut src)?;
+        }
+
         let mut subscribe_to = vec![];
 
         while !src.is_empty() {
             let topic_filter = super::Utf8StringDecoder::default()
                 .decode(&mut src)?
                 .ok_or(super::DecodeError::IncompletePacket)?;
-            let qos = match src.try_get_u8()? {
+
+            // MQTT 5 subscription options also carry the no local, retain as published
+            // and retain handling flags in the upper bits, which are ignored
+            let options = src.try_get_u8()?;
+            let options = if is_v5(protocol_level) {
+                if options & 0xC0 != 0 {
+                    return Err(super::DecodeError::UnrecognizedSubscriptionOptions(options));
+                }
+                options & 0x03
+            } else {
+                options
+            };
+
+            let qos = match options {
                 0x00 => QoS::AtMostOnce,
                 0x01 => QoS::AtLeastOnce,
                 0x02 => QoS::ExactlyOnce,
@@ -740,7 +878,7 @@ impl PacketMeta for Subscribe {
         })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, protocol_level: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -751,6 +889,10 @@ impl PacketMeta for Subscribe {
 
         dst.put_packet_identifier_bytes(*packet_identifier);
 
+        if is_v5(protocol_level) {
+            Properties::default().encode(dst)?;
+        }
+
         for SubscribeTo { topic_filter, qos } in subscribe_to {
             super::encode_utf8_str(topic_filter, dst)?;
             dst.put_u8_bytes((*qos).into());
@@ -769,8 +911,13 @@ pub struct UnsubAck {
 impl PacketMeta for UnsubAck {
     const PACKET_TYPE: u8 = 0xB0;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 0 || src.len() != std::mem::size_of::<u16>() {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        protocol_level: u8,
+    ) -> Result<Self, super::DecodeError> {
+        // MQTT 5 adds properties and a reason code per topic filter, which are ignored
+        if flags != 0 || !is_valid_ack_len(src.len(), protocol_level) {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
                 flags,
@@ -783,7 +930,7 @@ impl PacketMeta for UnsubAck {
         Ok(UnsubAck { packet_identifier })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, _: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -803,7 +950,11 @@ pub struct Unsubscribe {
 impl PacketMeta for Unsubscribe {
     const PACKET_TYPE: u8 = 0xA0;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        protocol_level: u8,
+    ) -> Result<Self, super::DecodeError> {
         if flags != 2 || src.len() < std::mem::size_of::<u16>() {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
@@ -814,6 +965,10 @@ impl PacketMeta for Unsubscribe {
 
         let packet_identifier = src.get_packet_identifier()?;
 
+        if is_v5(protocol_level) {
+            let _ = Properties::decode(&mut src)?;
+        }
+
         let mut unsubscribe_from = vec![];
 
         while !src.is_empty() {
@@ -834,7 +989,7 @@ impl PacketMeta for Unsubscribe {
         })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, protocol_level: u8) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -845,6 +1000,10 @@ impl PacketMeta for Unsubscribe {
 
         dst.put_packet_identifier_bytes(*packet_identifier);
 
+        if is_v5(protocol_level) {
+            Properties::default().encode(dst)?;
+        }
+
         for unsubscribe_from in unsubscribe_from {
             super::encode_utf8_str(unsubscribe_from, dst)?;
         }
@@ -864,6 +1023,15 @@ pub enum PacketIdentifierDupQoS {
     ExactlyOnce(super::PacketIdentifier, bool),
 }
 
+/// MQTT 5 acknowledgements may be followed by a reason code and properties.
+fn is_valid_ack_len(len: usize, protocol_level: u8) -> bool {
+    if is_v5(protocol_level) {
+        len >= std::mem::size_of::<u16>()
+    } else {
+        len == std::mem::size_of::<u16>()
+    }
+}
+
 /// A subscription request.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct SubscribeTo {
@@ -919,14 +1087,84 @@ pub struct Publication {
     #[cfg_attr(feature = "serde1", serde(serialize_with = "serialize_bytes"))]
     #[cfg_attr(feature = "serde1", serde(deserialize_with = "deserialize_bytes"))]
     pub payload: bytes::Bytes,
+    pub properties: Properties,
 }
 
 /// A tokio codec that encodes and decodes MQTT packets.
 ///
+/// The codec starts out speaking MQTT 3.1.1 and switches to the protocol level
+/// of the first CONNECT packet it decodes or encodes.
+///
 /// Ref: 2 MQTT Control Packet format
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct PacketCodec {
     decoder_state: PacketDecoderState,
+    protocol_level: u8,
+    max_packet_size: Option<usize>,
+    topic_alias_maximum: u16,
+    topic_aliases: HashMap<u16, String>,
+    pending_unsubscribes: HashMap<super::PacketIdentifier, usize>,
+}
+
+impl PacketCodec {
+    /// Limits the size of decoded packets, including the fixed header.
+    ///
+    /// Decoding fails with [`DecodeError::PacketTooLarge`] as soon as the fixed header
+    /// of a larger packet is read, without buffering the rest of the packet.
+    ///
+    /// [`DecodeError::PacketTooLarge`]: enum.DecodeError.html#variant.PacketTooLarge
+    pub fn with_max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = Some(max_packet_size);
+        self
+    }
+
+    /// Sets the highest topic alias accepted in decoded PUBLISH packets.
+    ///
+    /// This should match the topic alias maximum advertised to the peer. The default is `0`,
+    /// which means that topic aliases are not accepted.
+    pub fn with_topic_alias_maximum(mut self, topic_alias_maximum: u16) -> Self {
+        self.topic_alias_maximum = topic_alias_maximum;
+        self
+    }
+
+    /// The protocol level negotiated on this connection
+    pub fn protocol_level(&self) -> u8 {
+        self.protocol_level
+    }
+
+    fn resolve_topic_alias(&mut self, publish: &mut Publish) -> Result<(), super::DecodeError> {
+        if let Some(topic_alias) = publish.properties.topic_alias.take() {
+            if topic_alias == 0 || topic_alias > self.topic_alias_maximum {
+                return Err(super::DecodeError::TopicAliasOutOfRange(topic_alias));
+            }
+
+            if publish.topic_name.is_empty() {
+                publish.topic_name = self
+                    .topic_aliases
+                    .get(&topic_alias)
+                    .ok_or(super::DecodeError::UnrecognizedTopicAlias(topic_alias))?
+                    .clone();
+            } else {
+                self.topic_aliases
+                    .insert(topic_alias, publish.topic_name.clone());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for PacketCodec {
+    fn default() -> Self {
+        PacketCodec {
+            decoder_state: PacketDecoderState::default(),
+            protocol_level: crate::PROTOCOL_LEVEL,
+            max_packet_size: None,
+            topic_alias_maximum: 0,
+            topic_aliases: HashMap::new(),
+            pending_unsubscribes: HashMap::new(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -948,6 +1186,17 @@ impl Default for PacketDecoderState {
     }
 }
 
+/// Size of a packet including the first byte and the encoded remaining length.
+fn packet_size(remaining_length: usize) -> usize {
+    let remaining_length_size = match remaining_length {
+        0..=0x7F => 1,
+        0x80..=0x3FFF => 2,
+        0x4000..=0x001F_FFFF => 3,
+        _ => 4,
+    };
+    1 + remaining_length_size + remaining_length
+}
+
 impl tokio_util::codec::Decoder for PacketCodec {
     type Item = Packet;
     type Error = super::DecodeError;
@@ -971,6 +1220,11 @@ impl tokio_util::codec::Decoder for PacketCodec {
                     remaining_length,
                 } => match remaining_length.decode(src)? {
                     Some(remaining_length) => {
+                        let packet_size = packet_size(remaining_length);
+                        if self.max_packet_size.map_or(false, |max| packet_size > max) {
+                            return Err(super::DecodeError::PacketTooLarge(packet_size));
+                        }
+
                         self.decoder_state = PacketDecoderState::HaveFixedHeader {
                             first_byte: *first_byte,
                             remaining_length,
@@ -997,24 +1251,48 @@ impl tokio_util::codec::Decoder for PacketCodec {
 
         let packet_type = first_byte & 0xF0;
         let flags = first_byte & 0x0F;
+        let level = self.protocol_level;
         match packet_type {
-            ConnAck::PACKET_TYPE => Ok(Some(Packet::ConnAck(ConnAck::decode(flags, src)?))),
-            Connect::PACKET_TYPE => Ok(Some(Packet::Connect(Connect::decode(flags, src)?))),
-            Disconnect::PACKET_TYPE => {
-                Ok(Some(Packet::Disconnect(Disconnect::decode(flags, src)?)))
+            ConnAck::PACKET_TYPE => Ok(Some(Packet::ConnAck(ConnAck::decode(flags, src, level)?))),
+            Connect::PACKET_TYPE => {
+                let connect = Connect::decode(flags, src, level)?;
+                self.protocol_level = connect.protocol_level;
+                Ok(Some(Packet::Connect(connect)))
+            }
+            Disconnect::PACKET_TYPE => Ok(Some(Packet::Disconnect(Disconnect::decode(
+                flags, src, level,
+            )?))),
+            PingReq::PACKET_TYPE => Ok(Some(Packet::PingReq(PingReq::decode(flags, src, level)?))),
+            PingResp::PACKET_TYPE => {
+                Ok(Some(Packet::PingResp(PingResp::decode(flags, src, level)?)))
+            }
+            PubAck::PACKET_TYPE => Ok(Some(Packet::PubAck(PubAck::decode(flags, src, level)?))),
+            PubComp::PACKET_TYPE => Ok(Some(Packet::PubComp(PubComp::decode(flags, src, level)?))),
+            Publish::PACKET_TYPE => {
+                let mut publish = Publish::decode(flags, src, level)?;
+                if is_v5(level) {
+                    self.resolve_topic_alias(&mut publish)?;
+                }
+                Ok(Some(Packet::Publish(publish)))
+            }
+            PubRec::PACKET_TYPE => Ok(Some(Packet::PubRec(PubRec::decode(flags, src, level)?))),
+            PubRel::PACKET_TYPE => Ok(Some(Packet::PubRel(PubRel::decode(flags, src, level)?))),
+            SubAck::PACKET_TYPE => Ok(Some(Packet::SubAck(SubAck::decode(flags, src, level)?))),
+            Subscribe::PACKET_TYPE => Ok(Some(Packet::Subscribe(Subscribe::decode(
+                flags, src, level,
+            )?))),
+            UnsubAck::PACKET_TYPE => {
+                Ok(Some(Packet::UnsubAck(UnsubAck::decode(flags, src, level)?)))
             }
-            PingReq::PACKET_TYPE => Ok(Some(Packet::PingReq(PingReq::decode(flags, src)?))),
-            PingResp::PACKET_TYPE => Ok(Some(Packet::PingResp(PingResp::decode(flags, src)?))),
-            PubAck::PACKET_TYPE => Ok(Some(Packet::PubAck(PubAck::decode(flags, src)?))),
-            PubComp::PACKET_TYPE => Ok(Some(Packet::PubComp(PubComp::decode(flags, src)?))),
-            Publish::PACKET_TYPE => Ok(Some(Packet::Publish(Publish::decode(flags, src)?))),
-            PubRec::PACKET_TYPE => Ok(Some(Packet::PubRec(PubRec::decode(flags, src)?))),
-            PubRel::PACKET_TYPE => Ok(Some(Packet::PubRel(PubRel::decode(flags, src)?))),
-            SubAck::PACKET_TYPE => Ok(Some(Packet::SubAck(SubAck::decode(flags, src)?))),
-            Subscribe::PACKET_TYPE => Ok(Some(Packet::Subscribe(Subscribe::decode(flags, src)?))),
-            UnsubAck::PACKET_TYPE => Ok(Some(Packet::UnsubAck(UnsubAck::decode(flags, src)?))),
             Unsubscribe::PACKET_TYPE => {
-                Ok(Some(Packet::Unsubscribe(Unsubscribe::decode(flags, src)?)))
+                let unsubscribe = Unsubscribe::decode(flags, src, level)?;
+                if is_v5(level) {
+                    self.pending_unsubscribes.insert(
+                        unsubscribe.packet_identifier,
+                        unsubscribe.unsubscribe_from.len(),
+                    );
+                }
+                Ok(Some(Packet::Unsubscribe(unsubscribe)))
             }
             packet_type => Err(super::DecodeError::UnrecognizedPacket {
                 packet_type,
@@ -1032,14 +1310,19 @@ impl tokio_util::codec::Encoder for PacketCodec {
     fn encode(&mut self, item: Self::Item, dst: &mut bytes::BytesMut) -> Result<(), Self::Error> {
         dst.reserve(std::mem::size_of::<u8>() + 4 * std::mem::size_of::<u8>());
 
+        if let Packet::Connect(packet) = &item {
+            self.protocol_level = packet.protocol_level;
+        }
+
+        let level = self.protocol_level;
         match &item {
-            Packet::ConnAck(packet) => encode_packet(packet, 0, dst),
-            Packet::Connect(packet) => encode_packet(packet, 0, dst),
-            Packet::Disconnect(packet) => encode_packet(packet, 0, dst),
-            Packet::PingReq(packet) => encode_packet(packet, 0, dst),
-            Packet::PingResp(packet) => encode_packet(packet, 0, dst),
-            Packet::PubAck(packet) => encode_packet(packet, 0, dst),
-            Packet::PubComp(packet) => encode_packet(packet, 0, dst),
+            Packet::ConnAck(packet) => encode_packet(packet, 0, level, dst),
+            Packet::Connect(packet) => encode_packet(packet, 0, level, dst),
+            Packet::Disconnect(packet) => encode_packet(packet, 0, level, dst),
+            Packet::PingReq(packet) => encode_packet(packet, 0, level, dst),
+            Packet::PingResp(packet) => encode_packet(packet, 0, level, dst),
+            Packet::PubAck(packet) => encode_packet(packet, 0, level, dst),
+            Packet::PubComp(packet) => encode_packet(packet, 0, level, dst),
             Packet::Publish(packet) => {
                 let mut flags = match packet.packet_identifier_dup_qos {
                     PacketIdentifierDupQoS::AtMostOnce => 0x00,
@@ -1051,14 +1334,22 @@ impl tokio_util::codec::Encoder for PacketCodec {
                 if packet.retain {
                     flags |= 0x01;
                 };
-                encode_packet(packet, flags, dst)
+                encode_packet(packet, flags, level, dst)
+            }
+            Packet::PubRec(packet) => encode_packet(packet, 0, level, dst),
+            Packet::PubRel(packet) => encode_packet(packet, 0x02, level, dst),
+            Packet::SubAck(packet) => encode_packet(packet, 0, level, dst),
+            Packet::Subscribe(packet) => encode_packet(packet, 0x02, level, dst),
+            Packet::UnsubAck(packet) if is_v5(level) => {
+                // MQTT 5 requires a reason code for each topic filter of the UNSUBSCRIBE
+                let reason_codes = self
+                    .pending_unsubscribes
+                    .remove(&packet.packet_identifier)
+                    .unwrap_or(1);
+                encode_unsuback_v5(packet, reason_codes, dst)
             }
-            Packet::PubRec(packet) => encode_packet(packet, 0, dst),
-            Packet::PubRel(packet) => encode_packet(packet, 0x02, dst),
-            Packet::SubAck(packet) => encode_packet(packet, 0, dst),
-            Packet::Subscribe(packet) => encode_packet(packet, 0x02, dst),
-            Packet::UnsubAck(packet) => encode_packet(packet, 0, dst),
-            Packet::Unsubscribe(packet) => encode_packet(packet, 0x02, dst),
+            Packet::UnsubAck(packet) => encode_packet(packet, 0, level, dst),
+            Packet::Unsubscribe(packet) => encode_packet(packet, 0x02, level, dst),
         }
     }
 }
@@ -1066,13 +1357,14 @@ impl tokio_util::codec::Encoder for PacketCodec {
 fn encode_packet<P>(
     packet: &P,
     flags: u8,
+    protocol_level: u8,
     dst: &mut bytes::BytesMut,
 ) -> Result<(), super::EncodeError>
 where
     P: PacketMeta,
 {
     let mut counter = super::ByteCounter::new();
-    packet.encode(&mut counter)?;
+    packet.encode(&mut counter, protocol_level)?;
     let body_len = counter.0;
 
     dst.reserve(
@@ -1083,7 +1375,33 @@ where
 
     dst.put_u8(<P as PacketMeta>::PACKET_TYPE | flags);
     super::encode_remaining_length(body_len, dst)?;
-    packet.encode(dst)?;
+    packet.encode(dst, protocol_level)?;
+
+    Ok(())
+}
+
+fn encode_unsuback_v5(
+    packet: &UnsubAck,
+    reason_codes: usize,
+    dst: &mut bytes::BytesMut,
+) -> Result<(), super::EncodeError> {
+    let body_len = std::mem::size_of::<u16>() + // packet identifier
+        std::mem::size_of::<u8>() + // empty properties
+        reason_codes;
+
+    dst.reserve(
+        std::mem::size_of::<u8>() + // packet type
+            4 * std::mem::size_of::<u8>() + // remaining length
+            body_len,
+    );
+
+    dst.put_u8(UnsubAck::PACKET_TYPE);
+    super::encode_remaining_length(body_len, dst)?;
+    dst.put_packet_identifier_bytes(packet.packet_identifier);
+    Properties::default().encode(dst)?;
+    for _ in 0..reason_codes {
+        dst.put_u8(0x00);
+    }
 
     Ok(())
 }
@@ -0,0 +1,409 @@
+use std::convert::TryInto;
+
+#[cfg(feature = "serde1")]
+use serde::{Deserialize, Serialize};
+use tokio_util::codec::Decoder;
+
+use crate::proto::{BufMutExt, ByteBuf};
+
+const PAYLOAD_FORMAT_INDICATOR: u8 = 0x01;
+const MESSAGE_EXPIRY_INTERVAL: u8 = 0x02;
+const CONTENT_TYPE: u8 = 0x03;
+const RESPONSE_TOPIC: u8 = 0x08;
+const CORRELATION_DATA: u8 = 0x09;
+const SUBSCRIPTION_IDENTIFIER: u8 = 0x0B;
+const SESSION_EXPIRY_INTERVAL: u8 = 0x11;
+const ASSIGNED_CLIENT_IDENTIFIER: u8 = 0x12;
+const SERVER_KEEP_ALIVE: u8 = 0x13;
+const AUTHENTICATION_METHOD: u8 = 0x15;
+const AUTHENTICATION_DATA: u8 = 0x16;
+const REQUEST_PROBLEM_INFORMATION: u8 = 0x17;
+const WILL_DELAY_INTERVAL: u8 = 0x18;
+const REQUEST_RESPONSE_INFORMATION: u8 = 0x19;
+const RESPONSE_INFORMATION: u8 = 0x1A;
+const SERVER_REFERENCE: u8 = 0x1C;
+const REASON_STRING: u8 = 0x1F;
+const RECEIVE_MAXIMUM: u8 = 0x21;
+const TOPIC_ALIAS_MAXIMUM: u8 = 0x22;
+const TOPIC_ALIAS: u8 = 0x23;
+const MAXIMUM_QOS: u8 = 0x24;
+const RETAIN_AVAILABLE: u8 = 0x25;
+const USER_PROPERTY: u8 = 0x26;
+const MAXIMUM_PACKET_SIZE: u8 = 0x27;
+const WILDCARD_SUBSCRIPTION_AVAILABLE: u8 = 0x28;
+const SUBSCRIPTION_IDENTIFIER_AVAILABLE: u8 = 0x29;
+const SHARED_SUBSCRIPTION_AVAILABLE: u8 = 0x2A;
+
+/// MQTT 5 properties.
+///
+/// Properties are only present on the wire for connections that negotiated MQTT 5.
+/// They are ignored when encoding packets for MQTT 3.1.1 connections, and are empty
+/// when decoding packets from MQTT 3.1.1 connections.
+///
+/// Ref: 2.2.2 Properties (MQTT 5.0)
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(Deserialize, Serialize))]
+pub struct Properties {
+    pub payload_format_indicator: Option<u8>,
+    pub message_expiry_interval: Option<u32>,
+    pub content_type: Option<String>,
+    pub response_topic: Option<String>,
+    pub correlation_data: Option<Vec<u8>>,
+    pub subscription_identifiers: Vec<usize>,
+    pub session_expiry_interval: Option<u32>,
+    pub assigned_client_identifier: Option<String>,
+    pub server_keep_alive: Option<u16>,
+    pub authentication_method: Option<String>,
+    pub authentication_data: Option<Vec<u8>>,
+    pub request_problem_information: Option<u8>,
+    pub will_delay_interval: Option<u32>,
+    pub request_response_information: Option<u8>,
+    pub response_information: Option<String>,
+    pub server_reference: Option<String>,
+    pub reason_string: Option<String>,
+    pub receive_maximum: Option<u16>,
+    pub topic_alias_maximum: Option<u16>,
+    pub topic_alias: Option<u16>,
+    pub maximum_qos: Option<u8>,
+    pub retain_available: Option<u8>,
+    pub user_properties: Vec<(String, String)>,
+    pub maximum_packet_size: Option<u32>,
+    pub wildcard_subscription_available: Option<u8>,
+    pub subscription_identifier_available: Option<u8>,
+    pub shared_subscription_available: Option<u8>,
+}
+
+impl Properties {
+    /// Decodes the properties, prefixed with their variable byte integer length, from the given buffer.
+    pub(crate) fn decode(src: &mut bytes::BytesMut) -> Result<Self, super::DecodeError> {
+        let len = super::RemainingLengthDecoder::default()
+            .decode(src)?
+            .ok_or(super::DecodeError::IncompletePacket)?;
+        if src.len() < len {
+            return Err(super::DecodeError::IncompletePacket);
+        }
+        let mut src = src.split_to(len);
+
+        let mut properties = Properties::default();
+
+        macro_rules! set_once {
+            ($field:ident, $identifier:expr, $value:expr) => {{
+                if properties.$field.is_some() {
+                    return Err(super::DecodeError::DuplicateProperty($identifier));
+                }
+                properties.$field = Some($value);
+            }};
+        }
+
+        while !src.is_empty() {
+            let identifier = src.try_get_u8()?;
+            match identifier {
+                PAYLOAD_FORMAT_INDICATOR => {
+                    set_once!(payload_format_indicator, identifier, src.try_get_u8()?)
+                }
+                MESSAGE_EXPIRY_INTERVAL => {
+                    set_once!(message_expiry_interval, identifier, src.try_get_u32_be()?)
+                }
+                CONTENT_TYPE => set_once!(content_type, identifier, decode_string(&mut src)?),
+                RESPONSE_TOPIC => set_once!(response_topic, identifier, decode_string(&mut src)?),
+                CORRELATION_DATA => {
+                    set_once!(correlation_data, identifier, decode_binary(&mut src)?)
+                }
+                SUBSCRIPTION_IDENTIFIER => {
+                    let subscription_identifier = super::RemainingLengthDecoder::default()
+                        .decode(&mut src)?
+                        .ok_or(super::DecodeError::IncompletePacket)?;
+                    properties
+                        .subscription_identifiers
+                        .push(subscription_identifier);
+                }
+                SESSION_EXPIRY_INTERVAL => {
+                    set_once!(session_expiry_interval, identifier, src.try_get_u32_be()?)
+                }
+                ASSIGNED_CLIENT_IDENTIFIER => set_once!(
+                    assigned_client_identifier,
+                    identifier,
+                    decode_string(&mut src)?
+                ),
+                SERVER_KEEP_ALIVE => {
+                    set_once!(server_keep_alive, identifier, src.try_get_u16_be()?)
+                }
+                AUTHENTICATION_METHOD => {
+                    set_once!(authentication_method, identifier, decode_string(&mut src)?)
+                }
+                AUTHENTICATION_DATA => {
+                    set_once!(authentication_data, identifier, decode_binary(&mut src)?)
+                }
+                REQUEST_PROBLEM_INFORMATION => {
+                    set_once!(request_problem_information, identifier, src.try_get_u8()?)
+                }
+                WILL_DELAY_INTERVAL => {
+                    set_once!(will_delay_interval, identifier, src.try_get_u32_be()?)
+                }
+                REQUEST_RESPONSE_INFORMATION => {
+                    set_once!(request_response_information, identifier, src.try_get_u8()?)
+                }
+                RESPONSE_INFORMATION => {
+                    set_once!(response_information, identifier, decode_string(&mut src)?)
+                }
+                SERVER_REFERENCE => {
+                    set_once!(server_reference, identifier, decode_string(&mut src)?)
+                }
+                REASON_STRING => set_once!(reason_string, identifier, decode_string(&mut src)?),
+                RECEIVE_MAXIMUM => set_once!(receive_maximum, identifier, src.try_get_u16_be()?),
+                TOPIC_ALIAS_MAXIMUM => {
+                    set_once!(topic_alias_maximum, identifier, src.try_get_u16_be()?)
+                }
+                TOPIC_ALIAS => set_once!(topic_alias, identifier, src.try_get_u16_be()?),
+                MAXIMUM_QOS => set_once!(maximum_qos, identifier, src.try_get_u8()?),
+                RETAIN_AVAILABLE => set_once!(retain_available, identifier, src.try_get_u8()?),
+                USER_PROPERTY => {
+                    let key = decode_string(&mut src)?;
+                    let value = decode_string(&mut src)?;
+                    properties.user_properties.push((key, value));
+                }
+                MAXIMUM_PACKET_SIZE => {
+                    set_once!(maximum_packet_size, identifier, src.try_get_u32_be()?)
+                }
+                WILDCARD_SUBSCRIPTION_AVAILABLE => set_once!(
+                    wildcard_subscription_available,
+                    identifier,
+                    src.try_get_u8()?
+                ),
+                SUBSCRIPTION_IDENTIFIER_AVAILABLE => set_once!(
+                    subscription_identifier_available,
+                    identifier,
+                    src.try_get_u8()?
+                ),
+                SHARED_SUBSCRIPTION_AVAILABLE => {
+                    set_once!(shared_subscription_available, identifier, src.try_get_u8()?)
+                }
+                identifier => return Err(super::DecodeError::UnrecognizedProperty(identifier)),
+            }
+        }
+
+        Ok(properties)
+    }
+
+    /// Encodes the properties, prefixed with their variable byte integer length, into the given buffer.
+    pub(crate) fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    where
+        B: ByteBuf,
+    {
+        let mut counter = super::ByteCounter::new();
+        self.encode_inner(&mut counter)?;
+        let len = counter.0;
+
+        // Properties length is a variable byte integer, encoded the same way as the remaining length
+        super::encode_remaining_length(len, dst)?;
+        self.encode_inner(dst)
+    }
+
+    fn encode_inner<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    where
+        B: ByteBuf,
+    {
+        if let Some(value) = self.payload_format_indicator {
+            dst.put_u8_bytes(PAYLOAD_FORMAT_INDICATOR);
+            dst.put_u8_bytes(value);
+        }
+        if let Some(value) = self.message_expiry_interval {
+            dst.put_u8_bytes(MESSAGE_EXPIRY_INTERVAL);
+            dst.put_u32_bytes(value);
+        }
+        if let Some(value) = &self.content_type {
+            dst.put_u8_bytes(CONTENT_TYPE);
+            super::encode_utf8_str(value, dst)?;
+        }
+        if let Some(value) = &self.response_topic {
+            dst.put_u8_bytes(RESPONSE_TOPIC);
+            super::encode_utf8_str(value, dst)?;
+        }
+        if let Some(value) = &self.correlation_data {
+            dst.put_u8_bytes(CORRELATION_DATA);
+            encode_binary(value, dst)?;
+        }
+        for &value in &self.subscription_identifiers {
+            dst.put_u8_bytes(SUBSCRIPTION_IDENTIFIER);
+            super::encode_remaining_length(value, dst)?;
+        }
+        if let Some(value) = self.session_expiry_interval {
+            dst.put_u8_bytes(SESSION_EXPIRY_INTERVAL);
+            dst.put_u32_bytes(value);
+        }
+        if let Some(value) = &self.assigned_client_identifier {
+            dst.put_u8_bytes(ASSIGNED_CLIENT_IDENTIFIER);
+            super::encode_utf8_str(value, dst)?;
+        }
+        if let Some(value) = self.server_keep_alive {
+            dst.put_u8_bytes(SERVER_KEEP_ALIVE);
+            dst.put_u16_bytes(value);
+        }
+        if let Some(value) = &self.authentication_method {
+            dst.put_u8_bytes(AUTHENTICATION_METHOD);
+            super::encode_utf8_str(value, dst)?;
+        }
+        if let Some(value) = &self.authentication_data {
+            dst.put_u8_bytes(AUTHENTICATION_DATA);
+            encode_binary(value, dst)?;
+        }
+        if let Some(value) = self.request_problem_information {
+            dst.put_u8_bytes(REQUEST_PROBLEM_INFORMATION);
+            dst.put_u8_bytes(value);
+        }
+        if let Some(value) = self.will_delay_interval {
+            dst.put_u8_bytes(WILL_DELAY_INTERVAL);
+            dst.put_u32_bytes(value);
+        }
+        if let Some(value) = self.request_response_information {
+            dst.put_u8_bytes(REQUEST_RESPONSE_INFORMATION);
+            dst.put_u8_bytes(value);
+        }
+        if let Some(value) = &self.response_information {
+            dst.put_u8_bytes(RESPONSE_INFORMATION);
+            super::encode_utf8_str(value, dst)?;
+        }
+        if let Some(value) = &self.server_reference {
+            dst.put_u8_bytes(SERVER_REFERENCE);
+            super::encode_utf8_str(value, dst)?;
+        }
+        if let Some(value) = &self.reason_string {
+            dst.put_u8_bytes(REASON_STRING);
+            super::encode_utf8_str(value, dst)?;
+        }
+        if let Some(value) = self.receive_maximum {
+            dst.put_u8_bytes(RECEIVE_MAXIMUM);
+            dst.put_u16_bytes(value);
+        }
+        if let Some(value) = self.topic_alias_maximum {
+            dst.put_u8_bytes(TOPIC_ALIAS_MAXIMUM);
+            dst.put_u16_bytes(value);
+        }
+        if let Some(value) = self.topic_alias {
+            dst.put_u8_bytes(TOPIC_ALIAS);
+            dst.put_u16_bytes(value);
+        }
+        if let Some(value) = self.maximum_qos {
+            dst.put_u8_bytes(MAXIMUM_QOS);
+            dst.put_u8_bytes(value);
+        }
+        if let Some(value) = self.retain_available {
+            dst.put_u8_bytes(RETAIN_AVAILABLE);
+            dst.put_u8_bytes(value);
+        }
+        for (key, value) in &self.user_properties {
+            dst.put_u8_bytes(USER_PROPERTY);
+            super::encode_utf8_str(key, dst)?;
+            super::encode_utf8_str(value, dst)?;
+        }
+        if let Some(value) = self.maximum_packet_size {
+            dst.put_u8_bytes(MAXIMUM_PACKET_SIZE);
+            dst.put_u32_bytes(value);
+        }
+        if let Some(value) = self.wildcard_subscription_available {
+            dst.put_u8_bytes(WILDCARD_SUBSCRIPTION_AVAILABLE);
+            dst.put_u8_bytes(value);
+        }
+        if let Some(value) = self.subscription_identifier_available {
+            dst.put_u8_bytes(SUBSCRIPTION_IDENTIFIER_AVAILABLE);
+            dst.put_u8_bytes(value);
+        }
+        if let Some(value) = self.shared_subscription_available {
+            dst.put_u8_bytes(SHARED_SUBSCRIPTION_AVAILABLE);
+            dst.put_u8_bytes(value);
+        }
+
+        Ok(())
+    }
+}
+
+fn decode_string(src: &mut bytes::BytesMut) -> Result<String, super::DecodeError> {
+    super::Utf8StringDecoder::default()
+        .decode(src)?
+        .ok_or(super::DecodeError::IncompletePacket)
+}
+
+/// Ref: 1.5.6 Binary Data (MQTT 5.0)
+fn decode_binary(src: &mut bytes::BytesMut) -> Result<Vec<u8>, super::DecodeError> {
+    let len = usize::from(src.try_get_u16_be()?);
+    if src.len() < len {
+        return Err(super::DecodeError::IncompletePacket);
+    }
+
+    Ok(src.split_to(len).to_vec())
+}
+
+fn encode_binary<B>(item: &[u8], dst: &mut B) -> Result<(), super::EncodeError>
+where
+    B: ByteBuf,
+{
+    let len = item.len();
+    dst.put_u16_bytes(
+        len.try_into()
+            .map_err(|_| super::EncodeError::BinaryDataTooLarge(len))?,
+    );
+
+    dst.put_slice_bytes(item);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Properties;
+
+    #[test]
+    fn properties_roundtrip() {
+        let properties = Properties {
+            payload_format_indicator: Some(1),
+            message_expiry_interval: Some(3600),
+            content_type: Some("application/json".to_string()),
+            response_topic: Some("response/topic".to_string()),
+            correlation_data: Some(vec![0x01, 0x02, 0x03]),
+            subscription_identifiers: vec![1, 268_435_455],
+            topic_alias: Some(7),
+            user_properties: vec![
+                ("key".to_string(), "value".to_string()),
+                ("key".to_string(), "other value".to_string()),
+            ],
+            ..Properties::default()
+        };
+
+        let mut bytes = bytes::BytesMut::new();
+        properties.encode(&mut bytes).unwrap();
+
+        let decoded = Properties::decode(&mut bytes).unwrap();
+        assert_eq!(decoded, properties);
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn empty_properties() {
+        let mut bytes = bytes::BytesMut::new();
+        Properties::default().encode(&mut bytes).unwrap();
+        assert_eq!(&*bytes, &[0x00]);
+
+        let decoded = Properties::decode(&mut bytes).unwrap();
+        assert_eq!(decoded, Properties::default());
+    }
+
+    #[test]
+    fn duplicate_property() {
+        let mut bytes = bytes::BytesMut::from(&[0x06, 0x23, 0x00, 0x01, 0x23, 0x00, 0x02][..]);
+        let err = Properties::decode(&mut bytes).unwrap_err();
+        if let super::super::DecodeError::DuplicateProperty(0x23) = err {
+        } else {
+            panic!("{:?}", err);
+        }
+    }
+
+    #[test]
+    fn unrecognized_property() {
+        let mut bytes = bytes::BytesMut::from(&[0x02, 0x7F, 0x00][..]);
+        let err = Properties::decode(&mut bytes).unwrap_err();
+        if let super::super::DecodeError::UnrecognizedProperty(0x7F) = err {
+        } else {
+            panic!("{:?}", err);
+        }
+    }
+}
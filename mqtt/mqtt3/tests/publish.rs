@@ -20,11 +20,13 @@ fn server_publishes_at_most_once() {
                 keep_alive: std::time::Duration::from_secs(4),
                 protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                 protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: mqtt3::proto::Properties::default(),
             },
         )),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
             session_present: false,
             return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+            properties: mqtt3::proto::Properties::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
             mqtt3::proto::Subscribe {
@@ -48,6 +50,7 @@ fn server_publishes_at_most_once() {
             retain: false,
             topic_name: "topic1".to_owned(),
             payload: [0x01, 0x02, 0x03][..].into(),
+            properties: mqtt3::proto::Properties::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(mqtt3::proto::PingReq)),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::PingResp(mqtt3::proto::PingResp)),
@@ -114,11 +117,13 @@ fn server_publishes_at_least_once() {
                 keep_alive: std::time::Duration::from_secs(4),
                 protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                 protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: mqtt3::proto::Properties::default(),
             },
         )),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
             session_present: false,
             return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+            properties: mqtt3::proto::Properties::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
             mqtt3::proto::Subscribe {
@@ -145,6 +150,7 @@ fn server_publishes_at_least_once() {
             retain: false,
             topic_name: "topic1".to_owned(),
             payload: [0x01, 0x02, 0x03][..].into(),
+            properties: mqtt3::proto::Properties::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::PubAck(mqtt3::proto::PubAck {
             packet_identifier: mqtt3::proto::PacketIdentifier::new(2).unwrap(),
@@ -215,12 +221,14 @@ fn server_publishes_at_least_once_with_reconnect_before_publish() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
         ],
@@ -236,12 +244,14 @@ fn server_publishes_at_least_once_with_reconnect_before_publish() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: true,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
@@ -272,12 +282,14 @@ fn server_publishes_at_least_once_with_reconnect_before_publish() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: true,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -295,6 +307,7 @@ fn server_publishes_at_least_once_with_reconnect_before_publish() {
                     retain: false,
                     topic_name: "topic1".to_owned(),
                     payload: [0x01, 0x02, 0x03][..].into(),
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PubAck(
@@ -379,12 +392,14 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
         ],
@@ -400,12 +415,14 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: true,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
@@ -432,6 +449,7 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
                     retain: false,
                     topic_name: "topic1".to_owned(),
                     payload: [0x01, 0x02, 0x03][..].into(),
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
         ],
@@ -447,12 +465,14 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: true,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -470,6 +490,7 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
                     retain: false,
                     topic_name: "topic1".to_owned(),
                     payload: [0x01, 0x02, 0x03][..].into(),
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PubAck(
@@ -560,11 +581,13 @@ fn should_reject_invalid_publications() {
                 keep_alive: std::time::Duration::from_secs(4),
                 protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                 protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: mqtt3::proto::Properties::default(),
             },
         )),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
             session_present: false,
             return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+            properties: mqtt3::proto::Properties::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(mqtt3::proto::PingReq)),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::PingResp(mqtt3::proto::PingResp)),
@@ -588,6 +611,7 @@ fn should_reject_invalid_publications() {
         qos: mqtt3::proto::QoS::AtMostOnce,
         retain: false,
         payload: Default::default(),
+        properties: mqtt3::proto::Properties::default(),
     });
 
     common::verify_client_events(
@@ -606,3 +630,110 @@ fn should_reject_invalid_publications() {
 		result => panic!("expected client.publish() to fail with EncodePacket(StringTooLarge) but it returned {:?}", result),
 	}
 }
+
+#[test]
+fn client_resends_stored_publications_after_restart() {
+    use mqtt3::PublicationStore;
+
+    let mut runtime = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .expect("couldn't initialize tokio runtime");
+
+    let dir = tempfile::tempdir().expect("couldn't create temp dir");
+    let path = dir.path().join("publications");
+
+    let stored_publish = mqtt3::proto::Publish {
+        packet_identifier_dup_qos: mqtt3::proto::PacketIdentifierDupQoS::AtLeastOnce(
+            mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+            true,
+        ),
+        retain: false,
+        topic_name: "topic1".to_owned(),
+        payload: [0x01, 0x02, 0x03][..].into(),
+        properties: mqtt3::proto::Properties::default(),
+    };
+    {
+        let mut store = mqtt3::FilePublicationStore::new(&path);
+        store.store(&stored_publish).unwrap();
+    }
+
+    let (io_source, done) = common::IoSource::new(vec![vec![
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Connect(
+            mqtt3::proto::Connect {
+                username: None,
+                password: None,
+                will: None,
+                client_id: mqtt3::proto::ClientId::IdWithExistingSession("client_a".to_owned()),
+                keep_alive: std::time::Duration::from_secs(4),
+                protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
+                protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: mqtt3::proto::Properties::default(),
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
+            session_present: true,
+            return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+            properties: mqtt3::proto::Properties::default(),
+        })),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Publish(stored_publish)),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Publish(
+            mqtt3::proto::Publish {
+                packet_identifier_dup_qos: mqtt3::proto::PacketIdentifierDupQoS::AtLeastOnce(
+                    mqtt3::proto::PacketIdentifier::new(2).unwrap(),
+                    false,
+                ),
+                retain: false,
+                topic_name: "topic2".to_owned(),
+                payload: [0x04, 0x05, 0x06][..].into(),
+                properties: mqtt3::proto::Properties::default(),
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PubAck(mqtt3::proto::PubAck {
+            packet_identifier: mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+        })),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PubAck(mqtt3::proto::PubAck {
+            packet_identifier: mqtt3::proto::PacketIdentifier::new(2).unwrap(),
+        })),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(mqtt3::proto::PingReq)),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PingResp(mqtt3::proto::PingResp)),
+    ]]);
+
+    let mut client = mqtt3::Client::from_state(
+        "client_a".to_owned(),
+        None,
+        None,
+        io_source,
+        std::time::Duration::from_secs(0),
+        std::time::Duration::from_secs(4),
+    )
+    .with_publication_store(mqtt3::FilePublicationStore::new(&path))
+    .expect("couldn't restore publications");
+
+    let publish_future = client.publish(mqtt3::proto::Publication {
+        topic_name: "topic2".to_owned(),
+        qos: mqtt3::proto::QoS::AtLeastOnce,
+        retain: false,
+        payload: [0x04, 0x05, 0x06][..].into(),
+        properties: mqtt3::proto::Properties::default(),
+    });
+
+    common::verify_client_events(
+        &mut runtime,
+        client,
+        vec![mqtt3::Event::NewConnection {
+            reset_session: false,
+        }],
+    );
+
+    let () = runtime
+        .block_on(done)
+        .expect("connection broken while there were still steps remaining on the server");
+    let () = runtime
+        .block_on(publish_future)
+        .expect("publication was not acked");
+
+    let mut store = mqtt3::FilePublicationStore::new(&path);
+    assert_eq!(store.load().unwrap(), vec![]);
+}
@@ -21,12 +21,14 @@ fn server_generated_id_can_connect_and_idle() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -52,12 +54,14 @@ fn server_generated_id_can_connect_and_idle() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -123,12 +127,14 @@ fn client_id_can_connect_and_idle() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -156,6 +162,7 @@ fn client_id_can_connect_and_idle() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
@@ -165,6 +172,7 @@ fn client_id_can_connect_and_idle() {
                     // So this second session will still have `session_present == false`
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -192,12 +200,14 @@ fn client_id_can_connect_and_idle() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: true,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -21,12 +21,14 @@ fn server_generated_id_must_always_resubscribe() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
@@ -73,12 +75,14 @@ fn server_generated_id_must_always_resubscribe() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
@@ -228,12 +232,14 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
@@ -282,6 +288,7 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
@@ -291,6 +298,7 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
                     // So this second session will still have `session_present == false`
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
@@ -339,12 +347,14 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: true,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -450,11 +460,13 @@ fn should_combine_pending_subscription_updates() {
                 keep_alive: std::time::Duration::from_secs(4),
                 protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                 protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: mqtt3::proto::Properties::default(),
             },
         )),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
             session_present: false,
             return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+            properties: mqtt3::proto::Properties::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
             mqtt3::proto::Subscribe {
@@ -0,0 +1,351 @@
+//! Local admin interface of the broker.
+//!
+//! * `GET /sessions` lists the connected and offline sessions
+//! * `GET /sessions/{client_id}` shows a single session
+//! * `DELETE /sessions/{client_id}` removes an offline session
+//! * `POST /sessions/{client_id}/disconnect` drops the connection of a client
+//! * `DELETE /retained?filter={topic_filter}` removes the matching retained messages
+//! * `POST /snapshot` persists the broker state
+//!
+//! Client ids and topic filters are percent-encoded, so `#` is sent as `%23`.
+//!
+//! The admin interface isn't authenticated. It is served on a unix socket only
+//! the owner of the broker process can connect to, or on a loopback address.
+//! Over TCP, a request must carry the exact address of the interface in its
+//! `Host` header, so that a DNS name rebound to the loopback address can't reach it,
+//! and an `X-Mqttd-Admin` header, which web pages can't send without a CORS preflight
+//! the interface never allows.
+
+use std::{fs, io, net::SocketAddr, path::Path, sync::Arc};
+
+use percent_encoding::percent_decode_str;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{self, TcpListener, UnixListener};
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
+
+use mqtt_broker::{AdminError, AdminHandle, ClientId, Error};
+
+use crate::http::{self, Request, Response};
+use crate::snapshot::Snapshot;
+
+static UNIX_SCHEME: &str = "unix://";
+static JSON: &str = "application/json";
+static ADMIN_HEADER: &str = "X-Mqttd-Admin";
+
+/// Only the owner of the broker process can connect to the admin socket.
+const SOCKET_MODE: u32 = 0o600;
+
+/// At most this many admin connections are served at the same time.
+const MAX_CONNECTIONS: usize = 8;
+
+/// Serves the admin interface on `host:port` or on a unix socket given as `unix://<path>`.
+///
+/// The admin interface isn't authenticated, so `host` must be a loopback address.
+pub async fn serve(
+    address: String,
+    admin_handle: AdminHandle,
+    snapshot: Snapshot,
+) -> io::Result<()> {
+    let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    if address.starts_with(UNIX_SCHEME) {
+        let path = &address[UNIX_SCHEME.len()..];
+        // a socket left behind by a previous run would fail the bind
+        if let Err(e) = fs::remove_file(path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                return Err(e);
+            }
+        }
+
+        let mut listener = bind_owner_only(Path::new(path))?;
+        info!("Serving admin interface on {}", address);
+        loop {
+            let slot = Slot::acquire(&connections).await;
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let admin = Admin::new(admin_handle.clone(), snapshot.clone(), None);
+                    spawn(stream, admin, slot);
+                }
+                Err(e) => warn!(message = "failed to accept admin connection", error = %e),
+            }
+        }
+    } else {
+        let addrs = net::lookup_host(&address).await?.collect::<Vec<_>>();
+        if addrs.is_empty() || addrs.iter().any(|addr| !addr.ip().is_loopback()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "admin interface can only be served on a loopback address or a unix socket, not on {}",
+                    address
+                ),
+            ));
+        }
+
+        let mut listener = TcpListener::bind(addrs.as_slice()).await?;
+        let hosts = Arc::new(hosts(&address, listener.local_addr()?));
+        info!("Serving admin interface on http://{}", address);
+        loop {
+            let slot = Slot::acquire(&connections).await;
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let admin = Admin::new(
+                        admin_handle.clone(),
+                        snapshot.clone(),
+                        Some(hosts.clone()),
+                    );
+                    spawn(stream, admin, slot);
+                }
+                Err(e) => warn!(message = "failed to accept admin connection", error = %e),
+            }
+        }
+    }
+}
+
+/// Binds with a umask that leaves the socket file accessible to the owner only,
+/// so that nobody else can connect before it is served.
+fn bind_owner_only(path: &Path) -> io::Result<UnixListener> {
+    // umask applies to the whole process, it is restored right after bind
+    #[allow(clippy::cast_possible_truncation)]
+    let umask = unsafe { libc::umask((!SOCKET_MODE & 0o777) as libc::mode_t) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(umask) };
+    listener
+}
+
+/// Returns the `Host` header values accepted over TCP: the configured address
+/// and the address the interface is bound to.
+fn hosts(address: &str, local_addr: SocketAddr) -> Vec<String> {
+    let mut hosts = vec![address.to_string(), local_addr.to_string()];
+    hosts.dedup();
+    hosts
+}
+
+/// Everything needed to serve an admin request.
+struct Admin {
+    admin_handle: AdminHandle,
+    snapshot: Snapshot,
+    /// the accepted `Host` header values, if the request came over TCP
+    hosts: Option<Arc<Vec<String>>>,
+}
+
+impl Admin {
+    fn new(admin_handle: AdminHandle, snapshot: Snapshot, hosts: Option<Arc<Vec<String>>>) -> Self {
+        Self {
+            admin_handle,
+            snapshot,
+            hosts,
+        }
+    }
+}
+
+/// One of the `MAX_CONNECTIONS` admin connections, given back once the connection is closed.
+struct Slot(Arc<Semaphore>);
+
+impl Slot {
+    async fn acquire(connections: &Arc<Semaphore>) -> Self {
+        connections.acquire().await.forget();
+        Slot(connections.clone())
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.add_permits(1);
+    }
+}
+
+fn spawn<S>(stream: S, admin: Admin, slot: Slot)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        if let Err(e) = respond(stream, admin).await {
+            debug!(message = "failed to serve admin request", error = %e);
+        }
+        drop(slot);
+    });
+}
+
+async fn respond<S>(mut stream: S, mut admin: Admin) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = http::read_request(&mut stream).await?;
+    debug!("admin request {} {}", request.method(), request.path());
+
+    let response = match check(&request, admin.hosts.as_deref().map(Vec::as_slice)) {
+        Ok(()) => route(&request, &mut admin.admin_handle, &admin.snapshot).await,
+        Err(e) => Err(e),
+    };
+    let response = response.unwrap_or_else(error_response);
+    http::write_response(&mut stream, response).await
+}
+
+/// Refuses a TCP request which may have been sent by a web page.
+fn check(request: &Request, hosts: Option<&[String]>) -> Result<(), RouteError> {
+    let hosts = match hosts {
+        Some(hosts) => hosts,
+        None => return Ok(()),
+    };
+
+    match request.header("Host") {
+        Some(host) if hosts.iter().any(|allowed| allowed == host) => (),
+        _ => return Err(RouteError::Forbidden("unexpected Host header")),
+    }
+    if request.header(ADMIN_HEADER).is_none() {
+        return Err(RouteError::Forbidden("missing X-Mqttd-Admin header"));
+    }
+    Ok(())
+}
+
+async fn route(
+    request: &Request,
+    admin_handle: &mut AdminHandle,
+    snapshot: &Snapshot,
+) -> Result<Response, RouteError> {
+    let segments = request
+        .path()
+        .trim_start_matches('/')
+        .split('/')
+        .collect::<Vec<_>>();
+
+    let response = match (request.method(), segments.as_slice()) {
+        ("GET", ["sessions"]) => {
+            let sessions = admin_handle.sessions().await?;
+            json(serde_json::to_string(&sessions))?
+        }
+        ("GET", ["sessions", client_id]) => {
+            let client_id = decode(client_id)?;
+            let sessions = admin_handle.sessions().await?;
+            let session = sessions
+                .iter()
+                .find(|session| session.client_id() == &client_id)
+                .ok_or_else(|| AdminError::NoSession(client_id.clone()))?;
+            json(serde_json::to_string(session))?
+        }
+        ("DELETE", ["sessions", client_id]) => {
+            admin_handle.delete_session(decode(client_id)?).await?;
+            Response::empty("204 No Content")
+        }
+        ("POST", ["sessions", client_id, "disconnect"]) => {
+            admin_handle.disconnect(decode(client_id)?).await?;
+            Response::empty("202 Accepted")
+        }
+        ("DELETE", ["retained"]) => {
+            let topic_filter = request
+                .query()
+                .into_iter()
+                .flat_map(|query| query.split('&'))
+                .find(|param| param.starts_with("filter="))
+                .map(|param| decode_str(&param["filter=".len()..]))
+                .ok_or(RouteError::BadRequest("missing topic filter"))??;
+            let removed = admin_handle.clear_retained(topic_filter).await?;
+            Response::new("200 OK", JSON, format!("{{\"removed\":{}}}", removed))
+        }
+        ("POST", ["snapshot"]) => {
+            snapshot.request(admin_handle).await?;
+            Response::empty("202 Accepted")
+        }
+        _ => Response::empty("404 Not Found"),
+    };
+    Ok(response)
+}
+
+fn json(body: serde_json::Result<String>) -> Result<Response, RouteError> {
+    let body = body.map_err(RouteError::Serialize)?;
+    Ok(Response::new("200 OK", JSON, body))
+}
+
+fn decode(client_id: &str) -> Result<ClientId, RouteError> {
+    decode_str(client_id).map(Into::into)
+}
+
+fn decode_str(value: &str) -> Result<String, RouteError> {
+    percent_decode_str(value)
+        .decode_utf8()
+        .map(|value| value.into_owned())
+        .map_err(|_| RouteError::BadRequest("invalid percent-encoding"))
+}
+
+fn error_response(error: RouteError) -> Response {
+    let error = match error {
+        RouteError::BadRequest(message) => return Response::text("400 Bad Request", message),
+        RouteError::Forbidden(message) => return Response::text("403 Forbidden", message),
+        RouteError::Serialize(e) => {
+            return Response::text("500 Internal Server Error", e.to_string())
+        }
+        RouteError::Admin(error) => *error,
+    };
+
+    match error {
+        e @ AdminError::NoSession(_) => Response::text("404 Not Found", e.to_string()),
+        e @ AdminError::SessionConnected(_) => Response::text("409 Conflict", e.to_string()),
+        AdminError::Broker(e @ Error::InvalidTopicFilter(_)) => {
+            Response::text("400 Bad Request", e.to_string())
+        }
+        e => Response::text("500 Internal Server Error", e.to_string()),
+    }
+}
+
+#[derive(Debug)]
+enum RouteError {
+    BadRequest(&'static str),
+    Forbidden(&'static str),
+    Admin(Box<AdminError>),
+    Serialize(serde_json::Error),
+}
+
+impl From<AdminError> for RouteError {
+    fn from(error: AdminError) -> Self {
+        RouteError::Admin(Box::new(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::{self, Request};
+
+    use super::{check, RouteError};
+
+    async fn request(head: &str) -> Request {
+        http::read_request(&mut head.as_bytes()).await.unwrap()
+    }
+
+    fn hosts() -> Vec<String> {
+        vec!["localhost:9601".to_string(), "127.0.0.1:9601".to_string()]
+    }
+
+    #[tokio::test]
+    async fn it_allows_tcp_requests_to_loopback_host_with_admin_header() {
+        for host in &["localhost:9601", "127.0.0.1:9601"] {
+            let head = format!(
+                "GET /sessions HTTP/1.1\r\nHost: {}\r\nx-mqttd-admin: 1\r\n\r\n",
+                host
+            );
+            let request = request(&head).await;
+            assert!(check(&request, Some(&hosts())).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn it_refuses_tcp_requests_from_web_pages() {
+        for head in &[
+            "GET /sessions HTTP/1.1\r\nX-Mqttd-Admin: 1\r\n\r\n",
+            "GET /sessions HTTP/1.1\r\nHost: attacker.example:9601\r\nX-Mqttd-Admin: 1\r\n\r\n",
+            "GET /sessions HTTP/1.1\r\nHost: localhost\r\nX-Mqttd-Admin: 1\r\n\r\n",
+            "POST /snapshot HTTP/1.1\r\nHost: localhost:9601\r\n\r\n",
+        ] {
+            let request = request(head).await;
+            assert!(matches!(
+                check(&request, Some(&hosts())),
+                Err(RouteError::Forbidden(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_allows_unix_socket_requests_without_headers() {
+        let request = request("GET /sessions HTTP/1.1\r\n\r\n").await;
+        assert!(check(&request, None).is_ok());
+    }
+}
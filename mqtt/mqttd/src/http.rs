@@ -0,0 +1,131 @@
+//! Just enough HTTP/1.1 to serve the local endpoints of the broker.
+//! Every connection carries a single request without a body.
+
+use std::{io, time::Duration};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Requests larger than this are refused.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// Clients that don't send their whole request within this time are dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub(crate) struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub(crate) fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub(crate) fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    /// Returns the value of the header `name`, which is case-insensitive.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    pub(crate) fn new(status: &'static str, content_type: &'static str, body: String) -> Self {
+        Self {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    pub(crate) fn text(status: &'static str, body: impl Into<String>) -> Self {
+        Self::new(status, "text/plain", body.into())
+    }
+
+    pub(crate) fn empty(status: &'static str) -> Self {
+        Self::text(status, "")
+    }
+}
+
+pub(crate) async fn read_request<S>(stream: &mut S) -> io::Result<Request>
+where
+    S: AsyncRead + Unpin,
+{
+    tokio::time::timeout(REQUEST_TIMEOUT, read_request_head(stream))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timed out reading request"))?
+}
+
+async fn read_request_head<S>(stream: &mut S) -> io::Result<Request>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buffer = vec![0; MAX_REQUEST_HEAD];
+    let mut len = 0;
+    while len < buffer.len() {
+        let read = stream.read(&mut buffer[len..]).await?;
+        len += read;
+        if read == 0 || buffer[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+            break;
+        }
+    }
+
+    let head = String::from_utf8_lossy(&buffer[..len]);
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let target = request_line.next().unwrap_or_default();
+    let mut target = target.splitn(2, '?');
+    let path = target.next().unwrap_or_default().to_owned();
+    let query = target.next().map(ToOwned::to_owned);
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| {
+            let mut header = line.splitn(2, ':');
+            let name = header.next()?.trim().to_owned();
+            let value = header.next()?.trim().to_owned();
+            Some((name, value))
+        })
+        .collect();
+
+    Ok(Request {
+        method,
+        path,
+        query,
+        headers,
+    })
+}
+
+pub(crate) async fn write_response<S>(stream: &mut S, response: Response) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
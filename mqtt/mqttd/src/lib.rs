@@ -1,18 +1,23 @@
 use std::fmt;
 
-use mqtt_broker::Error;
+use mqtt_broker::{Error, PasswordFileError};
 
+pub mod admin;
+mod http;
+pub mod metrics;
+pub mod reload;
 pub mod shutdown;
 pub mod snapshot;
+pub mod users;
 
 pub struct Terminate {
-    error: Error,
+    error: Box<dyn std::error::Error>,
 }
 
 impl fmt::Debug for Terminate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.error)?;
-        let mut current: &dyn std::error::Error = &self.error;
+        let mut current: &dyn std::error::Error = self.error.as_ref();
         while let Some(source) = current.source() {
             write!(f, "\n\tcaused by: {}", source)?;
             current = source;
@@ -23,6 +28,16 @@ impl fmt::Debug for Terminate {
 
 impl From<Error> for Terminate {
     fn from(error: Error) -> Self {
-        Terminate { error }
+        Terminate {
+            error: Box::new(error),
+        }
+    }
+}
+
+impl From<PasswordFileError> for Terminate {
+    fn from(error: PasswordFileError) -> Self {
+        Terminate {
+            error: Box::new(error),
+        }
     }
 }
@@ -1,13 +1,22 @@
-use std::{convert::TryInto, env, io};
+use std::{env, io};
 
-use clap::{crate_description, crate_name, crate_version, App, Arg};
+use clap::{crate_description, crate_name, crate_version, App, Arg, ArgMatches};
 use futures_util::pin_mut;
 use mqtt_broker::*;
-use tokio::time::{Duration, Instant};
+use tokio::{
+    sync::mpsc,
+    time::{Duration, Instant},
+};
 use tracing::{info, warn, Level};
 use tracing_subscriber::{fmt, EnvFilter};
 
-use mqttd::{shutdown, snapshot, Terminate};
+use mqttd::{
+    admin, metrics,
+    reload::{self, Auth},
+    shutdown,
+    snapshot::{self, Snapshot},
+    users, Terminate,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Terminate> {
@@ -19,13 +28,18 @@ async fn main() -> Result<(), Terminate> {
         .finish();
     let _ = tracing::subscriber::set_global_default(subscriber);
 
-    run().await?;
+    let matches = create_app().get_matches();
+    if let ("users", Some(matches)) = matches.subcommand() {
+        users::run(matches)?;
+    } else {
+        run(&matches).await?;
+    }
+
     Ok(())
 }
 
-async fn run() -> Result<(), Error> {
-    let config = create_app()
-        .get_matches()
+async fn run(matches: &ArgMatches<'_>) -> Result<(), Error> {
+    let config = matches
         .value_of("config")
         .map_or(BrokerConfig::new(), BrokerConfig::from_file)
         .map_err(InitializeBrokerError::LoadConfiguration)?;
@@ -34,18 +48,53 @@ async fn run() -> Result<(), Error> {
     let shutdown = shutdown::shutdown();
     pin_mut!(shutdown);
 
-    // Setup the snapshotter
+    // Setup the write-ahead log if configured, otherwise the snapshotter
     let mut persistor = FilePersistor::new(
         env::current_dir().expect("can't get cwd").join("state"),
         VersionedFileFormat::default(),
     );
+    let mut wal = config
+        .persistence()
+        .map(|persistence| WalPersistor::new(persistence.file_path()));
     info!("Loading state...");
-    let state = persistor.load().await?.unwrap_or_else(BrokerState::default);
-    let broker = BrokerBuilder::default()
-        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
-        .authorizer(|_| Ok(true))
+    let state = match &mut wal {
+        Some(wal) => wal.load().await?,
+        None => persistor.load().await?,
+    };
+    let state = state.unwrap_or_else(BrokerState::default);
+    let (journal, journal_writer) = match (wal, config.persistence()) {
+        (Some(wal), Some(persistence)) => {
+            let max_unsaved = persistence.unsaved_message_count() as usize;
+            let (journal, writer) = journal(wal, max_unsaved);
+            (Some(journal), Some(writer))
+        }
+        _ => (None, None),
+    };
+
+    // Setup the password file authenticator and the policy based authorizer if configured,
+    // otherwise allow anonymous clients to do everything
+    let auth = Auth::from_config(&config)?;
+
+    if let Some(authorization) = config.authorization() {
+        let tick = tick_policy_reload(authorization.reload_interval(), auth.clone());
+        tokio::spawn(tick);
+    }
+
+    let mut broker = BrokerBuilder::default()
+        .authenticator({
+            let auth = auth.clone();
+            move |credentials| auth.authenticate(credentials)
+        })
+        .authorizer({
+            let auth = auth.clone();
+            move |activity| auth.authorize(activity)
+        })
         .state(state)
-        .build();
+        .config(config.clone());
+    if let Some(journal) = journal {
+        broker = broker.journal(journal);
+    }
+    let broker = broker.build();
     info!("state loaded.");
 
     let snapshotter = Snapshotter::new(persistor);
@@ -53,28 +102,88 @@ async fn run() -> Result<(), Error> {
     let mut shutdown_handle = snapshotter.shutdown_handle();
     let join_handle = tokio::spawn(snapshotter.run());
 
-    // Tick the snapshotter
-    let tick = tick_snapshot(
-        Duration::from_secs(5 * 60),
-        broker.handle(),
-        snapshot_handle.clone(),
-    );
+    let journal_handle = match (journal_writer, config.persistence()) {
+        (Some(writer), Some(persistence)) => {
+            // Tick the journal
+            let tick = tick_journal(persistence.time_interval(), broker.handle());
+            tokio::spawn(tick);
+
+            Some(tokio::spawn(writer.run(broker.handle())))
+        }
+        _ => {
+            // Tick the snapshotter
+            let tick = tick_snapshot(
+                Duration::from_secs(5 * 60),
+                broker.handle(),
+                snapshot_handle.clone(),
+            );
+            tokio::spawn(tick);
+            None
+        }
+    };
+
+    // Tick the session cleanup
+    let tick = tick_session_cleanup(config.session().cleanup_interval(), broker.handle());
     tokio::spawn(tick);
 
-    // Signal the snapshotter
-    let snapshot = snapshot::snapshot(broker.handle(), snapshot_handle.clone());
-    tokio::spawn(snapshot);
+    // Signal the snapshotter, or checkpoint the write-ahead log if configured
+    let snapshot = if journal_handle.is_some() {
+        Snapshot::Checkpoint
+    } else {
+        Snapshot::State(snapshot_handle.clone())
+    };
+    tokio::spawn(snapshot::snapshot(broker.handle(), snapshot.clone()));
+
+    // Tick the broker statistics
+    let statistics = config.statistics();
+    if statistics.interval() > Duration::default() {
+        let tick = tick_stats(statistics.interval(), broker.handle());
+        tokio::spawn(tick);
+    }
+
+    // Serve the metrics endpoint if configured
+    if let Some(address) = statistics.metrics_address() {
+        let serve = metrics::serve(address.to_string(), broker.stats_handle());
+        tokio::spawn(async move {
+            if let Err(e) = serve.await {
+                warn!(message = "metrics endpoint stopped with an error", error = %e);
+            }
+        });
+    }
+
+    // Serve the admin interface if configured
+    if let Some(settings) = config.admin() {
+        let serve = admin::serve(
+            settings.address().to_string(),
+            AdminHandle::new(broker.handle()),
+            snapshot,
+        );
+        tokio::spawn(async move {
+            if let Err(e) = serve.await {
+                warn!(message = "admin interface stopped with an error", error = %e);
+            }
+        });
+    }
+
+    // Start configured bridges
+    for settings in config.bridges() {
+        let bridge = Bridge::new(settings.clone(), broker.handle())?;
+        tokio::spawn(run_bridge(bridge, settings.clone(), broker.handle()));
+    }
 
     // Create configured transports
-    let transports = config
-        .transports()
-        .iter()
-        .map(|transport| transport.clone().try_into())
-        .collect::<Result<Vec<_>, _>>()?;
+    let transports = reload::listeners(&config)?;
+
+    // Apply changes of the configuration file while running
+    let (listeners, updates) = mpsc::channel(1);
+    if let Some(path) = matches.value_of("config") {
+        let reload = reload::reload(path.into(), config, auth, broker.handle(), listeners);
+        tokio::spawn(reload);
+    }
 
     info!("Starting server...");
     let state = Server::from_broker(broker)
-        .serve(transports, shutdown)
+        .serve_with_reload(transports, updates, shutdown)
         .await?;
 
     // Stop snapshotting
@@ -83,13 +192,41 @@ async fn run() -> Result<(), Error> {
     info!("state snapshotter shutdown.");
 
     info!("persisting state before exiting...");
-    persistor.store(state).await?;
+    match journal_handle {
+        Some(journal_handle) => {
+            let mut wal = journal_handle.await??;
+            info!("journal writer shutdown.");
+            wal.store(state).await?;
+        }
+        None => persistor.store(state).await?,
+    }
     info!("state persisted.");
     info!("exiting... goodbye");
 
     Ok(())
 }
 
+/// Runs a bridge and starts it again after a back-off whenever it stops,
+/// e.g. when the broker drops the bridge session.
+async fn run_bridge(mut bridge: Bridge, settings: BridgeSettings, broker_handle: BrokerHandle) {
+    loop {
+        if let Err(e) = bridge.run().await {
+            warn!(message = "bridge stopped with an error", error = %e);
+        }
+
+        tokio::time::delay_for(settings.max_reconnect_back_off()).await;
+        info!("restarting bridge {}...", settings.name());
+
+        bridge = match Bridge::new(settings.clone(), broker_handle.clone()) {
+            Ok(bridge) => bridge,
+            Err(e) => {
+                warn!(message = "failed to restart bridge", error = %e);
+                return;
+            }
+        };
+    }
+}
+
 async fn tick_snapshot(
     period: Duration,
     mut broker_handle: BrokerHandle,
@@ -111,6 +248,64 @@ async fn tick_snapshot(
     }
 }
 
+async fn tick_journal(period: Duration, mut broker_handle: BrokerHandle) {
+    info!("Flushing journal every {:?}", period);
+    let start = Instant::now() + period;
+    let mut interval = tokio::time::interval_at(start, period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = broker_handle
+            .send(Message::System(SystemEvent::JournalFlush))
+            .await
+        {
+            warn!(message = "failed to tick the journal", error=%e);
+        }
+    }
+}
+
+async fn tick_stats(period: Duration, mut broker_handle: BrokerHandle) {
+    info!("Publishing broker statistics every {:?}", period);
+    let start = Instant::now() + period;
+    let mut interval = tokio::time::interval_at(start, period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = broker_handle
+            .send(Message::System(SystemEvent::PublishStats))
+            .await
+        {
+            warn!(message = "failed to tick the broker statistics", error=%e);
+        }
+    }
+}
+
+async fn tick_session_cleanup(period: Duration, mut broker_handle: BrokerHandle) {
+    info!("Removing expired sessions every {:?}", period);
+    let start = Instant::now() + period;
+    let mut interval = tokio::time::interval_at(start, period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = broker_handle
+            .send(Message::System(SystemEvent::SessionCleanup))
+            .await
+        {
+            warn!(message = "failed to tick the session cleanup", error=%e);
+        }
+    }
+}
+
+async fn tick_policy_reload(period: Duration, auth: Auth) {
+    info!(
+        "Checking authorization policy for changes every {:?}",
+        period
+    );
+    let start = Instant::now() + period;
+    let mut interval = tokio::time::interval_at(start, period);
+    loop {
+        interval.tick().await;
+        auth.reload_policy();
+    }
+}
+
 fn create_app() -> App<'static, 'static> {
     App::new(crate_name!())
         .version(crate_version!())
@@ -123,4 +318,5 @@ fn create_app() -> App<'static, 'static> {
                 .help("Sets a custom config file")
                 .takes_value(true),
         )
+        .subcommand(users::command())
 }
@@ -0,0 +1,39 @@
+use std::io;
+
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+use mqtt_broker::StatsHandle;
+
+use crate::http::{self, Response};
+
+static CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Serves the broker stats on `GET /metrics` in the Prometheus text format.
+pub async fn serve(address: String, stats_handle: StatsHandle) -> io::Result<()> {
+    let mut listener = TcpListener::bind(&address).await?;
+    info!("Serving metrics on http://{}/metrics", address);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let stats_handle = stats_handle.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &stats_handle).await {
+                debug!(message = "failed to serve metrics", peer = %peer, error = %e);
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, stats_handle: &StatsHandle) -> io::Result<()> {
+    let request = http::read_request(&mut stream).await?;
+    let response = match (request.method(), request.path()) {
+        ("GET", "/metrics") => {
+            Response::new("200 OK", CONTENT_TYPE, stats_handle.get().prometheus())
+        }
+        ("GET", _) => Response::empty("404 Not Found"),
+        _ => Response::empty("405 Method Not Allowed"),
+    };
+
+    http::write_response(&mut stream, response).await
+}
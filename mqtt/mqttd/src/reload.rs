@@ -0,0 +1,318 @@
+use std::{
+    convert::TryInto,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use futures_util::stream::{self, StreamExt};
+use tokio::{
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use mqtt_broker::{
+    Activity, AuthId, AuthenticateError, Authenticator, AuthorizeError, Authorizer, BrokerConfig,
+    BrokerHandle, Credentials, InitializeBrokerError, Message, PasswordAuthenticator,
+    PolicyAuthorizer, SystemEvent, TransportBuilder, TransportConfig,
+};
+
+/// How often the configuration file is checked for changes.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Listeners keyed by the configuration they were created from.
+pub type Listeners = Vec<(TransportConfig, TransportBuilder<String>)>;
+
+/// Creates listeners for every transport in the configuration.
+pub fn listeners(config: &BrokerConfig) -> Result<Listeners, InitializeBrokerError> {
+    config
+        .transports()
+        .iter()
+        .map(|transport| Ok((transport.clone(), transport.clone().try_into()?)))
+        .collect()
+}
+
+/// Authenticator and authorizer which can be replaced while the broker is running.
+///
+/// Anonymous clients are allowed when no password file is configured and
+/// everything is allowed when no authorization policy is configured.
+/// With a password file, clients must connect with username and password,
+/// client certificates and unix socket peer credentials are refused.
+#[derive(Clone, Default)]
+pub struct Auth {
+    authenticator: Arc<RwLock<Option<PasswordAuthenticator>>>,
+    authorizer: Arc<RwLock<Option<PolicyAuthorizer>>>,
+}
+
+impl Auth {
+    pub fn from_config(config: &BrokerConfig) -> Result<Self, InitializeBrokerError> {
+        let auth = Self::default();
+        auth.replace(load_auth(config)?);
+        Ok(auth)
+    }
+
+    pub fn authenticate(
+        &self,
+        credentials: Credentials,
+    ) -> Result<Option<AuthId>, AuthenticateError> {
+        let authenticator = self.authenticator.read().map_err(|_| AuthenticateError)?;
+        match authenticator.as_ref() {
+            // Other credentials would let clients skip the password check,
+            // the password authenticator doesn't identify them.
+            Some(authenticator) => authenticator.authenticate(credentials),
+            None => Ok(Some(AuthId::Anonymous)),
+        }
+    }
+
+    pub fn authorize(&self, activity: Activity) -> Result<bool, AuthorizeError> {
+        let authorizer = self.authorizer.read().map_err(|_| AuthorizeError)?;
+        authorizer
+            .as_ref()
+            .map_or(Ok(true), |authorizer| authorizer.authorize(activity))
+    }
+
+    /// Reloads the authorization policy if the policy file has changed.
+    pub fn reload_policy(&self) {
+        let authorizer = match self.authorizer.read() {
+            Ok(authorizer) => authorizer.clone(),
+            Err(_) => return,
+        };
+
+        if let Some(authorizer) = authorizer {
+            if let Err(e) = authorizer.reload() {
+                warn!(message = "failed to reload authorization policy", error=%e);
+            }
+        }
+    }
+
+    fn replace(&self, (authenticator, authorizer): LoadedAuth) {
+        if let Ok(mut current) = self.authenticator.write() {
+            *current = authenticator;
+        }
+        if let Ok(mut current) = self.authorizer.write() {
+            *current = authorizer;
+        }
+    }
+}
+
+type LoadedAuth = (Option<PasswordAuthenticator>, Option<PolicyAuthorizer>);
+
+fn load_auth(config: &BrokerConfig) -> Result<LoadedAuth, InitializeBrokerError> {
+    let authenticator = config
+        .authentication()
+        .map(|authentication| PasswordAuthenticator::from_file(authentication.password_file()))
+        .transpose()
+        .map_err(InitializeBrokerError::LoadPasswordFile)?;
+
+    let authorizer = config
+        .authorization()
+        .map(|authorization| PolicyAuthorizer::from_file(authorization.policy_file()))
+        .transpose()
+        .map_err(InitializeBrokerError::LoadPolicy)?;
+
+    Ok((authenticator, authorizer))
+}
+
+/// Applies changes of the configuration file to the running broker.
+///
+/// The file is checked for changes periodically and reloaded unconditionally
+/// on SIGHUP. A configuration which fails to load or validate doesn't replace
+/// the current one. Listeners, limits, the password file and the authorization
+/// policy are updated in place, other settings take effect after a restart.
+pub async fn reload(
+    path: PathBuf,
+    mut config: BrokerConfig,
+    auth: Auth,
+    mut broker_handle: BrokerHandle,
+    mut listeners: Sender<Listeners>,
+) {
+    info!(
+        "Checking configuration file {} for changes every {:?}",
+        path.display(),
+        CHECK_INTERVAL
+    );
+    let mut contents = tokio::fs::read(&path).await.ok();
+
+    let start = Instant::now() + CHECK_INTERVAL;
+    let interval = tokio::time::interval_at(start, CHECK_INTERVAL).map(|_| false);
+    let mut events = stream::select(interval, imp::hangup().map(|_| true));
+
+    while let Some(hangup) = events.next().await {
+        let current = tokio::fs::read(&path).await.ok();
+        if !hangup && current == contents {
+            continue;
+        }
+        contents = current;
+
+        info!("reloading configuration from {}...", path.display());
+        // loading reads the password file, the policy and the certificates
+        let (new_path, current) = (path.clone(), config.clone());
+        let update = tokio::task::spawn_blocking(move || load(&new_path, &current)).await;
+        let (new_config, auth_update, listeners_update) = match update {
+            Ok(Ok(update)) => update,
+            Ok(Err(e)) => {
+                warn!(message = "failed to reload configuration", error=%e);
+                continue;
+            }
+            Err(e) => {
+                warn!(message = "failed to reload configuration", error=%e);
+                continue;
+            }
+        };
+
+        for setting in restart_required(&config, &new_config) {
+            warn!("changes to {} take effect after restart", setting);
+        }
+
+        auth.replace(auth_update);
+        if let Err(e) = broker_handle
+            .send(Message::System(SystemEvent::ConfigUpdate(
+                new_config.clone(),
+            )))
+            .await
+        {
+            warn!(message = "failed to send configuration update to the broker", error=%e);
+        }
+        if let Some(update) = listeners_update {
+            if listeners.send(update).await.is_err() {
+                warn!("failed to update listeners, the server has stopped");
+            }
+        }
+
+        config = new_config;
+        info!("configuration reloaded.");
+    }
+}
+
+/// Loads and validates a new configuration, creating everything
+/// that needs to be replaced before anything is applied.
+fn load(
+    path: &Path,
+    current: &BrokerConfig,
+) -> Result<(BrokerConfig, LoadedAuth, Option<Listeners>), InitializeBrokerError> {
+    let config = BrokerConfig::from_file(path).map_err(InitializeBrokerError::LoadConfiguration)?;
+
+    let auth = load_auth(&config)?;
+
+    let listeners = if config.transports() == current.transports() {
+        None
+    } else {
+        Some(listeners(&config)?)
+    };
+
+    Ok((config, auth, listeners))
+}
+
+/// Names the settings which changed but can't be applied to the running broker.
+fn restart_required(current: &BrokerConfig, config: &BrokerConfig) -> Vec<&'static str> {
+    let mut settings = Vec::new();
+    if current.persistence() != config.persistence() {
+        settings.push("persistence");
+    }
+    if current.statistics() != config.statistics() {
+        settings.push("statistics");
+    }
+    if current.admin() != config.admin() {
+        settings.push("admin");
+    }
+    if current.bridges() != config.bridges() {
+        settings.push("bridges");
+    }
+    if current.authorization().map(|a| a.reload_interval())
+        != config.authorization().map(|a| a.reload_interval())
+    {
+        settings.push("authorization reload interval");
+    }
+    if current.limits().max_packet_size() != config.limits().max_packet_size() {
+        settings.push("maximum packet size");
+    }
+    settings
+}
+
+#[cfg(unix)]
+mod imp {
+    use futures_util::stream::{self, Stream, StreamExt};
+    use tokio::signal::unix::{signal, SignalKind};
+    use tracing::{info, warn};
+
+    pub(super) fn hangup() -> impl Stream<Item = ()> + Unpin {
+        match signal(SignalKind::hangup()) {
+            Ok(stream) => {
+                info!("Setup to reload configuration on HUP signal");
+                stream
+                    .inspect(|_| info!("Received signal HUP"))
+                    .left_stream()
+            }
+            Err(e) => {
+                warn!(message = "an error occurred setting up the signal handler", error=%e);
+                stream::pending().right_stream()
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use futures_util::stream::{self, Stream};
+
+    pub(super) fn hangup() -> impl Stream<Item = ()> + Unpin {
+        stream::pending()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use mqtt_broker::{
+        AuthId, Credentials, PasswordAuthenticator, PasswordFile, PeerCredentials,
+    };
+
+    use super::Auth;
+
+    fn auth(password_file: Option<PasswordFile>) -> Auth {
+        Auth {
+            authenticator: Arc::new(RwLock::new(password_file.map(PasswordAuthenticator::new))),
+            authorizer: Arc::default(),
+        }
+    }
+
+    fn credentials() -> Vec<Credentials> {
+        vec![
+            Credentials::Basic(Some("device-1".into()), Some("secret".into())),
+            Credentials::ClientCertificate(b"cert".to_vec().into()),
+            Credentials::PeerCredentials(
+                Some("device-1".into()),
+                PeerCredentials::new(Some(1), 0, 0),
+            ),
+        ]
+    }
+
+    #[test]
+    fn it_allows_anonymous_clients_without_password_file() {
+        let auth = auth(None);
+
+        for credentials in credentials() {
+            assert!(matches!(
+                auth.authenticate(credentials),
+                Ok(Some(AuthId::Anonymous))
+            ));
+        }
+    }
+
+    #[test]
+    fn it_refuses_credentials_without_password_with_password_file() {
+        let mut file = PasswordFile::default();
+        file.add_password("device-1", "secret").unwrap();
+        let auth = auth(Some(file));
+
+        let mut credentials = credentials().into_iter();
+        assert!(matches!(
+            auth.authenticate(credentials.next().unwrap()),
+            Ok(Some(AuthId::Identity(id))) if id == "device-1"
+        ));
+        for credentials in credentials {
+            assert!(matches!(auth.authenticate(credentials), Ok(None)));
+        }
+    }
+}
@@ -1,7 +1,34 @@
-use mqtt_broker::{BrokerHandle, StateSnapshotHandle};
+use mqtt_broker::{AdminError, AdminHandle, BrokerHandle, StateSnapshotHandle, SystemEvent};
 
-pub async fn snapshot(broker_handle: BrokerHandle, snapshot_handle: StateSnapshotHandle) {
-    imp::snapshot(broker_handle, snapshot_handle).await;
+/// Persists the broker state on request.
+#[derive(Clone, Debug)]
+pub enum Snapshot {
+    /// The broker sends its state to the snapshotter.
+    State(StateSnapshotHandle),
+
+    /// The broker compacts its write-ahead log into a checkpoint.
+    Checkpoint,
+}
+
+impl Snapshot {
+    /// Asks the broker to persist its state.
+    pub async fn request(&self, admin_handle: &mut AdminHandle) -> Result<(), AdminError> {
+        match self {
+            Snapshot::State(handle) => admin_handle.snapshot(handle.clone()).await,
+            Snapshot::Checkpoint => admin_handle.checkpoint().await,
+        }
+    }
+
+    fn event(&self) -> SystemEvent {
+        match self {
+            Snapshot::State(handle) => SystemEvent::StateSnapshot(handle.clone()),
+            Snapshot::Checkpoint => SystemEvent::JournalCheckpoint,
+        }
+    }
+}
+
+pub async fn snapshot(broker_handle: BrokerHandle, snapshot: Snapshot) {
+    imp::snapshot(broker_handle, snapshot).await;
 }
 
 #[cfg(unix)]
@@ -9,13 +36,12 @@ mod imp {
     use tokio::signal::unix::{signal, SignalKind};
     use tracing::{info, warn};
 
-    use mqtt_broker::{BrokerHandle, Message, StateSnapshotHandle, SystemEvent};
+    use mqtt_broker::{BrokerHandle, Message};
+
+    use super::Snapshot;
 
     #[cfg(unix)]
-    pub(super) async fn snapshot(
-        mut broker_handle: BrokerHandle,
-        snapshot_handle: StateSnapshotHandle,
-    ) {
+    pub(super) async fn snapshot(mut broker_handle: BrokerHandle, snapshot: Snapshot) {
         let mut stream = match signal(SignalKind::user_defined1()) {
             Ok(stream) => stream,
             Err(e) => {
@@ -29,9 +55,7 @@ mod imp {
             stream.recv().await;
             info!("Received signal USR1");
             if let Err(e) = broker_handle
-                .send(Message::System(SystemEvent::StateSnapshot(
-                    snapshot_handle.clone(),
-                )))
+                .send(Message::System(snapshot.event()))
                 .await
             {
                 warn!(message = "failed to signal the snapshotter", error=%e);
@@ -42,11 +66,9 @@ mod imp {
 
 #[cfg(not(unix))]
 mod imp {
-    use mqtt_broker::{BrokerHandle, StateSnapshotHandle};
+    use mqtt_broker::BrokerHandle;
 
-    pub(super) async fn snapshot(
-        _broker_handle: BrokerHandle,
-        _snapshot_handle: StateSnapshotHandle,
-    ) {
-    }
+    use super::Snapshot;
+
+    pub(super) async fn snapshot(_broker_handle: BrokerHandle, _snapshot: Snapshot) {}
 }
@@ -0,0 +1,101 @@
+use std::{
+    io::{self, BufRead},
+    path::Path,
+};
+
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
+
+use mqtt_broker::{PasswordFile, PasswordFileError};
+
+/// Creates `users` subcommand to manage a password file.
+pub fn command() -> App<'static, 'static> {
+    SubCommand::with_name("users")
+        .about("Manages users in a password file")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("file")
+                .short("f")
+                .long("file")
+                .value_name("FILE")
+                .help("Sets a password file to manage")
+                .takes_value(true)
+                .required(true),
+        )
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("Adds a user or replaces secret of an existing one")
+                .arg(
+                    Arg::with_name("username")
+                        .value_name("USERNAME")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("password")
+                        .short("p")
+                        .long("password")
+                        .value_name("PASSWORD")
+                        .help("Sets a password. Read from stdin if not specified")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("sas-key")
+                        .long("sas-key")
+                        .value_name("KEY")
+                        .help("Sets a base64 encoded key to validate SAS tokens with")
+                        .takes_value(true),
+                )
+                .group(ArgGroup::with_name("secret").args(&["password", "sas-key"])),
+        )
+        .subcommand(
+            SubCommand::with_name("remove").about("Removes a user").arg(
+                Arg::with_name("username")
+                    .value_name("USERNAME")
+                    .required(true),
+            ),
+        )
+}
+
+/// Runs `users` subcommand.
+pub fn run(matches: &ArgMatches<'_>) -> Result<(), PasswordFileError> {
+    let path = Path::new(matches.value_of("file").expect("required argument"));
+    let mut file = if path.exists() {
+        PasswordFile::load(path)?
+    } else {
+        PasswordFile::default()
+    };
+
+    match matches.subcommand() {
+        ("add", Some(matches)) => {
+            let username = matches.value_of("username").expect("required argument");
+            if let Some(key) = matches.value_of("sas-key") {
+                file.add_sas_key(username, key)?;
+            } else if let Some(password) = matches.value_of("password") {
+                file.add_password(username, password)?;
+            } else {
+                let password = read_password().map_err(PasswordFileError::Read)?;
+                file.add_password(username, &password)?;
+            }
+            file.save(path)?;
+            println!("User {} added", username);
+        }
+        ("remove", Some(matches)) => {
+            let username = matches.value_of("username").expect("required argument");
+            if file.remove(username) {
+                file.save(path)?;
+                println!("User {} removed", username);
+            } else {
+                println!("User {} not found", username);
+            }
+        }
+        _ => unreachable!("subcommand is required"),
+    }
+
+    Ok(())
+}
+
+fn read_password() -> io::Result<String> {
+    eprint!("Password: ");
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    Ok(password.trim_end_matches(&['\r', '\n'][..]).to_string())
+}